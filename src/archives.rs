@@ -0,0 +1,336 @@
+use core::{error::Error, fmt::Display};
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde_json::{Map, Value, json};
+
+use crate::{
+    json::{read_envelope, write_json_flatten_primitive_arrays},
+    keys::KeyTable,
+    measurements::Measurement,
+    metrics::{BigramMetric, Metric, TrigramMetric, UnigramMetric},
+    permutations::Provenance,
+    records::Record,
+    scores::Score,
+    symbols::{Symbol, intern, resolve},
+};
+
+const VERSION: u64 = 1;
+
+// NOTE An archive holds the metadata and the selected records of a run, so that they can be
+// printed again (with other formats, filters, sorts, or selections) without permuting. Each
+// measurement keeps both sums, so the records can be weighed either way. Overlays, heatmaps,
+// ranks, and clusters are not archived. The source is the key table file a record was permuted
+// from, if more than one was given. The table sums (of the unigram, bigram, and trigram tables)
+// are missing from older archives, in which case values per billion n-grams are not available.
+pub struct Archive<const C: usize, const R: usize> {
+    pub metadata_opt: Option<Value>,
+    pub records: Vec<Record<C, R>>,
+}
+
+fn measurements_to_value<M: Display, K: Display>(
+    measurements: &BTreeMap<M, Measurement<K>>,
+) -> Value {
+    Value::Object(
+        measurements
+            .iter()
+            .map(|(metric, measurement)| {
+                (
+                    metric.to_string(),
+                    json!({
+                        "sum": measurement.sum,
+                        "sum_ew": measurement.sum_ew,
+                        "details": measurement.details_opt.as_ref().map(|details| {
+                            details
+                                .iter()
+                                .map(|score| {
+                                    json!([score.key.to_string(), score.value, score.value_ew])
+                                })
+                                .collect::<Vec<_>>()
+                        }),
+                    }),
+                )
+            })
+            .collect(),
+    )
+}
+
+fn provenance_to_value(provenance: &Provenance) -> Value {
+    json!({
+        "permutation_index": provenance.permutation_index,
+        "regions": provenance
+            .assignments
+            .iter()
+            .map(|assignment| {
+                assignment
+                    .iter()
+                    .map(|&(byte, (r, c))| json!([resolve(byte).to_string(), r, c]))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+pub fn record_to_value<const C: usize, const R: usize>(record: &Record<C, R>) -> Value {
+    json!({
+        "key_table": Value::from(&KeyTable::from_byte_matrix(&record.key_table_matrix)),
+        "measurements": {
+            "unigram": measurements_to_value(&record.unigram_measurements),
+            "bigram": measurements_to_value(&record.bigram_measurements),
+            "trigram": measurements_to_value(&record.trigram_measurements),
+        },
+        "sums": {
+            "unigram": [record.uf_sum, record.uf_sum_ew],
+            "bigram": [record.bf_sum, record.bf_sum_ew],
+            "trigram": [record.tf_sum, record.tf_sum_ew],
+        },
+        "table_sums": [record.uf_table_sum, record.bf_table_sum, record.tf_table_sum],
+        "percentiles": record
+            .percentiles
+            .iter()
+            .map(|(metric, percentile)| (metric.to_string(), json!(percentile)))
+            .collect::<Map<_, _>>(),
+        "provenance": record.provenance_opt.as_ref().map(provenance_to_value),
+        "source": record.source_opt,
+    })
+}
+
+pub fn write_archive<W: Write + ?Sized, const C: usize, const R: usize>(
+    writer: &mut W,
+    metadata_opt: Option<&Value>,
+    records: &[Record<C, R>],
+) -> io::Result<()> {
+    let value = json!({
+        "version": VERSION,
+        "data": {
+            "metadata": metadata_opt,
+            "records": records.iter().map(record_to_value).collect::<Vec<_>>(),
+        },
+    });
+    write_json_flatten_primitive_arrays::<2, _>(writer, &value, 0)?;
+    writeln!(writer)
+}
+
+fn get<'a>(object: &'a Map<String, Value>, field: &str) -> Result<&'a Value, String> {
+    object
+        .get(field)
+        .ok_or_else(|| format!("Expected '{}' field", field))
+}
+
+fn get_object<'a>(
+    object: &'a Map<String, Value>,
+    field: &str,
+) -> Result<&'a Map<String, Value>, String> {
+    get(object, field)?
+        .as_object()
+        .ok_or_else(|| format!("Value of '{}' field must be of type 'object'", field))
+}
+
+fn get_u64(value: &Value, field: &str) -> Result<u64, String> {
+    value
+        .as_u64()
+        .ok_or_else(|| format!("Value of '{}' field must be of type 'u64'", field))
+}
+
+fn get_sums(object: &Map<String, Value>, field: &str) -> Result<(u64, u64), String> {
+    match get(object, field)?.as_array().map(Vec::as_slice) {
+        Some([sum, sum_ew]) => Ok((get_u64(sum, field)?, get_u64(sum_ew, field)?)),
+        _ => Err(format!(
+            "Value of '{}' field must be an array of two 'u64's",
+            field
+        )),
+    }
+}
+
+fn parse_metric<M: Copy + Display>(variants: &[M], s: &str) -> Result<M, String> {
+    variants
+        .iter()
+        .copied()
+        .find(|metric| metric.to_string() == s)
+        .ok_or_else(|| format!("Unknown metric '{}'", s))
+}
+
+fn measurements_from_value<M, K>(
+    value: &Value,
+    variants: &[M],
+) -> Result<BTreeMap<M, Measurement<K>>, String>
+where
+    M: Copy + Display + Ord,
+    K: for<'a> TryFrom<&'a str, Error = String>,
+{
+    let object = value
+        .as_object()
+        .ok_or("Measurements must be of type 'object'")?;
+    object
+        .iter()
+        .map(|(name, value)| {
+            let metric = parse_metric(variants, name)?;
+            let object = value
+                .as_object()
+                .ok_or_else(|| format!("Measurement '{}' must be of type 'object'", name))?;
+            let details_opt = match get(object, "details")? {
+                Value::Null => None,
+                Value::Array(details) => Some(
+                    details
+                        .iter()
+                        .map(|detail| match detail.as_array().map(Vec::as_slice) {
+                            Some([Value::String(key), value, value_ew]) => Ok(Score {
+                                key: K::try_from(key.as_str())?,
+                                value: get_u64(value, "details")?,
+                                value_ew: get_u64(value_ew, "details")?,
+                            }),
+                            _ => Err(format!("Invalid detail in measurement '{}'", name)),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+                _ => Err(format!(
+                    "Value of 'details' field of measurement '{}' must be of type 'array' or 'null'",
+                    name
+                ))?,
+            };
+            Ok((
+                metric,
+                Measurement::new(
+                    details_opt,
+                    get_u64(get(object, "sum")?, "sum")?,
+                    get_u64(get(object, "sum_ew")?, "sum_ew")?,
+                ),
+            ))
+        })
+        .collect()
+}
+
+fn provenance_from_value(value: &Value) -> Result<Option<Provenance>, String> {
+    let object = match value {
+        Value::Null => return Ok(None),
+        Value::Object(object) => object,
+        _ => Err("Value of 'provenance' field must be of type 'object' or 'null'")?,
+    };
+    let regions = get(object, "regions")?
+        .as_array()
+        .filter(|regions| regions.len() == 3)
+        .ok_or("Value of 'regions' field must be an array of three arrays")?;
+    let mut assignments: [Vec<(Symbol, (usize, usize))>; 3] = Default::default();
+    for (assignment, region) in assignments.iter_mut().zip(regions) {
+        for item in region.as_array().ok_or("Invalid region")? {
+            match item.as_array().map(Vec::as_slice) {
+                Some([Value::String(s), r, c]) if s.chars().count() == 1 => {
+                    let ch = s.chars().next().unwrap_or_default();
+                    assignment.push((
+                        intern(ch)?,
+                        (
+                            get_u64(r, "regions")? as usize,
+                            get_u64(c, "regions")? as usize,
+                        ),
+                    ));
+                }
+                _ => Err("Invalid assignment in region")?,
+            }
+        }
+    }
+    Ok(Some(Provenance {
+        permutation_index: get_u64(get(object, "permutation_index")?, "permutation_index")?,
+        assignments,
+    }))
+}
+
+fn record_from_value<const C: usize, const R: usize>(
+    value: &Value,
+) -> Result<Record<C, R>, String> {
+    let object = value.as_object().ok_or("Record must be of type 'object'")?;
+    let key_table = KeyTable::<C, R>::try_from_value(get(object, "key_table")?, true)?;
+    let measurements = get_object(object, "measurements")?;
+    let sums = get_object(object, "sums")?;
+    let (uf_sum, uf_sum_ew) = get_sums(sums, "unigram")?;
+    let (bf_sum, bf_sum_ew) = get_sums(sums, "bigram")?;
+    let (tf_sum, tf_sum_ew) = get_sums(sums, "trigram")?;
+    let [uf_table_sum, bf_table_sum, tf_table_sum] = match object.get("table_sums") {
+        None => [0; 3],
+        Some(value) => match value.as_array().map(Vec::as_slice) {
+            Some([u, b, t]) => [
+                get_u64(u, "table_sums")?,
+                get_u64(b, "table_sums")?,
+                get_u64(t, "table_sums")?,
+            ],
+            _ => Err("Value of 'table_sums' field must be an array of three 'u64's")?,
+        },
+    };
+    let percentiles = get_object(object, "percentiles")?
+        .iter()
+        .map(|(name, value)| {
+            let metric = parse_metric(&UnigramMetric::VARIANT_ARRAY, name)
+                .map(Metric::Unigram)
+                .or_else(|_| parse_metric(&BigramMetric::VARIANT_ARRAY, name).map(Metric::Bigram))
+                .or_else(|_| {
+                    parse_metric(&TrigramMetric::VARIANT_ARRAY, name).map(Metric::Trigram)
+                })?;
+            let percentile = value
+                .as_f64()
+                .ok_or_else(|| format!("Percentile of '{}' must be a number", name))?;
+            Ok((metric, percentile))
+        })
+        .collect::<Result<BTreeMap<_, _>, String>>()?;
+    Ok(Record {
+        key_table_matrix: key_table.to_byte_matrix(),
+        unigram_measurements: measurements_from_value(
+            get(measurements, "unigram")?,
+            &UnigramMetric::VARIANT_ARRAY,
+        )?,
+        bigram_measurements: measurements_from_value(
+            get(measurements, "bigram")?,
+            &BigramMetric::VARIANT_ARRAY,
+        )?,
+        trigram_measurements: measurements_from_value(
+            get(measurements, "trigram")?,
+            &TrigramMetric::VARIANT_ARRAY,
+        )?,
+        uf_sum,
+        uf_sum_ew,
+        bf_sum,
+        bf_sum_ew,
+        tf_sum,
+        tf_sum_ew,
+        uf_table_sum,
+        bf_table_sum,
+        tf_table_sum,
+        percentiles,
+        provenance_opt: provenance_from_value(get(object, "provenance")?)?,
+        overlay_opt: None,
+        heatmap_opt: None,
+        utilization_opt: None,
+        ranks: Vec::new(),
+        cluster_size_opt: None,
+        source_opt: object
+            .get("source")
+            .and_then(Value::as_str)
+            .map(String::from),
+    })
+}
+
+impl<const C: usize, const R: usize> Archive<C, R> {
+    pub fn read_from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let object = read_envelope(file, VERSION)?;
+        let data = get_object(&object, "data")?;
+        let metadata_opt = match get(data, "metadata")? {
+            Value::Null => None,
+            value => Some(value.clone()),
+        };
+        let records = get(data, "records")?
+            .as_array()
+            .ok_or("Value of 'records' field must be of type 'array'")?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| record_from_value(value).map_err(|e| format!("Record {}: {}", i, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            metadata_opt,
+            records,
+        })
+    }
+}
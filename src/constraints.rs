@@ -0,0 +1,584 @@
+use core::fmt::{self, Display};
+
+use crate::{
+    layouts::{Digits, Laterality, LayoutTable, Position},
+    ngrams::UnigramTable,
+    symbols::{Symbol, intern},
+};
+
+// NOTE Named rows, as in 'default.lt.json'.
+const ROWS: [(&str, usize); 3] = [("top_row", 0), ("home_row", 1), ("bottom_row", 2)];
+
+const HANDS: [(&str, Laterality); 2] = [("left", Laterality::Left), ("right", Laterality::Right)];
+
+const FINGERS: [(&str, Position); 5] = [
+    ("thumb", Position::Thumb),
+    ("index", Position::Index),
+    ("middle", Position::Middle),
+    ("ring", Position::Ring),
+    ("pinky", Position::Pinky),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Character(char),
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Not,
+    Comma,
+    LeftParenthesis,
+    RightParenthesis,
+    LeftBracket,
+    RightBracket,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Token::*;
+        match self {
+            Number(n) => write!(f, "{}", n),
+            Identifier(s) => write!(f, "{}", s),
+            Character(ch) => write!(f, "{:?}", ch),
+            Eq => write!(f, "=="),
+            Neq => write!(f, "!="),
+            Lt => write!(f, "<"),
+            Le => write!(f, "<="),
+            Gt => write!(f, ">"),
+            Ge => write!(f, ">="),
+            And => write!(f, "&&"),
+            Or => write!(f, "||"),
+            Not => write!(f, "!"),
+            Comma => write!(f, ","),
+            LeftParenthesis => write!(f, "("),
+            RightParenthesis => write!(f, ")"),
+            LeftBracket => write!(f, "["),
+            RightBracket => write!(f, "]"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    use Token::*;
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let mut followed_by = |expected: char| chars.next_if_eq(&expected).is_some();
+        let token = match ch {
+            _ if ch.is_whitespace() => continue,
+            '0'..='9' => {
+                let mut s = ch.to_string();
+                while let Some(ch) = chars.next_if(|ch| ch.is_ascii_digit() || *ch == '.') {
+                    s.push(ch);
+                }
+                Number(s.parse().map_err(|_| format!("Invalid number: '{}'", s))?)
+            }
+            'A'..='Z' | '_' | 'a'..='z' => {
+                let mut s = ch.to_string();
+                while let Some(ch) = chars.next_if(|ch| ch.is_ascii_alphanumeric() || *ch == '_') {
+                    s.push(ch);
+                }
+                Identifier(s)
+            }
+            '\'' => {
+                let ch = match chars.next() {
+                    Some('\\') => chars.next(),
+                    ch_opt => ch_opt,
+                }
+                .ok_or("Unterminated character")?;
+                if chars.next() != Some('\'') {
+                    Err("Unterminated character")?;
+                }
+                Character(ch)
+            }
+            '=' if followed_by('=') => Eq,
+            '!' if followed_by('=') => Neq,
+            '!' => Not,
+            '<' if followed_by('=') => Le,
+            '<' => Lt,
+            '>' if followed_by('=') => Ge,
+            '>' => Gt,
+            '&' if followed_by('&') => And,
+            '|' if followed_by('|') => Or,
+            ',' => Comma,
+            '(' => LeftParenthesis,
+            ')' => RightParenthesis,
+            '[' => LeftBracket,
+            ']' => RightBracket,
+            _ => Err(format!("Unexpected token: '{}'", ch))?,
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Function {
+    Pos,
+    Row,
+    Col,
+    Hand,
+    Finger,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Number(f64),
+    Hand(Laterality),
+    Finger(Position),
+    Call(Function, Symbol),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(Box<Node>, Comparison, Box<Node>),
+    In(Box<Node>, Vec<Node>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    Boolean,
+    Number,
+    Hand,
+    Finger,
+    Position,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Boolean(bool),
+    Number(f64),
+    Hand(Laterality),
+    Finger(Position),
+    Position(usize, usize),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or("Unexpected end of input")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("Expected '{}', found '{}'", expected, token)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            node = Node::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            node = Node::And(Box::new(node), Box::new(self.parse_not()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_not(&mut self) -> Result<Node, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(Node::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Node, String> {
+        let left = self.parse_primary()?;
+        let comparison = match self.peek() {
+            Some(Token::Eq) => Comparison::Eq,
+            Some(Token::Neq) => Comparison::Neq,
+            Some(Token::Lt) => Comparison::Lt,
+            Some(Token::Le) => Comparison::Le,
+            Some(Token::Gt) => Comparison::Gt,
+            Some(Token::Ge) => Comparison::Ge,
+            Some(Token::Identifier(s)) if s == "in" => {
+                self.position += 1;
+                let nodes = if self.peek() == Some(&Token::LeftBracket) {
+                    self.position += 1;
+                    let mut nodes = vec![self.parse_primary()?];
+                    while self.peek() == Some(&Token::Comma) {
+                        self.position += 1;
+                        nodes.push(self.parse_primary()?);
+                    }
+                    self.expect(Token::RightBracket)?;
+                    nodes
+                } else {
+                    vec![self.parse_primary()?]
+                };
+                return Ok(Node::In(Box::new(left), nodes));
+            }
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.parse_primary()?;
+        Ok(Node::Compare(Box::new(left), comparison, Box::new(right)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, String> {
+        match self.next()? {
+            Token::Number(n) => Ok(Node::Number(n)),
+            Token::LeftParenthesis => {
+                let node = self.parse_or()?;
+                self.expect(Token::RightParenthesis)?;
+                Ok(node)
+            }
+            Token::Identifier(s) => {
+                let function_opt = match s.as_str() {
+                    "pos" => Some(Function::Pos),
+                    "row" => Some(Function::Row),
+                    "col" => Some(Function::Col),
+                    "hand" => Some(Function::Hand),
+                    "finger" => Some(Function::Finger),
+                    _ => None,
+                };
+                if let Some(function) = function_opt {
+                    self.expect(Token::LeftParenthesis)?;
+                    let ch = match self.next()? {
+                        Token::Character(ch) => ch,
+                        token => Err(format!(
+                            "Expected a character (such as 'e'), found '{}'",
+                            token
+                        ))?,
+                    };
+                    self.expect(Token::RightParenthesis)?;
+                    return Ok(Node::Call(function, intern(ch)?));
+                }
+                if let Some(&(_, r)) = ROWS.iter().find(|(name, _)| *name == s) {
+                    return Ok(Node::Number(r as f64));
+                }
+                if let Some(&(_, laterality)) = HANDS.iter().find(|(name, _)| *name == s) {
+                    return Ok(Node::Hand(laterality));
+                }
+                if let Some(&(_, position)) = FINGERS.iter().find(|(name, _)| *name == s) {
+                    return Ok(Node::Finger(position));
+                }
+                Err(format!("Undefined name: '{}'", s))
+            }
+            token => Err(format!("Unexpected token: '{}'", token)),
+        }
+    }
+}
+
+impl Node {
+    fn check(&self) -> Result<Type, String> {
+        use Node::*;
+        let expect_boolean = |node: &Node| match node.check()? {
+            Type::Boolean => Ok(()),
+            t => Err(format!("Expected a boolean, found a {}", t)),
+        };
+        match self {
+            Number(_) => Ok(Type::Number),
+            Hand(_) => Ok(Type::Hand),
+            Finger(_) => Ok(Type::Finger),
+            Call(function, _) => Ok(match function {
+                Function::Pos => Type::Position,
+                Function::Row | Function::Col => Type::Number,
+                Function::Hand => Type::Hand,
+                Function::Finger => Type::Finger,
+            }),
+            Not(node) => expect_boolean(node).map(|_| Type::Boolean),
+            And(left, right) | Or(left, right) => {
+                expect_boolean(left)?;
+                expect_boolean(right)?;
+                Ok(Type::Boolean)
+            }
+            Compare(left, comparison, right) => {
+                let (left, right) = (left.check()?, right.check()?);
+                use Comparison::*;
+                match comparison {
+                    Eq | Neq if left == right => Ok(Type::Boolean),
+                    Lt | Le | Gt | Ge if left == Type::Number && right == Type::Number => {
+                        Ok(Type::Boolean)
+                    }
+                    _ => Err(format!("Cannot compare a {} with a {}", left, right)),
+                }
+            }
+            In(left, nodes) => {
+                let left = left.check()?;
+                for node in nodes {
+                    match (left, node.check()?) {
+                        (left, right) if left == right => (),
+                        (Type::Position, Type::Number) => (),
+                        (left, right) => Err(format!("Cannot find a {} in a {}", left, right))?,
+                    }
+                }
+                Ok(Type::Boolean)
+            }
+        }
+    }
+
+    fn collect_symbols(&self, symbols: &mut Vec<Symbol>) {
+        use Node::*;
+        match self {
+            Number(_) | Hand(_) | Finger(_) => (),
+            Call(_, symbol) => symbols.push(*symbol),
+            Not(node) => node.collect_symbols(symbols),
+            And(left, right) | Or(left, right) | Compare(left, _, right) => {
+                left.collect_symbols(symbols);
+                right.collect_symbols(symbols);
+            }
+            In(left, nodes) => {
+                left.collect_symbols(symbols);
+                for node in nodes {
+                    node.collect_symbols(symbols);
+                }
+            }
+        }
+    }
+
+    // NOTE A value is unavailable if a character is not in the key table, or if its key has no
+    // digit in the layout table. Any comparison with an unavailable value is false.
+    fn evaluate<const C: usize, const R: usize>(
+        &self,
+        matrix: &[[Symbol; C]; R],
+        layout_table: &LayoutTable<C, R>,
+    ) -> Option<Value> {
+        use Node::*;
+        let locate = |symbol: Symbol| {
+            matrix
+                .iter()
+                .enumerate()
+                .find_map(|(r, row)| row.iter().position(|&byte| byte == symbol).map(|c| (r, c)))
+        };
+        let digit = |symbol: Symbol| {
+            let (r, c) = locate(symbol)?;
+            layout_table.0[r][c].as_ref().map(Digits::primary)
+        };
+        let is_true =
+            |node: &Node| node.evaluate(matrix, layout_table) == Some(Value::Boolean(true));
+        Some(match self {
+            Number(n) => Value::Number(*n),
+            Hand(laterality) => Value::Hand(*laterality),
+            Finger(position) => Value::Finger(*position),
+            Call(function, symbol) => match function {
+                Function::Pos => locate(*symbol).map(|(r, c)| Value::Position(r, c))?,
+                Function::Row => Value::Number(locate(*symbol)?.0 as f64),
+                Function::Col => Value::Number(locate(*symbol)?.1 as f64),
+                Function::Hand => Value::Hand(digit(*symbol)?.0),
+                Function::Finger => Value::Finger(digit(*symbol)?.1),
+            },
+            Not(node) => Value::Boolean(!is_true(node)),
+            And(left, right) => Value::Boolean(is_true(left) && is_true(right)),
+            Or(left, right) => Value::Boolean(is_true(left) || is_true(right)),
+            Compare(left, comparison, right) => {
+                let (left, right) = (
+                    left.evaluate(matrix, layout_table),
+                    right.evaluate(matrix, layout_table),
+                );
+                let (Some(left), Some(right)) = (left, right) else {
+                    return Some(Value::Boolean(false));
+                };
+                use Comparison::*;
+                Value::Boolean(match (comparison, left, right) {
+                    (Eq, left, right) => left == right,
+                    (Neq, left, right) => left != right,
+                    (Lt, Value::Number(a), Value::Number(b)) => a < b,
+                    (Le, Value::Number(a), Value::Number(b)) => a <= b,
+                    (Gt, Value::Number(a), Value::Number(b)) => a > b,
+                    (Ge, Value::Number(a), Value::Number(b)) => a >= b,
+                    _ => false,
+                })
+            }
+            In(left, nodes) => {
+                let Some(left) = left.evaluate(matrix, layout_table) else {
+                    return Some(Value::Boolean(false));
+                };
+                Value::Boolean(nodes.iter().any(|node| {
+                    match (left, node.evaluate(matrix, layout_table)) {
+                        (Value::Position(r, _), Some(Value::Number(n))) => r as f64 == n,
+                        (left, Some(right)) => left == right,
+                        (_, None) => false,
+                    }
+                }))
+            }
+        })
+    }
+}
+
+// NOTE A constraint is a condition on where characters are placed, which every permutation must
+// satisfy. It is checked before a permutation is scored, so permutations that violate it are
+// skipped at little cost.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constraint {
+    source: String,
+    node: Node,
+}
+
+impl Constraint {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s.trim().is_empty() {
+            Err("Empty input")?;
+        }
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+        let node = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            Err(format!("Unexpected token: '{}'", token))?;
+        }
+        match node.check()? {
+            Type::Boolean => Ok(Self {
+                source: s.into(),
+                node,
+            }),
+            t => Err(format!("Expected a boolean, found a {}", t)),
+        }
+    }
+
+    // NOTE The characters (as symbols) that the constraint refers to.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = Vec::new();
+        self.node.collect_symbols(&mut symbols);
+        symbols.sort_unstable();
+        symbols.dedup();
+        symbols
+    }
+
+    pub fn is_satisfied<const C: usize, const R: usize>(
+        &self,
+        matrix: &[[Symbol; C]; R],
+        layout_table: &LayoutTable<C, R>,
+    ) -> bool {
+        self.node.evaluate(matrix, layout_table) == Some(Value::Boolean(true))
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+// NOTE A cap is a minimum or maximum usage of a key: the percentage of all unigrams typed on it.
+// For example, "1,9<0.5" allows at most 0.5% of unigrams on the key at row 1, column 9 (0-based).
+// Like a constraint, it is checked before a permutation is scored, with one lookup in the unigram
+// table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyUsageCap {
+    source: String,
+    r: usize,
+    c: usize,
+    comparison: Comparison,
+    perc: f64,
+}
+
+impl KeyUsageCap {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let index = |n: f64| {
+            if n.fract() == 0.0 {
+                Ok(n as usize)
+            } else {
+                Err(format!("Expected a row or column, found '{}'", n))
+            }
+        };
+        match tokenize(s)?.as_slice() {
+            [
+                Token::Number(r),
+                Token::Comma,
+                Token::Number(c),
+                comparison,
+                Token::Number(perc),
+            ] => {
+                let comparison = match comparison {
+                    Token::Lt => Comparison::Lt,
+                    Token::Le => Comparison::Le,
+                    Token::Gt => Comparison::Gt,
+                    Token::Ge => Comparison::Ge,
+                    token => Err(format!(
+                        "Expected '<', '<=', '>', or '>=', found '{}'",
+                        token
+                    ))?,
+                };
+                Ok(Self {
+                    source: s.into(),
+                    r: index(*r)?,
+                    c: index(*c)?,
+                    comparison,
+                    perc: *perc,
+                })
+            }
+            _ => {
+                Err("Expected ROW,COLUMN, a comparison, and a percentage (such as 1,9<0.5)".into())
+            }
+        }
+    }
+
+    pub fn position(&self) -> (usize, usize) {
+        (self.r, self.c)
+    }
+
+    pub fn is_satisfied<const C: usize, const R: usize>(
+        &self,
+        matrix: &[[Symbol; C]; R],
+        unigram_table: &UnigramTable,
+        unigram_table_sum: u64,
+    ) -> bool {
+        let count = unigram_table.get([matrix[self.r][self.c]]);
+        let perc = if unigram_table_sum == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / unigram_table_sum as f64
+        };
+        use Comparison::*;
+        match self.comparison {
+            Lt => perc < self.perc,
+            Le => perc <= self.perc,
+            Gt => perc > self.perc,
+            Ge => perc >= self.perc,
+            Eq | Neq => unreachable!(),
+        }
+    }
+}
+
+impl Display for KeyUsageCap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
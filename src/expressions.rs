@@ -10,10 +10,13 @@ use std::collections::{HashMap, HashSet};
 enum Token {
     Number(f64),
     Identifier(String),
+    String(String),
+    Comma,
     Plus,
     Minus,
     Asterisk,
     Solidus,
+    Caret,
     Eq,
     Neq,
     Lt,
@@ -33,10 +36,13 @@ impl fmt::Display for Token {
         match self {
             Number(n) => write!(f, "number({})", n),
             Identifier(s) => write!(f, "identifier({})", s),
+            String(s) => write!(f, "string({})", s),
+            Comma => write!(f, ","),
             Plus => write!(f, "+"),
             Minus => write!(f, "-"),
             Asterisk => write!(f, "*"),
             Solidus => write!(f, "/"),
+            Caret => write!(f, "^"),
             Eq => write!(f, "=="),
             Neq => write!(f, "!="),
             Lt => write!(f, "<"),
@@ -62,7 +68,19 @@ impl<'a> Lexer<'a> {
         Self { input, position: 0 }
     }
 
-    fn next(&mut self) -> Option<Result<Token, ParseError>> {
+    // NOTE Each token is returned with its span in the input, and each error with the span of the
+    // text that caused it (at least one character long).
+    fn next(&mut self) -> Option<Result<(Token, Span), (ParseErrorKind, Span)>> {
+        self.skip_whitespace();
+        let start = self.position;
+        self.next_token().map(|result| {
+            result
+                .map(|token| (token, (start, self.position)))
+                .map_err(|e| (e, (start, self.position.max(start + 1))))
+        })
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token, ParseErrorKind>> {
         use Token::*;
         self.skip_whitespace();
         let slice = self.input.as_bytes();
@@ -72,6 +90,11 @@ impl<'a> Lexer<'a> {
         match slice[self.position] as char {
             '0'..='9' | '.' => Some(self.read_number()),
             'A'..='Z' | '_' | 'a'..='z' => Some(self.read_identifier()),
+            quote @ ('\'' | '"') => Some(self.read_string(quote)),
+            ',' => {
+                self.position += 1;
+                Some(Ok(Comma))
+            }
             '+' => {
                 self.position += 1;
                 Some(Ok(Plus))
@@ -88,6 +111,10 @@ impl<'a> Lexer<'a> {
                 self.position += 1;
                 Some(Ok(Solidus))
             }
+            '^' => {
+                self.position += 1;
+                Some(Ok(Caret))
+            }
             '!' => {
                 self.position += 1;
                 if self.consume('=') {
@@ -101,7 +128,7 @@ impl<'a> Lexer<'a> {
                 if self.consume('=') {
                     Some(Ok(Eq))
                 } else {
-                    Some(Err(ParseError::UnexpectedToken("=".to_string())))
+                    Some(Err(ParseErrorKind::UnexpectedToken("=".to_string())))
                 }
             }
             '<' => {
@@ -125,7 +152,7 @@ impl<'a> Lexer<'a> {
                 if self.consume('&') {
                     Some(Ok(And))
                 } else {
-                    Some(Err(ParseError::UnexpectedToken("&".to_string())))
+                    Some(Err(ParseErrorKind::UnexpectedToken("&".to_string())))
                 }
             }
             '|' => {
@@ -133,7 +160,7 @@ impl<'a> Lexer<'a> {
                 if self.consume('|') {
                     Some(Ok(Or))
                 } else {
-                    Some(Err(ParseError::UnexpectedToken("|".to_string())))
+                    Some(Err(ParseErrorKind::UnexpectedToken("|".to_string())))
                 }
             }
             '(' => {
@@ -144,7 +171,11 @@ impl<'a> Lexer<'a> {
                 self.position += 1;
                 Some(Ok(RightParenthesis))
             }
-            ch => Some(Err(ParseError::UnexpectedToken(ch.to_string()))),
+            _ => {
+                let ch = self.input[self.position..].chars().next()?;
+                self.position += ch.len_utf8();
+                Some(Err(ParseErrorKind::UnexpectedToken(ch.to_string())))
+            }
         }
     }
 
@@ -159,7 +190,7 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn read_number(&mut self) -> Result<Token, ParseError> {
+    fn read_number(&mut self) -> Result<Token, ParseErrorKind> {
         let start = self.position;
         while self.position < self.input.len()
             && self.input.as_bytes()[self.position].is_ascii_digit()
@@ -175,13 +206,16 @@ impl<'a> Lexer<'a> {
             }
         }
         let slice = &self.input[start..self.position];
+        // NOTE Scores are already percentages, so a '%' suffix (as in '2%') does not change the
+        // value of a number.
+        self.consume('%');
         match slice.parse::<f64>() {
             Ok(num) => Ok(Token::Number(num)),
-            Err(_) => Err(ParseError::InvalidNumber(slice.to_string())),
+            Err(_) => Err(ParseErrorKind::InvalidNumber(slice.to_string())),
         }
     }
 
-    fn read_identifier(&mut self) -> Result<Token, ParseError> {
+    fn read_identifier(&mut self) -> Result<Token, ParseErrorKind> {
         let start = self.position;
         while self.position < self.input.len()
             && (self.input.as_bytes()[self.position].is_ascii_alphanumeric()
@@ -194,6 +228,22 @@ impl<'a> Lexer<'a> {
         ))
     }
 
+    // NOTE A string is enclosed in single or double quotes, and cannot contain the quote that
+    // encloses it (there are no escape sequences).
+    fn read_string(&mut self, quote: char) -> Result<Token, ParseErrorKind> {
+        let start = self.position + 1;
+        match self.input[start..].find(quote) {
+            Some(length) => {
+                self.position = start + length + 1;
+                Ok(Token::String(self.input[start..start + length].to_string()))
+            }
+            None => {
+                self.position = self.input.len();
+                Err(ParseErrorKind::UnterminatedString)
+            }
+        }
+    }
+
     fn skip_whitespace(&mut self) {
         while self.position < self.input.len()
             && (self.input.as_bytes()[self.position] as char).is_ascii_whitespace()
@@ -203,50 +253,156 @@ impl<'a> Lexer<'a> {
     }
 }
 
-struct Parser {
-    tokens: Vec<Token>,
+// NOTE The type of an expression, checked while parsing (see 'Expression::parse'). A variable
+// parsed without a set of variables (see 'TryFrom') has the type 'Any', which is never a mismatch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    Boolean,
+    Number,
+    Position,
+    String,
+}
+
+impl Type {
+    fn is_one_of(self, types: &[Type]) -> bool {
+        self == Type::Any || types.contains(&self)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Type::Any => "any value",
+            Type::Boolean => "a boolean",
+            Type::Number => "a number",
+            Type::Position => "a position",
+            Type::String => "a string",
+        }
+    }
+}
+
+// NOTE Such as 'a number, a string, or a position'.
+fn describe_types(types: &[Type]) -> String {
+    match types {
+        [] => Type::Any.name().to_string(),
+        [ty] => ty.name().to_string(),
+        [ty1, ty2] => format!("{} or {}", ty1.name(), ty2.name()),
+        [init @ .., last] => format!(
+            "{}, or {}",
+            init.iter()
+                .map(|ty| ty.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            last.name()
+        ),
+    }
+}
+
+// NOTE The type of each variable that an expression may refer to. A function that depends on a
+// record (such as 'key') must also be defined, under its name.
+pub type Variables = HashMap<String, Type>;
+
+const LOGICAL_TYPES: [Type; 2] = [Type::Boolean, Type::Number];
+
+const EQUALITY_TYPES: [Type; 3] = [Type::Number, Type::String, Type::Position];
+
+// NOTE A span of the input, from a start offset to an end offset (exclusive), in bytes.
+type Span = (usize, usize);
+
+// NOTE An expression parsed so far, with its type and its span in the input (which is underlined
+// when a type mismatch is reported).
+struct Typed {
+    expression: Expression,
+    ty: Type,
+    span: Span,
+}
+
+impl Typed {
+    fn expect(&self, types: &[Type]) -> Result<(), (ParseErrorKind, Span)> {
+        if self.ty.is_one_of(types) {
+            Ok(())
+        } else {
+            Err((
+                ParseErrorKind::TypeMismatch {
+                    expected: describe_types(types),
+                    found: self.ty,
+                },
+                self.span,
+            ))
+        }
+    }
+
+    fn binary(left: Typed, operator: BinaryOperator, right: Typed, ty: Type) -> Typed {
+        Typed {
+            span: (left.span.0, right.span.1),
+            expression: Expression::Binary {
+                left: Box::new(left.expression),
+                operator,
+                right: Box::new(right.expression),
+            },
+            ty,
+        }
+    }
+}
+
+type ParseResult = Result<Typed, (ParseErrorKind, Span)>;
+
+struct Parser<'a> {
+    tokens: Vec<(Token, Span)>,
     position: usize,
+    end: usize,
+    variables_opt: Option<&'a Variables>,
 }
 
-impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
+impl<'a> Parser<'a> {
+    fn new(tokens: Vec<(Token, Span)>, end: usize, variables_opt: Option<&'a Variables>) -> Self {
         Parser {
             tokens,
             position: 0,
+            end,
+            variables_opt,
         }
     }
 
-    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
+    // NOTE The span of the next token, or an empty span at the end of the input.
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.position)
+            .map_or((self.end, self.end), |&(_, span)| span)
+    }
+
+    // NOTE The end of the span of the last token consumed.
+    fn previous_end(&self) -> usize {
+        self.tokens[self.position - 1].1.1
+    }
+
+    fn parse_expression(&mut self) -> ParseResult {
         self.parse_or()
     }
 
-    fn parse_or(&mut self) -> Result<Expression, ParseError> {
+    fn parse_or(&mut self) -> ParseResult {
         let mut expression = self.parse_and()?;
         while self.match_token(&Token::Or) {
             let right = self.parse_and()?;
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator: BinaryOperator::Or,
-                right: Box::new(right),
-            };
+            expression.expect(&LOGICAL_TYPES)?;
+            right.expect(&LOGICAL_TYPES)?;
+            expression = Typed::binary(expression, BinaryOperator::Or, right, Type::Boolean);
         }
         Ok(expression)
     }
 
-    fn parse_and(&mut self) -> Result<Expression, ParseError> {
+    fn parse_and(&mut self) -> ParseResult {
         let mut expression = self.parse_equality()?;
         while self.match_token(&Token::And) {
             let right = self.parse_equality()?;
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator: BinaryOperator::And,
-                right: Box::new(right),
-            };
+            expression.expect(&LOGICAL_TYPES)?;
+            right.expect(&LOGICAL_TYPES)?;
+            expression = Typed::binary(expression, BinaryOperator::And, right, Type::Boolean);
         }
         Ok(expression)
     }
 
-    fn parse_equality(&mut self) -> Result<Expression, ParseError> {
+    // NOTE Both sides must have the same type (a number, a string, or a position).
+    fn parse_equality(&mut self) -> ParseResult {
         let mut expression = self.parse_relational()?;
         if let Some(idx) = self.match_one_of(&[Token::Eq, Token::Neq]) {
             let right = self.parse_relational()?;
@@ -255,19 +411,23 @@ impl Parser {
                 1 => BinaryOperator::Neq,
                 _ => unreachable!(),
             };
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator: bop,
-                right: Box::new(right),
-            };
+            expression.expect(&EQUALITY_TYPES)?;
+            match expression.ty {
+                Type::Any => right.expect(&EQUALITY_TYPES)?,
+                ty => right.expect(&[ty])?,
+            }
+            expression = Typed::binary(expression, bop, right, Type::Boolean);
             if self.peek_one_of(&[Token::Eq, Token::Neq]).is_some() {
-                return Err(ParseError::ChainedNonAssociative("equality (==, !=)"));
+                return Err((
+                    ParseErrorKind::ChainedNonAssociative("equality (==, !=)"),
+                    self.span(),
+                ));
             }
         }
         Ok(expression)
     }
 
-    fn parse_relational(&mut self) -> Result<Expression, ParseError> {
+    fn parse_relational(&mut self) -> ParseResult {
         let mut expression = self.parse_term()?;
         if let Some(idx) = self.match_one_of(&[Token::Lt, Token::Le, Token::Gt, Token::Ge]) {
             let right = self.parse_term()?;
@@ -278,24 +438,23 @@ impl Parser {
                 3 => BinaryOperator::Ge,
                 _ => unreachable!(),
             };
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator: bop,
-                right: Box::new(right),
-            };
+            expression.expect(&[Type::Number])?;
+            right.expect(&[Type::Number])?;
+            expression = Typed::binary(expression, bop, right, Type::Boolean);
             if self
                 .peek_one_of(&[Token::Lt, Token::Le, Token::Gt, Token::Ge])
                 .is_some()
             {
-                return Err(ParseError::ChainedNonAssociative(
-                    "relational (<, <=, >, >=)",
+                return Err((
+                    ParseErrorKind::ChainedNonAssociative("relational (<, <=, >, >=)"),
+                    self.span(),
                 ));
             }
         }
         Ok(expression)
     }
 
-    fn parse_term(&mut self) -> Result<Expression, ParseError> {
+    fn parse_term(&mut self) -> ParseResult {
         let mut expression = self.parse_factor()?;
         while let Some(idx) = self.match_one_of(&[Token::Plus, Token::Minus]) {
             let right = self.parse_factor()?;
@@ -304,16 +463,14 @@ impl Parser {
                 1 => BinaryOperator::Sub,
                 _ => unreachable!(),
             };
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator: bop,
-                right: Box::new(right),
-            };
+            expression.expect(&[Type::Number])?;
+            right.expect(&[Type::Number])?;
+            expression = Typed::binary(expression, bop, right, Type::Number);
         }
         Ok(expression)
     }
 
-    fn parse_factor(&mut self) -> Result<Expression, ParseError> {
+    fn parse_factor(&mut self) -> ParseResult {
         let mut expression = self.parse_unary()?;
         while let Some(idx) = self.match_one_of(&[Token::Asterisk, Token::Solidus]) {
             let right = self.parse_unary()?;
@@ -322,62 +479,157 @@ impl Parser {
                 1 => BinaryOperator::Div,
                 _ => unreachable!(),
             };
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator: bop,
-                right: Box::new(right),
-            };
+            expression.expect(&[Type::Number])?;
+            right.expect(&[Type::Number])?;
+            expression = Typed::binary(expression, bop, right, Type::Number);
         }
         Ok(expression)
     }
 
-    fn parse_unary(&mut self) -> Result<Expression, ParseError> {
-        if self.match_token(&Token::Minus) {
-            let expression = self.parse_unary()?;
-            Ok(Expression::Unary {
-                operator: UnaryOperator::Negate,
-                expression: Box::new(expression),
-            })
+    fn parse_unary(&mut self) -> ParseResult {
+        let start = self.span().0;
+        let (operator, types, ty) = if self.match_token(&Token::Minus) {
+            (UnaryOperator::Negate, &[Type::Number][..], Type::Number)
         } else if self.match_token(&Token::Not) {
-            let expression = self.parse_unary()?;
-            Ok(Expression::Unary {
-                operator: UnaryOperator::Not,
-                expression: Box::new(expression),
-            })
+            (UnaryOperator::Not, &LOGICAL_TYPES[..], Type::Boolean)
         } else {
-            self.parse_primary()
+            return self.parse_power();
+        };
+        let expression = self.parse_unary()?;
+        expression.expect(types)?;
+        Ok(Typed {
+            span: (start, expression.span.1),
+            expression: Expression::Unary {
+                operator,
+                expression: Box::new(expression.expression),
+            },
+            ty,
+        })
+    }
+
+    // NOTE '^' is right-associative, and binds more tightly than negation, so '-2 ^ 2' is -4, and
+    // '2 ^ 3 ^ 2' is 512. An exponent may be negated, as in '2 ^ -1'.
+    fn parse_power(&mut self) -> ParseResult {
+        let base = self.parse_primary()?;
+        if !self.match_token(&Token::Caret) {
+            return Ok(base);
         }
+        let exponent = self.parse_unary()?;
+        base.expect(&[Type::Number])?;
+        exponent.expect(&[Type::Number])?;
+        Ok(Typed::binary(
+            base,
+            BinaryOperator::Pow,
+            exponent,
+            Type::Number,
+        ))
     }
 
-    fn parse_primary(&mut self) -> Result<Expression, ParseError> {
-        if let Some(token) = self.tokens.get(self.position).cloned() {
-            match token {
-                Token::Number(n) => {
-                    self.position += 1;
-                    Ok(Expression::Number(n))
+    fn parse_primary(&mut self) -> ParseResult {
+        let span = self.span();
+        let Some((token, _)) = self.tokens.get(self.position).cloned() else {
+            return Err((ParseErrorKind::UnexpectedEoi, span));
+        };
+        let typed = |expression, ty| Typed {
+            expression,
+            ty,
+            span,
+        };
+        match token {
+            Token::Number(n) => {
+                self.position += 1;
+                Ok(typed(Expression::Number(n), Type::Number))
+            }
+            Token::Identifier(s) => {
+                self.position += 1;
+                if self.match_token(&Token::LeftParenthesis) {
+                    self.parse_call(&s, span)
+                } else {
+                    let ty = self.variable_type(&s, span)?;
+                    Ok(typed(Expression::Name(s), ty))
                 }
-                Token::Identifier(s) => {
-                    self.position += 1;
-                    Ok(Expression::Name(s))
+            }
+            Token::String(s) => {
+                self.position += 1;
+                Ok(typed(Expression::String(s), Type::String))
+            }
+            Token::LeftParenthesis => {
+                self.position += 1;
+                let expression = self.parse_expression()?;
+                if self.match_token(&Token::RightParenthesis) {
+                    Ok(Typed {
+                        span: (span.0, self.previous_end()),
+                        ..expression
+                    })
+                } else {
+                    Err((ParseErrorKind::UnmatchedParenthesis, self.span()))
                 }
-                Token::LeftParenthesis => {
-                    self.position += 1;
-                    let expression = self.parse_expression()?;
-                    if self.match_token(&Token::RightParenthesis) {
-                        Ok(expression)
-                    } else {
-                        Err(ParseError::UnmatchedParenthesis)
-                    }
+            }
+            _ => Err((ParseErrorKind::UnexpectedToken(token.to_string()), span)),
+        }
+    }
+
+    fn variable_type(&self, name: &str, span: Span) -> Result<Type, (ParseErrorKind, Span)> {
+        match self.variables_opt {
+            None => Ok(Type::Any),
+            Some(variables) => variables
+                .get(name)
+                .copied()
+                .ok_or((ParseErrorKind::UndefinedVariable(name.to_string()), span)),
+        }
+    }
+
+    // NOTE Called after the opening parenthesis, with the span of the function's name.
+    fn parse_call(&mut self, name: &str, name_span: Span) -> ParseResult {
+        let function = Function::from_name(name)
+            .ok_or((ParseErrorKind::UnknownFunction(name.to_string()), name_span))?;
+        if function == Function::Key {
+            self.variable_type(function.name(), name_span)?;
+        }
+        let mut arguments = Vec::new();
+        if !self.match_token(&Token::RightParenthesis) {
+            loop {
+                arguments.push(self.parse_expression()?);
+                if self.match_token(&Token::Comma) {
+                    continue;
                 }
-                _ => Err(ParseError::UnexpectedToken(token.to_string())),
+                if self.match_token(&Token::RightParenthesis) {
+                    break;
+                }
+                return Err((ParseErrorKind::UnmatchedParenthesis, self.span()));
             }
-        } else {
-            Err(ParseError::UnexpectedEoi)
         }
+        let span = (name_span.0, self.previous_end());
+        let is_valid = match function {
+            Function::Key => matches!(
+                &arguments[..],
+                [Typed { expression: Expression::String(s), .. }] if s.chars().count() == 1
+            ),
+            Function::Pos => arguments.len() == 2,
+        };
+        if !is_valid {
+            return Err((ParseErrorKind::InvalidArguments(function), span));
+        }
+        if function == Function::Pos {
+            for argument in &arguments {
+                argument.expect(&[Type::Number])?;
+            }
+        }
+        Ok(Typed {
+            expression: Expression::Call {
+                function,
+                arguments: arguments
+                    .into_iter()
+                    .map(|argument| argument.expression)
+                    .collect(),
+            },
+            ty: Type::Position,
+            span,
+        })
     }
 
     fn match_token(&mut self, token: &Token) -> bool {
-        if let Some(t) = self.tokens.get(self.position) {
+        if let Some((t, _)) = self.tokens.get(self.position) {
             if t == token {
                 self.position += 1;
                 return true;
@@ -387,7 +639,7 @@ impl Parser {
     }
 
     fn match_one_of(&mut self, options: &[Token]) -> Option<usize> {
-        if let Some(t) = self.tokens.get(self.position) {
+        if let Some((t, _)) = self.tokens.get(self.position) {
             for (i, option) in options.iter().enumerate() {
                 if t == option {
                     self.position += 1;
@@ -399,7 +651,7 @@ impl Parser {
     }
 
     fn peek_one_of(&self, options: &[Token]) -> Option<usize> {
-        if let Some(t) = self.tokens.get(self.position) {
+        if let Some((t, _)) = self.tokens.get(self.position) {
             for (i, option) in options.iter().enumerate() {
                 if t == option {
                     return Some(i);
@@ -415,6 +667,11 @@ pub enum Expression {
     Name(String),
     Number(f64),
     Boolean(bool),
+    String(String),
+    Call {
+        function: Function,
+        arguments: Vec<Expression>,
+    },
     Unary {
         operator: UnaryOperator,
         expression: Box<Expression>,
@@ -426,6 +683,45 @@ pub enum Expression {
     },
 }
 
+// NOTE 'key' takes a single character, and is the position of that character in the key table
+// of a record (see 'key_variable'), or no position if it is not in the key table. 'pos' takes a
+// row and a column, counting from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    Key,
+    Pos,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "key" => Some(Function::Key),
+            "pos" => Some(Function::Pos),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Function::Key => "key",
+            Function::Pos => "pos",
+        }
+    }
+
+    fn usage(self) -> &'static str {
+        match self {
+            Function::Key => "a single character, such as key('e')",
+            Function::Pos => "a row and a column, such as pos(1, 4)",
+        }
+    }
+}
+
+// NOTE The name under which the position of a character is given to 'evaluate'. It cannot be the
+// name of a variable, as names cannot contain quotes.
+pub fn key_variable(ch: char) -> String {
+    format!("key('{}')", ch)
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum UnaryOperator {
     Negate,
@@ -438,6 +734,7 @@ pub enum BinaryOperator {
     Sub,
     Mul,
     Div,
+    Pow,
     Eq,
     Neq,
     Lt,
@@ -448,42 +745,133 @@ pub enum BinaryOperator {
     Or,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Boolean(bool),
     Number(f64),
+    String(String),
+    Position(Option<(usize, usize)>),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Position(Some((r, c))) => write!(f, "({}, {})", r, c),
+            Value::Position(None) => write!(f, "none"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub enum ParseError {
+pub enum ParseErrorKind {
     ChainedNonAssociative(&'static str),
     EmptyInput,
+    InvalidArguments(Function),
     InvalidNumber(String),
     UnexpectedEoi,
     UnexpectedToken(String),
+    TypeMismatch { expected: String, found: Type },
+    UndefinedVariable(String),
+    UnknownFunction(String),
     UnmatchedParenthesis,
+    UnterminatedString,
 }
 
-impl Display for ParseError {
+impl Display for ParseErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ParseError::ChainedNonAssociative(kind) => {
+            ParseErrorKind::ChainedNonAssociative(kind) => {
                 write!(f, "chained non-associative operator in {kind} expression")
             }
-            ParseError::EmptyInput => write!(f, "empty input"),
-            ParseError::InvalidNumber(s) => write!(f, "invalid number: '{s}'"),
-            ParseError::UnexpectedEoi => write!(f, "unexpected end of input"),
-            ParseError::UnexpectedToken(token) => write!(f, "unexpected token: '{token}'"),
-            ParseError::UnmatchedParenthesis => write!(f, "unmatched parenthesis"),
+            ParseErrorKind::EmptyInput => write!(f, "empty input"),
+            ParseErrorKind::InvalidArguments(function) => write!(
+                f,
+                "function '{}' takes {}",
+                function.name(),
+                function.usage()
+            ),
+            ParseErrorKind::InvalidNumber(s) => write!(f, "invalid number: '{s}'"),
+            ParseErrorKind::UnexpectedEoi => write!(f, "unexpected end of input"),
+            ParseErrorKind::UnexpectedToken(token) => write!(f, "unexpected token: '{token}'"),
+            ParseErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found.name())
+            }
+            ParseErrorKind::UndefinedVariable(name) => write!(f, "undefined variable: {name}"),
+            ParseErrorKind::UnknownFunction(name) => write!(f, "unknown function: '{name}'"),
+            ParseErrorKind::UnmatchedParenthesis => write!(f, "unmatched parenthesis"),
+            ParseErrorKind::UnterminatedString => write!(f, "unterminated string"),
         }
     }
 }
 
+// NOTE A parse error (including a type mismatch), with the input and the span of the offending
+// token or expression in it. It is displayed (including by 'Debug', as errors are when they reach
+// 'main') with carets beneath that span, such as:
+//
+//   unexpected token: '>'
+//     sfb <= > 5
+//            ^
+#[derive(Clone, PartialEq, Eq)]
+pub struct ParseError {
+    context_opt: Option<String>,
+    input: String,
+    span: Span,
+    kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    // NOTE The byte offsets of the start and end (exclusive) of the span in the input.
+    pub fn span(&self) -> (usize, usize) {
+        self.span
+    }
+
+    // NOTE Such as 'Invalid --filter argument'.
+    pub fn with_context(self, context: &str) -> Self {
+        Self {
+            context_opt: Some(context.to_string()),
+            ..self
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(context) = &self.context_opt {
+            write!(f, "{}: ", context)?;
+        }
+        let (start, end) = self.span;
+        let column = self.input[..start].chars().count();
+        let width = self.input[start..end].chars().count().max(1);
+        write!(
+            f,
+            "{}\n  {}\n  {}{}",
+            self.kind,
+            self.input,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Debug for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
 impl Error for ParseError {}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EvalError {
     DivisionByZero,
+    InvalidPosition,
     TypeMismatch,
     UndefinedVariable(String),
 }
@@ -492,6 +880,9 @@ impl Display for EvalError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::InvalidPosition => {
+                write!(f, "a row and a column must be non-negative integers")
+            }
             EvalError::TypeMismatch => write!(f, "type mismatch"),
             EvalError::UndefinedVariable(name) => write!(f, "undefined variable: {}", name),
         }
@@ -515,6 +906,20 @@ impl Expression {
             }
             Number(_) => {}
             Boolean(_) => {}
+            String(_) => {}
+            Call {
+                function,
+                arguments,
+            } => {
+                // NOTE 'key' depends on the key table of a record, so it must be defined like a
+                // variable.
+                if *function == Function::Key {
+                    set.insert(function.name().to_string());
+                }
+                for argument in arguments {
+                    argument.collect_variables_impl(set);
+                }
+            }
             Unary { expression, .. } => {
                 expression.collect_variables_impl(set);
             }
@@ -528,7 +933,15 @@ impl Expression {
     pub fn reduce(&self) -> Expression {
         use Expression::*;
         match self {
-            Name(_) | Number(_) | Boolean(_) => self.clone(),
+            Name(_) | Number(_) | Boolean(_) | String(_) => self.clone(),
+
+            Call {
+                function,
+                arguments,
+            } => Call {
+                function: *function,
+                arguments: arguments.iter().map(Expression::reduce).collect(),
+            },
 
             Unary {
                 operator,
@@ -576,6 +989,7 @@ impl Expression {
                     (Number(l), Number(r), BinaryOperator::Sub) => Number(l - r),
                     (Number(l), Number(r), BinaryOperator::Mul) => Number(l * r),
                     (Number(l), Number(r), BinaryOperator::Div) => Number(l / r),
+                    (Number(l), Number(r), BinaryOperator::Pow) => Number(l.powf(*r)),
 
                     (Number(l), Number(r), BinaryOperator::Eq) => Boolean(*l == *r),
                     (Number(l), Number(r), BinaryOperator::Neq) => Boolean(*l != *r),
@@ -632,9 +1046,7 @@ impl Expression {
     }
 
     pub fn evaluate(&self, name_to_value: &HashMap<String, Value>) -> Result<Value, EvalError> {
-        use BinaryOperator::*;
         use Expression::*;
-        use UnaryOperator::*;
         match self {
             Name(s) => name_to_value
                 .get(s)
@@ -645,104 +1057,273 @@ impl Expression {
 
             Boolean(b) => Ok(Value::Boolean(*b)),
 
-            Unary {
-                operator: op,
-                expression,
+            String(s) => Ok(Value::String(s.clone())),
+
+            Call {
+                function: Function::Key,
+                arguments,
+            } => match &arguments[..] {
+                [String(s)] => Ok(s
+                    .chars()
+                    .next()
+                    .and_then(|ch| name_to_value.get(&key_variable(ch)).cloned())
+                    .unwrap_or(Value::Position(None))),
+                _ => Err(EvalError::TypeMismatch),
+            },
+
+            Call {
+                function: Function::Pos,
+                arguments,
             } => {
-                let val = expression.evaluate(name_to_value)?;
-                match (op, val) {
-                    (Negate, Value::Number(n)) => Ok(Value::Number(-n)),
-                    (Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
-                    (Not, Value::Number(n)) => Ok(Value::Boolean(!truthy(&Value::Number(n)))),
+                let to_index = |argument: &Expression| match argument.evaluate(name_to_value)? {
+                    Value::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= u8::MAX as f64 => {
+                        Ok(n as usize)
+                    }
+                    Value::Number(_) => Err(EvalError::InvalidPosition),
+                    _ => Err(EvalError::TypeMismatch),
+                };
+                match &arguments[..] {
+                    [row, column] => Ok(Value::Position(Some((to_index(row)?, to_index(column)?)))),
                     _ => Err(EvalError::TypeMismatch),
                 }
             }
 
+            Unary {
+                operator,
+                expression,
+            } => evaluate_unary(*operator, expression.evaluate(name_to_value)?),
+
             Binary {
                 left,
-                operator: op,
+                operator,
                 right,
-            } => match op {
-                And => {
-                    let lv = left.evaluate(name_to_value)?;
-                    let lb = truthy(&lv);
-                    if !lb {
-                        return Ok(Value::Boolean(false));
-                    }
-                    let rv = right.evaluate(name_to_value)?;
-                    Ok(Value::Boolean(lb && truthy(&rv)))
-                }
-                Or => {
-                    let lv = left.evaluate(name_to_value)?;
-                    let lb = truthy(&lv);
-                    if lb {
-                        return Ok(Value::Boolean(true));
-                    }
-                    let rv = right.evaluate(name_to_value)?;
-                    Ok(Value::Boolean(lb || truthy(&rv)))
-                }
-                Add | Sub | Mul | Div | Eq | Neq | Lt | Le | Gt | Ge => {
-                    let l = left.evaluate(name_to_value)?;
-                    let r = right.evaluate(name_to_value)?;
-                    match (l, r, op) {
-                        (Value::Number(a), Value::Number(b), Add) => Ok(Value::Number(a + b)),
-                        (Value::Number(a), Value::Number(b), Sub) => Ok(Value::Number(a - b)),
-                        (Value::Number(a), Value::Number(b), Mul) => Ok(Value::Number(a * b)),
-                        (Value::Number(a), Value::Number(b), Div) => {
-                            if b == 0.0 {
-                                Err(EvalError::DivisionByZero)
-                            } else {
-                                Ok(Value::Number(a / b))
-                            }
-                        }
-                        (Value::Number(a), Value::Number(b), Eq) => Ok(Value::Boolean(a == b)),
-                        (Value::Number(a), Value::Number(b), Neq) => Ok(Value::Boolean(a != b)),
-                        (Value::Number(a), Value::Number(b), Lt) => Ok(Value::Boolean(a < b)),
-                        (Value::Number(a), Value::Number(b), Le) => Ok(Value::Boolean(a <= b)),
-                        (Value::Number(a), Value::Number(b), Gt) => Ok(Value::Boolean(a > b)),
-                        (Value::Number(a), Value::Number(b), Ge) => Ok(Value::Boolean(a >= b)),
-                        _ => Err(EvalError::TypeMismatch),
-                    }
-                }
-            },
+            } => evaluate_binary(
+                *operator,
+                &mut (),
+                |_| left.evaluate(name_to_value),
+                |_| right.evaluate(name_to_value),
+            ),
         }
     }
 
-    pub fn parse(s: &str, defined_variables: &HashSet<String>) -> Result<Self, Box<dyn Error>> {
-        Expression::try_from(s)
-            .map_err(|e| Box::new(e) as Box<dyn Error>)
-            .and_then(|expression| {
-                for expression_variable in expression.collect_variables() {
-                    if !defined_variables.contains(expression_variable.as_str()) {
-                        return Err(Box::new(EvalError::UndefinedVariable(expression_variable))
-                            as Box<dyn Error>);
-                    }
-                }
-                Ok(expression)
-            })
+    // NOTE The expression is type-checked as it is parsed, so that an expression that could never
+    // be evaluated (such as 'sfb && 3 + lh > 5') is rejected before any record is. Every variable
+    // must be defined in 'variables', and the whole expression must have one of 'result_types'.
+    pub fn parse(
+        s: &str,
+        variables: &Variables,
+        result_types: &[Type],
+    ) -> Result<Self, ParseError> {
+        parse_typed(s, Some(variables), result_types).map(|(expression, _)| expression)
+    }
+
+    // NOTE As 'parse', but also returns the type of the expression.
+    pub fn parse_with_type(
+        s: &str,
+        variables: &Variables,
+        result_types: &[Type],
+    ) -> Result<(Self, Type), ParseError> {
+        parse_typed(s, Some(variables), result_types)
     }
 }
 
-impl TryFrom<&str> for Expression {
-    type Error = ParseError;
+// NOTE A named expression, such as 'badness = sfb + fsb * 2' (see 'evaluate_definitions').
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub expression: Expression,
+}
 
-    fn try_from(input: &str) -> Result<Self, Self::Error> {
-        if input.trim().is_empty() {
-            return Err(ParseError::EmptyInput);
+impl Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} = {}", self.name, self.expression)
+    }
+}
+
+// NOTE Definitions are evaluated in order, each adding its value to 'name_to_value' under its name,
+// so each may refer to those before it.
+pub fn evaluate_definitions(
+    definitions: &[Definition],
+    name_to_value: &mut HashMap<String, Value>,
+) -> Result<(), EvalError> {
+    for definition in definitions {
+        let value = definition.expression.evaluate(name_to_value)?;
+        name_to_value.insert(definition.name.clone(), value);
+    }
+    Ok(())
+}
+
+// NOTE Expressions (such as filters) evaluated together against the same record. Each is reduced,
+// and each distinct sub-expression (across all of them) is stored once, so that a sub-expression
+// they share (such as 'sfb + fsb' in 'sfb + fsb < 5' and 'lh * (sfb + fsb) < 200') is evaluated
+// at most once per record (see 'new_memo').
+pub struct ExpressionSet {
+    expressions: Vec<Expression>,
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+}
+
+// NOTE The operands of a node are the indices of earlier nodes.
+enum Node {
+    Leaf(Expression),
+    Unary {
+        operator: UnaryOperator,
+        operand: usize,
+    },
+    Binary {
+        left: usize,
+        operator: BinaryOperator,
+        right: usize,
+    },
+}
+
+impl ExpressionSet {
+    pub fn new(expressions: Vec<Expression>) -> Self {
+        // NOTE Sub-expressions are identified by their 'Debug' form, which (unlike their 'Display'
+        // form) distinguishes every number.
+        fn intern(
+            expression: &Expression,
+            nodes: &mut Vec<Node>,
+            key_to_index: &mut HashMap<String, usize>,
+        ) -> usize {
+            let key = format!("{:?}", expression);
+            if let Some(&index) = key_to_index.get(&key) {
+                return index;
+            }
+            let node = match expression {
+                Expression::Unary {
+                    operator,
+                    expression,
+                } => Node::Unary {
+                    operator: *operator,
+                    operand: intern(expression, nodes, key_to_index),
+                },
+                Expression::Binary {
+                    left,
+                    operator,
+                    right,
+                } => Node::Binary {
+                    left: intern(left, nodes, key_to_index),
+                    operator: *operator,
+                    right: intern(right, nodes, key_to_index),
+                },
+                _ => Node::Leaf(expression.clone()),
+            };
+            nodes.push(node);
+            key_to_index.insert(key, nodes.len() - 1);
+            nodes.len() - 1
         }
-        let mut lexer = Lexer::new(input);
-        let mut tokens = Vec::new();
-        while let Some(result) = lexer.next() {
-            tokens.push(result?);
+
+        let mut nodes = Vec::new();
+        let mut key_to_index = HashMap::new();
+        let roots = expressions
+            .iter()
+            .map(|expression| intern(&expression.reduce(), &mut nodes, &mut key_to_index))
+            .collect();
+        Self {
+            expressions,
+            nodes,
+            roots,
         }
-        let mut parser = Parser::new(tokens);
-        let expression = parser.parse_expression()?;
-        if parser.position != parser.tokens.len() {
-            return Err(ParseError::UnexpectedToken(
-                parser.tokens[parser.position].to_string(),
-            ));
+    }
+
+    // NOTE The expressions as given (not reduced).
+    pub fn expressions(&self) -> &[Expression] {
+        &self.expressions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    // NOTE A memo must be used for one record only.
+    pub fn new_memo(&self) -> Vec<Option<Value>> {
+        vec![None; self.nodes.len()]
+    }
+
+    // NOTE Evaluates expression 'i'. Any sub-expression already evaluated (by this or another
+    // expression, with the same memo) is not evaluated again.
+    pub fn evaluate(
+        &self,
+        i: usize,
+        name_to_value: &HashMap<String, Value>,
+        memo: &mut [Option<Value>],
+    ) -> Result<Value, EvalError> {
+        self.evaluate_node(self.roots[i], name_to_value, memo)
+    }
+
+    fn evaluate_node(
+        &self,
+        index: usize,
+        name_to_value: &HashMap<String, Value>,
+        memo: &mut [Option<Value>],
+    ) -> Result<Value, EvalError> {
+        if let Some(value) = &memo[index] {
+            return Ok(value.clone());
         }
-        Ok(expression)
+        let value = match &self.nodes[index] {
+            Node::Leaf(expression) => expression.evaluate(name_to_value)?,
+            Node::Unary { operator, operand } => evaluate_unary(
+                *operator,
+                self.evaluate_node(*operand, name_to_value, memo)?,
+            )?,
+            Node::Binary {
+                left,
+                operator,
+                right,
+            } => evaluate_binary(
+                *operator,
+                memo,
+                |memo| self.evaluate_node(*left, name_to_value, memo),
+                |memo| self.evaluate_node(*right, name_to_value, memo),
+            )?,
+        };
+        memo[index] = Some(value.clone());
+        Ok(value)
+    }
+}
+
+fn parse_typed(
+    input: &str,
+    variables_opt: Option<&Variables>,
+    result_types: &[Type],
+) -> Result<(Expression, Type), ParseError> {
+    let to_error = |(kind, span)| ParseError {
+        context_opt: None,
+        input: input.to_string(),
+        span,
+        kind,
+    };
+    if input.trim().is_empty() {
+        return Err(to_error((ParseErrorKind::EmptyInput, (0, 0))));
+    }
+    let mut lexer = Lexer::new(input);
+    let mut tokens = Vec::new();
+    while let Some(result) = lexer.next() {
+        tokens.push(result.map_err(to_error)?);
+    }
+    let mut parser = Parser::new(tokens, input.len(), variables_opt);
+    let typed = parser.parse_expression().map_err(to_error)?;
+    if let Some((token, span)) = parser.tokens.get(parser.position) {
+        return Err(to_error((
+            ParseErrorKind::UnexpectedToken(token.to_string()),
+            *span,
+        )));
+    }
+    typed.expect(result_types).map_err(to_error)?;
+    Ok((typed.expression, typed.ty))
+}
+
+// NOTE Variables are not checked (see 'Type::Any').
+impl TryFrom<&str> for Expression {
+    type Error = ParseError;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        parse_typed(input, None, &[]).map(|(expression, _)| expression)
     }
 }
 
@@ -755,6 +1336,7 @@ const fn binary_precedence(op: BinaryOperator) -> u8 {
         Lt | Le | Gt | Ge => 4,
         Add | Sub => 5,
         Mul | Div => 6,
+        Pow => 8,
     }
 }
 
@@ -767,11 +1349,82 @@ const fn is_associative(op: BinaryOperator) -> bool {
     matches!(op, Add | Mul | And | Or)
 }
 
+fn evaluate_unary(operator: UnaryOperator, value: Value) -> Result<Value, EvalError> {
+    use UnaryOperator::*;
+    match (operator, value) {
+        (Negate, Value::Number(n)) => Ok(Value::Number(-n)),
+        (Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+        (Not, Value::Number(n)) => Ok(Value::Boolean(!truthy(&Value::Number(n)))),
+        _ => Err(EvalError::TypeMismatch),
+    }
+}
+
+// NOTE The operands are evaluated by 'left' and 'right' (each given 'context'), so that '&&' and
+// '||' need not evaluate the right operand.
+fn evaluate_binary<C: ?Sized>(
+    operator: BinaryOperator,
+    context: &mut C,
+    left: impl FnOnce(&mut C) -> Result<Value, EvalError>,
+    right: impl FnOnce(&mut C) -> Result<Value, EvalError>,
+) -> Result<Value, EvalError> {
+    use BinaryOperator::*;
+    match operator {
+        And => {
+            let lv = left(context)?;
+            let lb = truthy(&lv);
+            if !lb {
+                return Ok(Value::Boolean(false));
+            }
+            let rv = right(context)?;
+            Ok(Value::Boolean(lb && truthy(&rv)))
+        }
+        Or => {
+            let lv = left(context)?;
+            let lb = truthy(&lv);
+            if lb {
+                return Ok(Value::Boolean(true));
+            }
+            let rv = right(context)?;
+            Ok(Value::Boolean(lb || truthy(&rv)))
+        }
+        Add | Sub | Mul | Div | Pow | Eq | Neq | Lt | Le | Gt | Ge => {
+            let l = left(context)?;
+            let r = right(context)?;
+            match (l, r, operator) {
+                (Value::Number(a), Value::Number(b), Add) => Ok(Value::Number(a + b)),
+                (Value::Number(a), Value::Number(b), Sub) => Ok(Value::Number(a - b)),
+                (Value::Number(a), Value::Number(b), Mul) => Ok(Value::Number(a * b)),
+                (Value::Number(a), Value::Number(b), Div) => {
+                    if b == 0.0 {
+                        Err(EvalError::DivisionByZero)
+                    } else {
+                        Ok(Value::Number(a / b))
+                    }
+                }
+                (Value::Number(a), Value::Number(b), Pow) => Ok(Value::Number(a.powf(b))),
+                (Value::Number(a), Value::Number(b), Eq) => Ok(Value::Boolean(a == b)),
+                (Value::Number(a), Value::Number(b), Neq) => Ok(Value::Boolean(a != b)),
+                (Value::String(a), Value::String(b), Eq) => Ok(Value::Boolean(a == b)),
+                (Value::String(a), Value::String(b), Neq) => Ok(Value::Boolean(a != b)),
+                (Value::Position(a), Value::Position(b), Eq) => Ok(Value::Boolean(a == b)),
+                (Value::Position(a), Value::Position(b), Neq) => Ok(Value::Boolean(a != b)),
+                (Value::Number(a), Value::Number(b), Lt) => Ok(Value::Boolean(a < b)),
+                (Value::Number(a), Value::Number(b), Le) => Ok(Value::Boolean(a <= b)),
+                (Value::Number(a), Value::Number(b), Gt) => Ok(Value::Boolean(a > b)),
+                (Value::Number(a), Value::Number(b), Ge) => Ok(Value::Boolean(a >= b)),
+                _ => Err(EvalError::TypeMismatch),
+            }
+        }
+    }
+}
+
 #[inline]
 fn truthy(v: &Value) -> bool {
     match v {
         Value::Boolean(b) => *b,
         Value::Number(n) => n.is_finite() && *n != 0.0,
+        Value::String(s) => !s.is_empty(),
+        Value::Position(position_opt) => position_opt.is_some(),
     }
 }
 
@@ -796,6 +1449,24 @@ impl<'a> fmt::Display for Pretty<'a> {
 
             Boolean(b) => write!(f, "{}", if *b { "true" } else { "false" }),
 
+            String(s) if s.contains('\'') => write!(f, "\"{}\"", s),
+
+            String(s) => write!(f, "'{}'", s),
+
+            Call {
+                function,
+                arguments,
+            } => {
+                write!(f, "{}(", function.name())?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    Pretty(argument).fmt(f)?;
+                }
+                write!(f, ")")
+            }
+
             Unary {
                 operator,
                 expression,
@@ -840,6 +1511,11 @@ impl<'a> fmt::Display for Pretty<'a> {
                         child_prec < my_prec
                             || (child_prec == my_prec && !is_associative(*operator))
                     }
+                    // NOTE Only the base of '^' binds more tightly than negation.
+                    Unary {
+                        operator: child_uop,
+                        ..
+                    } => unary_precedence(*child_uop) < my_prec,
                     _ => false,
                 };
                 let right_needs_parentheses = match &**right {
@@ -864,6 +1540,7 @@ impl<'a> fmt::Display for Pretty<'a> {
                     BinaryOperator::Sub => "-",
                     BinaryOperator::Mul => "*",
                     BinaryOperator::Div => "/",
+                    BinaryOperator::Pow => "^",
                     BinaryOperator::Eq => "==",
                     BinaryOperator::Neq => "!=",
                     BinaryOperator::Lt => "<",
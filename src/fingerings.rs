@@ -1,4 +1,13 @@
-use crate::layouts::{Laterality, LayoutTable, Position};
+use core::array;
+
+use std::collections::HashMap;
+
+use crate::{
+    expressions::{Expression, Type, Value, Variables},
+    layouts::{
+        Digit, Digits, FingerWeights, Geometry, Laterality, LayoutTable, Penalties, Position,
+    },
+};
 
 pub type Effort = f64;
 
@@ -21,52 +30,319 @@ fn fast_distance(r1: usize, c1: usize, r2: usize, c2: usize) -> f64 {
     }
 }
 
+// NOTE The effort of an n-gram is multiplied by the mean weight of the digits pressing its keys.
+#[inline]
+fn mean_weight<const N: usize>(fingerings: &[Fingering; N], finger_weights: &FingerWeights) -> f64 {
+    fingerings
+        .iter()
+        .map(|&(_r, _c, l, p)| finger_weights.get(Digit(l, p)))
+        .sum::<f64>()
+        / N as f64
+}
+
+const EFFORT_VARIABLES: [&str; 5] = ["col_delta", "dist", "row_delta", "same_finger", "same_hand"];
+
+// NOTE By default, the effort of two consecutive keys is the distance between them if they are
+// pressed by the same hand, and 1 otherwise. An expression may be given to replace this (but a key
+// repeated is always 0). The effort of a trigram is the product of the efforts of its two pairs of
+// consecutive keys.
+pub struct EffortModel<'a, const C: usize, const R: usize> {
+    pub geometry_opt: Option<&'a Geometry<C, R>>,
+    pub finger_weights: FingerWeights,
+    pub expression_opt: Option<&'a Expression>,
+    pub penalties_opt: Option<&'a Penalties<C, R>>,
+}
+
+impl<const C: usize, const R: usize> EffortModel<'_, C, R> {
+    pub fn get_variables() -> Variables {
+        EFFORT_VARIABLES
+            .iter()
+            .map(|&s| (s.into(), Type::Number))
+            .collect()
+    }
+
+    #[inline]
+    fn distance(&self, r1: usize, c1: usize, r2: usize, c2: usize) -> f64 {
+        match self.geometry_opt {
+            Some(geometry) => geometry.distance(r1, c1, r2, c2),
+            None => fast_distance(r1, c1, r2, c2),
+        }
+    }
+
+    fn pair_effort(&self, f1: &Fingering, f2: &Fingering) -> Result<Effort, String> {
+        let &(r1, c1, l1, p1) = f1;
+        let &(r2, c2, l2, p2) = f2;
+        if (r1, c1) == (r2, c2) {
+            return Ok(0.0);
+        }
+        let Some(expression) = self.expression_opt else {
+            return Ok(if l1 == l2 {
+                self.distance(r1, c1, r2, c2)
+            } else {
+                1.0
+            });
+        };
+        let flag = |b: bool| Value::Number(if b { 1.0 } else { 0.0 });
+        let name_to_value = HashMap::from([
+            ("col_delta".into(), Value::Number(c2 as f64 - c1 as f64)),
+            ("dist".into(), Value::Number(self.distance(r1, c1, r2, c2))),
+            ("row_delta".into(), Value::Number(r2 as f64 - r1 as f64)),
+            ("same_finger".into(), flag((l1, p1) == (l2, p2))),
+            ("same_hand".into(), flag(l1 == l2)),
+        ]);
+        let map_err = |e: String| {
+            format!(
+                "Invalid effort for keys ({}, {}) and ({}, {}): {}",
+                r1, c1, r2, c2, e
+            )
+        };
+        match expression.evaluate(&name_to_value) {
+            Ok(Value::Number(n)) if n.is_finite() && n >= 0.0 => Ok(n),
+            Ok(Value::Number(n)) => Err(map_err(format!("{} is not a non-negative number", n))),
+            Ok(Value::Boolean(_)) => Err(map_err("expected a number, not a boolean".into())),
+            Ok(Value::String(_)) => Err(map_err("expected a number, not a string".into())),
+            Ok(Value::Position(_)) => Err(map_err("expected a number, not a position".into())),
+            Err(e) => Err(map_err(e.to_string())),
+        }
+    }
+
+    fn effort<const N: usize>(&self, fingerings: &[Fingering; N]) -> Result<Effort, String> {
+        let mut effort = mean_weight(fingerings, &self.finger_weights);
+        for pair in fingerings.windows(2) {
+            effort *= self.pair_effort(&pair[0], &pair[1])?;
+        }
+        Ok(effort)
+    }
+}
+
+impl<const C: usize, const R: usize> Default for EffortModel<'_, C, R> {
+    fn default() -> Self {
+        Self {
+            geometry_opt: None,
+            finger_weights: FingerWeights::default(),
+            expression_opt: None,
+            penalties_opt: None,
+        }
+    }
+}
+
+// NOTE When keys have more than one candidate digit, the combination with the lowest effort is
+// chosen. Ties are broken by the fewest consecutive keys pressed by the same digit, and then by
+// the order of preference of the candidates.
+fn select_fingerings<const N: usize>(
+    cells: [(usize, usize, Digits); N],
+    effort_fn: impl Fn(&[Fingering; N]) -> Result<Effort, String>,
+) -> Result<([Fingering; N], Effort), String> {
+    let lens = cells.map(|(.., digits)| digits.len());
+    let total = lens.iter().product::<usize>();
+    let mut best_opt: Option<([Fingering; N], Effort, (usize, usize))> = None;
+    for k in 0..total {
+        let mut k = k;
+        let mut rank = 0;
+        let fingerings = array::from_fn(|i| {
+            let (r, c, digits) = cells[i];
+            let j = k % lens[i];
+            k /= lens[i];
+            rank += j;
+            let digit = digits.iter().nth(j).unwrap_or(digits.primary());
+            (r, c, digit.0, digit.1)
+        });
+        let effort = effort_fn(&fingerings)?;
+        let same_digits = fingerings
+            .windows(2)
+            .filter(|pair| {
+                let (r1, c1, l1, p1) = pair[0];
+                let (r2, c2, l2, p2) = pair[1];
+                (r1, c1) != (r2, c2) && (l1, p1) == (l2, p2)
+            })
+            .count();
+        let is_better = match &best_opt {
+            None => true,
+            Some((_, best_effort, best_tie_breakers)) => effort
+                .total_cmp(best_effort)
+                .then((same_digits, rank).cmp(best_tie_breakers))
+                .is_lt(),
+        };
+        if is_better {
+            best_opt = Some((fingerings, effort, (same_digits, rank)));
+        }
+    }
+    let (fingerings, effort, _) = best_opt.expect("Digits must have at least one candidate");
+    Ok((fingerings, effort))
+}
+
 impl<const C: usize, const R: usize> LayoutTable<C, R> {
-    pub fn iter_f(&self) -> impl Iterator<Item = UnigramFingering> {
+    fn iter_cells(&self) -> impl Iterator<Item = (usize, usize, Digits)> + '_ {
         (0..R).flat_map(move |r| {
-            (0..C).filter_map(move |c| {
-                self.0[r][c].map(|digit| {
-                    let effort = 1.0;
-                    ((r, c, digit.0, digit.1), effort)
-                })
-            })
+            (0..C).filter_map(move |c| self.0[r][c].map(|digits| (r, c, digits)))
         })
     }
 
-    pub fn iter_fp(&self) -> impl Iterator<Item = BigramFingering> {
-        self.iter_f().flat_map(move |(f1, _effort)| {
-            self.iter_f().map(move |(f2, _effort)| {
-                let (r1, c1, l1, _p1) = f1;
-                let (r2, c2, l2, _p2) = f2;
-                let effort = if l1 == l2 {
-                    fast_distance(r1, c1, r2, c2)
-                } else {
-                    1.0
-                };
-                (f1, f2, effort)
+    // NOTE Unigrams do not depend on neighbouring keys, so the most preferred digit is used.
+    pub fn iter_f<'a>(
+        &'a self,
+        effort_model: &'a EffortModel<C, R>,
+    ) -> impl Iterator<Item = UnigramFingering> + 'a {
+        self.iter_cells().map(|(r, c, digits)| {
+            let digit = digits.primary();
+            let effort = effort_model.finger_weights.get(digit);
+            ((r, c, digit.0, digit.1), effort)
+        })
+    }
+
+    pub fn iter_fp<'a>(
+        &'a self,
+        effort_model: &'a EffortModel<C, R>,
+    ) -> impl Iterator<Item = Result<BigramFingering, String>> + 'a {
+        self.iter_cells().flat_map(move |cell1| {
+            self.iter_cells().map(move |cell2| {
+                let ([f1, f2], effort) =
+                    select_fingerings([cell1, cell2], |fs| effort_model.effort(fs))?;
+                Ok((f1, f2, effort))
             })
         })
     }
 
-    pub fn iter_ft(&self) -> impl Iterator<Item = TrigramFingering> {
-        self.iter_f().flat_map(move |(f1, _)| {
-            self.iter_f().flat_map(move |(f2, _)| {
-                self.iter_f().map(move |(f3, _)| {
-                    let (r1, c1, l1, _p1) = f1;
-                    let (r2, c2, l2, _p2) = f2;
-                    let (r3, c3, l3, _p3) = f3;
-                    let effort = if l1 == l2 {
-                        fast_distance(r1, c1, r2, c2)
-                    } else {
-                        1.0
-                    } * if l2 == l3 {
-                        fast_distance(r2, c2, r3, c3)
-                    } else {
-                        1.0
-                    };
-                    (f1, f2, f3, effort)
+    pub fn iter_ft<'a>(
+        &'a self,
+        effort_model: &'a EffortModel<C, R>,
+    ) -> impl Iterator<Item = Result<TrigramFingering, String>> + 'a {
+        self.iter_cells().flat_map(move |cell1| {
+            self.iter_cells().flat_map(move |cell2| {
+                self.iter_cells().map(move |cell3| {
+                    let ([f1, f2, f3], effort) =
+                        select_fingerings([cell1, cell2, cell3], |fs| effort_model.effort(fs))?;
+                    Ok((f1, f2, f3, effort))
                 })
             })
         })
     }
 }
+
+#[inline]
+fn flat_offset<const C: usize>(r: usize, c: usize) -> u32 {
+    (r * C + c) as u32
+}
+
+// NOTE Keys are read from the table of ngrams without bounds checks too, but a key built from bytes
+// is always in range for its table, so only the offsets (and the lengths) need to be checked.
+fn validate_offsets(offsets: &[u32], len: usize, n_positions: usize) -> Result<(), String> {
+    if offsets.len() != len {
+        Err(format!("Expected {} offsets, found {}", len, offsets.len()))?
+    }
+    if let Some(&o) = offsets.iter().find(|&&o| o as usize >= n_positions) {
+        Err(format!(
+            "Offset {} is out of range (expected less than {})",
+            o, n_positions
+        ))?
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UnigramFingeringArrays {
+    pub offsets: Vec<u32>,
+    pub efforts: Vec<Effort>,
+}
+
+impl UnigramFingeringArrays {
+    pub fn new<const C: usize>(uf_slice: &[UnigramFingering]) -> Self {
+        let mut arrays = Self {
+            offsets: Vec::with_capacity(uf_slice.len()),
+            efforts: Vec::with_capacity(uf_slice.len()),
+        };
+        for &((r, c, ..), effort) in uf_slice {
+            arrays.offsets.push(flat_offset::<C>(r, c));
+            arrays.efforts.push(effort);
+        }
+        arrays
+    }
+
+    pub fn len(&self) -> usize {
+        self.efforts.len()
+    }
+
+    pub fn validate(&self, n_positions: usize) -> Result<(), String> {
+        validate_offsets(&self.offsets, self.len(), n_positions)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.efforts.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BigramFingeringArrays {
+    pub offsets1: Vec<u32>,
+    pub offsets2: Vec<u32>,
+    pub efforts: Vec<Effort>,
+}
+
+impl BigramFingeringArrays {
+    pub fn new<const C: usize>(bf_slice: &[BigramFingering]) -> Self {
+        let mut arrays = Self {
+            offsets1: Vec::with_capacity(bf_slice.len()),
+            offsets2: Vec::with_capacity(bf_slice.len()),
+            efforts: Vec::with_capacity(bf_slice.len()),
+        };
+        for &((r1, c1, ..), (r2, c2, ..), effort) in bf_slice {
+            arrays.offsets1.push(flat_offset::<C>(r1, c1));
+            arrays.offsets2.push(flat_offset::<C>(r2, c2));
+            arrays.efforts.push(effort);
+        }
+        arrays
+    }
+
+    pub fn len(&self) -> usize {
+        self.efforts.len()
+    }
+
+    pub fn validate(&self, n_positions: usize) -> Result<(), String> {
+        validate_offsets(&self.offsets1, self.len(), n_positions)?;
+        validate_offsets(&self.offsets2, self.len(), n_positions)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.efforts.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TrigramFingeringArrays {
+    pub offsets1: Vec<u32>,
+    pub offsets2: Vec<u32>,
+    pub offsets3: Vec<u32>,
+    pub efforts: Vec<Effort>,
+}
+
+impl TrigramFingeringArrays {
+    pub fn new<const C: usize>(tf_slice: &[TrigramFingering]) -> Self {
+        let mut arrays = Self {
+            offsets1: Vec::with_capacity(tf_slice.len()),
+            offsets2: Vec::with_capacity(tf_slice.len()),
+            offsets3: Vec::with_capacity(tf_slice.len()),
+            efforts: Vec::with_capacity(tf_slice.len()),
+        };
+        for &((r1, c1, ..), (r2, c2, ..), (r3, c3, ..), effort) in tf_slice {
+            arrays.offsets1.push(flat_offset::<C>(r1, c1));
+            arrays.offsets2.push(flat_offset::<C>(r2, c2));
+            arrays.offsets3.push(flat_offset::<C>(r3, c3));
+            arrays.efforts.push(effort);
+        }
+        arrays
+    }
+
+    pub fn len(&self) -> usize {
+        self.efforts.len()
+    }
+
+    pub fn validate(&self, n_positions: usize) -> Result<(), String> {
+        validate_offsets(&self.offsets1, self.len(), n_positions)?;
+        validate_offsets(&self.offsets2, self.len(), n_positions)?;
+        validate_offsets(&self.offsets3, self.len(), n_positions)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.efforts.is_empty()
+    }
+}
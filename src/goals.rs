@@ -14,3 +14,34 @@ impl Display for Goal {
         }
     }
 }
+
+// NOTE How far a record's score may be from the best score for the record to be retained. A ratio
+// is of the best score (1.0 retains only the best records, and 0.0 retains every record), a
+// percentage is of the best score, away from it, and an absolute tolerance is a difference in score
+// (for both, 0 retains only the best records).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tolerance {
+    Ratio(f64),
+    Percent(f64),
+    Absolute(u64),
+}
+
+impl Tolerance {
+    pub fn mode(&self) -> &'static str {
+        match self {
+            Self::Ratio(_) => "ratio",
+            Self::Percent(_) => "percent",
+            Self::Absolute(_) => "absolute",
+        }
+    }
+}
+
+impl Display for Tolerance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ratio(ratio) => write!(f, "{}", ratio),
+            Self::Percent(percent) => write!(f, "{}", percent),
+            Self::Absolute(delta) => write!(f, "{}", delta),
+        }
+    }
+}
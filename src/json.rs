@@ -1,17 +1,23 @@
 use core::error::Error;
 
-use std::io::{self, Read, Write};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
 
 use serde::{Serialize, de::DeserializeOwned};
 
-use serde_json::{Serializer, Value, from_reader, ser::PrettyFormatter};
+use serde_json::{Map, Serializer, Value, from_reader, ser::PrettyFormatter};
 
-pub fn read_enveloped_data<R: Read, T: DeserializeOwned>(
+pub fn read_envelope<R: Read>(
     reader: R,
     expected_version: u64,
-) -> Result<T, Box<dyn Error>> {
+) -> Result<Map<String, Value>, Box<dyn Error>> {
     let value = read_json::<_, Value>(reader)?;
-    let object = value.as_object().ok_or("Expected top-level JSON object")?;
+    let object = match value {
+        Value::Object(object) => object,
+        _ => Err("Expected top-level JSON object")?,
+    };
     let version = object
         .get("version")
         .ok_or("Expected 'version' field")?
@@ -20,6 +26,90 @@ pub fn read_enveloped_data<R: Read, T: DeserializeOwned>(
     if version != expected_version {
         Err(format!("Unsupported version: {}", version))?;
     }
+    Ok(object)
+}
+
+pub struct Validation<'a> {
+    pub optional_fields: &'a [&'a str],
+    pub max_rows: usize,
+    pub max_columns: usize,
+    pub unique_strings: bool,
+}
+
+impl Validation<'_> {
+    // NOTE Unlike ordinary reading, which stops at the first problem, this reports every problem
+    // found.
+    pub fn validate(&self, object: &Map<String, Value>) -> Result<(), String> {
+        let problems = self.problems(object);
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    pub fn problems(&self, object: &Map<String, Value>) -> Vec<String> {
+        const REQUIRED_FIELDS: [&str; 2] = ["data", "version"];
+        let mut problems = Vec::new();
+        for field in object.keys() {
+            if !REQUIRED_FIELDS.contains(&field.as_str())
+                && !self.optional_fields.contains(&field.as_str())
+            {
+                problems.push(format!("Unknown field '{}'", field));
+            }
+        }
+        let rows = object
+            .get("data")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if rows.len() > self.max_rows {
+            problems.push(format!(
+                "Table has {} rows (maximum is {})",
+                rows.len(),
+                self.max_rows
+            ));
+        }
+        let mut seen = HashMap::new();
+        for (r, row) in rows.iter().enumerate() {
+            let Some(row) = row.as_array() else {
+                continue;
+            };
+            if row.len() > self.max_columns {
+                problems.push(format!(
+                    "Row {} has {} columns (maximum is {})",
+                    r,
+                    row.len(),
+                    self.max_columns
+                ));
+            }
+            if self.unique_strings {
+                for (c, cell) in row.iter().enumerate() {
+                    let Some(s) = cell.as_str() else {
+                        continue;
+                    };
+                    if let Some((r0, c0)) = seen.insert(s, (r, c)) {
+                        problems.push(format!(
+                            "Duplicate value '{}' at ({}, {}) and ({}, {})",
+                            s, r0, c0, r, c
+                        ));
+                    }
+                }
+            }
+        }
+        problems
+    }
+}
+
+pub fn read_enveloped_data<R: Read, T: DeserializeOwned>(
+    reader: R,
+    expected_version: u64,
+    validation_opt: Option<&Validation>,
+) -> Result<T, Box<dyn Error>> {
+    let object = read_envelope(reader, expected_version)?;
+    if let Some(validation) = validation_opt {
+        validation.validate(&object)?;
+    }
     let data = T::deserialize(object.get("data").ok_or("Expected 'data' field")?)?;
     Ok(data)
 }
@@ -1,14 +1,20 @@
 use core::error::Error;
 
-use std::{fs::File, path::Path};
+use std::{collections::BTreeMap, fs::File, path::Path};
 
-use serde_json::Value;
+use serde_json::{Value, json};
 
-use crate::{json::read_enveloped_data, tables::Table};
+use crate::{
+    json::{Validation, read_envelope, read_enveloped_data},
+    layouts::LayoutTable,
+    symbols::{Symbol, intern_str, resolve},
+    tables::Table,
+    util::strings::unescape,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Key {
-    Byte(u8),
+    Byte(Symbol),
     One,
     Two,
     Three,
@@ -19,7 +25,7 @@ impl From<Key> for Value {
         use Key::*;
         use Value::*;
         match value {
-            Byte(b) => String((b as char).to_string()),
+            Byte(b) => String(resolve(b).to_string()),
             One => Number(1.into()),
             Two => Number(2.into()),
             Three => Number(3.into()),
@@ -41,19 +47,19 @@ impl TryFrom<&Value> for Key {
                 Some(n) => Err(format!("Invalid key number '{}': expected 1, 2, or 3", n))?,
                 _ => Err("Invalid key number: expected 1, 2, or 3")?,
             },
-            String(s) if s.len() != 1 || !s.is_ascii() => Err(format!(
+            String(s) if s.chars().count() != 1 => Err(format!(
                 "Invalid key string '{}': \
-                 expected a single ASCII character",
+                 expected a single character",
                 s
             ))?,
             String(s) if s.chars().any(|ch| ('\x01'..='\x03').contains(&ch)) => Err(format!(
                 "Invalid key string '{}': \
-                 expected a single ASCII character, and the control characters \
+                 expected a single character, and the control characters \
                  SOH, STX, and ETX are reserved.",
                 s
             ))?,
-            String(s) => Byte(s.as_bytes()[0]),
-            _ => Err("Invalid type: expected 1, 2, 3, or a string of a single ASCII character")?,
+            String(s) => Byte(intern_str(s)?[0]),
+            _ => Err("Invalid type: expected 1, 2, 3, or a string of a single character")?,
         })
     }
 }
@@ -61,7 +67,21 @@ impl TryFrom<&Value> for Key {
 pub struct KeyTable<const C: usize, const R: usize>(pub Table<C, R, Key>);
 
 impl<const C: usize, const R: usize> KeyTable<C, R> {
-    pub fn from_byte_matrix(slice: &[[u8; C]; R]) -> Self {
+    // NOTE A placeholder of region 1 for each key of the layout table, and null elsewhere, so that
+    // a new key table matches the structure of the layout table.
+    pub fn from_layout_table(layout_table: &LayoutTable<C, R>) -> Self {
+        let mut key_table = Self::default();
+        for (r, row) in layout_table.0.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                if cell.is_some() {
+                    key_table.0[r][c] = Some(Key::One);
+                }
+            }
+        }
+        key_table
+    }
+
+    pub fn from_byte_matrix(slice: &[[Symbol; C]; R]) -> Self {
         use Key::*;
         let mut key_table = Self::default();
         for (r, row) in slice.iter().enumerate() {
@@ -78,9 +98,9 @@ impl<const C: usize, const R: usize> KeyTable<C, R> {
         key_table
     }
 
-    pub fn to_byte_matrix(&self) -> [[u8; C]; R] {
+    pub fn to_byte_matrix(&self) -> [[Symbol; C]; R] {
         use Key::*;
-        let mut byte_matrix = [[0u8; C]; R];
+        let mut byte_matrix = [[0; C]; R];
         for (r, row) in self.0.iter().enumerate() {
             for (c, cell) in row.iter().enumerate() {
                 byte_matrix[r][c] = match cell {
@@ -95,11 +115,196 @@ impl<const C: usize, const R: usize> KeyTable<C, R> {
         byte_matrix
     }
 
-    pub fn read_from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+    // NOTE Rows are separated by ';'. Within a row, each character is assigned to the next
+    // column, except for a space, which leaves the cell empty. Use '\;' for a semicolon, and
+    // '\x20' for a space. Other escape sequences are as for the '-1', '-2', and '-3' arguments.
+    // ASCII letters are converted to uppercase (like the built-in n-gram tables and key tables),
+    // so that 'qwerty' and 'QWERTY' are the same; an escaped letter (such as '\x71') is not.
+    pub fn from_rows_str(s: &str) -> Result<Self, String> {
+        let mut key_table = Self::default();
+        let (mut r, mut c) = (0, 0);
+        let mut chars = s.chars();
+        while let Some(ch) = chars.next() {
+            let key_str = match ch {
+                ';' => {
+                    (r, c) = (r + 1, 0);
+                    continue;
+                }
+                '\\' => match chars.next() {
+                    Some(';') => Some(String::from(";")),
+                    Some('x') => Some(format!("\\x{}", chars.by_ref().take(2).collect::<String>())),
+                    Some(ch) => Some(format!("\\{}", ch)),
+                    None => Some(String::from("\\")),
+                }
+                .map(|escape| {
+                    if escape == ";" {
+                        Ok(escape)
+                    } else {
+                        unescape::<true>(&escape).map_err(|e| e.to_string())
+                    }
+                })
+                .transpose()?,
+                ' ' => None,
+                ch => Some(ch.to_ascii_uppercase().to_string()),
+            };
+            if r >= R {
+                return Err(format!("Table has too many rows (maximum is {})", R));
+            }
+            if c >= C {
+                return Err(format!("Row {} has too many columns (maximum is {})", r, C));
+            }
+            key_table.0[r][c] = key_str
+                .map(|key_str| Key::try_from(&Value::String(key_str)))
+                .transpose()
+                .map_err(|e| format!("Invalid cell ({}, {}): {}", r, c, e))?;
+            c += 1;
+        }
+        Ok(key_table)
+    }
+
+    pub fn json_schema() -> Value {
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Perky key table",
+            "type": "object",
+            "properties": {
+                "data": {
+                    "type": "array",
+                    "maxItems": R,
+                    "items": {
+                        "type": "array",
+                        "maxItems": C,
+                        "items": {
+                            "oneOf": [
+                                { "type": "null" },
+                                { "type": "string", "pattern": "^[^\\x01-\\x03]$" },
+                                { "enum": [1, 2, 3] }
+                            ]
+                        }
+                    }
+                },
+                "layers": {
+                    "type": "array",
+                    "items": {
+                        "type": "array",
+                        "maxItems": R,
+                        "items": {
+                            "type": "array",
+                            "maxItems": C,
+                            "items": {
+                                "oneOf": [
+                                    { "type": "null" },
+                                    { "type": "string", "pattern": "^[^\\x01-\\x03]$" }
+                                ]
+                            }
+                        }
+                    }
+                },
+                "version": { "const": 1 }
+            },
+            "required": ["data", "version"],
+            "additionalProperties": false
+        })
+    }
+
+    // NOTE A character assigned to more than one key is counted once for each key, which is
+    // rarely intended (an exception being a keyboard with two space keys).
+    pub fn check_duplicates(&self) -> Result<(), String> {
+        let mut seen = BTreeMap::new();
+        let mut duplicates = Vec::new();
+        for (r, row) in self.0.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                if let Some(Key::Byte(b)) = cell
+                    && let Some((r0, c0)) = seen.insert(*b, (r, c))
+                {
+                    duplicates.push(format!(
+                        "'{}' at ({}, {}) and ({}, {})",
+                        resolve(*b).escape_default(),
+                        r0,
+                        c0,
+                        r,
+                        c
+                    ));
+                }
+            }
+        }
+        if duplicates.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Characters are assigned to more than one key: {}",
+                duplicates.join(", ")
+            ))
+        }
+    }
+
+    // NOTE Each region string is substituted into the placeholders of its region, so a character
+    // that also appears elsewhere in the key table (or in another region string) ends up assigned
+    // to more than one key.
+    pub fn check_regions(&self, regions: [Option<&[Symbol]>; 3]) -> Result<(), String> {
+        let mut seen = BTreeMap::new();
+        for (r, row) in self.0.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                if let Some(Key::Byte(b)) = cell {
+                    seen.entry(*b)
+                        .or_insert_with(|| format!("the key at ({}, {})", r, c));
+                }
+            }
+        }
+        let mut conflicts = Vec::new();
+        for (i, region) in regions.iter().enumerate() {
+            for &b in region.unwrap_or_default() {
+                let location = format!("'-{}'", i + 1);
+                if let Some(previous) = seen.insert(b, location.clone()) {
+                    conflicts.push(format!(
+                        "'{}' in {} is also in {}",
+                        resolve(b).escape_default(),
+                        location,
+                        previous
+                    ));
+                }
+            }
+        }
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Characters would be assigned to more than one key: {}",
+                conflicts.join(", ")
+            ))
+        }
+    }
+
+    pub fn try_from_value(value: &Value, allow_duplicates: bool) -> Result<Self, String> {
+        let key_table = Table::<C, R, Key>::try_from(value).map(KeyTable)?;
+        if !allow_duplicates {
+            key_table.check_duplicates()?;
+        }
+        Ok(key_table)
+    }
+
+    pub fn read_from_path(
+        path: &Path,
+        strict: bool,
+        allow_duplicates: bool,
+    ) -> Result<Self, Box<dyn Error>> {
         const EXPECTED_VERSION: u64 = 1;
+        let validation = Validation {
+            optional_fields: &["layers"],
+            max_rows: R,
+            max_columns: C,
+            unique_strings: !allow_duplicates,
+        };
         let file = File::open(path)?;
-        let value = read_enveloped_data::<_, Value>(file, EXPECTED_VERSION)?;
-        Ok(KeyTable::try_from(&value)?)
+        let value =
+            read_enveloped_data::<_, Value>(file, EXPECTED_VERSION, strict.then_some(&validation))?;
+        Ok(KeyTable::try_from_value(&value, allow_duplicates)?)
+    }
+
+    pub fn read_from_bytes(bytes: &[u8], allow_duplicates: bool) -> Result<Self, Box<dyn Error>> {
+        const EXPECTED_VERSION: u64 = 1;
+        let value = read_enveloped_data::<_, Value>(bytes, EXPECTED_VERSION, None)?;
+        Ok(KeyTable::try_from_value(&value, allow_duplicates)?)
     }
 }
 
@@ -119,6 +324,161 @@ impl<const C: usize, const R: usize> TryFrom<&Value> for KeyTable<C, R> {
     type Error = String;
 
     fn try_from(value: &Value) -> Result<Self, Self::Error> {
-        Table::<C, R, Key>::try_from(value).map(KeyTable)
+        KeyTable::try_from_value(value, false)
+    }
+}
+
+// NOTE Layers (such as shift or AltGr) are mapped to the same coordinates as the key table they
+// belong to. Each character in a layer is typed using the key at its coordinates, so for scoring,
+// it is folded into the character of that key in the key table.
+pub struct KeyLayers<const C: usize, const R: usize>(pub Vec<Table<C, R, Key>>);
+
+impl<const C: usize, const R: usize> KeyLayers<C, R> {
+    // NOTE A single layer of the shifted characters of imported keys. A shifted character is left
+    // out if it is the same as the key's own character, or is already in the key table or the layer
+    // (so that 'fold_map' never fails on an imported layer).
+    pub fn from_shifted(key_table: &KeyTable<C, R>, shifted: &Table<C, R, Key>) -> Self {
+        let mut is_used = vec![false; 1 << 16];
+        for cell in key_table.0.as_flattened() {
+            if let Some(Key::Byte(b)) = cell {
+                is_used[*b as usize] = true;
+            }
+        }
+        let mut layer = Table::<C, R, Key>::default();
+        for (r, row) in shifted.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                if let (Some(Key::Byte(b)), Some(Key::Byte(_))) = (cell, key_table.0[r][c])
+                    && !is_used[*b as usize]
+                {
+                    is_used[*b as usize] = true;
+                    layer[r][c] = Some(Key::Byte(*b));
+                }
+            }
+        }
+        if layer.as_flattened().iter().any(Option::is_some) {
+            Self(vec![layer])
+        } else {
+            Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn fold_map(
+        &self,
+        key_table: &KeyTable<C, R>,
+        allow_duplicates: bool,
+    ) -> Result<Vec<Symbol>, String> {
+        let mut fold_map = (0..=Symbol::MAX).collect::<Vec<_>>();
+        let mut is_folded = vec![false; 1 << 16];
+        let mut is_base = vec![false; 1 << 16];
+        for cell in key_table.0.as_flattened() {
+            if let Some(Key::Byte(b)) = cell {
+                is_base[*b as usize] = true;
+            }
+        }
+        for (i, layer) in self.0.iter().enumerate() {
+            for (r, row) in layer.iter().enumerate() {
+                for (c, cell) in row.iter().enumerate() {
+                    let layer_byte = match cell {
+                        None => continue,
+                        Some(Key::Byte(b)) => *b,
+                        Some(_) => Err(format!(
+                            "Invalid cell ({}, {}) in layer {}: layers cannot contain placeholders",
+                            r, c, i
+                        ))?,
+                    };
+                    let base_byte = match key_table.0[r][c] {
+                        Some(Key::Byte(b)) => b,
+                        _ => Err(format!(
+                            "Invalid cell ({}, {}) in layer {}: \
+                             the key table must have a character at the same position",
+                            r, c, i
+                        ))?,
+                    };
+                    if layer_byte == base_byte {
+                        continue;
+                    }
+                    if is_base[layer_byte as usize] || is_folded[layer_byte as usize] {
+                        if allow_duplicates {
+                            continue;
+                        }
+                        Err(format!(
+                            "Invalid cell ({}, {}) in layer {}: \
+                             '{}' is already assigned to another key",
+                            r,
+                            c,
+                            i,
+                            resolve(layer_byte).escape_default()
+                        ))?;
+                    }
+                    fold_map[layer_byte as usize] = base_byte;
+                    is_folded[layer_byte as usize] = true;
+                }
+            }
+        }
+        Ok(fold_map)
+    }
+
+    pub fn read_from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        const EXPECTED_VERSION: u64 = 1;
+        let file = File::open(path)?;
+        let object = read_envelope(file, EXPECTED_VERSION)?;
+        Ok(match object.get("layers") {
+            None => Self::default(),
+            Some(value) => Self::try_from(value)?,
+        })
+    }
+}
+
+impl<const C: usize, const R: usize> Default for KeyLayers<C, R> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<const C: usize, const R: usize> TryFrom<&Value> for KeyLayers<C, R> {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        value
+            .as_array()
+            .ok_or("Value of 'layers' field must be an array")?
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| {
+                Table::<C, R, Key>::try_from(layer)
+                    .map_err(|e| format!("Invalid layer {}: {}", i, e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(KeyLayers)
+    }
+}
+
+impl<const C: usize, const R: usize> From<&KeyLayers<C, R>> for Value {
+    fn from(value: &KeyLayers<C, R>) -> Self {
+        Value::Array(value.0.iter().map(Value::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NOTE The example layout string of '--layout-string' is lowercase, so it must give the same key
+    // table as the uppercase one (which the built-in n-gram tables cover).
+    #[test]
+    fn from_rows_str_converts_letters_to_uppercase() -> Result<(), String> {
+        let lowercase = KeyTable::<10, 3>::from_rows_str("qwertyuiop;asdfghjkl;zxcvbnm")?;
+        let uppercase = KeyTable::<10, 3>::from_rows_str("QWERTYUIOP;ASDFGHJKL;ZXCVBNM")?;
+        assert_eq!(lowercase.to_byte_matrix(), uppercase.to_byte_matrix());
+        assert_eq!(lowercase.0[0][0], Some(Key::Byte(b'Q' as Symbol)));
+        assert_eq!(lowercase.0[1][9], None);
+        let escaped = KeyTable::<10, 3>::from_rows_str(r"\x71\;")?;
+        assert_eq!(escaped.0[0][0], Some(Key::Byte(b'q' as Symbol)));
+        assert_eq!(escaped.0[0][1], Some(Key::Byte(b';' as Symbol)));
+        Ok(())
     }
 }
@@ -0,0 +1,165 @@
+use core::error::Error;
+
+use std::{fs::File, path::Path};
+
+use serde_json::Value;
+
+use crate::{
+    json::read_json,
+    keys::{Key, KeyLayers, KeyTable},
+    layouts::{Digit, Digits, Laterality, LayoutTable, Position},
+    tables::Table,
+};
+
+// NOTE The number of keys in each row of the 'standard' keyboard of keyboard-layout-analyzer
+// (patorjk.com), from the number row to the space bar row. Keys are assigned to rows in order of
+// their ids.
+const STANDARD_ROW_LENGTHS: [usize; 5] = [14, 14, 13, 12, 8];
+
+// NOTE Fingers are numbered from the left pinky (1) to the right pinky (10).
+fn digit_from_finger(finger: u64) -> Option<Digit> {
+    use Laterality::*;
+    use Position::*;
+    Some(match finger {
+        1 => Digit(Left, Pinky),
+        2 => Digit(Left, Ring),
+        3 => Digit(Left, Middle),
+        4 => Digit(Left, Index),
+        5 => Digit(Left, Thumb),
+        6 => Digit(Right, Thumb),
+        7 => Digit(Right, Index),
+        8 => Digit(Right, Middle),
+        9 => Digit(Right, Ring),
+        10 => Digit(Right, Pinky),
+        _ => None?,
+    })
+}
+
+fn position_from_id(id: usize) -> Option<(usize, usize)> {
+    let mut start = 0;
+    for (r, &length) in STANDARD_ROW_LENGTHS.iter().enumerate() {
+        if id < start + length {
+            return Some((r, id - start));
+        }
+        start += length;
+    }
+    None
+}
+
+// NOTE Characters are stored as character codes, where -1 (or a missing field) means none. Only
+// printable characters are imported, so keys such as 'Tab' or 'Shift' leave their cells empty.
+fn key_from_code(value_opt: Option<&Value>) -> Result<Option<Key>, String> {
+    let Some(code) = value_opt.and_then(Value::as_i64).filter(|&code| code >= 0) else {
+        return Ok(None);
+    };
+    match char::from_u32(code as u32).filter(|ch| !ch.is_control()) {
+        None => Ok(None),
+        Some(ch) => Key::try_from(&Value::String(ch.to_string())).map(Some),
+    }
+}
+
+// NOTE Each key's primary character is placed in the key table, and its shifted character in a
+// layer (see 'KeyLayers::from_shifted'), so that shifted characters are counted as their primary
+// characters. Each key's finger becomes its only candidate digit.
+pub fn read_kla_from_value<const C: usize, const R: usize>(
+    value: &Value,
+) -> Result<(LayoutTable<C, R>, KeyTable<C, R>, KeyLayers<C, R>), Box<dyn Error>> {
+    let object = value.as_object().ok_or("Expected top-level JSON object")?;
+    match object.get("keyboardType").and_then(Value::as_str) {
+        None | Some("standard") => (),
+        Some(keyboard_type) => Err(format!(
+            "Unsupported keyboard type '{}': expected 'standard'",
+            keyboard_type
+        ))?,
+    }
+    let keys = object
+        .get("keys")
+        .ok_or("Expected 'keys' field")?
+        .as_array()
+        .ok_or("Value of 'keys' field must be an array")?;
+    let mut layout_table = LayoutTable::<C, R>::default();
+    let mut key_table = KeyTable::<C, R>::default();
+    let mut shifted = Table::<C, R, Key>::default();
+    for (i, key) in keys.iter().enumerate() {
+        let properties = key
+            .as_object()
+            .ok_or_else(|| format!("Key {} must be an object", i))?;
+        let id = match properties.get("id") {
+            None => i,
+            Some(id) => id
+                .as_u64()
+                .ok_or_else(|| format!("Invalid id of key {}: expected an integer", i))?
+                as usize,
+        };
+        let (r, c) = position_from_id(id).ok_or_else(|| {
+            format!(
+                "Invalid id of key {}: expected less than {}",
+                i,
+                STANDARD_ROW_LENGTHS.iter().sum::<usize>()
+            )
+        })?;
+        let Some(primary) = key_from_code(properties.get("primary"))
+            .map_err(|e| format!("Invalid primary character of key {}: {}", i, e))?
+        else {
+            continue;
+        };
+        if r >= R || c >= C {
+            Err(format!(
+                "Key {} is outside of the table (maximum is {} columns by {} rows)",
+                i, C, R
+            ))?;
+        }
+        let finger = properties.get("finger").and_then(Value::as_u64);
+        let digit = finger.and_then(digit_from_finger).ok_or_else(|| {
+            format!(
+                "Invalid finger of key {}: expected an integer from 1 to 10",
+                i
+            )
+        })?;
+        key_table.0[r][c] = Some(primary);
+        layout_table.0[r][c] = Some(Digits::from(digit));
+        let shift = key_from_code(properties.get("shift"))
+            .map_err(|e| format!("Invalid shifted character of key {}: {}", i, e))?;
+        shifted[r][c] = shift;
+    }
+    let key_layers = KeyLayers::from_shifted(&key_table, &shifted);
+    Ok((layout_table, key_table, key_layers))
+}
+
+pub fn read_kla_from_path<const C: usize, const R: usize>(
+    path: &Path,
+) -> Result<(LayoutTable<C, R>, KeyTable<C, R>, KeyLayers<C, R>), Box<dyn Error>> {
+    let file = File::open(path)?;
+    let value = read_json::<_, Value>(file)?;
+    read_kla_from_value(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // NOTE The key table and layers of an imported layout, as written by 'convert', must read back
+    // as the same key table file.
+    #[test]
+    fn converted_layout_reads_back() -> Result<(), Box<dyn Error>> {
+        let value = json!({
+            "keyboardType": "standard",
+            "keys": [
+                { "id": 14, "primary": 113, "shift": 81, "finger": 1 },
+                { "id": 15, "primary": 119, "shift": 87, "finger": 2 },
+                { "id": 16, "primary": 49, "shift": 33, "finger": 3 },
+                { "id": 17, "primary": 9, "finger": 4 }
+            ]
+        });
+        let (_, key_table, key_layers) = read_kla_from_value::<16, 8>(&value)?;
+        let key_table_value = Value::from(&key_table);
+        let key_layers_value = Value::from(&key_layers);
+        assert_eq!(key_table_value, json!([[], ["q", "w", "1"]]));
+        assert_eq!(key_layers_value, json!([[[], ["Q", "W", "!"]]]));
+        let key_table = KeyTable::<16, 8>::try_from(&key_table_value)?;
+        KeyLayers::<16, 8>::try_from(&key_layers_value)?.fold_map(&key_table, false)?;
+        Ok(())
+    }
+}
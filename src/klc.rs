@@ -0,0 +1,141 @@
+use core::error::Error;
+
+use std::{fs, path::Path};
+
+use serde_json::Value;
+
+use crate::{
+    keys::{Key, KeyLayers, KeyTable},
+    tables::Table,
+};
+
+// NOTE The keys of the three letter rows are imported (by scan code), in the same positions as the
+// built-in key tables (and 'default.lt.json'). The backslash key (which is also the ISO key at the
+// end of the home row) is placed at the end of the top row.
+fn position_from_scan_code(scan_code: u8) -> Option<(usize, usize)> {
+    match scan_code {
+        0x10..=0x1b => Some((0, (scan_code - 0x10) as usize)),
+        0x2b => Some((0, 12)),
+        0x1e..=0x28 => Some((1, (scan_code - 0x1e) as usize)),
+        0x2c..=0x35 => Some((2, (scan_code - 0x2c) as usize)),
+        _ => None,
+    }
+}
+
+// NOTE KLC files written by Microsoft Keyboard Layout Creator are UTF-16 (with a byte order mark),
+// but UTF-8 files are also accepted.
+fn decode(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units = bytes
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16(&units).map_err(|e| e.to_string())
+    };
+    Ok(match bytes {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes)?,
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes)?,
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8(rest.to_vec())?,
+        _ => String::from_utf8(bytes.to_vec())?,
+    })
+}
+
+// NOTE A character is given as itself, or as four hexadecimal digits. '-1' is none, and a trailing
+// '@' marks a dead key, which is also treated as none (as is a ligature, '%%').
+fn char_from_field(field: &str) -> Option<char> {
+    if field == "-1" || field == "%%" || field.ends_with('@') {
+        return None;
+    }
+    let mut chars = field.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(ch),
+        _ => u32::from_str_radix(field, 16).ok().and_then(char::from_u32),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split_once("//").map_or(line, |(code, _)| code).trim()
+}
+
+// NOTE Each row of the 'LAYOUT' section is a scan code, a virtual key, a caps lock flag, and one
+// column per shift state, in the order of the 'SHIFTSTATE' section (by default, 0 then 1). The
+// character of shift state 0 is placed in the key table, and that of shift state 1 (Shift) in a
+// layer (see 'KeyLayers::from_shifted'). Keys whose character for shift state 0 is not printable
+// are left empty.
+pub fn read_klc_from_path<const C: usize, const R: usize>(
+    path: &Path,
+) -> Result<(KeyTable<C, R>, KeyLayers<C, R>), Box<dyn Error>> {
+    let s = decode(&fs::read(path)?)?;
+    let mut lines = s
+        .lines()
+        .map(strip_comment)
+        .filter(|line| !line.is_empty())
+        .peekable();
+    let mut base_column = 0;
+    let mut shifted_column_opt = Some(1);
+    let mut is_layout_found = false;
+    let mut key_table = KeyTable::<C, R>::default();
+    let mut shifted = Table::<C, R, Key>::default();
+    while let Some(line) = lines.next() {
+        let keyword = line.split_whitespace().next().unwrap_or_default();
+        if keyword == "SHIFTSTATE" {
+            let mut shift_states = Vec::new();
+            while let Some(shift_state) = lines.peek().and_then(|line| line.parse().ok()) {
+                shift_states.push(shift_state);
+                lines.next();
+            }
+            base_column = shift_states
+                .iter()
+                .position(|&shift_state: &u8| shift_state == 0)
+                .ok_or("Expected shift state 0 in 'SHIFTSTATE' section")?;
+            shifted_column_opt = shift_states
+                .iter()
+                .position(|&shift_state| shift_state == 1);
+        } else if keyword == "LAYOUT" {
+            is_layout_found = true;
+            while let Some(line) = lines.peek() {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                let Some(Ok(scan_code)) = fields
+                    .first()
+                    .filter(|field| field.len() == 2)
+                    .map(|field| u8::from_str_radix(field, 16))
+                else {
+                    break;
+                };
+                lines.next();
+                let Some((r, c)) = position_from_scan_code(scan_code) else {
+                    continue;
+                };
+                let char_at = |column: usize| {
+                    fields
+                        .get(3 + column)
+                        .and_then(|field| char_from_field(field))
+                        .filter(|ch| !ch.is_control())
+                };
+                let Some(ch) = char_at(base_column) else {
+                    continue;
+                };
+                if r >= R || c >= C {
+                    Err(format!(
+                        "Key ({}, {}) is outside of the table (maximum is {} columns by {} rows)",
+                        r, c, C, R
+                    ))?;
+                }
+                let to_key = |ch: char| {
+                    Key::try_from(&Value::String(ch.to_string()))
+                        .map_err(|e| format!("Invalid key ({}, {}): {}", r, c, e))
+                };
+                key_table.0[r][c] = Some(to_key(ch)?);
+                shifted[r][c] = shifted_column_opt
+                    .and_then(char_at)
+                    .map(to_key)
+                    .transpose()?;
+            }
+        }
+    }
+    if !is_layout_found {
+        Err("Expected 'LAYOUT' section")?;
+    }
+    let key_layers = KeyLayers::from_shifted(&key_table, &shifted);
+    Ok((key_table, key_layers))
+}
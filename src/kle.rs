@@ -0,0 +1,188 @@
+use core::error::Error;
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde_json::{Map, Value, json};
+
+use crate::{
+    json::read_json,
+    keys::{Key, KeyTable},
+    layouts::{Digit, Digits, Laterality, LayoutTable, Position},
+    symbols::{Symbol, resolve},
+    ui::colors::hsv_to_rgb,
+};
+
+// NOTE Digits assigned to imported keys by column, as in 'default.lt.json'. Columns beyond the
+// end of this array are assigned to the right pinky.
+const DIGITS: [Digit; 10] = {
+    use Laterality::*;
+    use Position::*;
+    [
+        Digit(Left, Pinky),
+        Digit(Left, Ring),
+        Digit(Left, Middle),
+        Digit(Left, Index),
+        Digit(Left, Index),
+        Digit(Right, Index),
+        Digit(Right, Index),
+        Digit(Right, Middle),
+        Digit(Right, Ring),
+        Digit(Right, Pinky),
+    ]
+};
+
+const DIGIT_DEFAULT: Digit = Digit(Laterality::Right, Position::Pinky);
+
+fn decode_legend(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+fn encode_legend(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// NOTE A KLE key may have up to twelve legends separated by '\n'. The bottom-left legend
+// (typically the unshifted character) is used if present, otherwise the top-left legend.
+fn select_legend(s: &str) -> String {
+    let mut legends = s.split('\n');
+    let top_left = legends.next().unwrap_or_default();
+    let bottom_left = legends.next().unwrap_or_default();
+    decode_legend(if bottom_left.is_empty() {
+        top_left
+    } else {
+        bottom_left
+    })
+}
+
+// NOTE Each KLE row becomes a row of the tables, and each key whose legend is a single character
+// is assigned to the next column. Keys with any other legend (such as 'Tab' or
+// 'Shift') are skipped. A horizontal gap of one or more units leaves that many cells empty.
+pub fn read_kle_from_value<const C: usize, const R: usize>(
+    value: &Value,
+) -> Result<(LayoutTable<C, R>, KeyTable<C, R>), Box<dyn Error>> {
+    let rows = value
+        .as_array()
+        .ok_or("Expected top-level JSON array")?
+        .iter()
+        .filter(|value| !value.is_object());
+    let mut layout_table = LayoutTable::<C, R>::default();
+    let mut key_table = KeyTable::<C, R>::default();
+    for (r, row) in rows.enumerate() {
+        let row = row
+            .as_array()
+            .ok_or_else(|| format!("Row {} must be an array", r))?;
+        if r >= R {
+            Err(format!("Table has too many rows (maximum is {})", R))?;
+        }
+        let mut c = 0;
+        for item in row {
+            match item {
+                Value::Object(properties) => {
+                    if let Some(x) = properties.get("x").and_then(Value::as_f64)
+                        && x >= 1.0
+                    {
+                        c += x.floor() as usize;
+                    }
+                }
+                Value::String(s) => {
+                    let legend = select_legend(s);
+                    if legend.chars().count() != 1 {
+                        continue;
+                    }
+                    if c >= C {
+                        Err(format!("Row {} has too many columns (maximum is {})", r, C))?;
+                    }
+                    let key = Key::try_from(&Value::String(legend))
+                        .map_err(|e| format!("Invalid key ({}, {}): {}", r, c, e))?;
+                    key_table.0[r][c] = Some(key);
+                    layout_table.0[r][c] =
+                        Some(Digits::from(*DIGITS.get(c).unwrap_or(&DIGIT_DEFAULT)));
+                    c += 1;
+                }
+                _ => Err(format!(
+                    "Invalid item in row {}: expected an object or a string",
+                    r
+                ))?,
+            }
+        }
+    }
+    Ok((layout_table, key_table))
+}
+
+pub fn read_kle_from_path<const C: usize, const R: usize>(
+    path: &Path,
+) -> Result<(LayoutTable<C, R>, KeyTable<C, R>), Box<dyn Error>> {
+    let file = File::open(path)?;
+    let value = read_json::<_, Value>(file)?;
+    read_kle_from_value(&value)
+}
+
+// NOTE Legend colours range from black (no heat) to red (most heat).
+fn heat_to_color(heat: f64) -> String {
+    const HUE: f32 = 0.0;
+    let s = heat.clamp(0.0, 1.0) as f32;
+    let (r, g, b) = hsv_to_rgb(HUE, s, s);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// NOTE Without a heat matrix, legends are left in the default colour.
+pub fn kle_value_from_matrix<const C: usize, const R: usize>(
+    key_table_matrix: &[[Symbol; C]; R],
+    heat_matrix_opt: Option<&[[f64; C]; R]>,
+) -> Value {
+    let mut rows = key_table_matrix
+        .iter()
+        .enumerate()
+        .map(|(r, row)| {
+            let mut items = Vec::new();
+            let mut gap = 0;
+            for (c, &byte) in row.iter().enumerate() {
+                if byte == 0 {
+                    gap += 1;
+                    continue;
+                }
+                let mut properties = Map::new();
+                if gap > 0 {
+                    properties.insert("x".into(), json!(gap));
+                    gap = 0;
+                }
+                if let Some(heat_matrix) = heat_matrix_opt {
+                    properties.insert("t".into(), json!(heat_to_color(heat_matrix[r][c])));
+                }
+                if !properties.is_empty() {
+                    items.push(Value::Object(properties));
+                }
+                let legend = match byte {
+                    1..=3 => ((b'0' + byte as u8) as char).to_string(),
+                    _ => resolve(byte).to_string(),
+                };
+                items.push(Value::String(encode_legend(&legend)));
+            }
+            items
+        })
+        .collect::<Vec<_>>();
+    while matches!(rows.last(), Some(items) if items.is_empty()) {
+        rows.pop();
+    }
+    Value::Array(rows.into_iter().map(Value::Array).collect())
+}
+
+// NOTE Each row is written on a single line, as keyboard-layout-editor.com does.
+pub fn write_kle<W: Write + ?Sized>(writer: &mut W, value: &Value) -> io::Result<()> {
+    let rows = value.as_array().map(Vec::as_slice).unwrap_or_default();
+    writeln!(writer, "[")?;
+    for (i, row) in rows.iter().enumerate() {
+        let separator = if i + 1 < rows.len() { "," } else { "" };
+        writeln!(writer, "  {}{}", row, separator)?;
+    }
+    writeln!(writer, "]")
+}
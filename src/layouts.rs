@@ -3,11 +3,17 @@ use core::{
     fmt::{self, Display},
 };
 
-use std::{fs::File, path::Path};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
 
-use serde_json::Value;
+use serde_json::{Map, Value, json};
 
-use crate::{json::read_enveloped_data, tables::Table};
+use crate::{
+    json::{Validation, read_envelope},
+    tables::Table,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Laterality {
@@ -133,10 +139,92 @@ impl TryFrom<&Value> for Digit {
     }
 }
 
-pub struct LayoutTable<const C: usize, const R: usize>(pub Table<C, R, Digit>);
+pub const MAX_CANDIDATE_DIGITS: usize = 4;
+
+// NOTE A key may have more than one candidate digit, in order of preference (for example, to model
+// an angle mod). The first candidate is always present.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Digits([Option<Digit>; MAX_CANDIDATE_DIGITS]);
+
+impl Digits {
+    pub fn primary(&self) -> Digit {
+        self.0[0].expect("Digits must have at least one candidate")
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Digit> + '_ {
+        self.0.iter().map_while(|digit_opt| *digit_opt)
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0[0].is_none()
+    }
+
+    // NOTE The candidates of one hand, in the same order (so the first may not be the primary).
+    pub fn of_hand(&self, laterality: Laterality) -> Self {
+        let mut digits = [None; MAX_CANDIDATE_DIGITS];
+        for (digit_opt, digit) in digits
+            .iter_mut()
+            .zip(self.iter().filter(|digit| digit.0 == laterality))
+        {
+            *digit_opt = Some(digit);
+        }
+        Self(digits)
+    }
+}
+
+impl From<Digit> for Digits {
+    fn from(value: Digit) -> Self {
+        let mut digits = [None; MAX_CANDIDATE_DIGITS];
+        digits[0] = Some(value);
+        Self(digits)
+    }
+}
+
+impl From<Digits> for Value {
+    fn from(value: Digits) -> Value {
+        if value.len() == 1 {
+            Value::from(value.primary())
+        } else {
+            Value::Array(value.iter().map(Value::from).collect())
+        }
+    }
+}
+
+impl TryFrom<&Value> for Digits {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(vec) => {
+                if vec.is_empty() || vec.len() > MAX_CANDIDATE_DIGITS {
+                    return Err(format!(
+                        "Invalid array of digits: expected 1 to {} digits",
+                        MAX_CANDIDATE_DIGITS
+                    ));
+                }
+                let mut digits = [None; MAX_CANDIDATE_DIGITS];
+                for (i, value) in vec.iter().enumerate() {
+                    let digit = Digit::try_from(value)?;
+                    if digits.contains(&Some(digit)) {
+                        return Err(format!("Invalid array of digits: '{}' is repeated", digit));
+                    }
+                    digits[i] = Some(digit);
+                }
+                Ok(Self(digits))
+            }
+            _ => Digit::try_from(value).map(Digits::from),
+        }
+    }
+}
+
+pub struct LayoutTable<const C: usize, const R: usize>(pub Table<C, R, Digits>);
 
 impl<const C: usize, const R: usize> LayoutTable<C, R> {
-    pub fn mask<F: FnMut(usize, usize, &Digit) -> bool>(&mut self, mut predicate: F) {
+    pub fn mask<F: FnMut(usize, usize, &Digits) -> bool>(&mut self, mut predicate: F) {
         (0..R).for_each(|r| {
             (0..C).for_each(|c| {
                 if let Some(digit) = &self.0[r][c] {
@@ -148,11 +236,137 @@ impl<const C: usize, const R: usize> LayoutTable<C, R> {
         })
     }
 
-    pub fn read_from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
-        const EXPECTED_VERSION: u64 = 1;
-        let file = File::open(path)?;
-        let value = read_enveloped_data::<_, Value>(file, EXPECTED_VERSION)?;
-        Ok(LayoutTable::try_from(&value)?)
+    // NOTE Removes the keys with no candidate digit of the hand, and the candidates of the other
+    // hand, so that every fingering is of the hand.
+    pub fn retain_hand(&mut self, laterality: Laterality) {
+        self.mask(|_r, _c, digits| !digits.of_hand(laterality).is_empty());
+        self.0
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|digits| *digits = digits.of_hand(laterality));
+    }
+
+    pub fn json_schema() -> Value {
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Perky layout table",
+            "type": "object",
+            "properties": {
+                "data": {
+                    "type": "array",
+                    "maxItems": R,
+                    "items": {
+                        "type": "array",
+                        "maxItems": C,
+                        "items": {
+                            "oneOf": [
+                                { "type": "null" },
+                                { "type": "string", "pattern": "^[lr][imprt]$" },
+                                {
+                                    "type": "array",
+                                    "minItems": 1,
+                                    "maxItems": MAX_CANDIDATE_DIGITS,
+                                    "uniqueItems": true,
+                                    "items": { "type": "string", "pattern": "^[lr][imprt]$" }
+                                }
+                            ]
+                        }
+                    }
+                },
+                "extends": { "type": "string" },
+                "finger_weights": {
+                    "type": "object",
+                    "propertyNames": { "pattern": "^[lr][imprt]$" },
+                    "additionalProperties": { "type": "number", "exclusiveMinimum": 0 }
+                },
+                "geometry": {
+                    "type": "array",
+                    "maxItems": R,
+                    "items": {
+                        "type": "array",
+                        "maxItems": C,
+                        "items": {
+                            "oneOf": [
+                                { "type": "null" },
+                                {
+                                    "type": "array",
+                                    "items": { "type": "number" },
+                                    "minItems": 2,
+                                    "maxItems": 2
+                                }
+                            ]
+                        }
+                    }
+                },
+                "penalties": {
+                    "type": "array",
+                    "maxItems": R,
+                    "items": {
+                        "type": "array",
+                        "maxItems": C,
+                        "items": {
+                            "oneOf": [
+                                { "type": "null" },
+                                { "type": "number", "exclusiveMinimum": 0 }
+                            ]
+                        }
+                    }
+                },
+                "mask": {
+                    "type": "array",
+                    "items": {
+                        "type": "array",
+                        "items": { "type": "integer", "minimum": 0 },
+                        "minItems": 2,
+                        "maxItems": 2
+                    }
+                },
+                "qmk": {
+                    "type": "object",
+                    "properties": {
+                        "layout": { "type": "string" },
+                        "positions": {
+                            "type": "array",
+                            "maxItems": R,
+                            "items": {
+                                "type": "array",
+                                "maxItems": C,
+                                "items": {
+                                    "oneOf": [
+                                        { "type": "null" },
+                                        { "type": "integer", "minimum": 0 }
+                                    ]
+                                }
+                            }
+                        },
+                        "size": { "type": "integer", "minimum": 0 }
+                    },
+                    "additionalProperties": false
+                },
+                "version": { "const": 1 }
+            },
+            "required": ["version"],
+            "anyOf": [{ "required": ["data"] }, { "required": ["extends"] }],
+            "additionalProperties": false
+        })
+    }
+
+    // NOTE When strict, the layout table is validated after any base has been merged into it (see
+    // 'read_layout_table_object').
+    pub fn read_from_path(path: &Path, strict: bool) -> Result<Self, Box<dyn Error>> {
+        let validation = Validation {
+            optional_fields: &["finger_weights", "geometry", "penalties", "qmk"],
+            max_rows: R,
+            max_columns: C,
+            unique_strings: false,
+        };
+        let object = read_layout_table_object(path)?;
+        if strict {
+            validation.validate(&object)?;
+        }
+        let value = object.get("data").ok_or("Expected 'data' field")?;
+        Ok(LayoutTable::try_from(value)?)
     }
 }
 
@@ -172,6 +386,262 @@ impl<const C: usize, const R: usize> TryFrom<&Value> for LayoutTable<C, R> {
     type Error = String;
 
     fn try_from(value: &Value) -> Result<Self, Self::Error> {
-        Table::<C, R, Digit>::try_from(value).map(LayoutTable)
+        Table::<C, R, Digits>::try_from(value).map(LayoutTable)
+    }
+}
+
+// NOTE Reads the fields of a layout table file. A layout table may extend another, named by its
+// 'extends' field (a path relative to the directory of the file), in which case it inherits every
+// field of the base that it does not give itself, except that its 'finger_weights' are merged with
+// those of the base, digit by digit. Its 'mask' field, a list of '[row, column]' positions, then
+// removes those keys from the resulting 'data'. A base may itself extend another.
+pub fn read_layout_table_object(path: &Path) -> Result<Map<String, Value>, Box<dyn Error>> {
+    fn read(path: &Path, visited: &mut Vec<PathBuf>) -> Result<Map<String, Value>, Box<dyn Error>> {
+        const EXPECTED_VERSION: u64 = 1;
+        let file = File::open(path)?;
+        let canonical_path = path.canonicalize()?;
+        if visited.contains(&canonical_path) {
+            Err(format!(
+                "Layout table '{}' extends itself",
+                canonical_path.display()
+            ))?;
+        }
+        visited.push(canonical_path);
+        let mut object = read_envelope(file, EXPECTED_VERSION)?;
+        if let Some(value) = object.remove("extends") {
+            let base_fpath = path.parent().unwrap_or(Path::new("")).join(
+                value
+                    .as_str()
+                    .ok_or("Value of 'extends' field must be a string")?,
+            );
+            let mut base = read(&base_fpath, visited).map_err(|e| {
+                format!(
+                    "Failed to load base layout table '{}': {e}",
+                    base_fpath.display()
+                )
+            })?;
+            for (field, value) in object {
+                if field == "finger_weights"
+                    && let (Some(Value::Object(base_weights)), Value::Object(weights)) =
+                        (base.get_mut(&field), &value)
+                {
+                    base_weights.extend(weights.clone());
+                } else {
+                    base.insert(field, value);
+                }
+            }
+            object = base;
+        }
+        if let Some(value) = object.remove("mask") {
+            apply_mask(&mut object, &value)?;
+        }
+        Ok(object)
+    }
+
+    read(path, &mut Vec::new())
+}
+
+fn apply_mask(object: &mut Map<String, Value>, mask: &Value) -> Result<(), Box<dyn Error>> {
+    let positions = mask
+        .as_array()
+        .ok_or("Value of 'mask' field must be an array")?;
+    let rows = object
+        .get_mut("data")
+        .and_then(Value::as_array_mut)
+        .ok_or("Expected 'data' field")?;
+    for position in positions {
+        let (r, c) = match position.as_array().map(Vec::as_slice) {
+            Some([r, c]) => match (r.as_u64(), c.as_u64()) {
+                (Some(r), Some(c)) => (r as usize, c as usize),
+                _ => Err("Invalid 'mask' field: rows and columns must be non-negative integers")?,
+            },
+            _ => Err("Invalid 'mask' field: each position must be an array of a row and a column")?,
+        };
+        match rows
+            .get_mut(r)
+            .and_then(Value::as_array_mut)
+            .and_then(|row| row.get_mut(c))
+        {
+            Some(cell) if !cell.is_null() => *cell = Value::Null,
+            _ => Err(format!("Invalid 'mask' field: no key at ({}, {})", r, c))?,
+        }
+    }
+    Ok(())
+}
+
+// NOTE The distance between the centres of adjacent keys on a typical keyboard. Distances between
+// points are divided by this so that efforts are comparable with those of grid positions.
+const KEY_PITCH_MM: f64 = 19.05;
+
+// NOTE The position of the centre of a key, in millimetres, with x increasing to the right and y
+// increasing downward.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point(pub f64, pub f64);
+
+impl From<Point> for Value {
+    fn from(value: Point) -> Value {
+        json!([value.0, value.1])
+    }
+}
+
+impl TryFrom<&Value> for Point {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value.as_array().map(Vec::as_slice) {
+            Some([x, y]) => match (x.as_f64(), y.as_f64()) {
+                (Some(x), Some(y)) => Ok(Point(x, y)),
+                _ => Err("Invalid point: coordinates must be numbers".into()),
+            },
+            _ => Err("Invalid type: expected an array of two numbers".into()),
+        }
+    }
+}
+
+pub struct Geometry<const C: usize, const R: usize>(pub Table<C, R, Point>);
+
+impl<const C: usize, const R: usize> Geometry<C, R> {
+    // NOTE Offsets are converted to key pitches and rounded to millionths of a key pitch, so that
+    // points on a regular grid give exactly the same efforts as the rows and columns of the layout
+    // table.
+    pub fn distance(&self, r1: usize, c1: usize, r2: usize, c2: usize) -> f64 {
+        fn to_key_pitches(mm: f64) -> f64 {
+            const PRECISION: f64 = 1e6;
+            (mm / KEY_PITCH_MM * PRECISION).round() / PRECISION
+        }
+        match (self.0[r1][c1], self.0[r2][c2]) {
+            (Some(Point(x1, y1)), Some(Point(x2, y2))) => {
+                let dx = to_key_pitches(x2 - x1);
+                let dy = to_key_pitches(y2 - y1);
+                (dx * dx + dy * dy).sqrt()
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub fn try_from_value(
+        value: &Value,
+        layout_table: &LayoutTable<C, R>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let points = Table::<C, R, Point>::try_from(value)
+            .map_err(|e| format!("Invalid 'geometry' field: {}", e))?;
+        for (r, row) in layout_table.0.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                if cell.is_some() && points[r][c].is_none() {
+                    Err(format!(
+                        "Invalid 'geometry' field: no point for the key at ({}, {})",
+                        r, c
+                    ))?;
+                }
+            }
+        }
+        Ok(Self(points))
+    }
+
+    // NOTE The geometry is read from the optional 'geometry' field of a layout table file. Without
+    // it, the row and column of each key are used as its position.
+    pub fn read_from_path(
+        path: &Path,
+        layout_table: &LayoutTable<C, R>,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        let object = read_layout_table_object(path)?;
+        object
+            .get("geometry")
+            .map(|value| Self::try_from_value(value, layout_table))
+            .transpose()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Penalty(pub f64);
+
+impl From<Penalty> for Value {
+    fn from(value: Penalty) -> Value {
+        Value::from(value.0)
+    }
+}
+
+impl TryFrom<&Value> for Penalty {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value.as_f64() {
+            Some(multiplier) if multiplier.is_finite() && multiplier > 0.0 => {
+                Ok(Penalty(multiplier))
+            }
+            _ => Err("Invalid penalty: expected a positive number".into()),
+        }
+    }
+}
+
+// NOTE Keys may be penalized (for example, the lateral index columns), each with a multiplier. Pen
+// counts the unigrams typed on penalized keys, and its effort-weighted score multiplies each by the
+// multiplier of its key. Penalties at positions without a key are ignored.
+pub struct Penalties<const C: usize, const R: usize>(pub Table<C, R, Penalty>);
+
+impl<const C: usize, const R: usize> Penalties<C, R> {
+    pub fn get(&self, r: usize, c: usize) -> Option<f64> {
+        self.0[r][c].map(|Penalty(multiplier)| multiplier)
+    }
+
+    pub fn try_from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let penalties = Table::<C, R, Penalty>::try_from(value)
+            .map_err(|e| format!("Invalid 'penalties' field: {}", e))?;
+        Ok(Self(penalties))
+    }
+
+    // NOTE The penalties are read from the optional 'penalties' field of a layout table file.
+    pub fn read_from_path(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        let object = read_layout_table_object(path)?;
+        object
+            .get("penalties")
+            .map(Self::try_from_value)
+            .transpose()
+    }
+}
+
+// NOTE Each digit's effort is multiplied by its weight, so that, for example, sequences that lean
+// on the pinkies can be penalized. Digits without a weight have a weight of 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FingerWeights([[f64; 5]; 2]);
+
+impl FingerWeights {
+    pub fn get(&self, Digit(laterality, position): Digit) -> f64 {
+        self.0[laterality as usize][position as usize]
+    }
+
+    pub fn try_from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let object = value
+            .as_object()
+            .ok_or("Value of 'finger_weights' field must be an object")?;
+        let mut finger_weights = Self::default();
+        for (key, value) in object {
+            let Digit(laterality, position) = Digit::try_from(key.as_str())
+                .map_err(|e| format!("Invalid 'finger_weights' field: {}", e))?;
+            let weight = match value.as_f64() {
+                Some(weight) if weight.is_finite() && weight > 0.0 => weight,
+                _ => Err(format!(
+                    "Invalid 'finger_weights' field: weight of '{}' must be a positive number",
+                    key
+                ))?,
+            };
+            finger_weights.0[laterality as usize][position as usize] = weight;
+        }
+        Ok(finger_weights)
+    }
+
+    // NOTE The weights are read from the optional 'finger_weights' field of a layout table file.
+    pub fn read_from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let object = read_layout_table_object(path)?;
+        match object.get("finger_weights") {
+            None => Ok(Self::default()),
+            Some(value) => Self::try_from_value(value),
+        }
+    }
+}
+
+impl Default for FingerWeights {
+    fn default() -> Self {
+        Self([[1.0; 5]; 2])
     }
 }
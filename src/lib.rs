@@ -1,19 +1,34 @@
+// NOTE The metadata object is too large for the default recursion limit of the json! macro.
+#![recursion_limit = "256"]
+
+pub mod archives;
+pub mod constraints;
 pub mod dsv;
 pub mod expressions;
 pub mod fingerings;
 pub mod goals;
 pub mod json;
 pub mod keys;
+pub mod kla;
+pub mod klc;
+pub mod kle;
 pub mod layouts;
 pub mod measurements;
 pub mod metadata;
 pub mod metrics;
 pub mod ngrams;
 pub mod permutations;
+pub mod qmk;
 pub mod records;
 pub mod scores;
+pub mod scoring;
+pub mod search;
+pub mod symbols;
 pub mod tables;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod ui;
 pub mod util;
 pub mod weights;
 pub mod writers;
+pub mod xkb;
@@ -1,62 +1,163 @@
-use core::{array, cmp, error::Error, iter, ops::RangeInclusive, time::Duration, u64};
+use core::{
+    cmp,
+    error::Error,
+    iter, mem,
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use std::{
     collections::{BTreeMap, HashSet},
     env,
-    io::Write,
+    fs::{self, File},
+    io::{self, Write},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    thread,
     time::Instant,
 };
 
-use clap::{ArgAction, Parser, ValueEnum};
-
-use rayon::ThreadPoolBuilder;
+use clap::{
+    ArgAction, Parser, Subcommand, ValueEnum,
+    builder::{PossibleValue, PossibleValuesParser, TypedValueParser},
+};
 
-use serde_json::Value;
+use serde_json::{Value, json};
 
 use termcolor::BufferedStandardStream;
 
 use perky::{
-    expressions::Expression,
+    archives::{Archive, write_archive},
+    constraints::{Constraint, KeyUsageCap},
+    expressions::{Definition, Expression, ExpressionSet, Type, Variables},
+    fingerings::EffortModel,
     goals,
-    json::write_json_flatten_primitive_arrays,
-    keys::{Key, KeyTable},
-    layouts::LayoutTable,
-    measurements::Measurement,
-    metadata::Metadata,
+    json::{Validation, read_envelope, write_json, write_json_flatten_primitive_arrays},
+    keys::{Key, KeyLayers, KeyTable},
+    kla::read_kla_from_path,
+    klc::read_klc_from_path,
+    kle::{kle_value_from_matrix, read_kle_from_path, write_kle},
+    layouts::{FingerWeights, Geometry, Laterality, LayoutTable, Penalties},
+    log_info,
+    metadata::{
+        InputHashes, Metadata, merge_metadata_values, read_run_quantities,
+        write_metadata_value_text,
+    },
     metrics::{self, partition_sort_rules},
     ngrams::{
-        read_bigram_table_from_bytes, read_bigram_table_from_path, read_trigram_table_from_bytes,
-        read_trigram_table_from_path, read_unigram_table_from_bytes, read_unigram_table_from_path,
-        sum_ngram_table,
+        self, BigramKey, BigramTable, Coverage, TrigramKey, TrigramTable, UnigramKey, UnigramTable,
+        fold_ngram_table, read_bigram_table_from_bytes, read_bigram_table_from_path,
+        read_ngram_list_from_path, read_trigram_table_from_bytes, read_trigram_table_from_path,
+        read_unigram_table_from_bytes, read_unigram_table_from_path, sum_ngram_table, top_ngrams,
+        zero_ngram_tables,
+    },
+    permutations::{
+        PermutationProgress, Pin, TieBreak, calculate_provenance, convert_vec_opt_to_array,
+        estimate_duration_per_permutation, map_in_parallel, permute_and_substitute, split_region,
+    },
+    qmk::{QmkMapping, write_qmk_keymap},
+    records::{
+        DetailLimit, Explanation, Heatmap, MemoryEstimate, Overlay, OverlayBigram, Record,
+        RunQuantities, Sparkline, StreamingSelection, Utilization, assign_ranks, cluster_records,
+        filter_record, filter_records, get_key_table_variables, get_meta_variables,
+        log_filter_drops, select_diverse_records, select_records, sort_records,
     },
-    permutations::{convert_vec_opt_to_array, permute_and_substitute},
-    records::{Record, filter_records, select_records, sort_records},
     scores::{
-        ScoreMode, score_bfs, score_bfs_without_details_unsafe, score_tfs,
-        score_tfs_without_details_unsafe, score_ufs, score_ufs_without_details_unsafe,
+        ScoreMode, explain_bfs, explain_tfs, explain_ufs, heat_bfs, heat_tfs, heat_ufs, locate_bfs,
+        normalize_heat_matrix, score_bfs, score_tfs, score_ufs, take_saturated, weigh_heat_matrix,
+    },
+    scoring::{self, MeasurementPlan, NgramTables, measure},
+    search::{self, BeamSearch, PermutationOptions, SwapSearch, TwoPhaseSearch},
+    symbols::{Symbol, intern_str, resolve},
+    ui::{
+        self, colors,
+        log::{Verbosity, is_enabled, set_verbosity},
+        pages::Pager,
+        progress::RateWindow,
+        styles::{Theme, WriteStyled, set_theme},
+    },
+    util::{
+        files::{read_modification_times, wait_for_modification},
+        format::{self, NumberFormat, decimal_places_or, format_bytes, set_number_format},
+        hash::sha256_hex_from_path,
+        math::{calculate_perc, checked_permutation_count, crop_matrix},
+        signals::ignore_sigpipe,
+        strings::unescape,
+        system,
+        threads::throttle,
     },
-    ui::{self, styles::WriteStyled},
-    util::{math::factorial, signals::ignore_sigpipe, strings::unescape, threads::throttle},
     weights,
-    writers::{write_progress, write_records_json, write_records_text},
+    writers::{
+        RowOptions, write_comparison_json, write_comparison_text, write_coverage_text,
+        write_estimate, write_explanation_json, write_explanation_text, write_grid_csv,
+        write_grid_html, write_grid_text, write_key_tables_json, write_key_tables_text,
+        write_leaderboard_json, write_leaderboard_text, write_progress, write_progress_rich,
+        write_records_json, write_records_text, write_sparklines_text, write_top_ngrams_json,
+        write_top_ngrams_text,
+    },
+    xkb::read_xkb_from_path,
 };
 
+// NOTE Tables are held in matrices of 16 columns by 8 rows, unless the layout table (or, with
+// 'show', a key table of the archive) has a key outside of them, in which case matrices of 32
+// columns by 16 rows are used. Smaller matrices are faster to permute.
 const C: usize = 16;
 const R: usize = 8;
+const LARGE_C: usize = 32;
+const LARGE_R: usize = 16;
+
+const ESTIMATE_SAMPLES: u64 = 4096;
+
+const SECONDS_PER_HOUR: f64 = 3600.0;
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+const PROGRESS_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+const EXPLAIN_TOP: usize = 10;
+
+// NOTE The number of records measured at a time when streaming (see '--stream').
+const STREAM_CHUNK: usize = 1024;
+
+// NOTE With '--min-results', the tolerance is lowered to the next multiple of 1 / this for each
+// re-run.
+const TOLERANCE_STEPS: f64 = 10.0;
 
-const PERMIT_PARTIAL_PERMUTATIONS: bool = true;
+// NOTE A filter keeps a record if it is true (or a non-zero number); a printed field may be of
+// any type.
+const FILTER_TYPES: [Type; 2] = [Type::Boolean, Type::Number];
+
+const PRINT_FIELD_TYPES: [Type; 4] = [Type::Boolean, Type::Number, Type::Position, Type::String];
+
+// NOTE A KLE document is a single layout, so several records cannot be written as one.
+const KLE_SINGLE_RECORD: &str = "The 'kle' format can only be used when a single record is \
+                                 selected (such as with '--index' or '--max-selections 1')";
 
 const DEFAULT_1_GRAMS: &[u8] = include_bytes!("../resources/charfreq-google/1-grams-uc.tsv");
 const DEFAULT_2_GRAMS: &[u8] = include_bytes!("../resources/charfreq-google/2-grams-uc.tsv");
 const DEFAULT_3_GRAMS: &[u8] = include_bytes!("../resources/charfreq-google/3-grams-uc.tsv");
 
+const PRESET_COLEMAK: &[u8] = include_bytes!("../examples/key-tables/colemak.kt.json");
+const PRESET_COLEMAK_DH: &[u8] = include_bytes!("../examples/key-tables/colemak-dh.kt.json");
+const PRESET_DVORAK: &[u8] = include_bytes!("../examples/key-tables/dvorak.kt.json");
+const PRESET_QWERTY: &[u8] = include_bytes!("../examples/key-tables/qwerty.kt.json");
+const PRESET_WORKMAN: &[u8] = include_bytes!("../examples/key-tables/workman.kt.json");
+
 // Cli
 
-#[derive(Parser)]
-#[command(about, author, long_about = None, next_line_help = true, version)]
+#[derive(Clone, Parser)]
+#[command(
+    about,
+    author,
+    long_about = None,
+    next_line_help = true,
+    version
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to layout table file. [default: 'default.lt.json']
     ///
     /// This must be a valid JSON file in the layout table format.
@@ -65,9 +166,128 @@ struct Cli {
 
     /// Path to key table file. [default: 'default.kt.json']
     ///
-    /// This must be a valid JSON file in the key table format
+    /// This must be a valid JSON file in the key table format. May be repeated, or be a
+    /// directory (of '.json' files), to permute each key table in turn with the same options and
+    /// print their records together.
     #[arg(short, long = "key-table", value_name = "FPATH")]
-    key_table_fpath: Option<PathBuf>,
+    key_table_fpaths: Vec<PathBuf>,
+
+    /// Key table as a string, used instead of a key table file.
+    ///
+    /// Rows are separated by ';' and each character is assigned to the next key in the row.
+    /// A space leaves a key unassigned. Use '\;' for a semicolon and '\x20' for a space.
+    /// ASCII letters are converted to uppercase.
+    #[arg(
+        long = "layout-string",
+        conflicts_with = "key_table_fpaths",
+        value_name = "STRING"
+    )]
+    layout_string: Option<String>,
+
+    /// Path to keyboard-layout-editor file, used instead of a key table file.
+    ///
+    /// This must be a valid JSON file in the raw data format of keyboard-layout-editor.com.
+    /// Unless '--layout-table' is specified, the layout table is also derived from this file.
+    #[arg(
+        long = "kle",
+        conflicts_with_all = ["key_table_fpaths", "layout_string"],
+        value_name = "FPATH"
+    )]
+    kle_fpath: Option<PathBuf>,
+
+    /// Path to keyboard-layout-analyzer file, used instead of a key table file.
+    ///
+    /// This must be a valid JSON file in the layout format of keyboard-layout-analyzer
+    /// (patorjk.com), for its 'standard' keyboard. Unless '--layout-table' is specified, the layout
+    /// table (with each key's finger) is also derived from this file.
+    #[arg(
+        long = "kla",
+        conflicts_with_all = ["key_table_fpaths", "layout_string", "kle_fpath"],
+        value_name = "FPATH"
+    )]
+    kla_fpath: Option<PathBuf>,
+
+    /// Path to XKB symbols file, used instead of a key table file.
+    ///
+    /// The base layer of the three letter rows is imported (with the shifted characters in a
+    /// layer). Includes are read from the same directory.
+    #[arg(
+        long = "xkb",
+        conflicts_with_all = ["key_table_fpaths", "layout_string", "kle_fpath", "kla_fpath"],
+        value_name = "FPATH"
+    )]
+    xkb_fpath: Option<PathBuf>,
+
+    /// Name of the block ('xkb_symbols') of the XKB symbols file to import.
+    ///
+    /// By default, the block marked 'default' (or else the first block) is imported.
+    #[arg(long = "xkb-variant", requires = "xkb_fpath", value_name = "NAME")]
+    xkb_variant: Option<String>,
+
+    /// Path to Windows keyboard layout source ('.klc') file, used instead of a key table file.
+    ///
+    /// The base layer of the three letter rows is imported (with the shifted characters in a
+    /// layer).
+    #[arg(
+        long = "klc",
+        conflicts_with_all = [
+            "key_table_fpaths",
+            "layout_string",
+            "kle_fpath",
+            "kla_fpath",
+            "xkb_fpath",
+        ],
+        value_name = "FPATH"
+    )]
+    klc_fpath: Option<PathBuf>,
+
+    /// Built-in key table, used instead of a key table file.
+    ///
+    /// Run 'perky presets' to list the built-in key tables.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "key_table_fpaths",
+            "layout_string",
+            "kle_fpath",
+            "kla_fpath",
+            "xkb_fpath",
+            "klc_fpath",
+        ],
+        value_enum,
+        value_name = "NAME"
+    )]
+    preset: Option<Preset>,
+
+    /// Allow a character to be assigned to more than one key.
+    ///
+    /// By default, this is an error, as the character's frequency would be counted once for each
+    /// key. This includes characters in '-1', '-2', or '-3' that are also elsewhere in the key
+    /// table. Specify this for intentional duplicates, such as two space keys.
+    #[arg(long)]
+    allow_duplicates: bool,
+
+    /// Validate the layout table and key table files strictly.
+    ///
+    /// Reports unknown fields, rows that are too long, and characters assigned to more than one
+    /// key, with their positions.
+    #[arg(long)]
+    strict: bool,
+
+    /// Crop the layout table and key table before scoring or permuting.
+    ///
+    /// Removes the given numbers of rows from the top, columns from the right, rows from the
+    /// bottom, and columns from the left of the keys of the layout table, to analyze only one hand
+    /// or one block of a larger keyboard. For example, "0,7,0,0" removes the rightmost 7 columns.
+    #[arg(long, value_name = "T,R,B,L", value_parser = validate_crop)]
+    crop: Option<[usize; 4]>,
+
+    /// Restrict the layout table and key table to one hand.
+    ///
+    /// Removes the keys with no digit of the hand (and the digits of the other hand from alternate
+    /// fingerings), so that every fingering, metric, and heatmap is of the hand alone.
+    #[arg(long, value_enum, value_name = "HAND")]
+    hand: Option<Hand>,
 
     /// Path to unigram table file.
     ///
@@ -90,11 +310,37 @@ struct Cli {
     #[arg(short, long = "trigram-table", value_name = "FPATH")]
     trigram_table_fpath: Option<PathBuf>,
 
-    /// Goal for the selected metric.
+    /// Format of the n-gram table files. [default: detected from the file extension]
+    ///
+    /// Files with a '.json' extension are read as genkey or oxeylyzer corpora (detected from their
+    /// contents). All other files are read as TSV files.
+    #[arg(long = "ngram-format", value_enum, value_name = "FORMAT")]
+    ngram_format: Option<NgramFormat>,
+
+    /// Case to convert the n-grams of the n-gram table files to.
+    ///
+    /// By default, genkey and oxeylyzer corpora (which are usually lowercase) are converted to
+    /// uppercase, like the built-in n-gram tables and key tables, and TSV files are not changed.
+    /// The counts of n-grams that become the same are summed.
+    #[arg(long = "ngram-case", value_enum, value_name = "CASE")]
+    ngram_case: Option<NgramCase>,
+
+    /// Path to a list of n-grams to ignore.
+    ///
+    /// This must be a valid TSV file.
+    /// Each line must have an n-gram in column 0; any other columns are ignored.
+    /// The counts of these n-grams (of any order) are set to zero after the n-gram tables load.
+    #[arg(long = "ignore-bigrams", value_name = "FPATH")]
+    ignore_ngrams_fpath: Option<PathBuf>,
+
+    /// Goal for the selected metric, or for any metrics.
     ///
-    /// This overrides the default goal for the metric.
-    #[arg(short = 'g', long, value_name = "GOAL")]
-    goal: Option<Goal>,
+    /// 'max' or 'min' overrides the goal for the selected metric when permuting. Goals for any
+    /// metrics (such as 'sfb=min,rol=max') override their default goals wherever they are used
+    /// (permuting, ranks, percentiles, 'score-all', and the arrows in reports), and take precedence
+    /// over 'max' or 'min'.
+    #[arg(short = 'g', long, value_name = "GOAL", value_parser = parse_goals)]
+    goal: Option<Goals>,
 
     /// Metric used for scoring.
     ///
@@ -108,12 +354,40 @@ struct Cli {
     )]
     metric: Metric,
 
-    /// Tolerance for the selected metric.
+    /// Tolerance for the selected metric. [default: 1.0 for 'ratio', otherwise 0]
     ///
     /// Results within this tolerance of the best score will be retained.
-    /// Permitted range is 0.0 to 1.0.
-    #[arg(long, default_value_t = 1.0, value_parser = validate_tolerance)]
-    tolerance: f64,
+    /// Permitted range is 0.0 to 1.0 for 'ratio', and at least 0 otherwise.
+    #[arg(long, value_parser = validate_tolerance)]
+    tolerance: Option<f64>,
+
+    /// How the tolerance is applied to the best score.
+    #[arg(long, default_value = "ratio", value_enum, value_name = "MODE")]
+    tolerance_mode: ToleranceMode,
+
+    /// Minimum number of records to survive filtering.
+    ///
+    /// If fewer records survive, the key table is permuted again with the tolerance lowered by 0.1
+    /// (to no less than 0.0), until enough records survive.
+    #[arg(long, conflicts_with = "stream", value_name = "N")]
+    min_results: Option<usize>,
+
+    /// Count a key repeated (such as "ee") as a same finger bigram.
+    ///
+    /// By default, repeated keys are counted only by the Rep metric.
+    #[arg(long)]
+    sfb_include_repeats: bool,
+
+    /// How thumbs are treated by the trigram metrics (except One).
+    #[arg(long, default_value = "hand", value_enum, value_name = "MODE")]
+    thumb_mode: ThumbMode,
+
+    /// Expression for the effort of two consecutive keys.
+    ///
+    /// Variables are 'dist', 'row_delta', 'col_delta', 'same_hand', and 'same_finger' (the last two
+    /// are 1 or 0). [default: the distance between the keys if same hand, otherwise 1]
+    #[arg(long = "effort-expr", value_name = "EXPRESSION")]
+    effort_expression: Option<String>,
 
     /// Weighing method used for the selected metric.
     #[arg(short = 'w', long, value_name = "WEIGHT")]
@@ -137,10 +411,119 @@ struct Cli {
     #[arg(short = '3', long, value_name = "STRING")]
     region3: Option<String>,
 
+    /// Regions to permute as one region (such as '1,2').
+    ///
+    /// The characters and placeholders of the regions are merged into the lowest-numbered of them,
+    /// so characters may move between the regions.
+    #[arg(long, value_parser = parse_regions, value_name = "REGIONS")]
+    merge_regions: Option<[bool; 3]>,
+
+    /// Characters of '-1' to permute only within certain rows or columns.
+    ///
+    /// For example, "AEIOU@r1,c6-10" permutes A, E, I, O, and U only among the 1s in row 1 and
+    /// columns 6 to 10 (counting from 0), and the other characters among the other 1s.
+    #[arg(long, value_name = "PIN")]
+    pin1: Option<String>,
+
+    /// Characters of '-2' to permute only within certain rows or columns.
+    #[arg(long, value_name = "PIN")]
+    pin2: Option<String>,
+
+    /// Characters of '-3' to permute only within certain rows or columns.
+    #[arg(long, value_name = "PIN")]
+    pin3: Option<String>,
+
+    /// Constraints on permutations (may be repeated).
+    ///
+    /// Permutations that violate any constraint are skipped before they are scored. For example,
+    /// "pos('e') in home_row" or "hand('a') != hand('o')".
+    #[arg(long = "constraint", value_name = "EXPRESSION")]
+    constraints: Vec<String>,
+
+    /// Caps on the usage of keys (may be repeated).
+    ///
+    /// Permutations that put too many (or too few) unigrams on a key are skipped before they are
+    /// scored. For example, "1,9<0.5" allows at most 0.5% of all unigrams on the key at row 1,
+    /// column 9.
+    #[arg(long = "key-usage-cap", value_name = "ROW,COLUMN<PERC")]
+    key_usage_caps: Vec<String>,
+
+    /// How to treat the placeholders of regions with no characters for permutation.
+    #[arg(
+        long = "partial",
+        default_value = "allow",
+        value_enum,
+        value_name = "MODE"
+    )]
+    partial_mode: PartialMode,
+
     /// Maximum number of permutations to consider.
+    ///
+    /// In a two-phase search, this is the maximum for each key table permuted by phase two.
     #[arg(long)]
     max_permutations: Option<u64>,
 
+    /// Width of a beam search, used instead of permuting.
+    ///
+    /// The characters of the regions are assigned one key at a time, keeping only the N best
+    /// partial key tables (scored by only the fingerings whose keys are all assigned) after each
+    /// key. This scores far fewer key tables than permuting, but may miss the best.
+    #[arg(
+        long,
+        conflicts_with_all = ["phase1_metric", "max_permutations"],
+        value_name = "N"
+    )]
+    beam_width: Option<usize>,
+
+    /// Path to reference key table file for a swap search, used instead of permuting.
+    ///
+    /// Only the key tables within '--max-swaps' swaps (each of two characters of the same region)
+    /// of the reference key table are scored. The reference key table must assign the characters of
+    /// each region to its placeholders.
+    #[arg(
+        long = "reference",
+        requires = "max_swaps",
+        conflicts_with_all = ["beam_width", "phase1_metric"],
+        value_name = "FPATH"
+    )]
+    reference_fpath: Option<PathBuf>,
+
+    /// Maximum number of swaps from the reference key table of a swap search.
+    #[arg(long, requires = "reference_fpath", value_name = "N")]
+    max_swaps: Option<usize>,
+
+    /// Metric used for scoring in phase one of a two-phase search.
+    ///
+    /// Phase one permutes every region, scored by this (typically cheaper) metric, and keeps the
+    /// '--phase1-top' best key tables. Phase two then permutes only the regions of
+    /// '--phase2-regions' of each of them, scored by '--metric'.
+    #[arg(long, requires = "phase2_regions", value_enum, value_name = "METRIC")]
+    phase1_metric: Option<Metric>,
+
+    /// Number of key tables kept by phase one of a two-phase search.
+    #[arg(
+        long,
+        default_value_t = 100,
+        requires = "phase1_metric",
+        value_name = "K"
+    )]
+    phase1_top: u32,
+
+    /// Maximum number of permutations to consider in phase one of a two-phase search.
+    #[arg(long, requires = "phase1_metric", value_name = "N")]
+    phase1_max_permutations: Option<u64>,
+
+    /// Regions permuted by phase two of a two-phase search (such as '1' or '23').
+    ///
+    /// The other regions keep the characters assigned to them by phase one.
+    #[arg(
+        long,
+        requires = "phase1_metric",
+        value_parser = parse_regions,
+        value_name = "REGIONS"
+    )]
+    phase2_regions: Option<[bool; 3]>,
+
     /// Maximum number of results to process before sorting, filtering, and selecting.
     ///
     /// An unreasonably large number of results can cause the post-processing steps to take a long
@@ -148,6 +531,26 @@ struct Cli {
     #[arg(long, default_value_t = 10000)]
     max_records: u32,
 
+    /// Seed with which to break ties between records with the same score.
+    ///
+    /// By default, ties are broken by permutation index, so truncating to '--max-records' keeps
+    /// the records found first. With a seed, ties are broken by a hash of each key table, so the
+    /// records kept are a sample of those tied that is the same for the same seed.
+    #[arg(long, value_name = "SEED")]
+    tie_seed: Option<u64>,
+
+    /// Maximum estimated memory, in MiB, for the records of a run.
+    ///
+    /// The estimate covers the records retained while permuting and the measurements (including
+    /// detail reports) of the records measured after. If it exceeds the maximum, '--max-records' is
+    /// reduced to fit, or Perky stops with an error, according to '--memory-limit-action'.
+    #[arg(long, value_name = "MB")]
+    max_memory_mb: Option<u64>,
+
+    /// What to do if the estimated memory exceeds '--max-memory-mb'.
+    #[arg(long, default_value = "truncate", value_enum, value_name = "ACTION")]
+    memory_limit_action: MemoryLimitAction,
+
     /// Use parallel execution algorithm.
     ///
     /// Setting this to false will force the use of a specialized
@@ -156,6 +559,13 @@ struct Cli {
     #[arg(long, action = ArgAction::Set, hide = true, default_value_t = true)]
     parallelize: bool,
 
+    /// Score permutations using bounds-checked indexing.
+    ///
+    /// This is slower, but does not rely on the unchecked indexing of the default scoring path.
+    /// Defaults to true in debug builds, and false in release builds.
+    #[arg(long, action = ArgAction::Set, default_value_t = cfg!(debug_assertions))]
+    safe_scoring: bool,
+
     /// Number of nanoseconds to yield threads per permutation batch.
     #[arg(long, default_value_t = 0)]
     sleep_ns: u64,
@@ -165,6 +575,17 @@ struct Cli {
     #[arg(long, default_value_t = 0)]
     threads: usize,
 
+    /// Maximum estimated duration (in hours) of permuting.
+    ///
+    /// The estimate is made by sampling permutations before permuting begins.
+    /// If the estimate exceeds this value, Perky exits without permuting (see '--force').
+    #[arg(long, value_name = "HOURS", value_parser = validate_hours)]
+    max_estimated_hours: Option<f64>,
+
+    /// Permute even if the estimated duration exceeds '--max-estimated-hours'.
+    #[arg(long)]
+    force: bool,
+
     /// Metrics to sort in ascending order.
     ///
     /// May be specified multiple times, with multiple metrics each time.
@@ -173,10 +594,10 @@ struct Cli {
         long = "sort-asc",
         action = ArgAction::Append,
         num_args = 1..,
-        value_enum,
+        value_parser = Metrics::value_parser(),
         value_name = "METRIC"
     )]
-    sort_asc: Vec<Metric>,
+    sort_asc: Vec<Metrics>,
 
     /// Metrics to sort in descending order.
     ///
@@ -186,10 +607,10 @@ struct Cli {
         long = "sort-desc",
         action = ArgAction::Append,
         num_args = 1..,
-        value_enum,
+        value_parser = Metrics::value_parser(),
         value_name = "METRIC"
     )]
-    sort_desc: Vec<Metric>,
+    sort_desc: Vec<Metrics>,
 
     /// Filter expression.
     ///
@@ -203,6 +624,28 @@ struct Cli {
     )]
     filters: Vec<String>,
 
+    /// Define a name for an expression, such as 'badness=sfb + fsb * 2'.
+    ///
+    /// The name may then be used in '--filter' and '--print-field' expressions, and in later
+    /// definitions. May be specified multiple times.
+    #[arg(
+        long = "define",
+        action = ArgAction::Append,
+        num_args = 1,
+        value_parser = validate_definition,
+        value_name = "NAME=EXPRESSION"
+    )]
+    definitions: Vec<(String, String)>,
+
+    /// Path to a directory of reference key tables.
+    ///
+    /// Each '.json' file in the directory must be a valid JSON file in the key table format. These
+    /// key tables are scored with the same layout table and n-gram tables, and each record reports,
+    /// for each metric, the percentage of reference key tables that it is better than. This is
+    /// available to filter expressions as the name of the metric followed by '_percentile'.
+    #[arg(long = "reference-layouts", value_name = "DPATH")]
+    reference_layouts_dpath: Option<PathBuf>,
+
     /// Maximum number of records to print.
     ///
     /// This is similar to max_records, but occurs after sorting, filtering, and selecting.
@@ -213,10 +656,79 @@ struct Cli {
     #[arg(short = 'i', long)]
     index: Option<isize>,
 
+    /// Select only the first N records.
+    #[arg(long, value_name = "N")]
+    head: Option<usize>,
+
+    /// Select only the last N records.
+    #[arg(long, value_name = "N")]
+    tail: Option<usize>,
+
+    /// Group records whose key tables differ in at most D key positions, and select only the first
+    /// record of each group.
+    ///
+    /// Records are linked to every other record within D key positions, and each group holds the
+    /// records linked directly or through other records. The size of each group is printed with
+    /// its first record. This is applied before the other selection options.
+    #[arg(long, value_name = "D")]
+    cluster: Option<usize>,
+
+    /// Select up to N records whose key tables differ pairwise in at least '--min-distance' keys.
+    ///
+    /// Records are considered in order (i.e., after sorting and filtering), and each is selected
+    /// only if it differs enough from every record already selected. This is applied before the
+    /// other selection options.
+    #[arg(long, value_name = "N", requires = "min_distance")]
+    diverse: Option<usize>,
+
+    /// Minimum number of key positions at which records selected by '--diverse' must differ.
+    #[arg(long, value_name = "D", requires = "diverse")]
+    min_distance: Option<usize>,
+
+    /// Select only every Nth record, starting with the first.
+    ///
+    /// This is applied before '--head' and '--tail', and is useful for sampling many records.
+    #[arg(long, value_name = "N")]
+    every: Option<usize>,
+
+    /// Measure, filter, select, and print the records a chunk at a time, rather than all at once.
+    ///
+    /// This bounds the memory used when many records are kept (e.g., with a large tolerance or
+    /// '--max-records'). Records are printed in the order they were kept, without their index, and
+    /// metadata is printed after them. Cannot be used with sorting, ranking, '--tail', a negative
+    /// '--index', '--cluster', '--diverse', '--explain', '--save-run', '--emit-qmk',
+    /// '--print-sparklines', 'compare', 'show', or more than one key table.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "sort_asc",
+            "sort_desc",
+            "print_ranks",
+            "tail",
+            "cluster",
+            "diverse",
+            "explain",
+            "save_run_fpath",
+            "emit_qmk_fpath",
+            "print_sparklines",
+        ]
+    )]
+    stream: bool,
+
     /// Format for printing.
     #[arg(long, default_value = "text", value_enum)]
     format: Format,
 
+    /// Print a separator after every N records, in the text format.
+    #[arg(long, value_name = "N", value_parser = validate_page_size)]
+    page_size: Option<usize>,
+
+    /// Wait for Enter before each separator printed by '--page-size'.
+    ///
+    /// Perky waits only if both stdout and stdin are terminals.
+    #[arg(long, requires = "page_size")]
+    page_pause: bool,
+
     /// Print metadata.
     ///
     /// If not specified, metadata is printed only when there is more than one permutation.
@@ -224,75 +736,625 @@ struct Cli {
     print_metadata: Option<bool>,
 
     /// Show detailed information for specific metrics.
-    #[arg(long, num_args = 1.., value_enum, value_name = "METRIC")]
-    print_details: Vec<Metric>,
+    #[arg(long, num_args = 1.., value_parser = Metrics::value_parser(), value_name = "METRIC")]
+    print_details: Vec<Metrics>,
+
+    /// Print at most N rows in each detail report.
+    #[arg(long = "details-top", value_name = "N")]
+    details_top_opt: Option<usize>,
+
+    /// Print only the rows of each detail report whose percentage within the metric is at least X.
+    #[arg(long = "details-min-perc", value_name = "X")]
+    details_min_perc_opt: Option<f64>,
+
+    /// Merge the rows of bigram detail reports regardless of direction, and of trigram detail
+    /// reports regardless of order.
+    #[arg(long)]
+    details_unordered: bool,
+
+    /// Show the rank of each record by specific metrics.
+    ///
+    /// Records are ranked after filtering, in the direction of each metric's goal. Records with
+    /// equal scores share a rank.
+    #[arg(long, num_args = 1.., value_parser = Metrics::value_parser(), value_name = "METRIC")]
+    print_ranks: Vec<Metrics>,
+
+    /// Print only the value of a field for each selected record, one per line.
+    ///
+    /// Any expression accepted by '--filter' may be used, such as a single metric (e.g. 'sfb').
+    #[arg(long, value_name = "FIELD")]
+    print_field: Option<String>,
+
+    /// Show the bigrams contributing most to the metric under each key table.
+    ///
+    /// The keys of the N highest-scoring bigrams are highlighted on a copy of the key table, and
+    /// each bigram is listed with an arrow showing its direction. Requires a bigram metric.
+    #[arg(long, value_name = "N")]
+    overlay: Option<usize>,
+
+    /// Explain the score of a metric for the first selected record.
+    ///
+    /// The n-grams contributing most to the metric (by '--weight') are listed with the
+    /// coordinates and digit of each of their keys and the effort of their fingering. The number of
+    /// n-grams listed is given by '--details-top' (10 by default).
+    #[arg(long, value_enum, value_name = "METRIC")]
+    explain: Option<Metric>,
+
+    /// Show the contribution of each key to a metric under each key table.
+    ///
+    /// Each key is shown with the sum of the scores (by '--weight') of the n-grams it is part of.
+    #[arg(long, value_enum, value_name = "METRIC")]
+    heatmap: Option<Metric>,
 
     /// Show summaries of metrics.
     #[arg(long, action = ArgAction::Set, default_value_t = true)]
     print_summaries: bool,
 
+    /// Print a report of the load on each digit and each hand.
+    ///
+    /// Each digit's load is shown with its finger weight and the ratio of its load to its weight.
+    #[arg(long, action = ArgAction::Set, default_value_t = false)]
+    print_report: bool,
+
     /// Print percentages.
     #[arg(long, action = ArgAction::Set, default_value_t = true)]
     print_perc: bool,
 
+    /// Print each summary sum per billion n-grams of its n-gram table.
+    #[arg(long, action = ArgAction::Set, default_value_t = false)]
+    print_per_billion: bool,
+
+    /// Print a sparkline of each metric across the selected records, after the records.
+    ///
+    /// Each character of a sparkline is the percentage of one record (in the order printed),
+    /// scaled from the metric's lowest percentage to its highest. Past 64 records, consecutive
+    /// records are bucketed, and each character is the mean of a bucket. Only for text.
+    #[arg(long, action = ArgAction::Set, default_value_t = false)]
+    print_sparklines: bool,
+
+    /// Families of values to print in detail rows, summary rows, and totals.
+    #[arg(long, value_enum, default_value_t = Columns::Both)]
+    columns: Columns,
+
+    /// Number of decimal places for percentages and durations in text.
+    #[arg(long = "decimals", value_name = "N")]
+    decimal_places_opt: Option<usize>,
+
+    /// Notation for counts in text.
+    #[arg(long, value_enum, default_value_t = Notation::Plain)]
+    notation: Notation,
+
+    /// Path to QMK keymap file to write.
+    ///
+    /// The key table of the first selected record is written as a QMK 'keymap.c' file.
+    /// The mapping to QMK positions is read from the 'qmk' field of the layout table file, if any.
+    #[arg(long = "emit-qmk", value_name = "FPATH")]
+    emit_qmk_fpath: Option<PathBuf>,
+
+    /// Path to run archive file to write.
+    ///
+    /// The metadata and the selected records (key tables and measurements) are written to a single
+    /// JSON file. Run 'perky show' to print it again, with any format, filters, or sorts, without
+    /// permuting.
+    #[arg(long = "save-run", value_name = "FPATH")]
+    save_run_fpath: Option<PathBuf>,
+
+    /// Path to manifest file to verify the inputs against.
+    ///
+    /// This must be a JSON file beginning with metadata, as printed by
+    /// '--format json --print-metadata true'. If the SHA-256 hash of any input file differs from the
+    /// one recorded in the manifest, Perky exits without permuting.
+    #[arg(long = "verify-manifest", value_name = "FPATH")]
+    verify_manifest_fpath: Option<PathBuf>,
+
+    /// Watch the layout table and key table files for changes.
+    ///
+    /// Whenever either file changes, it is reloaded, and the results are printed again.
+    #[arg(long)]
+    watch: bool,
+
     /// Specify when colours and text effects may be used.
     #[arg(long = "style", default_value_t = StylePolicy::Auto, value_enum, value_name = "STYLE")]
     style_policy: StylePolicy,
+
+    /// Palette used to colour keys by heat in the text format. [default: from the theme, or 'hue']
+    #[arg(long, value_enum, value_name = "PALETTE")]
+    palette: Option<Palette>,
+
+    /// What the colour of each key shows in the text format.
+    ///
+    /// 'frequency' is the frequency of the key's character, 'effort' is the effort-weighted
+    /// frequency of the key, and a unigram metric is the frequency of the key if it belongs to
+    /// that metric's digits (by '--weight').
+    #[arg(long, default_value = "frequency", value_enum, value_name = "HEAT")]
+    heat_metric: HeatMetric,
+
+    /// Path to theme file.
+    ///
+    /// This must be a valid JSON file in the theme format. It overrides the colours and text effects
+    /// used for each role in the text format.
+    #[arg(long = "theme", value_name = "FPATH")]
+    theme_fpath: Option<PathBuf>,
+
+    /// Increase the verbosity of messages written to stderr.
+    ///
+    /// Specify once to report loading times, fingering counts, and records dropped by filters.
+    /// Specify twice to also report each improvement of the best score while permuting.
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Print only the key tables of the selected records.
+    ///
+    /// Metadata and summaries are not printed, and all messages written to stderr (including
+    /// progress) are suppressed, except errors.
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Do not print the progress bar.
+    #[arg(long)]
+    no_progress: bool,
+
+    /// How to print progress while permuting.
+    #[arg(
+        long = "progress",
+        default_value = "bar",
+        value_enum,
+        value_name = "MODE"
+    )]
+    progress_mode: ProgressMode,
+}
+
+impl Cli {
+    fn comparison_fpaths(&self) -> Option<(&Path, &Path)> {
+        match &self.command {
+            Some(Command::Compare {
+                key_table_fpaths,
+                grid: false,
+                ..
+            }) => match key_table_fpaths.as_slice() {
+                [fpath1, fpath2] => Some((fpath1, fpath2)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn key_table_fpath(&self) -> PathBuf {
+        if let Some((fpath1, _)) = self.comparison_fpaths() {
+            return fpath1.to_path_buf();
+        }
+        self.key_table_fpaths
+            .first()
+            .cloned()
+            .or_else(|| self.import_fpath_opt().cloned())
+            .unwrap_or_else(|| PathBuf::from("default.kt.json"))
+    }
+
+    // NOTE Directories are replaced by the '.json' files they contain, in order of name.
+    fn expand_key_table_fpaths(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut expanded_fpaths = Vec::new();
+        for fpath in &self.key_table_fpaths {
+            if fpath.is_dir() {
+                expanded_fpaths.extend(read_key_table_fpaths(fpath, ".json")?);
+            } else {
+                expanded_fpaths.push(fpath.clone());
+            }
+        }
+        Ok(expanded_fpaths)
+    }
+
+    fn layout_table_fpath(&self) -> PathBuf {
+        self.layout_table_fpath
+            .clone()
+            .or_else(|| self.layout_import_fpath_opt().cloned())
+            .unwrap_or_else(|| PathBuf::from("default.lt.json"))
+    }
+
+    // NOTE The file the key table is imported from, if any (see '--kle', '--kla', '--xkb', and
+    // '--klc').
+    fn import_fpath_opt(&self) -> Option<&PathBuf> {
+        self.layout_import_fpath_opt()
+            .or(self.xkb_fpath.as_ref())
+            .or(self.klc_fpath.as_ref())
+    }
+
+    // NOTE Only some imported files also describe the layout table, which is used unless
+    // '--layout-table' is specified.
+    fn layout_import_fpath_opt(&self) -> Option<&PathBuf> {
+        self.kle_fpath.as_ref().or(self.kla_fpath.as_ref())
+    }
+
+    fn is_layout_table_imported(&self) -> bool {
+        self.layout_table_fpath.is_none() && self.layout_import_fpath_opt().is_some()
+    }
 }
 
 fn validate_tolerance(s: &str) -> Result<f64, String> {
-    const RANGE: RangeInclusive<f64> = 0.0..=1.0;
     s.parse::<f64>()
         .map_err(|_| format!("value must be a floating-point number, found '{}'", s))
         .and_then(|v| {
-            if RANGE.contains(&v) {
+            if v.is_finite() && v >= 0.0 {
                 Ok(v)
             } else {
                 Err(format!(
-                    "value must be a floating-point number between {} and {} (inclusive), found {}",
-                    RANGE.start(),
-                    RANGE.end(),
+                    "value must be a non-negative floating-point number, found {}",
                     v
                 ))
             }
         })
 }
 
-// Format
-
-#[derive(Clone, ValueEnum)]
-enum Format {
-    Json,
-    Text,
+// NOTE Such as '23' or '2,3'.
+fn parse_regions(s: &str) -> Result<[bool; 3], String> {
+    let mut regions = [false; 3];
+    for ch in s.chars() {
+        match ch {
+            '1'..='3' => regions[ch as usize - '1' as usize] = true,
+            ',' => (),
+            _ => Err(format!(
+                "value must be the digits 1 to 3, optionally separated by ',', found '{}'",
+                s
+            ))?,
+        }
+    }
+    if regions.iter().any(|&is_permuted| is_permuted) {
+        Ok(regions)
+    } else {
+        Err("value must name at least one region".to_string())
+    }
 }
 
-// Goal
-
-#[derive(Clone, ValueEnum)]
-enum Goal {
-    /// Maximize.
-    Max,
-    /// Minimize.
-    Min,
+// NOTE Splits a definition at the first '=', so the expression may contain '=='. The name must be
+// an identifier (as in an expression).
+fn validate_definition(s: &str) -> Result<(String, String), String> {
+    let (name, expression) = s
+        .split_once('=')
+        .ok_or_else(|| format!("value must be NAME=EXPRESSION, found '{}'", s))?;
+    let name = name.trim();
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+    if !is_identifier {
+        Err(format!(
+            "name must be letters, digits, and '_', not starting with a digit, found '{}'",
+            name
+        ))?;
+    }
+    Ok((name.to_string(), expression.to_string()))
 }
 
-impl From<&Goal> for goals::Goal {
-    fn from(value: &Goal) -> Self {
-        use Goal::*;
-        match value {
-            Max => Self::Max,
-            Min => Self::Min,
-        }
-    }
+// NOTE Such as '0,7,0,0' (top, right, bottom, left, as in CSS).
+fn validate_crop(s: &str) -> Result<[usize; 4], String> {
+    let values = s
+        .split(',')
+        .map(|part| part.trim().parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| {
+            format!(
+                "value must be four integers separated by ',', found '{}'",
+                s
+            )
+        })?;
+    values.try_into().map_err(|_| {
+        format!(
+            "value must be four integers separated by ',', found '{}'",
+            s
+        )
+    })
 }
 
-// Metric
+fn validate_page_size(s: &str) -> Result<usize, String> {
+    s.parse::<usize>()
+        .map_err(|_| format!("value must be an integer, found '{}'", s))
+        .and_then(|v| {
+            if v > 0 {
+                Ok(v)
+            } else {
+                Err("value must be a positive integer, found 0".to_string())
+            }
+        })
+}
 
-#[derive(Clone, ValueEnum)]
-enum Metric {
-    // Unigram metrics
-    Lt,
-    Li,
+fn validate_hours(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .map_err(|_| format!("value must be a floating-point number, found '{}'", s))
+        .and_then(|v| {
+            if v.is_finite() && v >= 0.0 {
+                Ok(v)
+            } else {
+                Err(format!(
+                    "value must be a non-negative floating-point number, found {}",
+                    v
+                ))
+            }
+        })
+}
+
+// Command
+
+#[derive(Clone, Subcommand)]
+enum Command {
+    /// Compare two key tables side by side, or any number of key tables in a grid.
+    ///
+    /// Options such as '--layout-table' must be given before 'compare'.
+    Compare {
+        /// Paths to the two key table files (or, with '--grid', key table files and directories).
+        #[arg(num_args = 1.., required = true, value_name = "FPATH")]
+        key_table_fpaths: Vec<PathBuf>,
+        /// Print a grid with one row per key table and one column per metric.
+        ///
+        /// Each key table is scored as with 'score-all' (each '.kt.json' file in a directory is
+        /// used), and rows are sorted by '--metric'. Each column is coloured from its worst value
+        /// (red) to its best (green).
+        #[arg(long)]
+        grid: bool,
+        /// Format of the grid.
+        #[arg(
+            long = "grid-format",
+            default_value = "text",
+            requires = "grid",
+            value_enum,
+            value_name = "FORMAT"
+        )]
+        grid_format: GridFormat,
+    },
+    /// Check layout table and key table files, and format them in place.
+    ///
+    /// Every problem found in a file is reported at once (as with '--strict'), and a file with any
+    /// problem is left unchanged. Otherwise, its fields are sorted by name and its table is
+    /// rewritten with the same layout that Perky uses when writing tables.
+    Fmt {
+        /// Paths to the table files.
+        #[arg(num_args = 1.., required = true, value_name = "FPATH")]
+        fpaths: Vec<PathBuf>,
+        /// The type of the table files.
+        ///
+        /// By default, it is inferred from each file name ('.lt.json' or '.kt.json').
+        #[arg(long, value_enum)]
+        table: Option<SchemaTable>,
+    },
+    /// Print a key table in another format, without scoring it.
+    ///
+    /// The key table is read as for scoring, from '--key-table', '--layout-string', '--preset',
+    /// '--kle', '--kla', '--xkb', or '--klc', which must be given before 'convert'.
+    Convert {
+        /// Format of the key table to print.
+        #[arg(long, default_value = "key-table", value_enum, value_name = "FORMAT")]
+        to: ConvertFormat,
+    },
+    /// Inspect the n-gram tables.
+    ///
+    /// Options such as '--bigram-table' and '--ignore-bigrams' must be given before 'ngrams'.
+    Ngrams {
+        #[command(subcommand)]
+        command: NgramsCommand,
+    },
+    /// List the built-in key tables.
+    Presets,
+    /// Score every key table ('.kt.json' file) in a directory, and print one row per file.
+    ///
+    /// Rows are sorted by '--metric' (best first, unless '--goal' is given). Options such as
+    /// '--metric' and '--weight' must be given before 'score-all'.
+    ScoreAll {
+        /// Path to the directory of key table files.
+        #[arg(value_name = "DPATH")]
+        dpath: PathBuf,
+    },
+    /// Print the records of a run archive (see '--save-run') without permuting.
+    ///
+    /// Options such as '--format', '--filter', '--sort-asc', and '--index' must be given before
+    /// 'show'.
+    Show {
+        /// Path to the run archive file.
+        #[arg(value_name = "FPATH")]
+        fpath: PathBuf,
+    },
+    /// Print a new key table matching the layout table, with a placeholder for each key.
+    ///
+    /// Each key of the layout table is given a placeholder of region 1, and every other cell is
+    /// null. Options such as '--layout-table' must be given before 'new-key-table'.
+    NewKeyTable,
+    /// Print a JSON Schema for a table file format.
+    Schema {
+        #[arg(value_enum)]
+        table: SchemaTable,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum NgramsCommand {
+    /// Print the most frequent n-grams of one order, with their counts and percentages.
+    ///
+    /// Each row has the n-gram, its count, the cumulative count, and both as percentages of the
+    /// sum of the whole table.
+    Top {
+        /// The number of n-grams to print.
+        #[arg(long = "n", value_name = "N", default_value_t = 20)]
+        n: usize,
+        /// The order of the n-grams (1 for unigrams, 2 for bigrams, 3 for trigrams).
+        #[arg(long, value_name = "ORDER", default_value_t = 2,
+              value_parser = clap::value_parser!(u8).range(1..=3))]
+        order: u8,
+    },
+}
+
+#[derive(Clone, ValueEnum)]
+enum ConvertFormat {
+    /// A key table file, with its layers.
+    KeyTable,
+    /// Keyboard-layout-editor raw data, without layers or legend colours.
+    Kle,
+}
+
+#[derive(Clone, ValueEnum)]
+enum SchemaTable {
+    /// Layout table files.
+    LayoutTable,
+    /// Key table files.
+    KeyTable,
+}
+
+// Preset
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Preset {
+    /// Colemak
+    Colemak,
+    /// Colemak Mod-DH
+    ColemakDh,
+    /// Dvorak
+    Dvorak,
+    /// QWERTY
+    Qwerty,
+    /// Workman
+    Workman,
+}
+
+impl Preset {
+    fn bytes(self) -> &'static [u8] {
+        use Preset::*;
+        match self {
+            Colemak => PRESET_COLEMAK,
+            ColemakDh => PRESET_COLEMAK_DH,
+            Dvorak => PRESET_DVORAK,
+            Qwerty => PRESET_QWERTY,
+            Workman => PRESET_WORKMAN,
+        }
+    }
+
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default()
+    }
+}
+
+// Columns
+
+#[derive(Clone, ValueEnum)]
+enum Columns {
+    /// Raw and effort-weighted values.
+    Both,
+    /// Only effort-weighted values.
+    Effort,
+    /// Only raw values.
+    Raw,
+}
+
+impl From<&Columns> for weights::Columns {
+    fn from(value: &Columns) -> Self {
+        use Columns::*;
+        match value {
+            Both => Self::Both,
+            Effort => Self::Effort,
+            Raw => Self::Raw,
+        }
+    }
+}
+
+// Format
+
+#[derive(Clone, ValueEnum)]
+enum Format {
+    Json,
+    /// Keyboard-layout-editor raw data, with legend colours showing the heat of the metric.
+    Kle,
+    Text,
+}
+
+// GridFormat
+
+#[derive(Clone, ValueEnum)]
+enum GridFormat {
+    /// Comma-separated values, without colours.
+    Csv,
+    /// An HTML document with a table.
+    Html,
+    Text,
+}
+
+// Goal
+
+#[derive(Clone, ValueEnum)]
+enum Goal {
+    /// Maximize.
+    Max,
+    /// Minimize.
+    Min,
+}
+
+// NOTE A goal without a metric applies to the selected metric, unless a goal is also given for that
+// metric.
+#[derive(Clone)]
+struct Goals {
+    goal_opt: Option<Goal>,
+    overrides: BTreeMap<metrics::Metric, goals::Goal>,
+}
+
+impl Goals {
+    fn goal_for(&self, metric: metrics::Metric) -> Option<goals::Goal> {
+        self.overrides
+            .get(&metric)
+            .copied()
+            .or_else(|| self.goal_opt.as_ref().map(goals::Goal::from))
+    }
+
+    fn metric_goals(&self) -> metrics::MetricGoals {
+        metrics::MetricGoals::new(self.overrides.clone())
+    }
+}
+
+fn parse_goals(s: &str) -> Result<Goals, String> {
+    let mut goals = Goals {
+        goal_opt: None,
+        overrides: BTreeMap::new(),
+    };
+    for item in s.split(',').map(str::trim) {
+        match item.split_once('=') {
+            None => goals.goal_opt = Some(Goal::from_str(item, true)?),
+            Some((metric, goal)) => {
+                let metric = Metric::from_str(metric.trim(), true)?;
+                let goal = Goal::from_str(goal.trim(), true)?;
+                goals
+                    .overrides
+                    .insert(metrics::Metric::from(&metric), goals::Goal::from(&goal));
+            }
+        }
+    }
+    Ok(goals)
+}
+
+impl From<&Goal> for goals::Goal {
+    fn from(value: &Goal) -> Self {
+        use Goal::*;
+        match value {
+            Max => Self::Max,
+            Min => Self::Min,
+        }
+    }
+}
+
+// MemoryLimitAction
+
+#[derive(Clone, PartialEq, ValueEnum)]
+enum MemoryLimitAction {
+    /// Stop with an error.
+    Refuse,
+    /// Reduce '--max-records' to fit, with a warning.
+    Truncate,
+}
+
+// Metric
+
+#[derive(Clone, ValueEnum)]
+enum Metric {
+    // Unigram metrics
+    Lt,
+    Li,
     Lm,
     Lr,
     Lp,
@@ -303,18 +1365,26 @@ enum Metric {
     Rr,
     Rp,
     Rh,
+    Pen,
     // Bigram metrics
     Fsb,
     Hsb,
     Irb,
     Lsb,
     Orb,
+    Rep,
     Sfb,
+    Sfd,
     // Trigram metrics
     Alt,
+    Asf,
     One,
+    Rdw,
     Red,
+    Rli,
+    Rlo,
     Rol,
+    Shr,
 }
 
 macro_rules! map_metrics {
@@ -340,9 +1410,49 @@ macro_rules! map_metrics {
 }
 
 map_metrics! {
-    Lt, Li, Lm, Lr, Lp, Lh, Rt, Ri, Rm, Rr, Rp, Rh => (Unigram, UnigramMetric),
-    Fsb, Hsb, Irb, Lsb, Orb, Sfb => (Bigram, BigramMetric),
-    Alt, One, Red, Rol => (Trigram, TrigramMetric)
+    Lt, Li, Lm, Lr, Lp, Lh, Rt, Ri, Rm, Rr, Rp, Rh, Pen => (Unigram, UnigramMetric),
+    Fsb, Hsb, Irb, Lsb, Orb, Rep, Sfb, Sfd => (Bigram, BigramMetric),
+    Alt, Asf, One, Rdw, Red, Rli, Rlo, Rol, Shr => (Trigram, TrigramMetric)
+}
+
+// Metrics
+
+// NOTE A metric, or a group of metrics (see 'metrics::MetricGroup'), given to an argument that
+// accepts a list of metrics.
+#[derive(Clone)]
+struct Metrics(Vec<metrics::Metric>);
+
+impl Metrics {
+    fn parse(s: &str) -> Result<Self, String> {
+        if let Some(metric_group) = metrics::MetricGroup::from_name(s) {
+            return Ok(Self(metric_group.metrics()));
+        }
+        let metric = Metric::from_str(s, true)?;
+        Ok(Self(vec![metrics::Metric::from(&metric)]))
+    }
+
+    fn value_parser() -> impl TypedValueParser<Value = Self> {
+        let possible_values = Metric::value_variants()
+            .iter()
+            .filter_map(ValueEnum::to_possible_value)
+            .chain(
+                metrics::MetricGroup::VARIANT_ARRAY
+                    .into_iter()
+                    .map(|group| PossibleValue::new(group.name()).help(group.description())),
+            );
+        PossibleValuesParser::new(possible_values).try_map(|s| Self::parse(&s))
+    }
+
+    // NOTE Metrics are kept in the order first given, without duplicates.
+    fn flatten(metrics: &[Self]) -> Vec<metrics::Metric> {
+        let mut result = Vec::new();
+        for metric in metrics.iter().flat_map(|metrics| &metrics.0) {
+            if !result.contains(metric) {
+                result.push(*metric);
+            }
+        }
+        result
+    }
 }
 
 // SortRule
@@ -363,16 +1473,89 @@ fn parse_sort_rules() -> Result<Vec<metrics::SortRule>, Box<dyn Error>> {
                 arguments = Box::new(iter::once(next_argument).chain(arguments));
                 break;
             }
-            let metric = metrics::Metric::from(&Metric::from_str(&next_argument, true)?);
-            result.push(metrics::SortRule {
-                metric,
-                sort_direction: sort_direction.clone(),
-            });
+            for metric in Metrics::parse(&next_argument)?.0 {
+                result.push(metrics::SortRule {
+                    metric,
+                    sort_direction,
+                });
+            }
+        }
+    }
+    Ok(result)
+}
+
+// Definition
+
+// NOTE Each definition is parsed with the variables defined so far, then its name is added to them
+// (with the type of its expression). A name cannot be defined twice, nor can a metric or other
+// variable be redefined.
+fn parse_definitions(
+    definitions: &[(String, String)],
+    variables: &mut Variables,
+) -> Result<Vec<Definition>, Box<dyn Error>> {
+    let mut result = Vec::with_capacity(definitions.len());
+    for (name, s) in definitions {
+        if variables.contains_key(name) {
+            Err(format!(
+                "Invalid --define argument: '{}' is already defined",
+                name
+            ))?;
         }
+        let (expression, ty) = Expression::parse_with_type(s, variables, &PRINT_FIELD_TYPES)
+            .map_err(|e| e.with_context("Invalid --define argument"))?;
+        variables.insert(name.clone(), ty);
+        result.push(Definition {
+            name: name.clone(),
+            expression,
+        });
     }
     Ok(result)
 }
 
+// NgramFormat
+
+#[derive(Clone, ValueEnum)]
+enum NgramFormat {
+    /// Genkey corpus JSON.
+    Genkey,
+    /// Oxeylyzer corpus JSON.
+    Oxeylyzer,
+    /// Tab-separated values.
+    Tsv,
+}
+
+impl From<&NgramFormat> for ngrams::NgramFormat {
+    fn from(value: &NgramFormat) -> Self {
+        use NgramFormat::*;
+        match value {
+            Genkey => Self::Genkey,
+            Oxeylyzer => Self::Oxeylyzer,
+            Tsv => Self::Tsv,
+        }
+    }
+}
+
+// NgramCase
+
+#[derive(Clone, ValueEnum)]
+enum NgramCase {
+    Lower,
+    /// Leave the n-grams unchanged.
+    Preserve,
+    Upper,
+}
+
+impl From<&NgramCase> for ngrams::NgramCase {
+    fn from(value: &NgramCase) -> Self {
+        use NgramCase::*;
+        match value {
+            Lower => Self::Lower,
+            Preserve => Self::Preserve,
+            Upper => Self::Upper,
+        }
+    }
+}
+
 // StylePolicy
 
 #[derive(Clone, ValueEnum)]
@@ -396,6 +1579,192 @@ impl From<&StylePolicy> for ui::styles::StylePolicy {
     }
 }
 
+// Hand
+
+#[derive(Clone, ValueEnum)]
+enum Hand {
+    /// The left hand.
+    Left,
+    /// The right hand.
+    Right,
+}
+
+impl From<&Hand> for Laterality {
+    fn from(value: &Hand) -> Self {
+        use Hand::*;
+        match value {
+            Left => Self::Left,
+            Right => Self::Right,
+        }
+    }
+}
+
+// HeatMetric
+
+#[derive(Clone, ValueEnum)]
+enum HeatMetric {
+    /// Frequency of the key, weighted by effort.
+    Effort,
+    /// Frequency of the key's character.
+    Frequency,
+    Lt,
+    Li,
+    Lm,
+    Lr,
+    Lp,
+    Lh,
+    Rt,
+    Ri,
+    Rm,
+    Rr,
+    Rp,
+    Rh,
+    Pen,
+}
+
+impl From<&HeatMetric> for metrics::HeatMetric {
+    fn from(value: &HeatMetric) -> Self {
+        use HeatMetric::*;
+        use metrics::UnigramMetric;
+        match value {
+            Effort => Self::Effort,
+            Frequency => Self::Frequency,
+            Lt => Self::Unigram(UnigramMetric::Lt),
+            Li => Self::Unigram(UnigramMetric::Li),
+            Lm => Self::Unigram(UnigramMetric::Lm),
+            Lr => Self::Unigram(UnigramMetric::Lr),
+            Lp => Self::Unigram(UnigramMetric::Lp),
+            Lh => Self::Unigram(UnigramMetric::Lh),
+            Rt => Self::Unigram(UnigramMetric::Rt),
+            Ri => Self::Unigram(UnigramMetric::Ri),
+            Rm => Self::Unigram(UnigramMetric::Rm),
+            Rr => Self::Unigram(UnigramMetric::Rr),
+            Rp => Self::Unigram(UnigramMetric::Rp),
+            Rh => Self::Unigram(UnigramMetric::Rh),
+            Pen => Self::Unigram(UnigramMetric::Pen),
+        }
+    }
+}
+
+// Palette
+
+#[derive(Clone, ValueEnum)]
+enum Palette {
+    /// Shades of the theme's hue (red by default).
+    Hue,
+    /// From dark purple through blue and green to yellow.
+    Viridis,
+    /// From blue through grey to orange.
+    BlueOrange,
+    /// From grey to white.
+    Grayscale,
+}
+
+impl From<&Palette> for colors::Palette {
+    fn from(value: &Palette) -> Self {
+        use Palette::*;
+        match value {
+            Hue => Self::Hue,
+            Viridis => Self::Viridis,
+            BlueOrange => Self::BlueOrange,
+            Grayscale => Self::Grayscale,
+        }
+    }
+}
+
+// Notation
+
+#[derive(Clone, ValueEnum)]
+enum Notation {
+    /// Engineering notation (such as 3.564e12) for counts of a million or more.
+    Engineering,
+    /// Thousands separators, following the numeric locale.
+    Grouped,
+    /// Digits only.
+    Plain,
+}
+
+impl From<&Notation> for format::Notation {
+    fn from(value: &Notation) -> Self {
+        use Notation::*;
+        match value {
+            Engineering => Self::Engineering,
+            Grouped => Self::Grouped(format::locale_thousands_separator()),
+            Plain => Self::Plain,
+        }
+    }
+}
+
+// PartialMode
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+enum PartialMode {
+    /// Leave the placeholders of regions that are not permuted in the key table, and score them.
+    Allow,
+    /// Leave the placeholders of regions that are not permuted in the key table, but treat them as
+    /// empty keys when scoring.
+    Mask,
+    /// Require characters for every region that has placeholders.
+    Error,
+}
+
+impl From<&PartialMode> for scoring::PartialMode {
+    fn from(value: &PartialMode) -> Self {
+        use PartialMode::*;
+        match value {
+            Allow => Self::Allow,
+            Mask => Self::Mask,
+            Error => Self::Error,
+        }
+    }
+}
+
+// ProgressMode
+
+#[derive(Clone, PartialEq, ValueEnum)]
+enum ProgressMode {
+    /// A single line with a progress bar.
+    Bar,
+    /// Several lines, redrawn in place, with the best score, threshold, and number of records.
+    Rich,
+}
+
+// ThumbMode
+
+#[derive(Clone, ValueEnum)]
+enum ThumbMode {
+    /// A thumb ends a sequence of keys, like a change of hand.
+    Break,
+    /// Ignore any trigram that includes a thumb.
+    Exclude,
+    /// Treat a thumb as part of its hand.
+    Hand,
+}
+
+impl From<&ThumbMode> for metrics::ThumbMode {
+    fn from(value: &ThumbMode) -> Self {
+        use ThumbMode::*;
+        match value {
+            Break => Self::Break,
+            Exclude => Self::Exclude,
+            Hand => Self::Hand,
+        }
+    }
+}
+
+// ToleranceMode
+
+#[derive(Clone, PartialEq, ValueEnum)]
+enum ToleranceMode {
+    /// Within a difference in score of the best score.
+    Absolute,
+    /// Within a percentage of the best score (e.g., 2 for within 2%).
+    Percent,
+    /// At least a ratio of the best score if maximizing, or at most the best score divided by it if
+    /// minimizing.
+    Ratio,
+}
+
 // Weight
 
 #[derive(Clone, ValueEnum)]
@@ -423,67 +1792,982 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let cli = Cli::parse();
 
-    // Argument parsing (files)
+    set_verbosity(Verbosity::from_flags(cli.verbose, cli.quiet));
 
-    let layout_table_fpath = cli
-        .layout_table_fpath
-        .unwrap_or_else(|| PathBuf::from("default.lt.json"));
+    let mut theme = match &cli.theme_fpath {
+        None => Theme::default(),
+        Some(fpath) => Theme::read_from_path(fpath)
+            .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?,
+    };
+    if let Some(palette) = &cli.palette {
+        theme.heat_palette = colors::Palette::from(palette);
+    }
+    set_theme(theme);
+
+    set_number_format(NumberFormat {
+        decimal_places_opt: cli.decimal_places_opt,
+        notation: format::Notation::from(&cli.notation),
+    });
+
+    if let Some(Command::Presets) = &cli.command {
+        let mut stdout = io::stdout().lock();
+        for preset in Preset::value_variants() {
+            if let Some(value) = preset.to_possible_value() {
+                let help = value
+                    .get_help()
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+                writeln!(stdout, "{:<12}{}", value.get_name(), help)?;
+            }
+        }
+        return Ok(());
+    }
 
-    let mut layout_table =
-        LayoutTable::<C, R>::read_from_path(&layout_table_fpath).map_err(|e| {
-            format!(
-                "Failed to load file '{}': {e}",
-                layout_table_fpath.display()
-            )
-        })?;
+    if let Some(Command::Schema { table }) = &cli.command {
+        let schema = match table {
+            SchemaTable::LayoutTable => LayoutTable::<LARGE_C, LARGE_R>::json_schema(),
+            SchemaTable::KeyTable => KeyTable::<LARGE_C, LARGE_R>::json_schema(),
+        };
+        let mut stdout = io::stdout().lock();
+        write_json(&mut stdout, &schema)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
 
-    let key_table_fpath = cli
-        .key_table_fpath
-        .unwrap_or_else(|| PathBuf::from("default.kt.json"));
+    if let Some(Command::Fmt { fpaths, table }) = &cli.command {
+        for fpath in fpaths {
+            let table = match (table, fpath.to_string_lossy()) {
+                (Some(table), _) => table.clone(),
+                (None, name) if name.ends_with(".lt.json") => SchemaTable::LayoutTable,
+                (None, name) if name.ends_with(".kt.json") => SchemaTable::KeyTable,
+                _ => Err(format!(
+                    "Cannot infer the type of table file '{}'; specify '--table'",
+                    fpath.display()
+                ))?,
+            };
+            format_table_file::<LARGE_C, LARGE_R>(fpath, &table, cli.allow_duplicates)
+                .map_err(|e| format!("Failed to format file '{}': {e}", fpath.display()))?;
+        }
+        return Ok(());
+    }
 
-    let key_table = KeyTable::read_from_path(&key_table_fpath)
-        .map_err(|e| format!("Failed to load file '{}': {e}", key_table_fpath.display()))?;
+    if let Some(Command::NewKeyTable) = &cli.command {
+        let layout_table_fpath = cli.layout_table_fpath();
+        let layout_table =
+            LayoutTable::<LARGE_C, LARGE_R>::read_from_path(&layout_table_fpath, cli.strict)
+                .map_err(|e| {
+                    format!(
+                        "Failed to load file '{}': {e}",
+                        layout_table_fpath.display()
+                    )
+                })?;
+        let key_table = KeyTable::from_layout_table(&layout_table);
+        let mut stdout = io::stdout().lock();
+        write_json_flatten_primitive_arrays::<2, _>(
+            &mut stdout,
+            &json!({ "data": Value::from(&key_table), "version": 1 }),
+            0,
+        )?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
 
-    let unigram_table_fpath_opt = cli.unigram_table_fpath;
-    let bigram_table_fpath_opt = cli.bigram_table_fpath;
-    let trigram_table_fpath_opt = cli.trigram_table_fpath;
+    if let Some(Command::Convert { to }) = &cli.command {
+        let import_key_tables_opt = match cli.import_fpath_opt() {
+            None => None,
+            Some(fpath) => {
+                let (_, key_table, key_layers) =
+                    read_imported_tables::<LARGE_C, LARGE_R>(&cli, fpath)
+                        .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+                Some((key_table, key_layers))
+            }
+        };
+        let (key_table, key_layers) = read_key_tables(&cli, import_key_tables_opt)?;
+        let mut stdout = io::stdout().lock();
+        match to {
+            ConvertFormat::KeyTable => {
+                let value = if key_layers.is_empty() {
+                    json!({ "data": Value::from(&key_table), "version": 1 })
+                } else {
+                    json!({
+                        "data": Value::from(&key_table),
+                        "layers": Value::from(&key_layers),
+                        "version": 1
+                    })
+                };
+                write_json_flatten_primitive_arrays::<2, _>(&mut stdout, &value, 0)?;
+                writeln!(stdout)?;
+            }
+            ConvertFormat::Kle => {
+                write_kle(
+                    &mut stdout,
+                    &kle_value_from_matrix(&key_table.to_byte_matrix(), None),
+                )?;
+            }
+        }
+        return Ok(());
+    }
 
-    let unigram_table = match &unigram_table_fpath_opt {
-        None => read_unigram_table_from_bytes(DEFAULT_1_GRAMS)?,
+    // Argument parsing (n-gram tables)
+
+    let ngram_format_opt = cli.ngram_format.as_ref().map(ngrams::NgramFormat::from);
+    let ngram_case_opt = cli.ngram_case.as_ref().map(ngrams::NgramCase::from);
+
+    let start = Instant::now();
+    let mut unigram_table = match &cli.unigram_table_fpath {
+        None => read_unigram_table_from_bytes(DEFAULT_1_GRAMS)?,
         Some(fname) => {
             let fpath = Path::new(fname);
-            read_unigram_table_from_path(fpath)
-                .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?
+            let (table, skipped) =
+                read_unigram_table_from_path(fpath, ngram_format_opt, ngram_case_opt)
+                    .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+            warn_skipped_ngrams(fpath, &skipped);
+            table
         }
     };
+    log_info!("Loaded unigram table in {:?}", start.elapsed());
 
-    let bigram_table = match &bigram_table_fpath_opt {
+    let start = Instant::now();
+    let mut bigram_table = match &cli.bigram_table_fpath {
         None => read_bigram_table_from_bytes(DEFAULT_2_GRAMS)?,
         Some(fname) => {
             let fpath = Path::new(fname);
-            read_bigram_table_from_path(fpath)
-                .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?
+            let (table, skipped) =
+                read_bigram_table_from_path(fpath, ngram_format_opt, ngram_case_opt)
+                    .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+            warn_skipped_ngrams(fpath, &skipped);
+            table
         }
     };
+    log_info!("Loaded bigram table in {:?}", start.elapsed());
 
-    let trigram_table = match &trigram_table_fpath_opt {
+    let start = Instant::now();
+    let mut trigram_table = match &cli.trigram_table_fpath {
         None => read_trigram_table_from_bytes(DEFAULT_3_GRAMS)?,
         Some(fname) => {
             let fpath = Path::new(fname);
-            read_trigram_table_from_path(fpath)
-                .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?
+            let (table, skipped) =
+                read_trigram_table_from_path(fpath, ngram_format_opt, ngram_case_opt)
+                    .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+            warn_skipped_ngrams(fpath, &skipped);
+            table
         }
     };
+    log_info!("Loaded trigram table in {:?}", start.elapsed());
+
+    if let Some(fpath) = &cli.ignore_ngrams_fpath {
+        let ngrams = read_ngram_list_from_path(fpath)
+            .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+        let n_zeroed = zero_ngram_tables(
+            &mut unigram_table,
+            &mut bigram_table,
+            &mut trigram_table,
+            &ngrams,
+        )?;
+        log_info!("Ignored {} of {} listed n-grams", n_zeroed, ngrams.len());
+    }
 
-    // Argument parsing (scoring)
+    if let Some(Command::Ngrams {
+        command: NgramsCommand::Top { n, order },
+    }) = &cli.command
+    {
+        return print_top_ngrams(
+            &cli,
+            (&unigram_table, &bigram_table, &trigram_table),
+            *order,
+            *n,
+        );
+    }
+
+    if let Some(Command::Show { fpath }) = &cli.command {
+        return if is_large_archive(fpath) {
+            show::<LARGE_C, LARGE_R>(&cli, fpath, &unigram_table)
+        } else {
+            show::<C, R>(&cli, fpath, &unigram_table)
+        };
+    }
+
+    if cli.watch {
+        watch(&cli, &unigram_table, &bigram_table, &trigram_table)
+    } else {
+        run_sized(&cli, &unigram_table, &bigram_table, &trigram_table)
+    }
+}
+
+// NOTE Whether a matrix has an entry outside of the default size. A table that cannot be read is
+// not large; the error is reported when it is read again at the default size.
+fn warn_skipped_ngrams(fpath: &Path, skipped: &ngrams::SkippedNgrams) {
+    // NOTE Only the characters from U+0000 to U+FFFF can be symbols (see 'symbols').
+    if let Some(first) = &skipped.first_opt
+        && is_enabled(Verbosity::Normal)
+    {
+        eprintln!(
+            "Warning: Skipped {} n-grams (total count {}) in file '{}' with characters that \
+             are not supported (only characters from U+0000 to U+FFFF are supported), such as \
+             '{}'",
+            skipped.count,
+            skipped.sum,
+            fpath.display(),
+            first.escape_debug()
+        );
+    }
+}
+
+fn is_large_matrix<T>(matrix: &[[T; LARGE_C]; LARGE_R], predicate: impl Fn(&T) -> bool) -> bool {
+    let (_, right, bottom, _) = crop_matrix(matrix, predicate);
+    LARGE_C - right > C || LARGE_R - bottom > R
+}
+
+// NOTE The crop is relative to the keys of the layout table (ignoring its empty edges, as when
+// printed), and removes keys from the layout table and the key tables alike, so placeholders outside
+// of it are not permuted.
+fn crop_tables<'a, const C: usize, const R: usize>(
+    crop: [usize; 4],
+    layout_table: &mut LayoutTable<C, R>,
+    key_tables: impl Iterator<Item = &'a mut KeyTable<C, R>>,
+) -> Result<(), Box<dyn Error>> {
+    let [top, right, bottom, left] = crop;
+    let (top0, right0, bottom0, left0) = crop_matrix(&layout_table.0, Option::is_some);
+    let rows = (top0 + top)..R.saturating_sub(bottom0 + bottom);
+    let columns = (left0 + left)..C.saturating_sub(right0 + right);
+    let is_kept = |r: usize, c: usize| rows.contains(&r) && columns.contains(&c);
+    layout_table.mask(|r, c, _digit| is_kept(r, c));
+    if layout_table.0.iter().flatten().all(Option::is_none) {
+        Err("'--crop' removes every key of the layout table")?;
+    }
+    for key_table in key_tables {
+        for (r, row) in key_table.0.0.iter_mut().enumerate() {
+            for (c, cell) in row.iter_mut().enumerate() {
+                if !is_kept(r, c) {
+                    *cell = None;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// NOTE The keys of the key tables are removed at the positions removed from the layout table, as
+// with '--crop'.
+fn retain_hand_tables<'a, const C: usize, const R: usize>(
+    laterality: Laterality,
+    layout_table: &mut LayoutTable<C, R>,
+    key_tables: impl Iterator<Item = &'a mut KeyTable<C, R>>,
+) -> Result<(), Box<dyn Error>> {
+    layout_table.retain_hand(laterality);
+    if layout_table.0.iter().flatten().all(Option::is_none) {
+        Err("'--hand' removes every key of the layout table")?;
+    }
+    for key_table in key_tables {
+        for (r, row) in key_table.0.0.iter_mut().enumerate() {
+            for (c, cell) in row.iter_mut().enumerate() {
+                if layout_table.0[r][c].is_none() {
+                    *cell = None;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_large_layout_table(cli: &Cli) -> bool {
+    let layout_table_opt = match cli.layout_import_fpath_opt() {
+        Some(fpath) if cli.layout_table_fpath.is_none() => {
+            read_imported_tables::<LARGE_C, LARGE_R>(cli, fpath)
+                .ok()
+                .and_then(|(layout_table_opt, _, _)| layout_table_opt)
+        }
+        _ => LayoutTable::<LARGE_C, LARGE_R>::read_from_path(&cli.layout_table_fpath(), false).ok(),
+    };
+    layout_table_opt.is_some_and(|layout_table| is_large_matrix(&layout_table.0, Option::is_some))
+}
+
+// NOTE XKB and KLC files have no layout table.
+fn read_imported_tables<const C: usize, const R: usize>(
+    cli: &Cli,
+    fpath: &Path,
+) -> Result<(Option<LayoutTable<C, R>>, KeyTable<C, R>, KeyLayers<C, R>), Box<dyn Error>> {
+    if cli.kla_fpath.is_some() {
+        let (layout_table, key_table, key_layers) = read_kla_from_path(fpath)?;
+        Ok((Some(layout_table), key_table, key_layers))
+    } else if cli.xkb_fpath.is_some() {
+        let (key_table, key_layers) = read_xkb_from_path(fpath, cli.xkb_variant.as_deref())?;
+        Ok((None, key_table, key_layers))
+    } else if cli.klc_fpath.is_some() {
+        let (key_table, key_layers) = read_klc_from_path(fpath)?;
+        Ok((None, key_table, key_layers))
+    } else {
+        let (layout_table, key_table) = read_kle_from_path(fpath)?;
+        Ok((Some(layout_table), key_table, KeyLayers::default()))
+    }
+}
+
+// NOTE The key table (and its layers) is read from the imported file, if any, or else from
+// '--layout-string', '--preset', or '--key-table', in that order.
+fn read_key_tables<const C: usize, const R: usize>(
+    cli: &Cli,
+    import_key_tables_opt: Option<(KeyTable<C, R>, KeyLayers<C, R>)>,
+) -> Result<(KeyTable<C, R>, KeyLayers<C, R>), Box<dyn Error>> {
+    let key_table_fpath = cli.key_table_fpath();
+    let layout_string_opt = cli.layout_string.as_deref();
+
+    let (key_table, key_layers) = match (import_key_tables_opt, layout_string_opt) {
+        (Some(key_tables), _) => key_tables,
+        (None, None) if let Some(preset) = cli.preset => (
+            KeyTable::read_from_bytes(preset.bytes(), cli.allow_duplicates)?,
+            KeyLayers::default(),
+        ),
+        (None, None) => {
+            let map_err = |e| format!("Failed to load file '{}': {e}", key_table_fpath.display());
+            (
+                KeyTable::read_from_path(&key_table_fpath, cli.strict, cli.allow_duplicates)
+                    .map_err(map_err)?,
+                KeyLayers::read_from_path(&key_table_fpath).map_err(map_err)?,
+            )
+        }
+        (None, Some(s)) => (
+            KeyTable::from_rows_str(s)
+                .map_err(|e| format!("Invalid --layout-string argument: {e}"))?,
+            KeyLayers::default(),
+        ),
+    };
+
+    if !cli.allow_duplicates && (cli.import_fpath_opt().is_some() || layout_string_opt.is_some()) {
+        key_table.check_duplicates()?;
+    }
+
+    Ok((key_table, key_layers))
+}
+
+fn is_large_archive(fpath: &Path) -> bool {
+    Archive::<LARGE_C, LARGE_R>::read_from_path(fpath).is_ok_and(|archive| {
+        archive
+            .records
+            .iter()
+            .any(|record| is_large_matrix(&record.key_table_matrix, |&byte| byte != 0))
+    })
+}
+
+fn run_sized(
+    cli: &Cli,
+    unigram_table: &UnigramTable,
+    bigram_table: &BigramTable,
+    trigram_table: &TrigramTable,
+) -> Result<(), Box<dyn Error>> {
+    if is_large_layout_table(cli) {
+        run::<LARGE_C, LARGE_R>(cli, unigram_table, bigram_table, trigram_table)
+    } else {
+        run::<C, R>(cli, unigram_table, bigram_table, trigram_table)
+    }
+}
+
+fn watch(
+    cli: &Cli,
+    unigram_table: &UnigramTable,
+    bigram_table: &BigramTable,
+    trigram_table: &TrigramTable,
+) -> Result<(), Box<dyn Error>> {
+    let mut fpaths = if cli.layout_string.is_some() || cli.preset.is_some() {
+        vec![cli.layout_table_fpath()]
+    } else {
+        let mut fpaths = vec![cli.layout_table_fpath()];
+        match cli.expand_key_table_fpaths()?.as_slice() {
+            [] => fpaths.push(cli.key_table_fpath()),
+            key_table_fpaths => fpaths.extend_from_slice(key_table_fpaths),
+        }
+        fpaths
+    };
+    if let Some((_, fpath2)) = cli.comparison_fpaths() {
+        fpaths.push(fpath2.to_path_buf());
+    }
+    fpaths.dedup();
+    let mut modification_times = read_modification_times(&fpaths);
+    loop {
+        if let Err(e) = run_sized(cli, unigram_table, bigram_table, trigram_table) {
+            eprintln!("Error: {e}");
+        }
+        if !is_enabled(Verbosity::Normal) {
+            modification_times = wait_for_modification(&fpaths, modification_times, WATCH_INTERVAL);
+            continue;
+        }
+        eprintln!(
+            "\nWatching {} for changes (press Ctrl-C to exit)...",
+            fpaths
+                .iter()
+                .map(|fpath| format!("'{}'", fpath.display()))
+                .collect::<Vec<_>>()
+                .join(" and ")
+        );
+        modification_times = wait_for_modification(&fpaths, modification_times, WATCH_INTERVAL);
+    }
+}
+
+fn read_key_table_fpaths(dpath: &Path, suffix: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut fpaths = fs::read_dir(dpath)
+        .map_err(|e| format!("Failed to read directory '{}': {e}", dpath.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    fpaths.retain(|fpath| {
+        fpath
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().ends_with(suffix))
+    });
+    fpaths.sort();
+    if fpaths.is_empty() {
+        Err(format!(
+            "Directory '{}' contains no key tables",
+            dpath.display()
+        ))?;
+    }
+    Ok(fpaths)
+}
+
+fn read_reference_key_tables<const C: usize, const R: usize>(
+    dpath: &Path,
+    allow_duplicates: bool,
+) -> Result<Vec<KeyTable<C, R>>, Box<dyn Error>> {
+    let mut fpaths = fs::read_dir(dpath)
+        .map_err(|e| format!("Failed to read directory '{}': {e}", dpath.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    fpaths.retain(|fpath| {
+        fpath
+            .extension()
+            .is_some_and(|extension| extension == "json")
+    });
+    fpaths.sort();
+    if fpaths.is_empty() {
+        Err(format!(
+            "Directory '{}' contains no reference key tables",
+            dpath.display()
+        ))?;
+    }
+    fpaths
+        .iter()
+        .map(|fpath| {
+            KeyTable::read_from_path(fpath, false, allow_duplicates)
+                .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()).into())
+        })
+        .collect()
+}
+
+fn run<const C: usize, const R: usize>(
+    cli: &Cli,
+    unigram_table: &UnigramTable,
+    bigram_table: &BigramTable,
+    trigram_table: &TrigramTable,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(Command::ScoreAll { dpath }) = &cli.command {
+        return score_all::<C, R>(cli, dpath, unigram_table, bigram_table, trigram_table);
+    }
+    if let Some(Command::Compare {
+        key_table_fpaths,
+        grid,
+        grid_format,
+    }) = &cli.command
+    {
+        if *grid {
+            let tables = (unigram_table, bigram_table, trigram_table);
+            return compare_grid::<C, R>(cli, key_table_fpaths, grid_format, tables);
+        }
+        if key_table_fpaths.len() != 2 {
+            Err("'compare' requires two key tables, unless '--grid' is specified")?;
+        }
+    }
+    let key_table_fpaths = cli.expand_key_table_fpaths()?;
+    if key_table_fpaths.len() <= 1 {
+        let cli = Cli {
+            key_table_fpaths,
+            ..cli.clone()
+        };
+        return run_key_table::<C, R>(&cli, unigram_table, bigram_table, trigram_table, None);
+    }
+    if cli.emit_qmk_fpath.is_some() {
+        Err("'--emit-qmk' cannot be used with more than one key table")?;
+    }
+    let mut runs = Vec::new();
+    for fpath in key_table_fpaths {
+        let cli = Cli {
+            key_table_fpaths: vec![fpath],
+            ..cli.clone()
+        };
+        run_key_table::<C, R>(
+            &cli,
+            unigram_table,
+            bigram_table,
+            trigram_table,
+            Some(&mut runs),
+        )?;
+    }
+    let (metadata_values, records): (Vec<_>, Vec<_>) = runs.into_iter().unzip();
+    present_records(
+        cli,
+        merge_metadata_values(&metadata_values),
+        records.into_iter().flatten().collect(),
+        unigram_table,
+        cli.save_run_fpath.as_deref(),
+    )
+}
+
+// NOTE Each key table is scored as if it were given alone (so it is permuted, if regions are
+// given), and only its best record is kept. The records are sorted by the selected metric.
+fn score_best_records<const C: usize, const R: usize>(
+    cli: &Cli,
+    fpaths: Vec<PathBuf>,
+    unigram_table: &UnigramTable,
+    bigram_table: &BigramTable,
+    trigram_table: &TrigramTable,
+) -> Result<Vec<Record<C, R>>, Box<dyn Error>> {
+    let metric = metrics::Metric::from(&cli.metric);
+    let weight = weights::Weight::from(cli.weight.as_ref().unwrap_or(&Weight::Raw));
+    let goal = cli
+        .goal
+        .as_ref()
+        .and_then(|goals| goals.goal_for(metric))
+        .unwrap_or(metric.goal());
+    let sort_rule = metrics::SortRule {
+        metric,
+        sort_direction: match goal {
+            goals::Goal::Max => metrics::SortDirection::Descending,
+            goals::Goal::Min => metrics::SortDirection::Ascending,
+        },
+    };
+    let mut records = Vec::new();
+    for fpath in fpaths {
+        let cli = Cli {
+            command: None,
+            key_table_fpaths: vec![fpath],
+            ..cli.clone()
+        };
+        let mut runs = Vec::new();
+        run_key_table::<C, R>(
+            &cli,
+            unigram_table,
+            bigram_table,
+            trigram_table,
+            Some(&mut runs),
+        )?;
+        let run_records = runs.into_iter().flat_map(|(_, records)| records).collect();
+        records.extend(
+            sort_records(run_records, &[sort_rule], weight)
+                .into_iter()
+                .take(1),
+        );
+    }
+    Ok(sort_records(records, &[sort_rule], weight))
+}
+
+fn score_all<const C: usize, const R: usize>(
+    cli: &Cli,
+    dpath: &Path,
+    unigram_table: &UnigramTable,
+    bigram_table: &BigramTable,
+    trigram_table: &TrigramTable,
+) -> Result<(), Box<dyn Error>> {
+    let weight = weights::Weight::from(cli.weight.as_ref().unwrap_or(&Weight::Raw));
+    let fpaths = read_key_table_fpaths(dpath, ".kt.json")?;
+    let records =
+        score_best_records::<C, R>(cli, fpaths, unigram_table, bigram_table, trigram_table)?;
+    let style_policy = ui::styles::StylePolicy::from(&cli.style_policy);
+    let mut stdout =
+        BufferedStandardStream::stdout(style_policy.color_choice(atty::Stream::Stdout));
+    match cli.format {
+        Format::Json => {
+            write_json_flatten_primitive_arrays::<2, _>(
+                &mut stdout,
+                &write_leaderboard_json(&records, weight),
+                0,
+            )?;
+            writeln!(stdout)?;
+        }
+        Format::Kle => Err("The 'kle' format cannot be used with 'score-all'")?,
+        Format::Text => write_leaderboard_text(&mut stdout, &records, weight)?,
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// NOTE The grid has its own formats, so '--format' is not used.
+fn compare_grid<const C: usize, const R: usize>(
+    cli: &Cli,
+    key_table_fpaths: &[PathBuf],
+    grid_format: &GridFormat,
+    (unigram_table, bigram_table, trigram_table): (&UnigramTable, &BigramTable, &TrigramTable),
+) -> Result<(), Box<dyn Error>> {
+    if !matches!(cli.format, Format::Text) {
+        Err("'--format' cannot be used with '--grid' (use '--grid-format' instead)")?;
+    }
+    let weight = weights::Weight::from(cli.weight.as_ref().unwrap_or(&Weight::Raw));
+    let mut fpaths = Vec::new();
+    for fpath in key_table_fpaths {
+        if fpath.is_dir() {
+            fpaths.extend(read_key_table_fpaths(fpath, ".kt.json")?);
+        } else {
+            fpaths.push(fpath.clone());
+        }
+    }
+    let records =
+        score_best_records::<C, R>(cli, fpaths, unigram_table, bigram_table, trigram_table)?;
+    let metric_goals = cli
+        .goal
+        .as_ref()
+        .map(Goals::metric_goals)
+        .unwrap_or_default();
+    let style_policy = ui::styles::StylePolicy::from(&cli.style_policy);
+    let mut stdout =
+        BufferedStandardStream::stdout(style_policy.color_choice(atty::Stream::Stdout));
+    match grid_format {
+        GridFormat::Csv => write_grid_csv(&mut stdout, &records, weight)?,
+        GridFormat::Html => write_grid_html(&mut stdout, &records, weight, &metric_goals)?,
+        GridFormat::Text => write_grid_text(&mut stdout, &records, weight, &metric_goals)?,
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn print_top_ngrams(
+    cli: &Cli,
+    (unigram_table, bigram_table, trigram_table): (&UnigramTable, &BigramTable, &TrigramTable),
+    order: u8,
+    n_top: usize,
+) -> Result<(), Box<dyn Error>> {
+    let style_policy = ui::styles::StylePolicy::from(&cli.style_policy);
+    let mut stdout =
+        BufferedStandardStream::stdout(style_policy.color_choice(atty::Stream::Stdout));
+    match (&cli.format, order) {
+        (Format::Kle, _) => Err("The 'kle' format cannot be used with 'ngrams'")?,
+        (Format::Json, 1) => {
+            let entries = top_ngrams(unigram_table, n_top, UnigramKey::from);
+            let value = write_top_ngrams_json(&entries, sum_ngram_table(unigram_table));
+            write_json_flatten_primitive_arrays::<2, _>(&mut stdout, &value, 0)?;
+            writeln!(stdout)?;
+        }
+        (Format::Json, 2) => {
+            let entries = top_ngrams(bigram_table, n_top, BigramKey::from);
+            let value = write_top_ngrams_json(&entries, sum_ngram_table(bigram_table));
+            write_json_flatten_primitive_arrays::<2, _>(&mut stdout, &value, 0)?;
+            writeln!(stdout)?;
+        }
+        (Format::Json, _) => {
+            let entries = top_ngrams(trigram_table, n_top, TrigramKey::from);
+            let value = write_top_ngrams_json(&entries, sum_ngram_table(trigram_table));
+            write_json_flatten_primitive_arrays::<2, _>(&mut stdout, &value, 0)?;
+            writeln!(stdout)?;
+        }
+        (Format::Text, 1) => {
+            let entries = top_ngrams(unigram_table, n_top, UnigramKey::from);
+            write_top_ngrams_text(&mut stdout, &entries, sum_ngram_table(unigram_table))?;
+        }
+        (Format::Text, 2) => {
+            let entries = top_ngrams(bigram_table, n_top, BigramKey::from);
+            write_top_ngrams_text(&mut stdout, &entries, sum_ngram_table(bigram_table))?;
+        }
+        (Format::Text, _) => {
+            let entries = top_ngrams(trigram_table, n_top, TrigramKey::from);
+            write_top_ngrams_text(&mut stdout, &entries, sum_ngram_table(trigram_table))?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// NOTE The file is parsed only if validation finds no problems, as with '--strict'. The file is
+// written only if it changes.
+fn format_table_file<const C: usize, const R: usize>(
+    fpath: &Path,
+    table: &SchemaTable,
+    allow_duplicates: bool,
+) -> Result<(), Box<dyn Error>> {
+    const EXPECTED_VERSION: u64 = 1;
+    let bytes = fs::read(fpath)?;
+    let mut object = read_envelope(bytes.as_slice(), EXPECTED_VERSION)?;
+    let validation = match table {
+        SchemaTable::LayoutTable => Validation {
+            optional_fields: &[
+                "extends",
+                "finger_weights",
+                "geometry",
+                "mask",
+                "penalties",
+                "qmk",
+            ],
+            max_rows: R,
+            max_columns: C,
+            unique_strings: false,
+        },
+        SchemaTable::KeyTable => Validation {
+            optional_fields: &["layers"],
+            max_rows: R,
+            max_columns: C,
+            unique_strings: !allow_duplicates,
+        },
+    };
+    validation.validate(&object)?;
+    match table {
+        // NOTE A layout table that extends another need not have 'data' (see 'extends').
+        SchemaTable::LayoutTable => {
+            if let Some(value) = object.get("finger_weights") {
+                FingerWeights::try_from_value(value)?;
+            }
+            if let Some(value) = object.get("penalties") {
+                Penalties::<C, R>::try_from_value(value)?;
+            }
+            if let Some(value) = object.get("data") {
+                let layout_table = LayoutTable::<C, R>::try_from(value)?;
+                if let Some(value) = object.get("geometry") {
+                    Geometry::try_from_value(value, &layout_table)?;
+                }
+                if let Some(value) = object.get("qmk") {
+                    QmkMapping::try_from_value(value, &layout_table)?;
+                }
+                object.insert("data".to_string(), Value::from(&layout_table));
+            } else if !object.contains_key("extends") {
+                Err("Expected 'data' field")?;
+            }
+        }
+        SchemaTable::KeyTable => {
+            let value = object.get("data").ok_or("Expected 'data' field")?;
+            let key_table = KeyTable::<C, R>::try_from_value(value, allow_duplicates)?;
+            if let Some(value) = object.get("layers") {
+                KeyLayers::try_from(value)?.fold_map(&key_table, allow_duplicates)?;
+            }
+            object.insert("data".to_string(), Value::from(&key_table));
+        }
+    }
+    object.sort_keys();
+    let mut formatted = Vec::with_capacity(bytes.len());
+    write_json_flatten_primitive_arrays::<2, _>(&mut formatted, &Value::Object(object), 0)?;
+    writeln!(formatted)?;
+    if formatted != bytes {
+        fs::write(fpath, formatted)?;
+    }
+    Ok(())
+}
+
+fn run_key_table<const C: usize, const R: usize>(
+    cli: &Cli,
+    unigram_table: &UnigramTable,
+    bigram_table: &BigramTable,
+    trigram_table: &TrigramTable,
+    runs_opt: Option<&mut Vec<(Value, Vec<Record<C, R>>)>>,
+) -> Result<(), Box<dyn Error>> {
+    // Argument parsing (files)
+
+    if cli.comparison_fpaths().is_some()
+        && (!cli.key_table_fpaths.is_empty()
+            || cli.layout_string.is_some()
+            || cli.import_fpath_opt().is_some()
+            || cli.preset.is_some())
+    {
+        Err(
+            "'compare' cannot be used with '--key-table', '--layout-string', '--kle', '--kla', \
+             '--xkb', '--klc', or '--preset'",
+        )?;
+    }
+
+    let (import_layout_table_opt, import_key_tables_opt) = match cli.import_fpath_opt() {
+        None => (None, None),
+        Some(fpath) => {
+            let (layout_table_opt, key_table, key_layers) =
+                read_imported_tables::<C, R>(cli, fpath)
+                    .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+            (layout_table_opt, Some((key_table, key_layers)))
+        }
+    };
+
+    let layout_table_fpath = cli.layout_table_fpath();
+
+    let start = Instant::now();
+
+    let mut layout_table = match import_layout_table_opt {
+        Some(layout_table) if cli.layout_table_fpath.is_none() => layout_table,
+        _ => LayoutTable::<C, R>::read_from_path(&layout_table_fpath, cli.strict).map_err(|e| {
+            format!(
+                "Failed to load file '{}': {e}",
+                layout_table_fpath.display()
+            )
+        })?,
+    };
+
+    let (geometry_opt, finger_weights, penalties_opt) = if cli.is_layout_table_imported() {
+        (None, FingerWeights::default(), None)
+    } else {
+        let map_err = |e| {
+            format!(
+                "Failed to load file '{}': {e}",
+                layout_table_fpath.display()
+            )
+        };
+        (
+            Geometry::read_from_path(&layout_table_fpath, &layout_table).map_err(map_err)?,
+            FingerWeights::read_from_path(&layout_table_fpath).map_err(map_err)?,
+            Penalties::read_from_path(&layout_table_fpath).map_err(map_err)?,
+        )
+    };
+
+    let key_table_fpath = cli.key_table_fpath();
+
+    let layout_string_opt = cli.layout_string.as_deref();
+
+    let unigram_table_fpath_opt = cli.unigram_table_fpath.as_deref();
+    let bigram_table_fpath_opt = cli.bigram_table_fpath.as_deref();
+    let trigram_table_fpath_opt = cli.trigram_table_fpath.as_deref();
+
+    let qmk_mapping_opt = match &cli.emit_qmk_fpath {
+        None => None,
+        Some(_) if cli.is_layout_table_imported() => {
+            Some(QmkMapping::from_layout_table(&layout_table))
+        }
+        Some(_) => Some(
+            QmkMapping::read_from_path(&layout_table_fpath, &layout_table).map_err(|e| {
+                format!(
+                    "Failed to load file '{}': {e}",
+                    layout_table_fpath.display()
+                )
+            })?,
+        ),
+    };
+
+    let preset_name_opt = cli.preset.map(Preset::name);
+
+    let (mut key_table, key_layers) = read_key_tables(cli, import_key_tables_opt)?;
+
+    log_info!("Loaded layout table and key table in {:?}", start.elapsed());
+
+    let mut comparison_key_table_opt = match cli.comparison_fpaths() {
+        None => None,
+        Some((_, fpath2)) => Some(
+            KeyTable::<C, R>::read_from_path(fpath2, cli.strict, cli.allow_duplicates)
+                .map_err(|e| format!("Failed to load file '{}': {e}", fpath2.display()))?,
+        ),
+    };
+
+    if let Some(crop) = cli.crop {
+        crop_tables(
+            crop,
+            &mut layout_table,
+            iter::once(&mut key_table).chain(comparison_key_table_opt.as_mut()),
+        )?;
+    }
+
+    if let Some(hand) = &cli.hand {
+        retain_hand_tables(
+            Laterality::from(hand),
+            &mut layout_table,
+            iter::once(&mut key_table).chain(comparison_key_table_opt.as_mut()),
+        )?;
+    }
+
+    // Argument parsing (manifest)
+
+    let hash_fpath = |fpath: &Path| {
+        sha256_hex_from_path(fpath)
+            .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))
+    };
 
-    let goal = goals::Goal::from(&cli.goal.unwrap_or(Goal::Min));
+    let input_hashes = InputHashes {
+        layout_table_opt: Some(hash_fpath(
+            match (cli.layout_import_fpath_opt(), &cli.layout_table_fpath) {
+                (Some(import_fpath), None) => import_fpath,
+                _ => &layout_table_fpath,
+            },
+        )?),
+        key_table_opt: match (cli.import_fpath_opt(), layout_string_opt, cli.preset) {
+            (Some(import_fpath), _, _) => Some(hash_fpath(import_fpath)?),
+            (None, None, None) => Some(hash_fpath(&key_table_fpath)?),
+            _ => None,
+        },
+        unigram_table_opt: unigram_table_fpath_opt.map(hash_fpath).transpose()?,
+        bigram_table_opt: bigram_table_fpath_opt.map(hash_fpath).transpose()?,
+        trigram_table_opt: trigram_table_fpath_opt.map(hash_fpath).transpose()?,
+    };
+
+    if let Some(fpath) = &cli.verify_manifest_fpath {
+        let manifest = InputHashes::read_manifest_from_path(fpath)
+            .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+        input_hashes.verify(&manifest)?;
+        log_info!("Verified inputs against manifest '{}'", fpath.display());
+    }
+
+    // Argument parsing (layers)
+
+    let fold_map_opt = (!key_layers.is_empty())
+        .then(|| key_layers.fold_map(&key_table, cli.allow_duplicates))
+        .transpose()
+        .map_err(|e| format!("Failed to load file '{}': {e}", key_table_fpath.display()))?;
+
+    let folded_ngram_tables_opt = match &fold_map_opt {
+        None => None,
+        Some(fold_map) => Some((
+            fold_ngram_table(unigram_table, fold_map)?,
+            fold_ngram_table(bigram_table, fold_map)?,
+            fold_ngram_table(trigram_table, fold_map)?,
+        )),
+    };
+
+    let (unigram_table, bigram_table, trigram_table) = match &folded_ngram_tables_opt {
+        None => (unigram_table, bigram_table, trigram_table),
+        Some((unigram_table, bigram_table, trigram_table)) => {
+            (unigram_table, bigram_table, trigram_table)
+        }
+    };
+
+    // Argument parsing (scoring)
 
     let metric = metrics::Metric::from(&cli.metric);
 
-    let tolerance = cli.tolerance;
+    let goal = cli
+        .goal
+        .as_ref()
+        .and_then(|goals| goals.goal_for(metric))
+        .unwrap_or(goals::Goal::Min);
+
+    const RATIO_RANGE: RangeInclusive<f64> = 0.0..=1.0;
 
-    let weight = weights::Weight::from(&cli.weight.unwrap_or(Weight::Raw));
+    let tolerance = match cli.tolerance_mode {
+        ToleranceMode::Absolute => {
+            let delta = cli.tolerance.unwrap_or(0.0);
+            if delta.fract() != 0.0 {
+                Err("Invalid --tolerance argument: An absolute tolerance must be a whole number")?;
+            }
+            goals::Tolerance::Absolute(delta as u64)
+        }
+        ToleranceMode::Percent => goals::Tolerance::Percent(cli.tolerance.unwrap_or(0.0)),
+        ToleranceMode::Ratio => {
+            let ratio = cli.tolerance.unwrap_or(1.0);
+            if !RATIO_RANGE.contains(&ratio) {
+                Err(format!(
+                    "Invalid --tolerance argument: A ratio must be between {} and {} (inclusive)",
+                    RATIO_RANGE.start(),
+                    RATIO_RANGE.end()
+                ))?;
+            }
+            goals::Tolerance::Ratio(ratio)
+        }
+    };
+
+    let weight = weights::Weight::from(cli.weight.as_ref().unwrap_or(&Weight::Raw));
+
+    let thumb_mode = metrics::ThumbMode::from(&cli.thumb_mode);
+
+    let heat_metric = metrics::HeatMetric::from(&cli.heat_metric);
+
+    let overlay_opt = match (cli.overlay, metric) {
+        (None, _) => None,
+        (Some(n), metrics::Metric::Bigram(bigram_metric)) => Some((bigram_metric, n)),
+        (Some(_), _) => Err("'--overlay' requires a bigram metric (see '--metric')")?,
+    };
+
+    let heatmap_metric_opt = cli.heatmap.as_ref().map(metrics::Metric::from);
+
+    let effort_expression_opt = cli
+        .effort_expression
+        .as_deref()
+        .map(|s| {
+            Expression::parse(s, &EffortModel::<C, R>::get_variables(), &[Type::Number])
+                .map_err(|e| e.with_context("Invalid --effort-expr argument"))
+        })
+        .transpose()?;
 
     // Argument parsing (permuting)
 
@@ -491,12 +2775,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         None => None,
         Some(s) => {
             let s = unescape::<true>(s).map_err(|e| format!("Invalid -1 argument: {}", e))?;
-            if !s.is_ascii() || s.chars().any(|ch| ('\x01'..='\x03').contains(&ch)) {
-                Err(
-                    "Invalid -1 argument: Characters must be ASCII, and the control characters SOH, STX, and ETX are reserved.",
-                )?;
+            if s.chars().any(|ch| ('\x01'..='\x03').contains(&ch)) {
+                Err("Invalid -1 argument: The control characters SOH, STX, and ETX are reserved.")?;
             }
-            Some(s.into_bytes())
+            Some(intern_str(&s).map_err(|e| format!("Invalid -1 argument: {}", e))?)
         }
     };
 
@@ -504,12 +2786,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         None => None,
         Some(s) => {
             let s = unescape::<true>(s).map_err(|e| format!("Invalid -2 argument: {}", e))?;
-            if !s.is_ascii() || s.chars().any(|ch| ('\x01'..='\x03').contains(&ch)) {
-                Err(
-                    "Invalid -2 argument: Characters must be ASCII, and the control characters SOH, STX, and ETX are reserved.",
-                )?;
+            if s.chars().any(|ch| ('\x01'..='\x03').contains(&ch)) {
+                Err("Invalid -2 argument: The control characters SOH, STX, and ETX are reserved.")?;
             }
-            Some(s.into_bytes())
+            Some(intern_str(&s).map_err(|e| format!("Invalid -2 argument: {}", e))?)
         }
     };
 
@@ -517,403 +2797,1845 @@ fn main() -> Result<(), Box<dyn Error>> {
         None => None,
         Some(s) => {
             let s = unescape::<true>(s).map_err(|e| format!("Invalid -3 argument: {}", e))?;
-            if !s.is_ascii() || s.chars().any(|ch| ('\x01'..='\x03').contains(&ch)) {
-                Err(
-                    "Invalid -3 argument: Characters must be ASCII, and the control characters SOH, STX, and ETX are reserved.",
-                )?;
+            if s.chars().any(|ch| ('\x01'..='\x03').contains(&ch)) {
+                Err("Invalid -3 argument: The control characters SOH, STX, and ETX are reserved.")?;
+            }
+            Some(intern_str(&s).map_err(|e| format!("Invalid -3 argument: {}", e))?)
+        }
+    };
+
+    if !cli.allow_duplicates {
+        key_table.check_regions([
+            region1_vec_opt.as_deref(),
+            region2_vec_opt.as_deref(),
+            region3_vec_opt.as_deref(),
+        ])?;
+    }
+
+    let (array1, length1) = convert_vec_opt_to_array::<256, _>(region1_vec_opt)?;
+    let (array2, length2) = convert_vec_opt_to_array::<256, _>(region2_vec_opt)?;
+    let (array3, length3) = convert_vec_opt_to_array::<256, _>(region3_vec_opt)?;
+
+    let mut coordinates1 = Vec::new();
+    let mut coordinates2 = Vec::new();
+    let mut coordinates3 = Vec::new();
+
+    for (r, row) in key_table.0.iter().enumerate() {
+        for (c, cell) in row.iter().enumerate() {
+            use Key::*;
+            match cell {
+                Some(One) => coordinates1.push((r, c)),
+                Some(Two) => coordinates2.push((r, c)),
+                Some(Three) => coordinates3.push((r, c)),
+                _ => (),
+            };
+        }
+    }
+
+    let partial_mode = scoring::PartialMode::from(&cli.partial_mode);
+
+    let len_1s = coordinates1.len();
+    if len_1s >= 1 && length1 != 0 && length1 != len_1s {
+        Err(format!(
+            "There are ({}) 1s in the key table, \
+                 but the length of '-1' is {}. \
+                 Provide a string for permutation of the same length via '-1'",
+            len_1s, length1
+        ))?
+    }
+
+    let len_2s = coordinates2.len();
+    if len_2s >= 1 && length2 != 0 && length2 != len_2s {
+        Err(format!(
+            "There are ({}) 2s in the key table, \
+                 but the length of '-2' is {}. \
+                 Provide a string for permutation of the same length via '-2'",
+            len_2s, length2
+        ))?
+    }
+
+    let len_3s = coordinates3.len();
+    if len_3s >= 1 && length3 != 0 && length3 != len_3s {
+        Err(format!(
+            "There are ({}) 3s in the key table, \
+                 but the length of '-3' is {}. \
+                 Provide a string for permutation of the same length via '-3'",
+            len_3s, length3
+        ))?
+    }
+
+    // NOTE The placeholders of a region that is not permuted are left in the key table (see
+    // 'scoring::PartialMode').
+    let unfilled_coordinates =
+        partial_mode.apply(&mut layout_table, &key_table, [length1, length2, length3])?;
+
+    if !unfilled_coordinates.is_empty() {
+        log_info!(
+            "{} placeholders are not permuted (partial mode: {:?})",
+            unfilled_coordinates.len(),
+            partial_mode
+        );
+    }
+
+    // NOTE A pinned sub-region is permuted independently of the rest of its region, so it is moved
+    // to a region that is otherwise unused (of which there must be one).
+    let mut regions = [
+        (array1, length1, coordinates1),
+        (array2, length2, coordinates2),
+        (array3, length3, coordinates3),
+    ];
+
+    // NOTE The region (as given) of each region (after pinning).
+    let mut region_sources = [0, 1, 2];
+
+    // NOTE Merged regions are permuted as one region, under the lowest of their numbers. Its
+    // characters are those of each region in turn, and its placeholders are in the order that they
+    // appear in the key table.
+    if let Some(merge_regions) = cli.merge_regions {
+        let merged = (0..3).filter(|&i| merge_regions[i]).collect::<Vec<_>>();
+        let [first, rest @ ..] = merged.as_slice() else {
+            unreachable!()
+        };
+        if rest.is_empty() {
+            Err("Invalid --merge-regions argument: At least two regions are required")?;
+        }
+        for &i in &merged {
+            if regions[i].1 == 0 {
+                Err(format!(
+                    "Invalid --merge-regions argument: Region {} has no characters to permute",
+                    i + 1
+                ))?;
+            }
+        }
+        let pins = [&cli.pin1, &cli.pin2, &cli.pin3];
+        for &i in rest {
+            if pins[i].is_some() {
+                Err(format!(
+                    "'--pin{}' cannot be used with a region merged into region {}",
+                    i + 1,
+                    first + 1
+                ))?;
+            }
+            let (array, length, coordinates) =
+                mem::replace(&mut regions[i], ([0; 256], 0, Vec::new()));
+            let (first_array, first_length, first_coordinates) = &mut regions[*first];
+            if *first_length + length > first_array.len() {
+                Err(format!(
+                    "Invalid --merge-regions argument: The merged region has more than {} \
+                     characters",
+                    first_array.len()
+                ))?;
+            }
+            first_array[*first_length..*first_length + length].copy_from_slice(&array[..length]);
+            *first_length += length;
+            first_coordinates.extend(coordinates);
+        }
+        regions[*first].2.sort_unstable();
+    }
+
+    for (i, pin_opt) in [&cli.pin1, &cli.pin2, &cli.pin3].into_iter().enumerate() {
+        let Some(s) = pin_opt else {
+            continue;
+        };
+        let map_err = |e: String| format!("Invalid --pin{} argument: {}", i + 1, e);
+        let s = unescape::<true>(s).map_err(|e| map_err(e.to_string()))?;
+        let pin = Pin::parse(&s).map_err(map_err)?;
+        let free = regions
+            .iter()
+            .position(|(_, length, coordinates)| *length == 0 && coordinates.is_empty())
+            .ok_or_else(|| map_err("All three regions are in use".into()))?;
+        regions[free] = split_region(&mut regions[i], &pin).map_err(map_err)?;
+        region_sources[free] = i;
+    }
+
+    let beam_search_opt = match cli.beam_width {
+        None => None,
+        Some(0) => Err("Invalid --beam-width argument: value must be at least 1")?,
+        Some(width) => Some(BeamSearch { width }),
+    };
+
+    let swap_search_opt = match (&cli.reference_fpath, cli.max_swaps) {
+        (Some(fpath), Some(max_swaps)) => Some((
+            KeyTable::<C, R>::read_from_path(fpath, cli.strict, cli.allow_duplicates)
+                .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?
+                .to_byte_matrix(),
+            SwapSearch { max_swaps },
+        )),
+        _ => None,
+    };
+
+    // NOTE A pinned sub-region is permuted by phase two if its region is.
+    let two_phase_search_opt = match (&cli.phase1_metric, cli.phase2_regions) {
+        (Some(phase1_metric), Some(phase2_regions)) => {
+            let phase1_metric = metrics::Metric::from(phase1_metric);
+            let phase2_regions = region_sources.map(|i| phase2_regions[i]);
+            if !regions
+                .iter()
+                .zip(phase2_regions)
+                .any(|((_, length, _), is_permuted)| is_permuted && *length > 0)
+            {
+                Err("Invalid --phase2-regions argument: No region has characters to permute")?;
+            }
+            Some((
+                phase1_metric,
+                TwoPhaseSearch {
+                    goal: cli
+                        .goal
+                        .as_ref()
+                        .and_then(|goals| goals.overrides.get(&phase1_metric).copied())
+                        .unwrap_or(goals::Goal::Min),
+                    top: cli.phase1_top,
+                    max_permutations_opt: cli.phase1_max_permutations,
+                    regions: phase2_regions,
+                },
+            ))
+        }
+        _ => None,
+    };
+
+    let [
+        (array1, length1, coordinates1),
+        (array2, length2, coordinates2),
+        (array3, length3, coordinates3),
+    ] = regions;
+
+    let key_table_matrix = key_table.to_byte_matrix();
+
+    // NOTE Each character a constraint refers to must be in the key table, or in a region.
+    let constraints = cli
+        .constraints
+        .iter()
+        .map(|s| {
+            let constraint =
+                Constraint::parse(s).map_err(|e| format!("Invalid --constraint argument: {e}"))?;
+            let is_known = |symbol: &Symbol| {
+                key_table_matrix.iter().flatten().any(|byte| byte == symbol)
+                    || array1[..length1].contains(symbol)
+                    || array2[..length2].contains(symbol)
+                    || array3[..length3].contains(symbol)
+            };
+            if let Some(&symbol) = constraint.symbols().iter().find(|symbol| !is_known(symbol)) {
+                Err(format!(
+                    "Invalid --constraint argument: Character '{}' is not in the key table or \
+                     in a region",
+                    resolve(symbol)
+                ))?
+            }
+            Ok(constraint)
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    // NOTE Each key a cap refers to must be in the layout table.
+    let key_usage_caps = cli
+        .key_usage_caps
+        .iter()
+        .map(|s| {
+            let key_usage_cap = KeyUsageCap::parse(s)
+                .map_err(|e| format!("Invalid --key-usage-cap argument: {e}"))?;
+            let (r, c) = key_usage_cap.position();
+            if layout_table
+                .0
+                .get(r)
+                .and_then(|row| row.get(c))
+                .is_none_or(Option::is_none)
+            {
+                Err(format!(
+                    "Invalid --key-usage-cap argument: There is no key at ({}, {}) in the layout \
+                     table",
+                    r, c
+                ))?
+            }
+            Ok(key_usage_cap)
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    let max_permutations_opt = cli.max_permutations;
+
+    let mut max_records_opt = Some(cli.max_records);
+
+    let tie_break = cli.tie_seed.map_or(TieBreak::Index, TieBreak::Seeded);
+
+    let parallelize = cli.parallelize;
+    let safe_scoring = cli.safe_scoring;
+
+    let sleep_ns = cli.sleep_ns;
+
+    let threads = cli.threads;
+
+    let max_estimated_hours_opt = cli.max_estimated_hours;
+
+    let force = cli.force;
+
+    // Argument parsing (sorting)
+
+    let sort_rules = parse_sort_rules()?;
+
+    let (
+        unigram_metrics_required_for_sorting,
+        bigram_metrics_required_for_sorting,
+        trigram_metrics_required_for_sorting,
+    ) = partition_sort_rules(&sort_rules);
+
+    // Argument parsing (filtering)
+
+    let mut filter_variables = metrics::Metric::get_variables();
+    filter_variables.extend(metrics::Metric::get_per_billion_variables());
+    filter_variables.extend(get_key_table_variables());
+    filter_variables.extend(get_meta_variables());
+    if cli.reference_layouts_dpath.is_some() {
+        filter_variables.extend(metrics::Metric::get_percentile_variables());
+    }
+
+    let definitions = parse_definitions(&cli.definitions, &mut filter_variables)?;
+
+    let filters = ExpressionSet::new(
+        cli.filters
+            .iter()
+            .map(|s| {
+                Expression::parse(s.as_str(), &filter_variables, &FILTER_TYPES)
+                    .map_err(|e| e.with_context("Invalid --filter argument"))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+
+    // Argument parsing (reference layouts)
+
+    let reference_key_table_matrices = match &cli.reference_layouts_dpath {
+        None => Vec::new(),
+        Some(dpath) => read_reference_key_tables::<C, R>(dpath, cli.allow_duplicates)?
+            .iter()
+            .map(KeyTable::to_byte_matrix)
+            .collect(),
+    };
+
+    // Argument parsing (selecting)
+
+    let max_selections_opt = cli.max_selections;
+
+    let index_opt = cli.index;
+
+    if cli.stream {
+        if index_opt.is_some_and(|index| index < 0) {
+            Err("'--stream' cannot be used with a negative '--index'")?;
+        }
+        if runs_opt.is_some() {
+            Err("'--stream' cannot be used with more than one key table")?;
+        }
+        if comparison_key_table_opt.is_some() {
+            Err("'--stream' cannot be used with 'compare'")?;
+        }
+    }
+
+    if cli.min_results.is_some() {
+        if runs_opt.is_some() {
+            Err("'--min-results' cannot be used with more than one key table")?;
+        }
+        if cli.tolerance_mode != ToleranceMode::Ratio {
+            Err("'--min-results' requires '--tolerance-mode ratio'")?;
+        }
+    }
+
+    // Argument parsing (printing)
+
+    let format = &cli.format;
+
+    let quiet = cli.quiet;
+
+    let print_metadata = if quiet {
+        Some(false)
+    } else {
+        cli.print_metadata
+    };
+
+    let print_summaries = cli.print_summaries && !quiet;
+
+    let print_field_opt = cli
+        .print_field
+        .as_deref()
+        .map(|s| {
+            Expression::parse(s, &filter_variables, &PRINT_FIELD_TYPES)
+                .map_err(|e| e.with_context("Invalid --print-field argument"))
+        })
+        .transpose()?;
+
+    let print_details = Metrics::flatten(&cli.print_details);
+
+    let print_ranks = Metrics::flatten(&cli.print_ranks);
+
+    let metric_goals = cli
+        .goal
+        .as_ref()
+        .map(Goals::metric_goals)
+        .unwrap_or_default();
+
+    let row_options = RowOptions {
+        print_perc: cli.print_perc,
+        print_per_billion: cli.print_per_billion,
+        columns: weights::Columns::from(&cli.columns),
+        detail_limit: DetailLimit {
+            top_opt: cli.details_top_opt,
+            min_perc_opt: cli.details_min_perc_opt,
+            weight,
+        },
+        goals: &metric_goals,
+    };
+
+    let style_policy = ui::styles::StylePolicy::from(&cli.style_policy);
+
+    // Permuting (setup)
+
+    let stderr = BufferedStandardStream::stderr(style_policy.color_choice(atty::Stream::Stderr));
+    let stderr = Arc::new(Mutex::new(stderr));
+    let mut stdout =
+        BufferedStandardStream::stdout(style_policy.color_choice(atty::Stream::Stdout));
+
+    // NOTE Characters on other layers are folded into the n-gram tables, so an n-gram is covered if
+    // each of its characters is in the key table or in a region. Empty cells and placeholders are
+    // never present.
+    let mut is_present = vec![false; 1 << 16];
+    for &symbol in key_table_matrix
+        .iter()
+        .flatten()
+        .chain(&array1[..length1])
+        .chain(&array2[..length2])
+        .chain(&array3[..length3])
+    {
+        is_present[symbol as usize] = symbol > 3;
+    }
+    let coverage = Coverage::new(unigram_table, bigram_table, trigram_table, &is_present);
+    if !coverage.is_complete() && is_enabled(Verbosity::Normal) {
+        let mut stderr = stderr.lock().unwrap();
+        write_coverage_text(&mut *stderr, &coverage)?;
+        stderr.flush()?;
+    }
+
+    layout_table.mask(|r, c, _digit| key_table.0[r][c].is_some());
+
+    let effort_model = EffortModel {
+        geometry_opt: geometry_opt.as_ref(),
+        finger_weights,
+        penalties_opt: penalties_opt.as_ref(),
+        expression_opt: effort_expression_opt.as_ref(),
+    };
+
+    let start = Instant::now();
+
+    let unigram_fingerings = layout_table.unigram_fingerings(&effort_model);
+    let bigram_fingerings =
+        layout_table.bigram_fingerings(&effort_model, cli.sfb_include_repeats)?;
+    let trigram_fingerings = layout_table.trigram_fingerings(&effort_model, thumb_mode)?;
+
+    log_info!(
+        "Generated {} unigram, {} bigram, and {} trigram fingerings in {:?}",
+        unigram_fingerings.get().len(),
+        bigram_fingerings.get().len(),
+        trigram_fingerings.get().len(),
+        start.elapsed()
+    );
+
+    // NOTE The unsafe summary indexes the key table without bounds checks, which is only sound
+    // because every offset of every fingering array is checked here, once.
+    unigram_fingerings.validate()?;
+    bigram_fingerings.validate()?;
+    trigram_fingerings.validate()?;
+
+    let summary_mode = if safe_scoring {
+        ScoreMode::SummarySafe
+    } else {
+        ScoreMode::SummaryUnsafe
+    };
+
+    let n_skipped_permutations = AtomicU64::new(0);
+
+    let unigram_table_sum = sum_ngram_table(unigram_table);
+
+    let unconstrained_score_fn = |metric: metrics::Metric, key_table_matrix: &[[Symbol; C]; R]| {
+        let (score, score_ew) = match metric {
+            metrics::Metric::Unigram(unigram_metric) => {
+                let (_, sum, sum_ew) = score_ufs(
+                    unigram_fingerings.get_by_metric(unigram_metric),
+                    unigram_fingerings.get_arrays_by_metric(unigram_metric),
+                    key_table_matrix,
+                    unigram_table,
+                    summary_mode,
+                );
+                (sum, sum_ew)
+            }
+            metrics::Metric::Bigram(bigram_metric) => {
+                let (_, sum, sum_ew) = score_bfs(
+                    bigram_fingerings.get_by_metric(bigram_metric),
+                    bigram_fingerings.get_arrays_by_metric(bigram_metric),
+                    key_table_matrix,
+                    bigram_table,
+                    summary_mode,
+                );
+                (sum, sum_ew)
+            }
+            metrics::Metric::Trigram(trigram_metric) => {
+                let (_, sum, sum_ew) = score_tfs(
+                    trigram_fingerings.get_by_metric(trigram_metric),
+                    trigram_fingerings.get_arrays_by_metric(trigram_metric),
+                    key_table_matrix,
+                    trigram_table,
+                    summary_mode,
+                );
+                (sum, sum_ew)
+            }
+        };
+        use weights::Weight::*;
+        match metric.weight_for(weight) {
+            Effort => score_ew,
+            Raw => score,
+        }
+    };
+
+    let score_fn = |metric: metrics::Metric, key_table_matrix: &[[Symbol; C]; R]| {
+        if !constraints
+            .iter()
+            .all(|constraint| constraint.is_satisfied(key_table_matrix, &layout_table))
+            || !key_usage_caps.iter().all(|key_usage_cap| {
+                key_usage_cap.is_satisfied(key_table_matrix, unigram_table, unigram_table_sum)
+            })
+        {
+            n_skipped_permutations.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(unconstrained_score_fn(metric, key_table_matrix))
+    };
+
+    let scoring_fn = |key_table_matrix: &[[Symbol; C]; R]| score_fn(metric, key_table_matrix);
+
+    // NOTE If the number of permutations does not fit in a u64, it is counted as 'u64::MAX' (and
+    // printed as more than that), as no more could be enumerated.
+    let possible_permutations_opt = checked_permutation_count(&[length1, length2, length3]);
+
+    if possible_permutations_opt.is_none() && is_enabled(Verbosity::Normal) {
+        eprintln!(
+            "Warning: The number of permutations ({}! * {}! * {}!) is more than {} and cannot be \
+             enumerated in full{}",
+            length1,
+            length2,
+            length3,
+            u64::MAX,
+            if max_permutations_opt.is_none() {
+                " (specify '--max-permutations' to limit it)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    let expected_permutations = match (&two_phase_search_opt, &beam_search_opt, &swap_search_opt) {
+        (None, None, None) => cmp::min(
+            max_permutations_opt.unwrap_or(u64::MAX),
+            possible_permutations_opt.unwrap_or(u64::MAX),
+        ),
+        (None, Some(beam_search), _) => {
+            beam_search.expected_permutations([length1, length2, length3])
+        }
+        (None, None, Some((_, swap_search))) => cmp::min(
+            max_permutations_opt.unwrap_or(u64::MAX),
+            swap_search.expected_permutations([length1, length2, length3]),
+        ),
+        (Some((_, two_phase_search)), _, _) => {
+            let phase2_lengths = [length1, length2, length3]
+                .into_iter()
+                .zip(two_phase_search.regions)
+                .map(|(length, is_permuted)| if is_permuted { length } else { 0 })
+                .collect::<Vec<_>>();
+            let [phase1_permutations, phase2_permutations] = two_phase_search
+                .expected_permutations(
+                    possible_permutations_opt,
+                    checked_permutation_count(&phase2_lengths),
+                    max_permutations_opt,
+                );
+            phase1_permutations.saturating_add(phase2_permutations)
+        }
+    };
+
+    let should_write_progress =
+        expected_permutations > 1 && is_enabled(Verbosity::Normal) && !cli.no_progress;
+
+    // Permuting (estimating)
+
+    let effective_threads = if !parallelize {
+        1
+    } else if threads >= 1 {
+        threads
+    } else {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    };
+
+    let estimated_duration_opt = if expected_permutations > ESTIMATE_SAMPLES {
+        estimate_duration_per_permutation(
+            &key_table_matrix,
+            (array1, length1, &coordinates1),
+            (array2, length2, &coordinates2),
+            (array3, length3, &coordinates3),
+            scoring_fn,
+            ESTIMATE_SAMPLES,
+        )
+        .map(|duration| {
+            (
+                duration,
+                duration.mul_f64(expected_permutations as f64 / effective_threads as f64),
+                effective_threads,
+            )
+        })
+    } else {
+        None
+    };
+
+    if let Some((duration_per_permutation, estimated_duration, effective_threads)) =
+        estimated_duration_opt
+    {
+        if is_enabled(Verbosity::Normal) {
+            let mut stderr = stderr.lock().unwrap();
+            write_estimate(
+                &mut *stderr,
+                expected_permutations,
+                duration_per_permutation,
+                estimated_duration,
+                effective_threads,
+                decimal_places_or(1),
+            )?;
+        }
+        if let Some(max_estimated_hours) = max_estimated_hours_opt
+            && estimated_duration.as_secs_f64() > max_estimated_hours * SECONDS_PER_HOUR
+            && !force
+        {
+            Err(format!(
+                "Estimated duration ({:.1} hours) exceeds the maximum ({} hours). \
+                 Specify '--force' to permute anyway",
+                estimated_duration.as_secs_f64() / SECONDS_PER_HOUR,
+                max_estimated_hours
+            ))?
+        }
+    }
+
+    // Permuting (memory)
+
+    // NOTE The estimate assumes that every metric is measured (see 'MemoryEstimate'). References
+    // are measured alongside the records, and when streaming, at most one chunk of records is
+    // measured at a time.
+    let mut n_detail_rows = [0; 3];
+    for &metric in &print_details {
+        match metric {
+            metrics::Metric::Unigram(metric) => {
+                n_detail_rows[0] += unigram_fingerings.get_by_metric(metric).len()
+            }
+            metrics::Metric::Bigram(metric) => {
+                n_detail_rows[1] += bigram_fingerings.get_by_metric(metric).len()
+            }
+            metrics::Metric::Trigram(metric) => {
+                n_detail_rows[2] += trigram_fingerings.get_by_metric(metric).len()
+            }
+        }
+    }
+
+    let memory_estimate =
+        MemoryEstimate::new::<C, R>(metrics::Metric::iter_all().count(), n_detail_rows);
+
+    let n_references = reference_key_table_matrices.len() as u64;
+
+    let n_measured_records_fn = |n_records: u64| {
+        let n_records = if cli.stream {
+            n_records.min(STREAM_CHUNK as u64)
+        } else {
+            n_records
+        };
+        n_records + n_references
+    };
+
+    if let Some(max_memory_mb) = cli.max_memory_mb {
+        let max_memory_bytes = max_memory_mb.saturating_mul(1 << 20);
+        let peak_bytes_fn = |max_records: u32| {
+            memory_estimate.peak_bytes(
+                max_records as u64 + 1,
+                effective_threads as u64,
+                n_measured_records_fn(max_records as u64),
+            )
+        };
+        let max_records = cli.max_records;
+        let peak_bytes = peak_bytes_fn(max_records);
+        if peak_bytes > max_memory_bytes {
+            if max_records <= 1 || peak_bytes_fn(1) > max_memory_bytes {
+                Err(format!(
+                    "Estimated peak memory ({}) exceeds the maximum ({} MiB), even for a single \
+                     record",
+                    format_bytes(peak_bytes_fn(max_records.min(1))),
+                    max_memory_mb
+                ))?
+            }
+            // NOTE The estimate grows with the number of records, so the largest number of
+            // records that fits is found by bisection ('low' fits and 'high' does not).
+            let (mut low, mut high) = (1, max_records);
+            while high - low > 1 {
+                let mid = low + (high - low) / 2;
+                if peak_bytes_fn(mid) <= max_memory_bytes {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            match cli.memory_limit_action {
+                MemoryLimitAction::Refuse => Err(format!(
+                    "Estimated peak memory ({}) exceeds the maximum ({} MiB). Specify \
+                     '--max-records {}' or fewer",
+                    format_bytes(peak_bytes),
+                    max_memory_mb,
+                    low
+                ))?,
+                MemoryLimitAction::Truncate => {
+                    if is_enabled(Verbosity::Normal) {
+                        eprintln!(
+                            "Warning: Estimated peak memory ({}) exceeds the maximum ({} MiB); \
+                             reducing '--max-records' from {} to {}",
+                            format_bytes(peak_bytes),
+                            max_memory_mb,
+                            max_records,
+                            low
+                        );
+                    }
+                    max_records_opt = Some(low);
+                }
+            }
+        }
+    }
+
+    // Permuting (main)
+
+    // NOTE Permutations skipped while estimating are not counted.
+    n_skipped_permutations.store(0, Ordering::Relaxed);
+
+    let start = Instant::now();
+
+    let stderr_clone = Arc::clone(&stderr);
+
+    let mut rate_window = RateWindow::new(PROGRESS_RATE_WINDOW);
+
+    let progress_mode = cli.progress_mode.clone();
+
+    let mut progress_rows = 0;
+
+    let progress_fn = throttle(
+        move |progress: PermutationProgress| {
+            if should_write_progress {
+                let elapsed = start.elapsed();
+                rate_window.push(elapsed, progress.n_permutations);
+                let mut stderr = stderr_clone.lock().unwrap();
+                match progress_mode {
+                    ProgressMode::Bar => write_progress(
+                        &mut *stderr,
+                        progress.n_permutations,
+                        Some(expected_permutations),
+                        Some(elapsed),
+                        true,
+                        rate_window.rate(),
+                        progress.best_score_opt,
+                        decimal_places_or(1),
+                        None,
+                        None,
+                    ),
+                    ProgressMode::Rich => write_progress_rich(
+                        &mut *stderr,
+                        progress_rows,
+                        &progress,
+                        expected_permutations,
+                        elapsed,
+                        rate_window.rate(),
+                        effective_threads,
+                    )
+                    .map(|rows| progress_rows = rows),
+                }
+                .ok();
+            }
+        },
+        Duration::from_millis(200),
+    );
+
+    let (total_permutations, permutations_truncated, mut records, records_truncated) =
+        match (&two_phase_search_opt, &beam_search_opt, &swap_search_opt) {
+            (None, None, None) => permute_and_substitute(
+                &key_table_matrix,
+                (array1, length1, &coordinates1),
+                (array2, length2, &coordinates2),
+                (array3, length3, &coordinates3),
+                progress_fn,
+                scoring_fn,
+                goal,
+                tolerance,
+                max_permutations_opt,
+                max_records_opt,
+                tie_break,
+                parallelize,
+                threads,
+                sleep_ns,
+            )?,
+            (None, Some(beam_search), _) => search::beam_search(
+                &key_table_matrix,
+                [
+                    (array1, length1, &coordinates1),
+                    (array2, length2, &coordinates2),
+                    (array3, length3, &coordinates3),
+                ],
+                beam_search,
+                &PermutationOptions {
+                    goal,
+                    tolerance,
+                    max_permutations_opt,
+                    max_records_opt,
+                    tie_break,
+                    parallelize,
+                    threads,
+                    sleep_ns,
+                },
+                progress_fn,
+                |key_table_matrix: &[[Symbol; C]; R]| {
+                    unconstrained_score_fn(metric, key_table_matrix)
+                },
+                scoring_fn,
+            )?,
+            (None, None, Some((reference_matrix, swap_search))) => search::swap_search(
+                &key_table_matrix,
+                [
+                    (array1, length1, &coordinates1),
+                    (array2, length2, &coordinates2),
+                    (array3, length3, &coordinates3),
+                ],
+                reference_matrix,
+                swap_search,
+                &PermutationOptions {
+                    goal,
+                    tolerance,
+                    max_permutations_opt,
+                    max_records_opt,
+                    tie_break,
+                    parallelize,
+                    threads,
+                    sleep_ns,
+                },
+                progress_fn,
+                scoring_fn,
+            )?,
+            (Some((phase1_metric, two_phase_search)), _, _) => search::search(
+                &key_table_matrix,
+                [
+                    (array1, length1, &coordinates1),
+                    (array2, length2, &coordinates2),
+                    (array3, length3, &coordinates3),
+                ],
+                two_phase_search,
+                &PermutationOptions {
+                    goal,
+                    tolerance,
+                    max_permutations_opt,
+                    max_records_opt,
+                    tie_break,
+                    parallelize,
+                    threads,
+                    sleep_ns,
+                },
+                progress_fn,
+                |key_table_matrix: &[[Symbol; C]; R]| score_fn(*phase1_metric, key_table_matrix),
+                scoring_fn,
+            )?,
+        };
+
+    let mut stderr = stderr.lock().unwrap();
+
+    // NOTE The rich status area already ends with a newline.
+    if should_write_progress && cli.progress_mode == ProgressMode::Bar {
+        writeln!(stderr)?;
+        stderr.flush()?;
+    }
+
+    let elapsed_duration = start.elapsed();
+
+    let run_quantities = RunQuantities {
+        total_permutations,
+        elapsed_duration,
+    };
+
+    // Permuting (teardown)
+
+    let total_skipped_permutations = n_skipped_permutations.load(Ordering::Relaxed);
+
+    if !constraints.is_empty() || !key_usage_caps.is_empty() {
+        log_info!(
+            "Skipped {} of {} permutations that violate a constraint or key usage cap",
+            total_skipped_permutations,
+            total_permutations
+        );
+    }
+
+    let total_records = records.len();
+
+    // Deduplicating
+
+    let mut seen = HashSet::new();
+    records.retain(|k| seen.insert(*k));
+    let total_unique_records = records.len();
+
+    // Measuring
+
+    let metric_heat_fn = |metric: metrics::Metric, key_table_matrix: &[[Symbol; C]; R]| {
+        let heat_matrix = match metric {
+            metrics::Metric::Unigram(unigram_metric) => heat_ufs(
+                unigram_fingerings.get_by_metric(unigram_metric),
+                key_table_matrix,
+                unigram_table,
+            ),
+            metrics::Metric::Bigram(bigram_metric) => heat_bfs(
+                bigram_fingerings.get_by_metric(bigram_metric),
+                key_table_matrix,
+                bigram_table,
+            ),
+            metrics::Metric::Trigram(trigram_metric) => heat_tfs(
+                trigram_fingerings.get_by_metric(trigram_metric),
+                key_table_matrix,
+                trigram_table,
+            ),
+        };
+        weigh_heat_matrix(&heat_matrix, metric.weight_for(weight))
+    };
+
+    let ngram_tables = NgramTables::new(unigram_table, bigram_table, trigram_table);
+    let [unigram_table_sum, bigram_table_sum, trigram_table_sum] = ngram_tables.sums();
+
+    // NOTE Only the metrics that are needed to sort, filter, rank, explain, or report on the records
+    // (or to print their details, overlays, or fields) are measured. Every metric is measured if
+    // summaries are printed, or if the records are saved, compared, or collected with the records
+    // of other key tables.
+    let mut plan = MeasurementPlan::only(&print_details);
+    plan.extend(
+        unigram_metrics_required_for_sorting
+            .into_iter()
+            .map(metrics::Metric::Unigram),
+    );
+    plan.extend(
+        bigram_metrics_required_for_sorting
+            .into_iter()
+            .map(metrics::Metric::Bigram),
+    );
+    plan.extend(
+        trigram_metrics_required_for_sorting
+            .into_iter()
+            .map(metrics::Metric::Trigram),
+    );
+    plan.extend(
+        filters
+            .expressions()
+            .iter()
+            .chain(&print_field_opt)
+            .chain(definitions.iter().map(|definition| &definition.expression))
+            .flat_map(Expression::collect_variables)
+            .filter_map(|variable| metrics::Metric::from_variable(&variable)),
+    );
+    plan.extend(print_ranks.iter().copied());
+    plan.extend(cli.explain.as_ref().map(metrics::Metric::from));
+    plan.extend(overlay_opt.map(|(bigram_metric, _)| metrics::Metric::Bigram(bigram_metric)));
+    if cli.print_report {
+        plan.extend(metrics::MetricGroup::AllUnigram.metrics());
+    }
+    let prints_summaries = print_summaries
+        && print_field_opt.is_none()
+        && cli.explain.is_none()
+        && !matches!(format, Format::Kle);
+    if prints_summaries
+        || cli.print_sparklines
+        || runs_opt.is_some()
+        || cli.save_run_fpath.is_some()
+        || comparison_key_table_opt.is_some()
+    {
+        plan.measure_all();
+    }
+
+    let (n_measured_metrics, n_metrics) = plan.count();
+    log_info!("Measuring {} of {} metrics", n_measured_metrics, n_metrics);
+
+    let measure_fn = |key_table_matrix: [[Symbol; C]; R]| {
+        let record = measure(
+            &unigram_fingerings,
+            &bigram_fingerings,
+            &trigram_fingerings,
+            &ngram_tables,
+            key_table_matrix,
+            &plan,
+            summary_mode,
+        );
+        let (bf_sum, bf_sum_ew) = (record.bf_sum, record.bf_sum_ew);
+        let utilization_opt = cli
+            .print_report
+            .then(|| Utilization::new(&record, &finger_weights, weight));
+        Record {
+            overlay_opt: overlay_opt.map(|(bigram_metric, n)| {
+                let mut bigrams = locate_bfs(
+                    bigram_fingerings.get_by_metric(bigram_metric),
+                    &key_table_matrix,
+                    bigram_table,
+                )
+                .into_iter()
+                .map(|(score, from, to)| {
+                    use weights::Weight::*;
+                    let (value, sum) =
+                        match metrics::Metric::Bigram(bigram_metric).weight_for(weight) {
+                            Effort => (score.value_ew, bf_sum_ew),
+                            Raw => (score.value, bf_sum),
+                        };
+                    OverlayBigram {
+                        from,
+                        to,
+                        value,
+                        perc_opt: calculate_perc(value, sum),
+                    }
+                })
+                .collect::<Vec<_>>();
+                bigrams.sort_by_key(|bigram| cmp::Reverse(bigram.value));
+                bigrams.truncate(n);
+                Overlay {
+                    metric: bigram_metric,
+                    bigrams,
+                }
+            }),
+            heatmap_opt: heatmap_metric_opt.map(|metric| Heatmap {
+                metric,
+                matrix: metric_heat_fn(metric, &key_table_matrix),
+            }),
+            utilization_opt,
+            ranks: Vec::new(),
+            cluster_size_opt: None,
+            ..record
+        }
+    };
+
+    let provenance_fn = |key_table_matrix: &[[Symbol; C]; R]| {
+        calculate_provenance(
+            key_table_matrix,
+            (array1, length1, &coordinates1),
+            (array2, length2, &coordinates2),
+            (array3, length3, &coordinates3),
+        )
+    };
+
+    let start = Instant::now();
+
+    // NOTE When streaming, only the key tables are kept here; their records are measured a chunk
+    // at a time as they are printed (see 'Streaming').
+    let (mut records, streamed_key_table_matrices) = if cli.stream {
+        (Vec::new(), records)
+    } else {
+        let records = map_in_parallel(records, measure_fn, parallelize, threads)?;
+        (records, Vec::new())
+    };
+
+    for record in &mut records {
+        record.provenance_opt = provenance_fn(&record.key_table_matrix);
+    }
+
+    let references = if reference_key_table_matrices.is_empty() {
+        Vec::new()
+    } else {
+        map_in_parallel(
+            reference_key_table_matrices,
+            measure_fn,
+            parallelize,
+            threads,
+        )?
+    };
+
+    if !references.is_empty() {
+        for record in &mut records {
+            record.calculate_percentiles(&references, weight, &metric_goals);
+        }
+    }
+
+    let comparison_record_opt =
+        comparison_key_table_opt.map(|key_table| measure_fn(key_table.to_byte_matrix()));
+
+    let measuring_duration = start.elapsed();
+
+    // Metadata
+
+    let hostname_opt = system::hostname();
+    let cpu_opt = system::cpu_model();
+
+    let mut metadata = Metadata {
+        version: env!("CARGO_PKG_VERSION"),
+        hostname_opt: hostname_opt.as_deref(),
+        cpu_opt: cpu_opt.as_deref(),
+        layout_table_fpath: &layout_table_fpath,
+        key_table_fpath_opt: (layout_string_opt.is_none() && preset_name_opt.is_none())
+            .then_some(key_table_fpath.as_path()),
+        preset_opt: preset_name_opt.as_deref(),
+        layout_string_opt,
+        unigram_table_fpath_opt,
+        bigram_table_fpath_opt,
+        trigram_table_fpath_opt,
+        input_hashes: &input_hashes,
+        unigram_table_sum,
+        bigram_table_sum,
+        trigram_table_sum,
+        coverage: &coverage,
+        goal,
+        metric,
+        tolerance,
+        weight,
+        max_permutations_opt,
+        max_records_opt,
+        tie_seed_opt: cli.tie_seed,
+        constraints: &constraints,
+        key_usage_caps: &key_usage_caps,
+        sort_rules: &sort_rules,
+        definitions: &definitions,
+        filters: filters.expressions(),
+        max_selections_opt,
+        index_opt,
+        parallelized: parallelize,
+        threads: effective_threads,
+        total_permutations,
+        total_skipped_permutations,
+        permutations_truncated,
+        total_records,
+        records_truncated,
+        sums_saturated: take_saturated(),
+        elapsed_duration,
+        measuring_duration,
+        estimated_peak_memory_bytes: memory_estimate.peak_bytes(
+            total_records as u64,
+            effective_threads as u64,
+            n_measured_records_fn(total_unique_records as u64),
+        ),
+        total_unique_records,
+        total_selected_records: 0,
+    };
+
+    // Collecting
+
+    // NOTE When permuting several key tables, the records of each are collected (with the metadata
+    // of its run) to be sorted, filtered, selected, and printed together.
+    if let Some(runs) = runs_opt {
+        for record in &mut records {
+            record.source_opt = Some(key_table_fpath.display().to_string());
+        }
+        runs.push((Value::from(&metadata), records));
+        return Ok(());
+    }
+
+    // Sorting
+
+    let records = sort_records(records, &sort_rules, weight);
+
+    // Filtering
+
+    // NOTE When streaming, the records are filtered and selected as they are printed.
+    let mut records = if cli.stream {
+        records
+    } else {
+        filter_records(records, &filters, &definitions, weight, &run_quantities)?
+    };
+
+    // NOTE If too few records survive filtering, the key table is permuted again with a lower
+    // tolerance, which retains more records (unless they were truncated, in which case it would
+    // retain no more of them).
+    if let Some(min_results) = cli.min_results
+        && records.len() < min_results
+    {
+        let n_records = records.len();
+        if records_truncated {
+            if is_enabled(Verbosity::Normal) {
+                eprintln!(
+                    "Warning: Only {} records survive filtering (at least {} required), but the \
+                     records were truncated, so a lower tolerance would not retain more of them \
+                     (specify a larger '--max-records')",
+                    n_records, min_results
+                );
+            }
+        } else if let goals::Tolerance::Ratio(ratio) = tolerance
+            && ratio > 0.0
+        {
+            let tolerance = (((ratio * TOLERANCE_STEPS).ceil() - 1.0) / TOLERANCE_STEPS).max(0.0);
+            if is_enabled(Verbosity::Normal) {
+                eprintln!(
+                    "Only {} records survive filtering (at least {} required); permuting again \
+                     with a tolerance of {}",
+                    n_records, min_results, tolerance
+                );
+            }
+            drop(stderr);
+            return run_key_table::<C, R>(
+                &Cli {
+                    tolerance: Some(tolerance),
+                    ..cli.clone()
+                },
+                unigram_table,
+                bigram_table,
+                trigram_table,
+                None,
+            );
+        } else if is_enabled(Verbosity::Normal) {
+            eprintln!(
+                "Warning: Only {} records survive filtering (at least {} required), even with a \
+                 tolerance of 0",
+                n_records, min_results
+            );
+        }
+    }
+
+    // Ranking
+
+    assign_ranks(&mut records, &print_ranks, weight, &metric_goals);
+
+    // Selecting
+
+    if let Some(max_distance) = cli.cluster {
+        records = cluster_records(records, max_distance);
+    }
+
+    if let (Some(n), Some(min_distance)) = (cli.diverse, cli.min_distance) {
+        records = select_diverse_records(records, n, min_distance);
+    }
+
+    let mut records = if cli.stream {
+        records
+    } else {
+        select_records(
+            records,
+            cli.every,
+            cli.head,
+            cli.tail,
+            max_selections_opt,
+            index_opt,
+        )?
+    };
+
+    // Emitting
+
+    if let (Some(fpath), Some(qmk_mapping)) = (&cli.emit_qmk_fpath, &qmk_mapping_opt) {
+        let record = records
+            .first()
+            .ok_or("No records were selected to write to the QMK keymap file")?;
+        let mut file = File::create(fpath)
+            .map_err(|e| format!("Failed to create file '{}': {e}", fpath.display()))?;
+        write_qmk_keymap(&mut file, &record.key_table_matrix, qmk_mapping)
+            .map_err(|e| format!("Failed to write file '{}': {e}", fpath.display()))?;
+    }
+
+    // Comparing
+
+    if let Some(record2) = &comparison_record_opt {
+        let record1 = records
+            .first()
+            .ok_or("No records were selected to compare")?;
+        match format {
+            Format::Json => {
+                write_json_flatten_primitive_arrays::<2, _>(
+                    &mut stdout,
+                    &write_comparison_json(record1, record2, weight),
+                    0,
+                )?;
+                writeln!(stdout)?;
+            }
+            Format::Kle => Err("The 'kle' format cannot be used with 'compare'")?,
+            Format::Text => {
+                writeln!(stdout)?;
+                write_comparison_text(&mut stdout, record1, record2, weight, &metric_goals)?;
+            }
+        }
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    // Explaining
+
+    if let Some(explain_metric) = cli.explain.as_ref().map(metrics::Metric::from) {
+        let record = records
+            .first()
+            .ok_or("No records were selected to explain")?;
+        let key_table_matrix = &record.key_table_matrix;
+        let mut contributions = match explain_metric {
+            metrics::Metric::Unigram(metric) => explain_ufs(
+                unigram_fingerings.get_by_metric(metric),
+                key_table_matrix,
+                unigram_table,
+            ),
+            metrics::Metric::Bigram(metric) => explain_bfs(
+                bigram_fingerings.get_by_metric(metric),
+                key_table_matrix,
+                bigram_table,
+            ),
+            metrics::Metric::Trigram(metric) => explain_tfs(
+                trigram_fingerings.get_by_metric(metric),
+                key_table_matrix,
+                trigram_table,
+            ),
+        };
+        let weight = explain_metric.weight_for(weight);
+        contributions
+            .sort_by_key(|contribution| cmp::Reverse(contribution.value_by_weight(weight)));
+        let total = contributions.len();
+        contributions.truncate(cli.details_top_opt.unwrap_or(EXPLAIN_TOP));
+        let explanation = Explanation {
+            metric: explain_metric,
+            weight,
+            sum: record.sum(explain_metric, weight).unwrap_or_default(),
+            perc_opt: record.perc(explain_metric, weight),
+            total,
+            contributions,
+        };
+        match format {
+            Format::Json => {
+                write_json_flatten_primitive_arrays::<2, _>(
+                    &mut stdout,
+                    &write_explanation_json(&explanation),
+                    0,
+                )?;
+                writeln!(stdout)?;
+            }
+            Format::Kle => Err("The 'kle' format cannot be used with '--explain'")?,
+            Format::Text => {
+                writeln!(stdout)?;
+                write_explanation_text(&mut stdout, &explanation, &metric_goals)?;
             }
-            Some(s.into_bytes())
         }
-    };
+        stdout.flush()?;
+        return Ok(());
+    }
 
-    let (array1, length1) = convert_vec_opt_to_array::<256, _>(region1_vec_opt)?;
-    let (array2, length2) = convert_vec_opt_to_array::<256, _>(region2_vec_opt)?;
-    let (array3, length3) = convert_vec_opt_to_array::<256, _>(region3_vec_opt)?;
+    let total_selected_records = records.len();
 
-    let mut coordinates1 = Vec::new();
-    let mut coordinates2 = Vec::new();
-    let mut coordinates3 = Vec::new();
+    metadata.total_selected_records = total_selected_records;
 
-    for (r, row) in key_table.0.iter().enumerate() {
-        for (c, cell) in row.iter().enumerate() {
-            use Key::*;
-            match cell {
-                Some(One) => coordinates1.push((r, c)),
-                Some(Two) => coordinates2.push((r, c)),
-                Some(Three) => coordinates3.push((r, c)),
-                _ => (),
-            };
-        }
+    let metadata_opt = print_metadata
+        .unwrap_or(total_permutations > 1)
+        .then_some(&metadata);
+
+    // Saving
+
+    if let Some(fpath) = &cli.save_run_fpath {
+        let mut file = File::create(fpath)
+            .map_err(|e| format!("Failed to create file '{}': {e}", fpath.display()))?;
+        write_archive(&mut file, Some(&Value::from(&metadata)), &records)
+            .map_err(|e| format!("Failed to write file '{}': {e}", fpath.display()))?;
     }
 
-    let len_1s = coordinates1.len();
-    if len_1s >= 1 {
-        if length1 == 0 {
-            if !PERMIT_PARTIAL_PERMUTATIONS {
-                Err(format!(
-                    "There are ({}) 1s in the key table. \
-                     Provide a string for permutation of the same length via '-1'",
-                    len_1s
-                ))?
-            }
-        } else if length1 != len_1s {
-            Err(format!(
-                "There are ({}) 1s in the key table, \
-                 but the length of '-1' is {}. \
-                 Provide a string for permutation of the same length via '-1'",
-                len_1s, length1
-            ))?
+    if print_field_opt.is_some() && matches!(format, Format::Kle) {
+        Err("The 'kle' format cannot be used with '--print-field'")?;
+    }
+
+    if matches!(format, Format::Kle) && total_selected_records > 1 {
+        Err(KLE_SINGLE_RECORD)?;
+    }
+
+    if let Some(print_field) = &print_field_opt
+        && !cli.stream
+    {
+        for record in &records {
+            let value = print_field.evaluate(&record.build_symbol_table(
+                weight,
+                &run_quantities,
+                &definitions,
+            )?)?;
+            writeln!(stdout, "{}", value)?;
         }
+        stdout.flush()?;
+        return Ok(());
     }
 
-    let len_2s = coordinates2.len();
-    if len_2s >= 1 {
-        if length2 == 0 {
-            if !PERMIT_PARTIAL_PERMUTATIONS {
-                Err(format!(
-                    "There are ({}) 2s in the key table. \
-                     Provide a string for permutation of the same length via '-2'",
-                    len_2s
-                ))?
-            }
-        } else if length2 != len_2s {
-            Err(format!(
-                "There are ({}) 2s in the key table, \
-                 but the length of '-2' is {}. \
-                 Provide a string for permutation of the same length via '-2'",
-                len_2s, length2
-            ))?
+    if cli.details_unordered {
+        for record in &mut records {
+            record.merge_unordered_details(weight);
         }
     }
 
-    let len_3s = coordinates3.len();
-    if len_3s >= 1 {
-        if length3 == 0 {
-            if !PERMIT_PARTIAL_PERMUTATIONS {
-                Err(format!(
-                    "There are ({}) 3s in the key table. \
-                     Provide a string for permutation of the same length via '-3'",
-                    len_3s
-                ))?
+    let unigram_table_max = unigram_table.entries().map(|(_, value)| value).max();
+    let unigram_table_normalized = |byte: Symbol| match unigram_table_max {
+        None | Some(0) => 0.0,
+        Some(max) => unigram_table.get([byte]) as f64 / max as f64,
+    };
+
+    let heat_fn = |key_table_matrix: &[[Symbol; C]; R]| {
+        use weights::Weight::*;
+        let (uf_slice, weight) = match heat_metric {
+            metrics::HeatMetric::Frequency => {
+                return key_table_matrix.map(|row| row.map(&unigram_table_normalized));
+            }
+            metrics::HeatMetric::Effort => (unigram_fingerings.get(), Effort),
+            metrics::HeatMetric::Unigram(unigram_metric) => {
+                (unigram_fingerings.get_by_metric(unigram_metric), weight)
+            }
+        };
+        let heat_matrix = heat_ufs(uf_slice, key_table_matrix, unigram_table);
+        normalize_heat_matrix(&weigh_heat_matrix(&heat_matrix, weight))
+    };
+
+    // Streaming
+
+    // NOTE Each chunk of records is measured (in parallel, as above), then each record is
+    // filtered, selected, and printed before the next chunk is measured. Measuring stops once no
+    // more records can be selected. Metadata is printed last, once the number of selected records
+    // is known.
+    if cli.stream {
+        let mut pager = Pager::new(cli.page_size, cli.page_pause, None);
+        let mut selection = StreamingSelection::new(
+            cli.every,
+            cli.head,
+            max_selections_opt,
+            index_opt.map(|index| index as usize),
+        )?;
+        let mut drops = vec![0; filters.len()];
+        let mut total_selected_records = 0;
+        let mut measuring_duration = metadata.measuring_duration;
+        for key_table_matrices in streamed_key_table_matrices.chunks(STREAM_CHUNK) {
+            if selection.is_done() {
+                break;
+            }
+            let start = Instant::now();
+            let records = map_in_parallel(
+                key_table_matrices.to_vec(),
+                measure_fn,
+                parallelize,
+                threads,
+            )?;
+            measuring_duration += start.elapsed();
+            for mut record in records {
+                record.provenance_opt = provenance_fn(&record.key_table_matrix);
+                if !references.is_empty() {
+                    record.calculate_percentiles(&references, weight, &metric_goals);
+                }
+                let Some(mut record) = filter_record(
+                    record,
+                    &filters,
+                    &definitions,
+                    weight,
+                    &run_quantities,
+                    &mut drops,
+                )?
+                else {
+                    continue;
+                };
+                if !selection.select() {
+                    continue;
+                }
+                if cli.details_unordered {
+                    record.merge_unordered_details(weight);
+                }
+                if let Some(print_field) = &print_field_opt {
+                    let value = print_field.evaluate(&record.build_symbol_table(
+                        weight,
+                        &run_quantities,
+                        &definitions,
+                    )?)?;
+                    writeln!(stdout, "{}", value)?;
+                } else {
+                    match format {
+                        Format::Json if quiet => {
+                            write_key_tables_json(&mut stdout, iter::once(record))?
+                        }
+                        Format::Text if quiet => write_key_tables_text(
+                            &mut stdout,
+                            iter::once(record),
+                            &heat_fn,
+                            &mut pager,
+                        )?,
+                        Format::Json => write_records_json(
+                            &mut stdout,
+                            iter::once(record),
+                            None,
+                            print_summaries,
+                            row_options,
+                        )?,
+                        Format::Kle if total_selected_records > 0 => Err(KLE_SINGLE_RECORD)?,
+                        Format::Kle => {
+                            let key_table_matrix = &record.key_table_matrix;
+                            let heat_matrix = metric_heat_fn(metric, key_table_matrix);
+                            write_kle(
+                                &mut stdout,
+                                &kle_value_from_matrix(
+                                    key_table_matrix,
+                                    Some(&normalize_heat_matrix(&heat_matrix)),
+                                ),
+                            )?
+                        }
+                        Format::Text => write_records_text(
+                            &mut stdout,
+                            iter::once(record),
+                            None,
+                            &heat_fn,
+                            print_summaries,
+                            row_options,
+                            &mut pager,
+                        )?,
+                    }
+                }
+                total_selected_records += 1;
+            }
+            stdout.flush()?;
+        }
+        log_filter_drops(&filters, &drops);
+        selection.finish()?;
+        metadata.measuring_duration = measuring_duration;
+        metadata.total_selected_records = total_selected_records;
+        if print_metadata.unwrap_or(total_permutations > 1) && print_field_opt.is_none() {
+            match format {
+                Format::Json => {
+                    write_json_flatten_primitive_arrays::<2, _>(
+                        &mut stdout,
+                        &Value::from(&metadata),
+                        0,
+                    )?;
+                    writeln!(stdout)?;
+                }
+                Format::Kle => {}
+                Format::Text => {
+                    writeln!(stdout)?;
+                    metadata.write_styled(&mut stdout)?;
+                }
             }
-        } else if length3 != len_3s {
-            Err(format!(
-                "There are ({}) 3s in the key table, \
-                 but the length of '-3' is {}. \
-                 Provide a string for permutation of the same length via '-3'",
-                len_3s, length3
-            ))?
         }
+        stdout.flush()?;
+        return Ok(());
     }
 
-    let max_permutations_opt = cli.max_permutations;
+    let sparklines = if cli.print_sparklines && !quiet {
+        Sparkline::collect(&records, weight)
+    } else {
+        Vec::new()
+    };
 
-    let max_records_opt = Some(cli.max_records);
+    let mut pager = Pager::new(cli.page_size, cli.page_pause, Some(total_selected_records));
 
-    let parallelize = cli.parallelize;
+    match format {
+        Format::Json if quiet => write_key_tables_json(&mut stdout, records.into_iter()),
+        Format::Text if quiet => {
+            write_key_tables_text(&mut stdout, records.into_iter(), &heat_fn, &mut pager)
+        }
+        Format::Json => {
+            if let Some(metadata) = metadata_opt {
+                write_json_flatten_primitive_arrays::<2, _>(
+                    &mut stdout,
+                    &Value::from(metadata),
+                    0,
+                )?;
+                writeln!(stdout)?;
+            }
+            write_records_json(
+                &mut stdout,
+                records.into_iter(),
+                Some(total_selected_records),
+                print_summaries,
+                row_options,
+            )
+        }
+        Format::Kle => {
+            for record in records {
+                let key_table_matrix = &record.key_table_matrix;
+                let heat_matrix = metric_heat_fn(metric, key_table_matrix);
+                write_kle(
+                    &mut stdout,
+                    &kle_value_from_matrix(
+                        key_table_matrix,
+                        Some(&normalize_heat_matrix(&heat_matrix)),
+                    ),
+                )?;
+            }
+            Ok(())
+        }
+        Format::Text => {
+            if let Some(metadata) = metadata_opt {
+                writeln!(stdout)?;
+                metadata.write_styled(&mut stdout)?;
+            }
+            write_records_text(
+                &mut stdout,
+                records.into_iter(),
+                (total_selected_records > 1).then_some(total_selected_records),
+                &heat_fn,
+                print_summaries,
+                row_options,
+                &mut pager,
+            )?;
+            write_sparklines_text(&mut stdout, &sparklines, &metric_goals)
+        }
+    }?;
 
-    let sleep_ns = cli.sleep_ns;
+    Ok(())
+}
 
-    let threads = cli.threads;
+fn show<const C: usize, const R: usize>(
+    cli: &Cli,
+    fpath: &Path,
+    unigram_table: &UnigramTable,
+) -> Result<(), Box<dyn Error>> {
+    if cli.stream {
+        Err("'--stream' cannot be used with 'show'")?;
+    }
+    let Archive {
+        metadata_opt,
+        records,
+    } = Archive::<C, R>::read_from_path(fpath)
+        .map_err(|e| format!("Failed to load file '{}': {e}", fpath.display()))?;
+    present_records(cli, metadata_opt, records, unigram_table, None)
+}
 
-    if threads >= 1 {
-        ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .map_err(|e| format!("Failed to initialize thread pool: {}", e))?;
+// NOTE Sorts, filters, selects, and prints records that were measured earlier (read from a run
+// archive, or collected from the runs over several key tables).
+fn present_records<const C: usize, const R: usize>(
+    cli: &Cli,
+    mut metadata_opt: Option<Value>,
+    mut records: Vec<Record<C, R>>,
+    unigram_table: &UnigramTable,
+    save_run_fpath_opt: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    if cli.explain.is_some() {
+        Err(
+            "'--explain' requires the fingerings of a single key table, so it cannot be used with \
+             'show' or with more than one key table",
+        )?;
     }
 
+    // Argument parsing (scoring)
+
+    let weight = weights::Weight::from(cli.weight.as_ref().unwrap_or(&Weight::Raw));
+
     // Argument parsing (sorting)
 
     let sort_rules = parse_sort_rules()?;
 
-    let (
-        _unigram_metrics_required_for_sorting,
-        _bigram_metrics_required_for_sorting,
-        _trigram_metrics_required_for_sorting,
-    ) = partition_sort_rules(&sort_rules);
-
     // Argument parsing (filtering)
 
-    let filters = cli
-        .filters
-        .into_iter()
-        .map(|s| Expression::parse(s.as_str(), &metrics::Metric::get_variables()))
-        .collect::<Result<Vec<_>, _>>()?;
-
-    // Argument parsing (selecting)
+    let mut filter_variables = metrics::Metric::get_variables();
+    filter_variables.extend(metrics::Metric::get_per_billion_variables());
+    filter_variables.extend(get_key_table_variables());
+    filter_variables.extend(get_meta_variables());
+    if records.iter().any(|record| !record.percentiles.is_empty()) {
+        filter_variables.extend(metrics::Metric::get_percentile_variables());
+    }
 
-    let max_selections_opt = cli.max_selections;
+    let definitions = parse_definitions(&cli.definitions, &mut filter_variables)?;
 
-    let index_opt = cli.index;
+    let filters = ExpressionSet::new(
+        cli.filters
+            .iter()
+            .map(|s| {
+                Expression::parse(s.as_str(), &filter_variables, &FILTER_TYPES)
+                    .map_err(|e| e.with_context("Invalid --filter argument"))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    );
 
     // Argument parsing (printing)
 
-    let format = cli.format;
+    let format = &cli.format;
 
-    let print_metadata = cli.print_metadata;
+    let quiet = cli.quiet;
 
-    let print_summaries = cli.print_summaries;
+    // NOTE As when permuting, metadata is printed by default only when there was more than one
+    // permutation.
+    let run_quantities = read_run_quantities(metadata_opt.as_ref());
 
-    let print_details = cli
-        .print_details
-        .iter()
-        .map(metrics::Metric::from)
-        .collect::<Vec<_>>();
+    let total_permutations = metadata_opt
+        .as_ref()
+        .and_then(|metadata| metadata.get("total_permutations"))
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
 
-    let print_perc = cli.print_perc;
+    let print_metadata = !quiet && cli.print_metadata.unwrap_or(total_permutations > 1);
 
-    let style_policy = ui::styles::StylePolicy::from(&cli.style_policy);
+    let print_summaries = cli.print_summaries && !quiet;
 
-    // Permuting (setup)
+    let print_field_opt = cli
+        .print_field
+        .as_deref()
+        .map(|s| {
+            Expression::parse(s, &filter_variables, &PRINT_FIELD_TYPES)
+                .map_err(|e| e.with_context("Invalid --print-field argument"))
+        })
+        .transpose()?;
 
-    let stderr = BufferedStandardStream::stderr(style_policy.color_choice());
-    let mut stdout = BufferedStandardStream::stdout(style_policy.color_choice());
+    let print_details = Metrics::flatten(&cli.print_details);
 
-    layout_table.mask(|r, c, _digit| key_table.0[r][c].is_some());
+    let print_ranks = Metrics::flatten(&cli.print_ranks);
 
-    let unigram_fingerings = layout_table.unigram_fingerings();
-    let bigram_fingerings = layout_table.bigram_fingerings();
-    let trigram_fingerings = layout_table.trigram_fingerings();
+    let metric_goals = cli
+        .goal
+        .as_ref()
+        .map(Goals::metric_goals)
+        .unwrap_or_default();
 
-    let scoring_fn = |key_table_matrix: &[[u8; C]; R]| {
-        let (score, score_ew) = match metric {
-            metrics::Metric::Unigram(unigram_metric) => score_ufs_without_details_unsafe(
-                unigram_fingerings.get_by_metric(unigram_metric),
-                key_table_matrix,
-                &unigram_table,
-            ),
-            metrics::Metric::Bigram(bigram_metric) => score_bfs_without_details_unsafe(
-                bigram_fingerings.get_by_metric(bigram_metric),
-                key_table_matrix,
-                &bigram_table,
-            ),
-            metrics::Metric::Trigram(trigram_metric) => score_tfs_without_details_unsafe(
-                trigram_fingerings.get_by_metric(trigram_metric),
-                key_table_matrix,
-                &trigram_table,
-            ),
-        };
-        use weights::Weight::*;
-        match weight {
-            Effort => score_ew,
-            Raw => score,
-        }
+    let row_options = RowOptions {
+        print_perc: cli.print_perc,
+        print_per_billion: cli.print_per_billion,
+        columns: weights::Columns::from(&cli.columns),
+        detail_limit: DetailLimit {
+            top_opt: cli.details_top_opt,
+            min_perc_opt: cli.details_min_perc_opt,
+            weight,
+        },
+        goals: &metric_goals,
     };
 
-    let key_table_matrix = key_table.to_byte_matrix();
-
-    let possible_permutations =
-        factorial(length1 as u64) * factorial(length2 as u64) * factorial(length3 as u64);
-
-    let expected_permutations = cmp::min(
-        max_permutations_opt.unwrap_or(u64::MAX),
-        possible_permutations,
-    );
-
-    // Permuting (main)
-
-    let start = Instant::now();
+    let style_policy = ui::styles::StylePolicy::from(&cli.style_policy);
 
-    let should_write_progress = expected_permutations > 1;
-    let stderr = Arc::new(Mutex::new(stderr));
-    let stderr_clone = Arc::clone(&stderr);
+    let mut stdout =
+        BufferedStandardStream::stdout(style_policy.color_choice(atty::Stream::Stdout));
 
-    let progress_fn = throttle(
-        move |i: u64| {
-            if should_write_progress {
-                let mut stderr = stderr_clone.lock().unwrap();
-                write_progress(
-                    &mut *stderr,
-                    i,
-                    Some(expected_permutations),
-                    Some(start.elapsed()),
-                    true,
-                    1,
-                    None,
-                    None,
-                )
-                .ok();
+    // NOTE Details are printed only for the metrics given to '--print-details', and only if they
+    // were measured (e.g., given to '--print-details' when the run was saved).
+    for record in &mut records {
+        for (&metric, measurement) in &mut record.unigram_measurements {
+            if !print_details.contains(&metrics::Metric::Unigram(metric)) {
+                measurement.details_opt = None;
             }
-        },
-        Duration::from_millis(200),
-    );
+        }
+        for (&metric, measurement) in &mut record.bigram_measurements {
+            if !print_details.contains(&metrics::Metric::Bigram(metric)) {
+                measurement.details_opt = None;
+            }
+        }
+        for (&metric, measurement) in &mut record.trigram_measurements {
+            if !print_details.contains(&metrics::Metric::Trigram(metric)) {
+                measurement.details_opt = None;
+            }
+        }
+    }
 
-    let (total_permutations, permutations_truncated, mut records, records_truncated) =
-        permute_and_substitute(
-            &key_table_matrix,
-            (array1, length1, &coordinates1),
-            (array2, length2, &coordinates2),
-            (array3, length3, &coordinates3),
-            progress_fn,
-            scoring_fn,
-            goal,
-            tolerance,
-            max_permutations_opt,
-            max_records_opt,
-            parallelize,
-            sleep_ns,
-        )?;
+    // Sorting
 
-    let mut stderr = stderr.lock().unwrap();
+    let records = sort_records(records, &sort_rules, weight);
 
-    if should_write_progress {
-        writeln!(stderr)?;
-        stderr.flush()?;
-    }
+    // Filtering
 
-    let elapsed_duration = start.elapsed();
+    let mut records = filter_records(records, &filters, &definitions, weight, &run_quantities)?;
 
-    // Permuting (teardown)
+    // Ranking
 
-    let total_records = records.len();
+    assign_ranks(&mut records, &print_ranks, weight, &metric_goals);
 
-    // Deduplicating
+    // Reporting
 
-    let mut seen = HashSet::new();
-    records.retain(|k| seen.insert(k.clone()));
-    let total_unique_records = records.len();
+    // NOTE Records read from a run archive have no report, so it is calculated here, using the
+    // finger weights of the layout table given to 'show'.
+    if cli.print_report
+        && records
+            .iter()
+            .any(|record| record.utilization_opt.is_none())
+    {
+        let layout_table_fpath = cli.layout_table_fpath();
+        let finger_weights = if cli.is_layout_table_imported() {
+            FingerWeights::default()
+        } else {
+            FingerWeights::read_from_path(&layout_table_fpath).map_err(|e| {
+                format!(
+                    "Failed to load file '{}': {e}",
+                    layout_table_fpath.display()
+                )
+            })?
+        };
+        for record in &mut records {
+            if record.utilization_opt.is_none() {
+                record.utilization_opt = Some(Utilization::new(record, &finger_weights, weight));
+            }
+        }
+    }
 
-    // Measuring
+    // Selecting
 
-    let mut records: Vec<_> = records
-        .into_iter()
-        .map(|key_table_matrix| {
-            let unigram_measurements = metrics::UnigramMetric::VARIANT_ARRAY
-                .iter()
-                .map(|&metric| {
-                    let fs = unigram_fingerings.get_by_metric(metric);
-                    let score_mode = if print_details.contains(&metrics::Metric::Unigram(metric)) {
-                        ScoreMode::Detailed
-                    } else {
-                        ScoreMode::SummaryUnsafe
-                    };
-                    let (details_opt, f_sum, f_sum_ew) =
-                        score_ufs(fs, &key_table_matrix, &unigram_table, score_mode);
-                    (metric, Measurement::new(details_opt, f_sum, f_sum_ew))
-                })
-                .collect::<BTreeMap<_, _>>();
+    if let Some(max_distance) = cli.cluster {
+        records = cluster_records(records, max_distance);
+    }
 
-            let bigram_measurements = metrics::BigramMetric::VARIANT_ARRAY
-                .iter()
-                .map(|&metric| {
-                    let fs = bigram_fingerings.get_by_metric(metric);
-                    let score_mode = if print_details.contains(&metrics::Metric::Bigram(metric)) {
-                        ScoreMode::Detailed
-                    } else {
-                        ScoreMode::SummaryUnsafe
-                    };
-                    let (details_opt, f_sum, f_sum_ew) =
-                        score_bfs(fs, &key_table_matrix, &bigram_table, score_mode);
-                    (metric, Measurement::new(details_opt, f_sum, f_sum_ew))
-                })
-                .collect::<BTreeMap<_, _>>();
+    if let (Some(n), Some(min_distance)) = (cli.diverse, cli.min_distance) {
+        records = select_diverse_records(records, n, min_distance);
+    }
 
-            let trigram_measurements = metrics::TrigramMetric::VARIANT_ARRAY
-                .iter()
-                .map(|&metric| {
-                    let fs = trigram_fingerings.get_by_metric(metric);
-                    let score_mode = if print_details.contains(&metrics::Metric::Trigram(metric)) {
-                        ScoreMode::Detailed
-                    } else {
-                        ScoreMode::SummaryUnsafe
-                    };
-                    let (details_opt, f_sum, f_sum_ew) =
-                        score_tfs(fs, &key_table_matrix, &trigram_table, score_mode);
-                    (metric, Measurement::new(details_opt, f_sum, f_sum_ew))
-                })
-                .collect::<BTreeMap<_, _>>();
+    let mut records = select_records(
+        records,
+        cli.every,
+        cli.head,
+        cli.tail,
+        cli.max_selections,
+        cli.index,
+    )?;
 
-            let (uf_sum, uf_sum_ew) = score_ufs_without_details_unsafe(
-                unigram_fingerings.get(),
-                &key_table_matrix,
-                &unigram_table,
-            );
+    // Printing
 
-            let (bf_sum, bf_sum_ew) = score_bfs_without_details_unsafe(
-                bigram_fingerings.get(),
-                &key_table_matrix,
-                &bigram_table,
-            );
+    let total_selected_records = records.len();
 
-            let (tf_sum, tf_sum_ew) = score_tfs_without_details_unsafe(
-                trigram_fingerings.get(),
-                &key_table_matrix,
-                &trigram_table,
-            );
+    if let Some(Value::Object(metadata)) = &mut metadata_opt {
+        metadata.insert(
+            "total_selected_records".into(),
+            Value::from(total_selected_records),
+        );
+    }
 
-            Record {
-                key_table_matrix,
-                unigram_measurements,
-                bigram_measurements,
-                trigram_measurements,
-                uf_sum,
-                uf_sum_ew,
-                bf_sum,
-                bf_sum_ew,
-                tf_sum,
-                tf_sum_ew,
-            }
-        })
-        .collect();
+    // Saving
 
-    // Sorting
+    if let Some(fpath) = save_run_fpath_opt {
+        let mut file = File::create(fpath)
+            .map_err(|e| format!("Failed to create file '{}': {e}", fpath.display()))?;
+        write_archive(&mut file, metadata_opt.as_ref(), &records)
+            .map_err(|e| format!("Failed to write file '{}': {e}", fpath.display()))?;
+    }
 
-    sort_records(&mut records, &sort_rules, weight);
+    if let Format::Kle = format
+        && total_selected_records > 1
+    {
+        Err(KLE_SINGLE_RECORD)?;
+    }
 
-    // Filtering
+    if let Some(print_field) = &print_field_opt {
+        if let Format::Kle = format {
+            Err("The 'kle' format cannot be used with '--print-field'")?;
+        }
+        for record in &records {
+            let value = print_field.evaluate(&record.build_symbol_table(
+                weight,
+                &run_quantities,
+                &definitions,
+            )?)?;
+            writeln!(stdout, "{}", value)?;
+        }
+        stdout.flush()?;
+        return Ok(());
+    }
 
-    let records = filter_records(records, &filters, weight)?;
+    if cli.details_unordered {
+        for record in &mut records {
+            record.merge_unordered_details(weight);
+        }
+    }
 
-    // Selecting
+    // NOTE The fingerings are not kept with the records, so keys are coloured only by the frequency
+    // of their characters.
+    let unigram_table_max = unigram_table.entries().map(|(_, value)| value).max();
+    let unigram_table_normalized = |byte: Symbol| match unigram_table_max {
+        None | Some(0) => 0.0,
+        Some(max) => unigram_table.get([byte]) as f64 / max as f64,
+    };
 
-    let records = select_records(records, max_selections_opt, index_opt)?;
+    let heat_fn = |key_table_matrix: &[[Symbol; C]; R]| {
+        key_table_matrix.map(|row| row.map(&unigram_table_normalized))
+    };
 
-    // Printing
+    let metadata_opt = metadata_opt.filter(|_| print_metadata);
 
-    let unigram_table_sum = sum_ngram_table(unigram_table.as_ref());
-    let bigram_table_sum = sum_ngram_table(bigram_table.as_ref());
-    let trigram_table_sum = sum_ngram_table(trigram_table.as_ref());
-    let total_selected_records = records.len();
+    let sparklines = if cli.print_sparklines && !quiet {
+        Sparkline::collect(&records, weight)
+    } else {
+        Vec::new()
+    };
 
-    let metadata_opt = print_metadata
-        .unwrap_or(total_permutations > 1)
-        .then(|| Metadata {
-            layout_table_fpath: &layout_table_fpath,
-            key_table_fpath: &key_table_fpath,
-            unigram_table_fpath_opt: unigram_table_fpath_opt.as_deref(),
-            bigram_table_fpath_opt: bigram_table_fpath_opt.as_deref(),
-            trigram_table_fpath_opt: trigram_table_fpath_opt.as_deref(),
-            unigram_table_sum,
-            bigram_table_sum,
-            trigram_table_sum,
-            goal,
-            metric,
-            tolerance,
-            weight,
-            max_permutations_opt,
-            max_records_opt,
-            sort_rules: &sort_rules,
-            filters: &filters,
-            max_selections_opt,
-            index_opt,
-            total_permutations,
-            permutations_truncated,
-            total_records,
-            records_truncated,
-            elapsed_duration,
-            total_unique_records,
-            total_selected_records,
-        });
+    let mut pager = Pager::new(cli.page_size, cli.page_pause, Some(total_selected_records));
 
     match format {
+        Format::Json if quiet => write_key_tables_json(&mut stdout, records.into_iter()),
+        Format::Text if quiet => {
+            write_key_tables_text(&mut stdout, records.into_iter(), &heat_fn, &mut pager)
+        }
         Format::Json => {
-            if let Some(metadata) = metadata_opt {
-                write_json_flatten_primitive_arrays::<2, _>(
-                    &mut stdout,
-                    &Value::from(&metadata),
-                    0,
-                )?;
+            if let Some(metadata) = &metadata_opt {
+                write_json_flatten_primitive_arrays::<2, _>(&mut stdout, metadata, 0)?;
                 writeln!(stdout)?;
             }
             write_records_json(
@@ -921,26 +4643,34 @@ fn main() -> Result<(), Box<dyn Error>> {
                 records.into_iter(),
                 Some(total_selected_records),
                 print_summaries,
-                print_perc,
+                row_options,
             )
         }
+        Format::Kle => {
+            for record in records {
+                let key_table_matrix = &record.key_table_matrix;
+                write_kle(
+                    &mut stdout,
+                    &kle_value_from_matrix(key_table_matrix, Some(&heat_fn(key_table_matrix))),
+                )?;
+            }
+            Ok(())
+        }
         Format::Text => {
-            if let Some(metadata) = metadata_opt {
+            if let Some(metadata) = &metadata_opt {
                 writeln!(stdout)?;
-                metadata.write_styled(&mut stdout)?;
+                write_metadata_value_text(&mut stdout, metadata)?;
             }
-            let unigram_table_normalized = match unigram_table.iter().copied().max() {
-                None | Some(0) => [0.0; 1 << 8],
-                Some(max) => array::from_fn(|i| unigram_table[i] as f64 / max as f64),
-            };
             write_records_text(
                 &mut stdout,
                 records.into_iter(),
-                (total_selected_records > 1).then(|| total_selected_records),
-                unigram_table_normalized,
+                (total_selected_records > 1).then_some(total_selected_records),
+                &heat_fn,
                 print_summaries,
-                print_perc,
-            )
+                row_options,
+                &mut pager,
+            )?;
+            write_sparklines_text(&mut stdout, &sparklines, &metric_goals)
         }
     }?;
 
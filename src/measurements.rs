@@ -1,5 +1,7 @@
 use core::cmp::Reverse;
 
+use std::collections::{BTreeMap, btree_map::Entry};
+
 use crate::{scores::Score, weights::Weight};
 
 pub struct Measurement<K> {
@@ -17,6 +19,17 @@ impl<K> Measurement<K> {
         }
     }
 
+    // NOTE The raw values are replaced by the effort-weighted values, so that the measurement is the
+    // same whichever weight is selected (see 'metrics::BigramMetric::is_weighted_by_effort').
+    pub fn weigh_by_effort(&mut self) {
+        self.sum = self.sum_ew;
+        if let Some(details) = self.details_opt.as_mut() {
+            for score in details {
+                score.value = score.value_ew;
+            }
+        }
+    }
+
     pub fn retain_non_zero_details(&mut self) {
         if let Some(details) = self.details_opt.as_mut() {
             details.retain(|score| !score.is_zero());
@@ -33,6 +46,32 @@ impl<K> Measurement<K> {
         }
     }
 
+    // NOTE Details whose keys fall in the same group are merged into the first of them (the
+    // highest-scoring, if the details are sorted), with the values summed. The merged details are
+    // sorted again.
+    pub fn merge_details_by<G: Ord>(&mut self, group_fn: impl Fn(&K) -> G, weight: Weight) {
+        let Some(details) = self.details_opt.take() else {
+            return;
+        };
+        let mut indices = BTreeMap::<G, usize>::new();
+        let mut merged: Vec<Score<K>> = Vec::with_capacity(details.len());
+        for score in details {
+            match indices.entry(group_fn(&score.key)) {
+                Entry::Occupied(entry) => {
+                    let first = &mut merged[*entry.get()];
+                    first.value += score.value;
+                    first.value_ew += score.value_ew;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(merged.len());
+                    merged.push(score);
+                }
+            }
+        }
+        self.details_opt = Some(merged);
+        self.sort_details(weight);
+    }
+
     pub fn sum_by_weight(&self, weight: Weight) -> u64 {
         use Weight::*;
         match weight {
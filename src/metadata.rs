@@ -1,47 +1,150 @@
 use core::{
+    error::Error,
     fmt::{self, Display},
     time::Duration,
 };
 
-use std::{fmt::Debug, io, path::Path};
+use std::{fmt::Debug, fs::File, io, path::Path};
 
-use serde_json::{Value, json};
+use serde_json::{Deserializer, Value, json};
 
 use termcolor::WriteColor;
 
 use crate::{
-    expressions::Expression,
-    goals::Goal,
+    constraints::{Constraint, KeyUsageCap},
+    expressions::{Definition, Expression},
+    goals::{Goal, Tolerance},
     metrics::{Metric, SortRule},
+    ngrams::Coverage,
+    records::RunQuantities,
     ui::styles::WriteStyled,
+    util::format::{decimal_places_or, format_bytes, format_count, format_perc},
     weights::Weight,
 };
 
+// NOTE SHA-256 hashes (as lowercase hexadecimal) of the files the inputs were read from. Inputs not
+// read from a file (such as a preset, a layout string, or the default n-gram tables) have no hash.
+#[derive(Debug, Default, PartialEq)]
+pub struct InputHashes {
+    pub layout_table_opt: Option<String>,
+    pub key_table_opt: Option<String>,
+    pub unigram_table_opt: Option<String>,
+    pub bigram_table_opt: Option<String>,
+    pub trigram_table_opt: Option<String>,
+}
+
+impl InputHashes {
+    const NAMES: [&str; 5] = [
+        "layout table",
+        "key table",
+        "unigram table",
+        "bigram table",
+        "trigram table",
+    ];
+
+    fn as_array(&self) -> [&Option<String>; 5] {
+        [
+            &self.layout_table_opt,
+            &self.key_table_opt,
+            &self.unigram_table_opt,
+            &self.bigram_table_opt,
+            &self.trigram_table_opt,
+        ]
+    }
+
+    pub fn try_from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let object = value
+            .as_object()
+            .ok_or("Expected the first JSON value to be an object")?;
+        let get = |field: &str| match object.get(field) {
+            None => Err(format!("Expected '{}' field", field)),
+            Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(format!(
+                "Value of '{}' field must be of type 'string' or 'null'",
+                field
+            )),
+        };
+        Ok(Self {
+            layout_table_opt: get("layout_table_sha256")?,
+            key_table_opt: get("key_table_sha256")?,
+            unigram_table_opt: get("unigram_table_sha256")?,
+            bigram_table_opt: get("bigram_table_sha256")?,
+            trigram_table_opt: get("trigram_table_sha256")?,
+        })
+    }
+
+    // NOTE A manifest is any JSON output of Perky that begins with metadata (i.e., as printed with
+    // '--format json --print-metadata true'). Only the first JSON value in the file is read.
+    pub fn read_manifest_from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let value = Deserializer::from_reader(file)
+            .into_iter::<Value>()
+            .next()
+            .ok_or("Expected a JSON value")??;
+        Self::try_from_value(&value)
+    }
+
+    pub fn verify(&self, manifest: &Self) -> Result<(), String> {
+        for (name, (hash_opt, expected_hash_opt)) in Self::NAMES
+            .iter()
+            .zip(self.as_array().into_iter().zip(manifest.as_array()))
+        {
+            if hash_opt != expected_hash_opt {
+                Err(format!(
+                    "The {} does not match the manifest (expected {}, found {})",
+                    name,
+                    format_debug_opt(expected_hash_opt.as_ref()),
+                    format_debug_opt(hash_opt.as_ref())
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct Metadata<'a> {
+    pub version: &'a str,
+    pub hostname_opt: Option<&'a str>,
+    pub cpu_opt: Option<&'a str>,
     pub layout_table_fpath: &'a Path,
-    pub key_table_fpath: &'a Path,
+    pub key_table_fpath_opt: Option<&'a Path>,
+    pub preset_opt: Option<&'a str>,
+    pub layout_string_opt: Option<&'a str>,
     pub unigram_table_fpath_opt: Option<&'a Path>,
     pub bigram_table_fpath_opt: Option<&'a Path>,
     pub trigram_table_fpath_opt: Option<&'a Path>,
+    pub input_hashes: &'a InputHashes,
     pub unigram_table_sum: u64,
     pub bigram_table_sum: u64,
     pub trigram_table_sum: u64,
+    pub coverage: &'a Coverage,
     pub goal: Goal,
     pub metric: Metric,
-    pub tolerance: f64,
+    pub tolerance: Tolerance,
     pub weight: Weight,
     pub max_permutations_opt: Option<u64>,
     pub max_records_opt: Option<u32>,
+    pub tie_seed_opt: Option<u64>,
+    pub constraints: &'a [Constraint],
+    pub key_usage_caps: &'a [KeyUsageCap],
     pub sort_rules: &'a [SortRule],
+    pub definitions: &'a [Definition],
     pub filters: &'a [Expression],
     pub max_selections_opt: Option<usize>,
     pub index_opt: Option<isize>,
+    pub parallelized: bool,
+    pub threads: usize,
     pub total_permutations: u64,
+    pub total_skipped_permutations: u64,
     pub permutations_truncated: bool,
     pub total_records: usize,
     pub records_truncated: bool,
+    pub sums_saturated: bool,
     pub elapsed_duration: Duration,
+    pub measuring_duration: Duration,
+    pub estimated_peak_memory_bytes: u64,
     pub total_unique_records: usize,
     pub total_selected_records: usize,
 }
@@ -65,25 +168,57 @@ impl Display for Metadata<'_> {
 impl From<&Metadata<'_>> for Value {
     fn from(value: &Metadata<'_>) -> Self {
         json!({
+            "version": value.version,
+            "hostname": value.hostname_opt,
+            "cpu": value.cpu_opt,
             "layout_table_fpath": value.layout_table_fpath,
-            "key_table_fpath": value.key_table_fpath,
+            "key_table_fpath": value.key_table_fpath_opt,
+            "preset": value.preset_opt,
+            "layout_string": value.layout_string_opt,
             "unigram_table_fpath": value.unigram_table_fpath_opt,
             "bigram_table_fpath": value.bigram_table_fpath_opt,
             "trigram_table_fpath": value.trigram_table_fpath_opt,
+            "layout_table_sha256": value.input_hashes.layout_table_opt,
+            "key_table_sha256": value.input_hashes.key_table_opt,
+            "unigram_table_sha256": value.input_hashes.unigram_table_opt,
+            "bigram_table_sha256": value.input_hashes.bigram_table_opt,
+            "trigram_table_sha256": value.input_hashes.trigram_table_opt,
             "unigram_table_sum": value.unigram_table_sum,
             "bigram_table_sum": value.bigram_table_sum,
             "trigram_table_sum": value.trigram_table_sum,
+            "coverage": Value::from(value.coverage),
             "goal": value.goal.to_string(),
             "metric": value.metric.to_string(),
-            "tolerance": value.tolerance,
+            "tolerance": match value.tolerance {
+                Tolerance::Ratio(ratio) => json!(ratio),
+                Tolerance::Percent(percent) => json!(percent),
+                Tolerance::Absolute(delta) => json!(delta),
+            },
+            "tolerance_mode": value.tolerance.mode(),
             "weight": value.weight.to_string(),
             "max_permutations": value.max_permutations_opt,
             "max_records": value.max_records_opt,
+            "tie_seed": value.tie_seed_opt,
+            "constraints": value
+                .constraints
+                .iter()
+                .map(|constraint| constraint.to_string())
+                .collect::<Vec<String>>(),
+            "key_usage_caps": value
+                .key_usage_caps
+                .iter()
+                .map(|key_usage_cap| key_usage_cap.to_string())
+                .collect::<Vec<String>>(),
             "sort_rules": value
                 .sort_rules
                 .iter()
                 .map(|sort_rule| sort_rule.to_string())
                 .collect::<Vec<String>>(),
+            "definitions": value
+                .definitions
+                .iter()
+                .map(|definition| definition.to_string())
+                .collect::<Vec<String>>(),
             "filters": value
                 .filters
                 .iter()
@@ -91,12 +226,18 @@ impl From<&Metadata<'_>> for Value {
                 .collect::<Vec<String>>(),
             "max_selections": value.max_selections_opt,
             "index": value.index_opt,
+            "parallelized": value.parallelized,
+            "threads": value.threads,
             "total_permutations": value.total_permutations,
+            "total_skipped_permutations": value.total_skipped_permutations,
             "permutations_truncated": value.permutations_truncated,
             "total_records": value.total_records,
             "records_truncated": value.records_truncated,
+            "sums_saturated": value.sums_saturated,
             "elapsed_duration": value.elapsed_duration,
             "efficiency": value.efficiency(),
+            "measuring_duration": value.measuring_duration,
+            "estimated_peak_memory_bytes": value.estimated_peak_memory_bytes,
             "total_unique_records": value.total_unique_records,
             "total_selected_records": value.total_selected_records
         })
@@ -107,62 +248,251 @@ impl WriteStyled for Metadata<'_> {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
         writeln!(
             writer,
-            "layout table fpath:         {:?}\n\
-             key table fpath:            {:?}\n\
+            "version:                    {}\n\
+             hostname:                   {}\n\
+             cpu:                        {}\n\
+             layout table fpath:         {:?}\n\
+             key table fpath:            {}\n\
+             preset:                     {}\n\
+             layout string:              {}\n\
              unigram table fpath:        {}\n\
              bigram table fpath:         {}\n\
              trigram table fpath:        {}\n\
+             layout table sha256:        {}\n\
+             key table sha256:           {}\n\
+             unigram table sha256:       {}\n\
+             bigram table sha256:        {}\n\
+             trigram table sha256:       {}\n\
              unigram table sum:          {}\n\
              bigram table sum:           {}\n\
              trigram table sum:          {}\n\
+             coverage:                   {}\n\
              goal:                       {}\n\
              metric:                     {}\n\
              tolerance:                  {}\n\
+             tolerance mode:             {}\n\
              weight:                     {}\n\
              max permutations:           {}\n\
              max records:                {}\n\
+             tie seed:                   {}\n\
+             constraints:                {}\n\
+             key usage caps:             {}\n\
              sort rules:                 {}\n\
+             definitions:                {}\n\
              filters:                    {}\n\
              max selections:             {}\n\
              index:                      {}\n\
+             parallelized:               {}\n\
+             threads:                    {}\n\
              total permutations:         {}\n\
+             total skipped permutations: {}\n\
              permutations truncated:     {}\n\
              total records:              {}\n\
              records truncated:          {}\n\
+             sums saturated:             {}\n\
              elapsed duration:           {}\n\
              efficiency:                 {} / permutation\n\
+             measuring duration:         {}\n\
+             estimated peak memory:      {}\n\
              total unique records:       {}\n\
              total selected records:     {}",
+            self.version,
+            format_debug_opt(self.hostname_opt),
+            format_debug_opt(self.cpu_opt),
             self.layout_table_fpath,
-            self.key_table_fpath,
+            format_debug_opt(self.key_table_fpath_opt),
+            format_display_opt(self.preset_opt),
+            format_debug_opt(self.layout_string_opt),
             format_debug_opt(self.unigram_table_fpath_opt),
             format_debug_opt(self.bigram_table_fpath_opt),
             format_debug_opt(self.trigram_table_fpath_opt),
-            self.unigram_table_sum,
-            self.bigram_table_sum,
-            self.trigram_table_sum,
+            format_debug_opt(self.input_hashes.layout_table_opt.as_ref()),
+            format_debug_opt(self.input_hashes.key_table_opt.as_ref()),
+            format_debug_opt(self.input_hashes.unigram_table_opt.as_ref()),
+            format_debug_opt(self.input_hashes.bigram_table_opt.as_ref()),
+            format_debug_opt(self.input_hashes.trigram_table_opt.as_ref()),
+            format_count(self.unigram_table_sum),
+            format_count(self.bigram_table_sum),
+            format_count(self.trigram_table_sum),
+            [
+                self.coverage.unigrams.perc(),
+                self.coverage.bigrams.perc(),
+                self.coverage.trigrams.perc(),
+            ]
+            .map(|perc_opt| format_perc(decimal_places_or(3), perc_opt))
+            .join(", "),
             self.goal.to_string(),
             self.metric.to_string(),
             self.tolerance,
+            self.tolerance.mode(),
             self.weight.to_string(),
-            format_display_opt(self.max_permutations_opt),
-            format_display_opt(self.max_records_opt),
+            format_display_opt(self.max_permutations_opt.map(format_count)),
+            format_display_opt(
+                self.max_records_opt
+                    .map(|max_records| format_count(max_records as u64))
+            ),
+            format_display_opt(self.tie_seed_opt),
+            DisplaySlice(self.constraints),
+            DisplaySlice(self.key_usage_caps),
             DisplaySlice(self.sort_rules),
+            DisplaySlice(self.definitions),
             DisplaySlice(self.filters),
             format_display_opt(self.max_selections_opt),
             format_display_opt(self.index_opt),
-            self.total_permutations,
+            self.parallelized,
+            self.threads,
+            format_count(self.total_permutations),
+            format_count(self.total_skipped_permutations),
             self.permutations_truncated,
-            self.total_records,
+            format_count(self.total_records as u64),
             self.records_truncated,
+            self.sums_saturated,
             format_duration(self.elapsed_duration),
             format_duration_opt(self.efficiency()),
-            self.total_unique_records,
-            self.total_selected_records,
+            format_duration(self.measuring_duration),
+            format_bytes(self.estimated_peak_memory_bytes),
+            format_count(self.total_unique_records as u64),
+            format_count(self.total_selected_records as u64),
         )
     }
 }
 
+// NOTE Merges the metadata of the runs over several key tables, in the order they were run. The
+// key table paths, hashes, and coverages become arrays, and the totals and durations are summed.
+// NOTE Missing fields (e.g., in a run saved without metadata) are 0.
+pub fn read_run_quantities(value_opt: Option<&Value>) -> RunQuantities {
+    let field = |name: &str| value_opt.and_then(|value| value.get(name));
+    RunQuantities {
+        total_permutations: field("total_permutations")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        elapsed_duration: field("elapsed_duration")
+            .and_then(|value| {
+                Some(Duration::new(
+                    value.get("secs")?.as_u64()?,
+                    value.get("nanos")?.as_u64()? as u32,
+                ))
+            })
+            .unwrap_or_default(),
+    }
+}
+
+pub fn merge_metadata_values(values: &[Value]) -> Option<Value> {
+    const LISTED_FIELDS: [&str; 3] = ["key_table_fpath", "key_table_sha256", "coverage"];
+    const SUMMED_FIELDS: [&str; 5] = [
+        "total_permutations",
+        "total_skipped_permutations",
+        "total_records",
+        "estimated_peak_memory_bytes",
+        "total_unique_records",
+    ];
+    const FLAG_FIELDS: [&str; 3] = [
+        "permutations_truncated",
+        "records_truncated",
+        "sums_saturated",
+    ];
+    let objects = values
+        .iter()
+        .filter_map(Value::as_object)
+        .collect::<Vec<_>>();
+    let mut merged = (*objects.first()?).clone();
+    for field in LISTED_FIELDS {
+        let items = objects
+            .iter()
+            .map(|object| object.get(field).cloned().unwrap_or_default())
+            .collect();
+        merged.insert(field.into(), Value::Array(items));
+    }
+    for field in SUMMED_FIELDS {
+        let sum = objects
+            .iter()
+            .filter_map(|object| object.get(field)?.as_u64())
+            .sum::<u64>();
+        merged.insert(field.into(), json!(sum));
+    }
+    for field in FLAG_FIELDS {
+        let flag = objects
+            .iter()
+            .any(|object| object.get(field).and_then(Value::as_bool) == Some(true));
+        merged.insert(field.into(), json!(flag));
+    }
+    let sum_durations = |field| {
+        objects
+            .iter()
+            .filter_map(|object| {
+                let value = object.get(field)?;
+                Some(Duration::new(
+                    value.get("secs")?.as_u64()?,
+                    value.get("nanos")?.as_u64()? as u32,
+                ))
+            })
+            .sum::<Duration>()
+    };
+    let elapsed_duration = sum_durations("elapsed_duration");
+    let measuring_duration = sum_durations("measuring_duration");
+    let total_permutations = merged
+        .get("total_permutations")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    merged.insert("elapsed_duration".into(), json!(elapsed_duration));
+    merged.insert("measuring_duration".into(), json!(measuring_duration));
+    merged.insert(
+        "efficiency".into(),
+        json!((total_permutations != 0).then(|| {
+            Duration::from_secs_f64(elapsed_duration.as_secs_f64() / total_permutations as f64)
+        })),
+    );
+    Some(Value::Object(merged))
+}
+
+// NOTE Archived metadata is printed from its JSON value, in the same layout as above. Fields
+// are printed in the order they were archived.
+pub fn write_metadata_value_text(writer: &mut dyn WriteColor, value: &Value) -> io::Result<()> {
+    const DISPLAY_FIELDS: [&str; 6] = [
+        "version",
+        "preset",
+        "goal",
+        "metric",
+        "tolerance_mode",
+        "weight",
+    ];
+    let Some(object) = value.as_object() else {
+        return Ok(());
+    };
+    for (field, value) in object {
+        let label = format!("{}:", field.replace('_', " "));
+        let text = match value {
+            Value::String(s) if DISPLAY_FIELDS.contains(&field.as_str()) => s.clone(),
+            Value::String(s) => format!("{:?}", s),
+            Value::Number(n) if n.is_f64() => format_display_opt(n.as_f64()),
+            Value::Number(n) if n.is_u64() => format_display_opt(n.as_u64().map(format_count)),
+            Value::Array(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| item.as_str().map_or_else(|| item.to_string(), String::from))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Object(object) => match (
+                object.get("secs").and_then(Value::as_u64),
+                object.get("nanos").and_then(Value::as_u64),
+            ) {
+                (Some(secs), Some(nanos)) => format_duration(Duration::new(secs, nanos as u32)),
+                _ => value.to_string(),
+            },
+            _ => value.to_string(),
+        };
+        let suffix = if field == "efficiency" {
+            " / permutation"
+        } else {
+            ""
+        };
+        writeln!(writer, "{:<28}{}{}", label, text, suffix)?;
+    }
+    Ok(())
+}
+
 fn format_debug_opt<T: Debug>(debug_opt: Option<T>) -> String {
     match debug_opt {
         None => String::from("null"),
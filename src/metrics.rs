@@ -1,18 +1,23 @@
 use core::fmt::{self, Display};
 
-use std::{collections::HashSet, io, sync::LazyLock};
+use std::{collections::BTreeMap, io, iter, sync::LazyLock};
 
 use strum::{EnumCount, VariantNames};
 
 use strum_macros::{EnumCount, VariantNames};
 
-use termcolor::{ColorSpec, WriteColor};
+use termcolor::WriteColor;
 
 use crate::{
-    fingerings::{BigramFingering, TrigramFingering, UnigramFingering},
+    expressions::{Type, Variables},
+    fingerings::{
+        BigramFingering, BigramFingeringArrays, EffortModel, Fingering, TrigramFingering,
+        TrigramFingeringArrays, UnigramFingering, UnigramFingeringArrays,
+    },
     goals::Goal,
-    layouts::{Laterality, LayoutTable, Position},
-    ui::styles::WriteStyled,
+    layouts::{Digit, Laterality, LayoutTable, Position},
+    ui::styles::{WriteStyled, theme},
+    weights::Weight,
 };
 
 pub fn filter_lt(f: &UnigramFingering) -> bool {
@@ -45,6 +50,12 @@ pub fn filter_lh(f: &UnigramFingering) -> bool {
     l == Laterality::Left
 }
 
+// NOTE Which keys are penalized depends on the layout table, so Pen's fingerings are selected by
+// 'LayoutTable::unigram_fingerings' instead.
+pub fn filter_pen(_f: &UnigramFingering) -> bool {
+    false
+}
+
 pub fn filter_rt(f: &UnigramFingering) -> bool {
     let ((.., l, p), _) = *f;
     l == Laterality::Right && p == Position::Thumb
@@ -80,6 +91,10 @@ pub fn filter_distinct_pairs(fp: &BigramFingering) -> bool {
     (r1, c1) != (r2, c2)
 }
 
+pub fn filter_rep(fp: &BigramFingering) -> bool {
+    !filter_distinct_pairs(fp)
+}
+
 pub fn filter_hsb(fp: &BigramFingering) -> bool {
     use Position::*;
     let ((r1, c1, l1, p1), (r2, c2, l2, p2), _) = *fp;
@@ -136,8 +151,22 @@ pub fn filter_distinct_triples(ft: &TrigramFingering) -> bool {
 }
 
 pub fn filter_alt(ft: &TrigramFingering) -> bool {
-    let ((_, _, l1, _), (_, _, l2, _), (_, _, l3, _), _) = *ft;
-    l1 == l3 && l2 != l1
+    let ((_, _, l1, p1), (_, _, l2, _), (_, _, l3, p3), _) = *ft;
+    l1 == l3 && l2 != l1 && p1 != p3
+}
+
+// NOTE An alternating trigram whose first and third keys are pressed by the same digit (a same
+// finger skip). The keys of a trigram are distinct, so the digit always has to move.
+pub fn filter_asf(ft: &TrigramFingering) -> bool {
+    let ((_, _, l1, p1), (_, _, l2, _), (_, _, l3, p3), _) = *ft;
+    l1 == l3 && l2 != l1 && p1 == p3
+}
+
+// NOTE A run of same-hand keys that is n keys long (n >= 3) contains n - 2 such trigrams, so a
+// longer run counts for more.
+pub fn filter_shr(ft: &TrigramFingering) -> bool {
+    let ((.., l1, _), (.., l2, _), (.., l3, _), _) = *ft;
+    l1 == l2 && l2 == l3
 }
 
 pub fn filter_one(ft: &TrigramFingering) -> bool {
@@ -150,7 +179,7 @@ pub fn filter_one(ft: &TrigramFingering) -> bool {
         && ((c1 < c2 && c2 < c3) || (c1 > c2 && c2 > c3))
 }
 
-pub fn filter_red(ft: &TrigramFingering) -> bool {
+fn is_redirect(ft: &TrigramFingering) -> bool {
     let ((_, c1, l1, p1), (_, c2, l2, p2), (_, c3, l3, p3), _) = *ft;
     l1 == l2
         && l2 == l3
@@ -160,28 +189,153 @@ pub fn filter_red(ft: &TrigramFingering) -> bool {
         && ((c1 < c2 && c2 > c3) || (c1 > c2 && c2 < c3))
 }
 
+// NOTE A redirect is weak if any of its keys is pressed by a ring finger or a pinky; otherwise, it
+// is made by the index finger, middle finger, and thumb alone.
+fn is_weak(ft: &TrigramFingering) -> bool {
+    use Position::*;
+    let ((.., p1), (.., p2), (.., p3), _) = *ft;
+    [p1, p2, p3].iter().any(|p| matches!(p, Ring | Pinky))
+}
+
+pub fn filter_red(ft: &TrigramFingering) -> bool {
+    is_redirect(ft) && !is_weak(ft)
+}
+
+pub fn filter_rdw(ft: &TrigramFingering) -> bool {
+    is_redirect(ft) && is_weak(ft)
+}
+
+// NOTE The two keys of a roll that are pressed by the same hand, in order, if the trigram is a
+// roll.
+fn roll_keys(ft: &TrigramFingering) -> Option<(Fingering, Fingering)> {
+    let (f1, f2, f3, _) = *ft;
+    let ((.., l1, p1), (.., l2, p2), (.., l3, p3)) = (f1, f2, f3);
+    if l1 == l2 && l1 != l3 && p1 != p2 {
+        Some((f1, f2))
+    } else if l2 == l3 && l2 != l1 && p2 != p3 {
+        Some((f2, f3))
+    } else {
+        None
+    }
+}
+
+// NOTE A roll is inward if it moves towards the centre of the keyboard, i.e. to a greater column
+// on the left hand, or to a lesser column on the right hand. A roll between keys in the same
+// column is neither inward nor outward.
+fn is_inward((_, c1, l, _): Fingering, (_, c2, ..): Fingering) -> bool {
+    match l {
+        Laterality::Left => c1 < c2,
+        Laterality::Right => c1 > c2,
+    }
+}
+
+fn is_outward((_, c1, l, _): Fingering, (_, c2, ..): Fingering) -> bool {
+    match l {
+        Laterality::Left => c1 > c2,
+        Laterality::Right => c1 < c2,
+    }
+}
+
 pub fn filter_rol(ft: &TrigramFingering) -> bool {
-    let ((_, _, l1, p1), (_, _, l2, p2), (_, _, l3, p3), _) = *ft;
-    (l1 == l2 && l1 != l3 && p1 != p2) || (l2 == l3 && l2 != l1 && p2 != p3)
+    roll_keys(ft).is_some()
 }
 
-pub static STYLE_UNIGRAM_METRIC: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec
-});
+pub fn filter_rli(ft: &TrigramFingering) -> bool {
+    roll_keys(ft).is_some_and(|(f1, f2)| is_inward(f1, f2))
+}
 
-pub static STYLE_BIGRAM_METRIC: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec
-});
+pub fn filter_rlo(ft: &TrigramFingering) -> bool {
+    roll_keys(ft).is_some_and(|(f1, f2)| is_outward(f1, f2))
+}
 
-pub static STYLE_TRIGRAM_METRIC: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec
-});
+fn has_thumb(ft: &TrigramFingering) -> bool {
+    let ((.., p1), (.., p2), (.., p3), _) = *ft;
+    [p1, p2, p3].contains(&Position::Thumb)
+}
+
+pub fn filter_alt_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_alt(ft)
+}
+
+pub fn filter_asf_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_asf(ft)
+}
+
+pub fn filter_red_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_red(ft)
+}
+
+pub fn filter_shr_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_shr(ft)
+}
+
+pub fn filter_rdw_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_rdw(ft)
+}
+
+pub fn filter_rol_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_rol(ft)
+}
+
+pub fn filter_rli_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_rli(ft)
+}
+
+pub fn filter_rlo_without_thumbs(ft: &TrigramFingering) -> bool {
+    !has_thumb(ft) && filter_rlo(ft)
+}
+
+// NOTE A thumb at either end of a trigram ends the sequence, like a change of hand, so the other
+// two keys form a roll if they are pressed by different digits of the same hand. A thumb in the
+// middle of a trigram breaks it apart entirely.
+fn roll_keys_with_thumb_breaks(ft: &TrigramFingering) -> Option<(Fingering, Fingering)> {
+    use Position::*;
+    let (f1, f2, f3, _) = *ft;
+    let ((.., l1, p1), (.., l2, p2), (.., l3, p3)) = (f1, f2, f3);
+    match (p1 == Thumb, p2 == Thumb, p3 == Thumb) {
+        (false, false, false) => roll_keys(ft),
+        (false, false, true) => (l1 == l2 && p1 != p2).then_some((f1, f2)),
+        (true, false, false) => (l2 == l3 && p2 != p3).then_some((f2, f3)),
+        _ => None,
+    }
+}
+
+pub fn filter_rol_with_thumb_breaks(ft: &TrigramFingering) -> bool {
+    roll_keys_with_thumb_breaks(ft).is_some()
+}
+
+pub fn filter_rli_with_thumb_breaks(ft: &TrigramFingering) -> bool {
+    roll_keys_with_thumb_breaks(ft).is_some_and(|(f1, f2)| is_inward(f1, f2))
+}
+
+pub fn filter_rlo_with_thumb_breaks(ft: &TrigramFingering) -> bool {
+    roll_keys_with_thumb_breaks(ft).is_some_and(|(f1, f2)| is_outward(f1, f2))
+}
+
+// NOTE The heat of each key in printed key tables. 'Frequency' is the frequency of the key's
+// character (relative to the most frequent character), 'Effort' is the effort-weighted score of all
+// unigrams, and a unigram metric is the score (by the selected weight) of that metric's unigrams.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeatMetric {
+    Effort,
+    #[default]
+    Frequency,
+    Unigram(UnigramMetric),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThumbMode {
+    Break,
+    Exclude,
+    #[default]
+    Hand,
+}
+
+impl Display for ThumbMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, EnumCount, VariantNames)]
 #[repr(usize)]
@@ -198,6 +352,7 @@ pub enum UnigramMetric {
     Rr,
     Rp,
     Rh,
+    Pen,
 }
 
 impl UnigramMetric {
@@ -214,6 +369,7 @@ impl UnigramMetric {
         Self::Rr,
         Self::Rp,
         Self::Rh,
+        Self::Pen,
     ];
 
     pub fn as_usize(self) -> usize {
@@ -235,6 +391,27 @@ impl UnigramMetric {
             Rr => filter_rr,
             Rp => filter_rp,
             Rh => filter_rh,
+            Pen => filter_pen,
+        }
+    }
+
+    // NOTE The digit measured by a metric, or 'None' for the metrics of a whole hand (and Pen).
+    pub fn digit_opt(self) -> Option<Digit> {
+        use Laterality::*;
+        use Position::*;
+        use UnigramMetric::*;
+        match self {
+            Lt => Some(Digit(Left, Thumb)),
+            Li => Some(Digit(Left, Index)),
+            Lm => Some(Digit(Left, Middle)),
+            Lr => Some(Digit(Left, Ring)),
+            Lp => Some(Digit(Left, Pinky)),
+            Rt => Some(Digit(Right, Thumb)),
+            Ri => Some(Digit(Right, Index)),
+            Rm => Some(Digit(Right, Middle)),
+            Rr => Some(Digit(Right, Ring)),
+            Rp => Some(Digit(Right, Pinky)),
+            Lh | Rh | Pen => None,
         }
     }
 
@@ -243,7 +420,7 @@ impl UnigramMetric {
         use UnigramMetric::*;
         match self {
             Lt | Li | Lm | Lh | Rt | Ri | Rm | Rh => Max,
-            Lr | Lp | Rr | Rp => Min,
+            Lr | Lp | Rr | Rp | Pen => Min,
         }
     }
 }
@@ -256,7 +433,7 @@ impl Display for UnigramMetric {
 
 impl WriteStyled for UnigramMetric {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
-        writer.set_color(&STYLE_UNIGRAM_METRIC)?;
+        writer.set_color(&theme().unigram_metric)?;
         write!(writer, "{}", self.to_string())?;
         writer.reset()
     }
@@ -270,7 +447,9 @@ pub enum BigramMetric {
     Irb,
     Lsb,
     Orb,
+    Rep,
     Sfb,
+    Sfd,
 }
 
 impl BigramMetric {
@@ -280,7 +459,9 @@ impl BigramMetric {
         Self::Irb,
         Self::Lsb,
         Self::Orb,
+        Self::Rep,
         Self::Sfb,
+        Self::Sfd,
     ];
 
     pub fn as_usize(self) -> usize {
@@ -295,7 +476,8 @@ impl BigramMetric {
             Irb => filter_irb,
             Lsb => filter_lsb,
             Orb => filter_orb,
-            Sfb => filter_sfb,
+            Rep => filter_rep,
+            Sfb | Sfd => filter_sfb,
         }
     }
 
@@ -304,9 +486,15 @@ impl BigramMetric {
         use Goal::*;
         match self {
             Irb | Orb => Max,
-            Fsb | Hsb | Lsb | Sfb => Min,
+            Fsb | Hsb | Lsb | Rep | Sfb | Sfd => Min,
         }
     }
+
+    // NOTE Sfd counts the same bigrams as Sfb, but each is weighted by its effort (by default, the
+    // distance travelled by the digit), whichever weight is selected.
+    pub fn is_weighted_by_effort(self) -> bool {
+        self == Self::Sfd
+    }
 }
 
 impl Display for BigramMetric {
@@ -317,7 +505,7 @@ impl Display for BigramMetric {
 
 impl WriteStyled for BigramMetric {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
-        writer.set_color(&STYLE_BIGRAM_METRIC)?;
+        writer.set_color(&theme().bigram_metric)?;
         write!(writer, "{}", self.to_string())?;
         writer.reset()
     }
@@ -327,13 +515,28 @@ impl WriteStyled for BigramMetric {
 #[repr(usize)]
 pub enum TrigramMetric {
     Alt,
+    Asf,
     One,
+    Rdw,
     Red,
+    Rli,
+    Rlo,
     Rol,
+    Shr,
 }
 
 impl TrigramMetric {
-    pub const VARIANT_ARRAY: [Self; Self::COUNT] = [Self::Alt, Self::One, Self::Red, Self::Rol];
+    pub const VARIANT_ARRAY: [Self; Self::COUNT] = [
+        Self::Alt,
+        Self::Asf,
+        Self::One,
+        Self::Rdw,
+        Self::Red,
+        Self::Rli,
+        Self::Rlo,
+        Self::Rol,
+        Self::Shr,
+    ];
 
     pub fn as_usize(self) -> usize {
         self as usize
@@ -343,9 +546,34 @@ impl TrigramMetric {
         use TrigramMetric::*;
         match self {
             Alt => filter_alt,
+            Asf => filter_asf,
             One => filter_one,
+            Rdw => filter_rdw,
             Red => filter_red,
+            Rli => filter_rli,
+            Rlo => filter_rlo,
             Rol => filter_rol,
+            Shr => filter_shr,
+        }
+    }
+
+    // NOTE Only One is unaffected by the thumb mode.
+    pub fn filter_fn_with_thumb_mode(self, thumb_mode: ThumbMode) -> fn(&TrigramFingering) -> bool {
+        use ThumbMode::*;
+        use TrigramMetric::*;
+        match (thumb_mode, self) {
+            (Hand, _) | (_, One) => self.filter_fn(),
+            (Break | Exclude, Alt) => filter_alt_without_thumbs,
+            (Break | Exclude, Asf) => filter_asf_without_thumbs,
+            (Break | Exclude, Rdw) => filter_rdw_without_thumbs,
+            (Break | Exclude, Red) => filter_red_without_thumbs,
+            (Break, Rli) => filter_rli_with_thumb_breaks,
+            (Exclude, Rli) => filter_rli_without_thumbs,
+            (Break, Rlo) => filter_rlo_with_thumb_breaks,
+            (Exclude, Rlo) => filter_rlo_without_thumbs,
+            (Break, Rol) => filter_rol_with_thumb_breaks,
+            (Exclude, Rol) => filter_rol_without_thumbs,
+            (Break | Exclude, Shr) => filter_shr_without_thumbs,
         }
     }
 
@@ -353,7 +581,7 @@ impl TrigramMetric {
         use Goal::*;
         use TrigramMetric::*;
         match self {
-            Alt | One | Red | Rol => Min,
+            Alt | Asf | One | Rdw | Red | Rli | Rlo | Rol | Shr => Min,
         }
     }
 }
@@ -366,7 +594,7 @@ impl Display for TrigramMetric {
 
 impl WriteStyled for TrigramMetric {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
-        writer.set_color(&STYLE_TRIGRAM_METRIC)?;
+        writer.set_color(&theme().trigram_metric)?;
         write!(writer, "{}", self.to_string())?;
         writer.reset()
     }
@@ -379,6 +607,31 @@ pub enum Metric {
     Trigram(TrigramMetric),
 }
 
+pub const PERCENTILE_SUFFIX: &str = "_percentile";
+
+pub const PER_BILLION_SUFFIX: &str = "_per_billion";
+
+// NOTE The goal of each metric: its default goal (see 'Metric::goal'), unless it is overridden with
+// '--goal' (such as 'rol=max'). Overrides apply wherever a goal is consulted: ranks, percentiles,
+// grids, and the arrows in reports.
+#[derive(Clone, Debug, Default)]
+pub struct MetricGoals {
+    overrides: BTreeMap<Metric, Goal>,
+}
+
+impl MetricGoals {
+    pub fn new(overrides: BTreeMap<Metric, Goal>) -> Self {
+        Self { overrides }
+    }
+
+    pub fn goal(&self, metric: Metric) -> Goal {
+        self.overrides
+            .get(&metric)
+            .copied()
+            .unwrap_or(metric.goal())
+    }
+}
+
 static VARIANTS: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
     [
         UnigramMetric::VARIANTS,
@@ -389,8 +642,75 @@ static VARIANTS: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
 });
 
 impl Metric {
-    pub fn get_variables() -> HashSet<String> {
-        VARIANTS.iter().map(|&s| s.to_lowercase()).collect()
+    pub fn get_variables() -> Variables {
+        VARIANTS
+            .iter()
+            .map(|&s| (s.to_lowercase(), Type::Number))
+            .collect()
+    }
+
+    pub fn get_percentile_variables() -> Variables {
+        VARIANTS
+            .iter()
+            .map(|&s| {
+                (
+                    format!("{}{}", s.to_lowercase(), PERCENTILE_SUFFIX),
+                    Type::Number,
+                )
+            })
+            .collect()
+    }
+
+    pub fn get_per_billion_variables() -> Variables {
+        VARIANTS
+            .iter()
+            .map(|&s| {
+                (
+                    format!("{}{}", s.to_lowercase(), PER_BILLION_SUFFIX),
+                    Type::Number,
+                )
+            })
+            .collect()
+    }
+
+    pub fn iter_all() -> impl Iterator<Item = Self> {
+        UnigramMetric::VARIANT_ARRAY
+            .into_iter()
+            .map(Metric::Unigram)
+            .chain(BigramMetric::VARIANT_ARRAY.into_iter().map(Metric::Bigram))
+            .chain(
+                TrigramMetric::VARIANT_ARRAY
+                    .into_iter()
+                    .map(Metric::Trigram),
+            )
+    }
+
+    // NOTE The metric that a variable of an expression refers to (e.g., 'sfb', 'sfb_percentile', or
+    // 'sfb_per_billion' all refer to Sfb).
+    pub fn from_variable(variable: &str) -> Option<Self> {
+        let name = variable
+            .strip_suffix(PERCENTILE_SUFFIX)
+            .or_else(|| variable.strip_suffix(PER_BILLION_SUFFIX))
+            .unwrap_or(variable);
+        Self::iter_all().find(|metric| metric.to_string().eq_ignore_ascii_case(name))
+    }
+
+    // NOTE The weight by which the metric is actually scored, given the selected weight (see
+    // 'BigramMetric::is_weighted_by_effort').
+    pub fn weight_for(self, weight: Weight) -> Weight {
+        match self {
+            Metric::Bigram(metric) if metric.is_weighted_by_effort() => Weight::Effort,
+            _ => weight,
+        }
+    }
+
+    pub fn goal(self) -> Goal {
+        use Metric::*;
+        match self {
+            Unigram(metric) => metric.goal(),
+            Bigram(metric) => metric.goal(),
+            Trigram(metric) => metric.goal(),
+        }
     }
 }
 
@@ -405,6 +725,94 @@ impl Display for Metric {
     }
 }
 
+impl WriteStyled for Metric {
+    fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
+        use Metric::*;
+        match self {
+            Unigram(metric) => metric.write_styled(writer),
+            Bigram(metric) => metric.write_styled(writer),
+            Trigram(metric) => metric.write_styled(writer),
+        }
+    }
+}
+
+// NOTE Named groups of metrics, accepted in place of a metric wherever a list of metrics is expected
+// (such as '--print-details' and '--sort-asc'). 'SpeedPack' is the set of metrics most commonly
+// compared by other analyzers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, EnumCount)]
+pub enum MetricGroup {
+    AllUnigram,
+    AllBigram,
+    AllTrigram,
+    SpeedPack,
+}
+
+impl MetricGroup {
+    pub const VARIANT_ARRAY: [Self; Self::COUNT] = [
+        Self::AllUnigram,
+        Self::AllBigram,
+        Self::AllTrigram,
+        Self::SpeedPack,
+    ];
+
+    pub fn name(self) -> &'static str {
+        use MetricGroup::*;
+        match self {
+            AllUnigram => "all-unigram",
+            AllBigram => "all-bigram",
+            AllTrigram => "all-trigram",
+            SpeedPack => "speed-pack",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::VARIANT_ARRAY
+            .into_iter()
+            .find(|group| group.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn description(self) -> &'static str {
+        use MetricGroup::*;
+        match self {
+            AllUnigram => "All unigram metrics",
+            AllBigram => "All bigram metrics",
+            AllTrigram => "All trigram metrics",
+            SpeedPack => "Sfb, Lsb, Fsb, Hsb, Alt, Rol, One, and Red",
+        }
+    }
+
+    pub fn metrics(self) -> Vec<Metric> {
+        use MetricGroup::*;
+        match self {
+            AllUnigram => Metric::iter_all()
+                .filter(|metric| matches!(metric, Metric::Unigram(_)))
+                .collect(),
+            AllBigram => Metric::iter_all()
+                .filter(|metric| matches!(metric, Metric::Bigram(_)))
+                .collect(),
+            AllTrigram => Metric::iter_all()
+                .filter(|metric| matches!(metric, Metric::Trigram(_)))
+                .collect(),
+            SpeedPack => vec![
+                Metric::Bigram(BigramMetric::Sfb),
+                Metric::Bigram(BigramMetric::Lsb),
+                Metric::Bigram(BigramMetric::Fsb),
+                Metric::Bigram(BigramMetric::Hsb),
+                Metric::Trigram(TrigramMetric::Alt),
+                Metric::Trigram(TrigramMetric::Rol),
+                Metric::Trigram(TrigramMetric::One),
+                Metric::Trigram(TrigramMetric::Red),
+            ],
+        }
+    }
+}
+
+impl Display for MetricGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, VariantNames)]
 pub enum SortDirection {
     Ascending,
@@ -449,6 +857,8 @@ pub fn partition_sort_rules(
 pub struct UnigramFingerings<const C: usize, const R: usize>(
     Vec<UnigramFingering>,
     [Vec<UnigramFingering>; UnigramMetric::COUNT],
+    UnigramFingeringArrays,
+    [UnigramFingeringArrays; UnigramMetric::COUNT],
 );
 
 impl<const C: usize, const R: usize> UnigramFingerings<C, R> {
@@ -459,11 +869,28 @@ impl<const C: usize, const R: usize> UnigramFingerings<C, R> {
     pub fn get_by_metric(&self, metric: UnigramMetric) -> &Vec<UnigramFingering> {
         &self.1[metric.as_usize()]
     }
+
+    pub fn get_arrays(&self) -> &UnigramFingeringArrays {
+        &self.2
+    }
+
+    pub fn get_arrays_by_metric(&self, metric: UnigramMetric) -> &UnigramFingeringArrays {
+        &self.3[metric.as_usize()]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        iter::once(&self.2)
+            .chain(&self.3)
+            .try_for_each(|arrays| arrays.validate(C * R))
+            .map_err(|e| format!("Invalid unigram fingerings: {}", e))
+    }
 }
 
 pub struct BigramFingerings<const C: usize, const R: usize>(
     Vec<BigramFingering>,
     [Vec<BigramFingering>; BigramMetric::COUNT],
+    BigramFingeringArrays,
+    [BigramFingeringArrays; BigramMetric::COUNT],
 );
 
 impl<const C: usize, const R: usize> BigramFingerings<C, R> {
@@ -474,11 +901,28 @@ impl<const C: usize, const R: usize> BigramFingerings<C, R> {
     pub fn get_by_metric(&self, metric: BigramMetric) -> &Vec<BigramFingering> {
         &self.1[metric.as_usize()]
     }
+
+    pub fn get_arrays(&self) -> &BigramFingeringArrays {
+        &self.2
+    }
+
+    pub fn get_arrays_by_metric(&self, metric: BigramMetric) -> &BigramFingeringArrays {
+        &self.3[metric.as_usize()]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        iter::once(&self.2)
+            .chain(&self.3)
+            .try_for_each(|arrays| arrays.validate(C * R))
+            .map_err(|e| format!("Invalid bigram fingerings: {}", e))
+    }
 }
 
 pub struct TrigramFingerings<const C: usize, const R: usize>(
     Vec<TrigramFingering>,
     [Vec<TrigramFingering>; TrigramMetric::COUNT],
+    TrigramFingeringArrays,
+    [TrigramFingeringArrays; TrigramMetric::COUNT],
 );
 
 impl<const C: usize, const R: usize> TrigramFingerings<C, R> {
@@ -489,45 +933,105 @@ impl<const C: usize, const R: usize> TrigramFingerings<C, R> {
     pub fn get_by_metric(&self, metric: TrigramMetric) -> &Vec<TrigramFingering> {
         &self.1[metric.as_usize()]
     }
+
+    pub fn get_arrays(&self) -> &TrigramFingeringArrays {
+        &self.2
+    }
+
+    pub fn get_arrays_by_metric(&self, metric: TrigramMetric) -> &TrigramFingeringArrays {
+        &self.3[metric.as_usize()]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        iter::once(&self.2)
+            .chain(&self.3)
+            .try_for_each(|arrays| arrays.validate(C * R))
+            .map_err(|e| format!("Invalid trigram fingerings: {}", e))
+    }
 }
 
 impl<const C: usize, const R: usize> LayoutTable<C, R> {
-    pub fn unigram_fingerings(&self) -> UnigramFingerings<C, R> {
-        let fs = self.iter_f().collect::<Vec<_>>();
-        let fs_by_metric = UnigramMetric::VARIANT_ARRAY.map(|metric| {
-            fs.iter()
+    pub fn unigram_fingerings(&self, effort_model: &EffortModel<C, R>) -> UnigramFingerings<C, R> {
+        let fs = self.iter_f(effort_model).collect::<Vec<_>>();
+        let fs_by_metric = UnigramMetric::VARIANT_ARRAY.map(|metric| match metric {
+            UnigramMetric::Pen => fs
+                .iter()
+                .filter_map(|&(f, _)| {
+                    let (r, c, ..) = f;
+                    effort_model
+                        .penalties_opt
+                        .and_then(|penalties| penalties.get(r, c))
+                        .map(|multiplier| (f, multiplier))
+                })
+                .collect(),
+            _ => fs
+                .iter()
                 .cloned()
                 .filter(|f| metric.filter_fn()(f))
-                .collect()
+                .collect(),
         });
-        UnigramFingerings(fs, fs_by_metric)
+        let arrays = UnigramFingeringArrays::new::<C>(&fs);
+        let arrays_by_metric = fs_by_metric
+            .each_ref()
+            .map(|fs: &Vec<UnigramFingering>| UnigramFingeringArrays::new::<C>(fs));
+        UnigramFingerings(fs, fs_by_metric, arrays, arrays_by_metric)
     }
 
-    pub fn bigram_fingerings(&self) -> BigramFingerings<C, R> {
-        let fs = self
-            .iter_fp()
+    // NOTE A key repeated (such as "ee") is not counted in the bigram totals, nor by any metric but
+    // Rep, unless 'sfb_include_repeats' is true, in which case it is counted by Sfb and Sfd too.
+    pub fn bigram_fingerings(
+        &self,
+        effort_model: &EffortModel<C, R>,
+        sfb_include_repeats: bool,
+    ) -> Result<BigramFingerings<C, R>, String> {
+        let all_fs = self.iter_fp(effort_model).collect::<Result<Vec<_>, _>>()?;
+        let fs = all_fs
+            .iter()
+            .cloned()
             .filter(filter_distinct_pairs)
             .collect::<Vec<_>>();
         let fs_by_metric = BigramMetric::VARIANT_ARRAY.map(|metric| {
-            fs.iter()
+            use BigramMetric::*;
+            let source = match metric {
+                Rep => &all_fs,
+                Sfb | Sfd if sfb_include_repeats => &all_fs,
+                _ => &fs,
+            };
+            source
+                .iter()
                 .cloned()
                 .filter(|f| metric.filter_fn()(f))
                 .collect()
         });
-        BigramFingerings(fs, fs_by_metric)
+        let arrays = BigramFingeringArrays::new::<C>(&fs);
+        let arrays_by_metric = fs_by_metric
+            .each_ref()
+            .map(|fs: &Vec<BigramFingering>| BigramFingeringArrays::new::<C>(fs));
+        Ok(BigramFingerings(fs, fs_by_metric, arrays, arrays_by_metric))
     }
 
-    pub fn trigram_fingerings(&self) -> TrigramFingerings<C, R> {
+    pub fn trigram_fingerings(
+        &self,
+        effort_model: &EffortModel<C, R>,
+        thumb_mode: ThumbMode,
+    ) -> Result<TrigramFingerings<C, R>, String> {
         let fs = self
-            .iter_ft()
-            .filter(filter_distinct_triples)
-            .collect::<Vec<_>>();
+            .iter_ft(effort_model)
+            .filter(|f| f.as_ref().map_or(true, filter_distinct_triples))
+            .collect::<Result<Vec<_>, _>>()?;
         let fs_by_metric = TrigramMetric::VARIANT_ARRAY.map(|metric| {
-            fs.iter()
-                .cloned()
-                .filter(|f| metric.filter_fn()(f))
-                .collect()
+            let filter_fn = metric.filter_fn_with_thumb_mode(thumb_mode);
+            fs.iter().cloned().filter(filter_fn).collect()
         });
-        TrigramFingerings(fs, fs_by_metric)
+        let arrays = TrigramFingeringArrays::new::<C>(&fs);
+        let arrays_by_metric = fs_by_metric
+            .each_ref()
+            .map(|fs: &Vec<TrigramFingering>| TrigramFingeringArrays::new::<C>(fs));
+        Ok(TrigramFingerings(
+            fs,
+            fs_by_metric,
+            arrays,
+            arrays_by_metric,
+        ))
     }
 }
@@ -1,71 +1,63 @@
 use core::{
     error::Error,
     fmt::{self, Display},
-    iter,
 };
 
 use std::{
     fs::File,
-    io::{self, BufReader, Read},
+    io::{self, BufRead, BufReader, Read},
     path::Path,
-    sync::LazyLock,
 };
 
 use csv::StringRecord;
 
-use termcolor::{Color, ColorSpec, WriteColor};
+use serde_json::{Map, Value, json};
 
-use crate::{dsv::get_tsv_reader, ui::styles::WriteStyled, util::strings::unescape};
+use termcolor::WriteColor;
 
-pub static STYLE_UNIGRAM_KEY: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec.set_fg(Some(Color::Yellow));
-    color_spec
-});
-
-pub static STYLE_BIGRAM_KEY: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec.set_fg(Some(Color::Blue));
-    color_spec
-});
-
-pub static STYLE_TRIGRAM_KEY: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec.set_fg(Some(Color::Magenta));
-    color_spec
-});
+use crate::{
+    dsv::get_tsv_reader,
+    json::read_json,
+    symbols::{Symbol, intern_str, resolve},
+    ui::styles::{WriteStyled, theme},
+    util::{math::calculate_perc, strings::unescape},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct UnigramKey(u8);
+pub struct UnigramKey(Symbol);
 
 impl UnigramKey {
-    pub fn as_u8(&self) -> u8 {
+    pub fn as_symbol(&self) -> Symbol {
         self.0
     }
 
-    pub fn as_usize(&self) -> usize {
-        self.0 as usize
+    pub fn as_symbols(&self) -> [Symbol; 1] {
+        [self.0]
     }
 }
 
 impl Display for UnigramKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0 as char)
+        write!(f, "{}", resolve(self.0))
     }
 }
 
-impl From<u8> for UnigramKey {
-    fn from(value: u8) -> Self {
+impl From<Symbol> for UnigramKey {
+    fn from(value: Symbol) -> Self {
         Self(value)
     }
 }
 
-impl From<UnigramKey> for usize {
+impl From<[Symbol; 1]> for UnigramKey {
+    fn from(value: [Symbol; 1]) -> Self {
+        let [s1] = value;
+        Self(s1)
+    }
+}
+
+impl From<UnigramKey> for [Symbol; 1] {
     fn from(value: UnigramKey) -> Self {
-        value.as_usize()
+        value.as_symbols()
     }
 }
 
@@ -73,57 +65,59 @@ impl TryFrom<&str> for UnigramKey {
     type Error = String;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.len() == 1 && value.is_ascii() {
-            let bytes = value.as_bytes();
-            Ok(UnigramKey::from(bytes[0]))
-        } else {
-            Err(format!("Invalid unigram key '{}'", value))
+        match intern_str(value)?[..] {
+            [s1] => Ok(UnigramKey::from(s1)),
+            _ => Err(format!("Invalid unigram key '{}'", value)),
         }
     }
 }
 
 impl WriteStyled for UnigramKey {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
-        writer.set_color(&STYLE_UNIGRAM_KEY)?;
-        write!(writer, "{}", self.as_u8() as char)?;
+        writer.set_color(&theme().unigram_key)?;
+        write!(writer, "{}", self)?;
         writer.reset()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct BigramKey(u16);
+pub struct BigramKey(u32);
 
 impl BigramKey {
-    pub fn as_u8_pair(&self) -> (u8, u8) {
-        ((self.0 >> 8) as u8, self.0 as u8)
-    }
-
-    pub fn as_u16(&self) -> u16 {
-        self.0
+    pub fn as_symbol_pair(&self) -> (Symbol, Symbol) {
+        ((self.0 >> 16) as Symbol, self.0 as Symbol)
     }
 
-    pub fn as_usize(&self) -> usize {
-        self.0 as usize
+    pub fn as_symbols(&self) -> [Symbol; 2] {
+        let (s1, s2) = self.as_symbol_pair();
+        [s1, s2]
     }
 }
 
 impl Display for BigramKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (b1, b2) = self.as_u8_pair();
-        write!(f, "{}{}", b1 as char, b2 as char)
+        let (s1, s2) = self.as_symbol_pair();
+        write!(f, "{}{}", resolve(s1), resolve(s2))
+    }
+}
+
+impl From<(Symbol, Symbol)> for BigramKey {
+    fn from(value: (Symbol, Symbol)) -> Self {
+        let (s1, s2) = value;
+        Self((s1 as u32) << 16 | s2 as u32)
     }
 }
 
-impl From<(u8, u8)> for BigramKey {
-    fn from(value: (u8, u8)) -> Self {
-        let (b1, b2) = value;
-        Self((b1 as u16) << 8 | b2 as u16)
+impl From<[Symbol; 2]> for BigramKey {
+    fn from(value: [Symbol; 2]) -> Self {
+        let [s1, s2] = value;
+        Self::from((s1, s2))
     }
 }
 
-impl From<BigramKey> for usize {
+impl From<BigramKey> for [Symbol; 2] {
     fn from(value: BigramKey) -> Self {
-        value.as_usize()
+        value.as_symbols()
     }
 }
 
@@ -131,57 +125,63 @@ impl TryFrom<&str> for BigramKey {
     type Error = String;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.len() == 2 && value.is_ascii() {
-            let bytes = value.as_bytes();
-            Ok(BigramKey::from((bytes[0], bytes[1])))
-        } else {
-            Err(format!("Invalid bigram key '{}'", value))
+        match intern_str(value)?[..] {
+            [s1, s2] => Ok(BigramKey::from((s1, s2))),
+            _ => Err(format!("Invalid bigram key '{}'", value)),
         }
     }
 }
 
 impl WriteStyled for BigramKey {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
-        writer.set_color(&STYLE_BIGRAM_KEY)?;
+        writer.set_color(&theme().bigram_key)?;
         write!(writer, "{}", self)?;
         writer.reset()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct TrigramKey(u32);
+pub struct TrigramKey(u64);
 
 impl TrigramKey {
-    pub fn as_u8_triple(&self) -> (u8, u8, u8) {
-        ((self.0 >> 16) as u8, (self.0 >> 8) as u8, self.0 as u8)
-    }
-
-    pub fn as_u32(&self) -> u32 {
-        self.0
+    pub fn as_symbol_triple(&self) -> (Symbol, Symbol, Symbol) {
+        (
+            (self.0 >> 32) as Symbol,
+            (self.0 >> 16) as Symbol,
+            self.0 as Symbol,
+        )
     }
 
-    pub fn as_usize(&self) -> usize {
-        self.0 as usize
+    pub fn as_symbols(&self) -> [Symbol; 3] {
+        let (s1, s2, s3) = self.as_symbol_triple();
+        [s1, s2, s3]
     }
 }
 
 impl Display for TrigramKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (b1, b2, b3) = self.as_u8_triple();
-        write!(f, "{}{}{}", b1 as char, b2 as char, b3 as char)
+        let (s1, s2, s3) = self.as_symbol_triple();
+        write!(f, "{}{}{}", resolve(s1), resolve(s2), resolve(s3))
     }
 }
 
-impl From<(u8, u8, u8)> for TrigramKey {
-    fn from(value: (u8, u8, u8)) -> Self {
-        let (b1, b2, b3) = value;
-        Self((b1 as u32) << 16 | (b2 as u32) << 8 | b3 as u32)
+impl From<(Symbol, Symbol, Symbol)> for TrigramKey {
+    fn from(value: (Symbol, Symbol, Symbol)) -> Self {
+        let (s1, s2, s3) = value;
+        Self((s1 as u64) << 32 | (s2 as u64) << 16 | s3 as u64)
     }
 }
 
-impl From<TrigramKey> for usize {
+impl From<[Symbol; 3]> for TrigramKey {
+    fn from(value: [Symbol; 3]) -> Self {
+        let [s1, s2, s3] = value;
+        Self::from((s1, s2, s3))
+    }
+}
+
+impl From<TrigramKey> for [Symbol; 3] {
     fn from(value: TrigramKey) -> Self {
-        value.as_usize()
+        value.as_symbols()
     }
 }
 
@@ -189,98 +189,557 @@ impl TryFrom<&str> for TrigramKey {
     type Error = String;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.len() == 3 && value.is_ascii() {
-            let bytes = value.as_bytes();
-            Ok(TrigramKey::from((bytes[0], bytes[1], bytes[2])))
-        } else {
-            Err(format!("Invalid trigram key '{}'", value))
+        match intern_str(value)?[..] {
+            [s1, s2, s3] => Ok(TrigramKey::from((s1, s2, s3))),
+            _ => Err(format!("Invalid trigram key '{}'", value)),
         }
     }
 }
 
 impl WriteStyled for TrigramKey {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()> {
-        writer.set_color(&STYLE_TRIGRAM_KEY)?;
+        writer.set_color(&theme().trigram_key)?;
         write!(writer, "{}", self)?;
         writer.reset()
     }
 }
 
-pub type UnigramTable = [u64; 1 << 8];
-pub type BigramTable = [u64; 1 << 16];
-pub type TrigramTable = [u64; 1 << 24];
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NgramFormat {
+    Genkey,
+    Oxeylyzer,
+    Tsv,
+}
+
+impl NgramFormat {
+    fn field_names(&self) -> [&'static str; 3] {
+        use NgramFormat::*;
+        match self {
+            Genkey => ["Letters", "Bigrams", "Trigrams"],
+            Oxeylyzer => ["characters", "bigrams", "trigrams"],
+            Tsv => ["", "", ""],
+        }
+    }
+
+    fn detect_json(object: &Map<String, Value>) -> Result<Self, String> {
+        use NgramFormat::*;
+        [Genkey, Oxeylyzer]
+            .into_iter()
+            .find(|format| object.contains_key(format.field_names()[0]))
+            .ok_or_else(|| {
+                "Unrecognized JSON n-gram format: expected a genkey or oxeylyzer corpus".into()
+            })
+    }
+}
+
+impl Display for NgramFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use NgramFormat::*;
+        match self {
+            Genkey => write!(f, "genkey"),
+            Oxeylyzer => write!(f, "oxeylyzer"),
+            Tsv => write!(f, "tsv"),
+        }
+    }
+}
 
-pub fn read_unigram_table<R: Read>(reader: R) -> Result<Box<UnigramTable>, Box<dyn Error>> {
-    read_ngram_table(reader, |s| UnigramKey::try_from(s))
+// NOTE Genkey and oxeylyzer corpora are usually lowercase, while the built-in n-gram tables and key
+// tables are uppercase, so by default the n-grams of these corpora are converted to uppercase (see
+// 'read_ngram_table_from_path').
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NgramCase {
+    Lower,
+    Preserve,
+    Upper,
 }
 
-pub fn read_unigram_table_from_bytes(
-    bytes: &'static [u8],
-) -> Result<Box<UnigramTable>, Box<dyn Error>> {
+impl NgramCase {
+    // NOTE A character whose other case is more than one character (such as 'ß') is not changed, so
+    // that the length of the n-gram is not changed.
+    fn apply(self, s: &str) -> String {
+        fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Some(ch),
+                _ => None,
+            }
+        }
+        s.chars()
+            .map(|ch| match self {
+                NgramCase::Lower => single(ch.to_lowercase()).unwrap_or(ch),
+                NgramCase::Preserve => ch,
+                NgramCase::Upper => single(ch.to_uppercase()).unwrap_or(ch),
+            })
+            .collect()
+    }
+}
+
+// NOTE An n-gram table only has room for the characters of its own n-grams (its alphabet), so its
+// size depends on the corpus rather than on the number of possible symbols. The symbols of the
+// alphabet are given the indices 1 to A in order; index 0 stands for every other symbol, so the
+// counts of n-grams with a symbol that is not in the alphabet are always zero. Since every index is
+// at most A, every key is in bounds of the (A + 1)^N counts.
+pub struct NgramTable<const N: usize> {
+    alphabet: Vec<Symbol>,
+    indices: Box<[u16; 1 << 16]>,
+    counts: Box<[u64]>,
+}
+
+pub type UnigramTable = NgramTable<1>;
+pub type BigramTable = NgramTable<2>;
+pub type TrigramTable = NgramTable<3>;
+
+// NOTE The largest n-gram table (in counts) that will be allocated: 1 GiB, which is room for a
+// trigram table of 511 distinct characters.
+const MAX_NGRAM_TABLE_LEN: usize = 1 << 27;
+
+impl<const N: usize> NgramTable<N> {
+    // NOTE The counts of repeated n-grams are summed.
+    pub fn from_entries(entries: Vec<([Symbol; N], u64)>) -> Result<Self, Box<dyn Error>> {
+        let mut alphabet = entries.iter().flat_map(|(key, _)| *key).collect::<Vec<_>>();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+        let len = (0..N)
+            .try_fold(1usize, |len, _| len.checked_mul(alphabet.len() + 1))
+            .filter(|&len| len <= MAX_NGRAM_TABLE_LEN)
+            .ok_or_else(|| {
+                format!(
+                    "Unable to allocate an n-gram table of order {} for {} distinct characters",
+                    N,
+                    alphabet.len()
+                )
+            })?;
+        let mut indices: Box<[u16; 1 << 16]> = vec![0; 1 << 16]
+            .into_boxed_slice()
+            .try_into()
+            .map_err(|_| "Unable to allocate an n-gram table index")?;
+        for (i, &symbol) in alphabet.iter().enumerate() {
+            indices[symbol as usize] = (i + 1) as u16;
+        }
+        let mut table = Self {
+            alphabet,
+            indices,
+            counts: vec![0; len].into_boxed_slice(),
+        };
+        for (key, value) in entries {
+            let i = table.index(key);
+            table.counts[i] = table.counts[i].saturating_add(value);
+        }
+        Ok(table)
+    }
+
+    #[inline]
+    fn index(&self, key: [Symbol; N]) -> usize {
+        let stride = self.alphabet.len() + 1;
+        key.iter().fold(0, |i, &symbol| {
+            i * stride + self.indices[symbol as usize] as usize
+        })
+    }
+
+    #[inline]
+    pub fn get(&self, key: [Symbol; N]) -> u64 {
+        self.counts[self.index(key)]
+    }
+
+    // NOTE Every key is in bounds (see 'NgramTable'), so this only skips the bounds check.
+    #[inline]
+    pub(crate) fn get_unchecked(&self, key: [Symbol; N]) -> u64 {
+        unsafe { *self.counts.get_unchecked(self.index(key)) }
+    }
+
+    // NOTE Returns whether the count was not already zero.
+    pub fn zero(&mut self, key: [Symbol; N]) -> bool {
+        let i = self.index(key);
+        let is_nonzero = self.counts[i] != 0;
+        self.counts[i] = 0;
+        is_nonzero
+    }
+
+    // NOTE The n-grams with a count other than zero, in order of key.
+    pub fn entries(&self) -> impl Iterator<Item = ([Symbol; N], u64)> + '_ {
+        let stride = self.alphabet.len() + 1;
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &value)| value != 0)
+            .map(move |(i, &value)| {
+                let mut key = [0; N];
+                let mut j = i;
+                for symbol in key.iter_mut().rev() {
+                    *symbol = self.alphabet[j % stride - 1];
+                    j /= stride;
+                }
+                (key, value)
+            })
+    }
+}
+
+pub fn read_unigram_table<R: Read>(reader: R) -> Result<UnigramTable, Box<dyn Error>> {
+    let (table, _) = read_ngram_table(reader, NgramCase::Preserve, |s| UnigramKey::try_from(s))?;
+    Ok(table)
+}
+
+pub fn read_unigram_table_from_bytes(bytes: &'static [u8]) -> Result<UnigramTable, Box<dyn Error>> {
     read_unigram_table(BufReader::new(bytes))
 }
 
-pub fn read_unigram_table_from_path(path: &Path) -> Result<Box<UnigramTable>, Box<dyn Error>> {
-    read_unigram_table(BufReader::new(File::open(path)?))
+pub fn read_unigram_table_from_path(
+    path: &Path,
+    format_opt: Option<NgramFormat>,
+    case_opt: Option<NgramCase>,
+) -> ReadResult<1> {
+    read_ngram_table_from_path(path, format_opt, case_opt, |s| UnigramKey::try_from(s))
 }
 
-pub fn read_bigram_table<R: Read>(reader: R) -> Result<Box<BigramTable>, Box<dyn Error>> {
-    read_ngram_table(reader, |s| BigramKey::try_from(s))
+pub fn read_bigram_table<R: Read>(reader: R) -> Result<BigramTable, Box<dyn Error>> {
+    let (table, _) = read_ngram_table(reader, NgramCase::Preserve, |s| BigramKey::try_from(s))?;
+    Ok(table)
 }
 
-pub fn read_bigram_table_from_bytes(
-    bytes: &'static [u8],
-) -> Result<Box<BigramTable>, Box<dyn Error>> {
+pub fn read_bigram_table_from_bytes(bytes: &'static [u8]) -> Result<BigramTable, Box<dyn Error>> {
     read_bigram_table(BufReader::new(bytes))
 }
 
-pub fn read_bigram_table_from_path(path: &Path) -> Result<Box<BigramTable>, Box<dyn Error>> {
-    read_bigram_table(BufReader::new(File::open(path)?))
+pub fn read_bigram_table_from_path(
+    path: &Path,
+    format_opt: Option<NgramFormat>,
+    case_opt: Option<NgramCase>,
+) -> ReadResult<2> {
+    read_ngram_table_from_path(path, format_opt, case_opt, |s| BigramKey::try_from(s))
 }
 
-pub fn read_trigram_table<R: Read>(reader: R) -> Result<Box<TrigramTable>, Box<dyn Error>> {
-    read_ngram_table(reader, |s| TrigramKey::try_from(s))
+pub fn read_trigram_table<R: Read>(reader: R) -> Result<TrigramTable, Box<dyn Error>> {
+    let (table, _) = read_ngram_table(reader, NgramCase::Preserve, |s| TrigramKey::try_from(s))?;
+    Ok(table)
 }
 
-pub fn read_trigram_table_from_bytes(
-    bytes: &'static [u8],
-) -> Result<Box<TrigramTable>, Box<dyn Error>> {
+pub fn read_trigram_table_from_bytes(bytes: &'static [u8]) -> Result<TrigramTable, Box<dyn Error>> {
     read_trigram_table(BufReader::new(bytes))
 }
 
-pub fn read_trigram_table_from_path(path: &Path) -> Result<Box<TrigramTable>, Box<dyn Error>> {
-    read_trigram_table(BufReader::new(File::open(path)?))
+pub fn read_trigram_table_from_path(
+    path: &Path,
+    format_opt: Option<NgramFormat>,
+    case_opt: Option<NgramCase>,
+) -> ReadResult<3> {
+    read_ngram_table_from_path(path, format_opt, case_opt, |s| TrigramKey::try_from(s))
+}
+
+// NOTE Each character of each n-gram is mapped using 'fold_map' (indexed by symbol), and the counts
+// of n-grams that are mapped to the same n-gram are summed.
+pub fn fold_ngram_table<const N: usize>(
+    table: &NgramTable<N>,
+    fold_map: &[Symbol],
+) -> Result<NgramTable<N>, Box<dyn Error>> {
+    NgramTable::from_entries(
+        table
+            .entries()
+            .map(|(key, value)| (key.map(|symbol| fold_map[symbol as usize]), value))
+            .collect(),
+    )
+}
+
+// NOTE Reads a list of n-grams of any order, one per line in column 0 of a TSV file. Any other
+// columns (such as counts) are ignored, so an n-gram table can also be used as a list. Unlike
+// n-gram tables, lines may have different numbers of columns, and blank lines are skipped.
+pub fn read_ngram_list_from_path(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut ngrams = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let key_str = line.split('\t').next().unwrap_or_default();
+        if !key_str.is_empty() {
+            ngrams.push(unescape::<true>(key_str)?);
+        }
+    }
+    Ok(ngrams)
 }
 
-pub fn sum_ngram_table<T: Copy + iter::Sum<T>>(slice: &[T]) -> T {
-    slice.iter().copied().sum()
+// NOTE Zeroes the counts of the listed n-grams in each table. Each n-gram is matched against the
+// table of its order; n-grams longer than three characters, and those with characters that are
+// not permitted, are skipped. Returns the number of n-grams zeroed.
+pub fn zero_ngram_tables(
+    unigram_table: &mut UnigramTable,
+    bigram_table: &mut BigramTable,
+    trigram_table: &mut TrigramTable,
+    ngrams: &[String],
+) -> Result<usize, Box<dyn Error>> {
+    Ok(
+        zero_ngram_table(unigram_table, ngrams, |s| UnigramKey::try_from(s))?
+            + zero_ngram_table(bigram_table, ngrams, |s| BigramKey::try_from(s))?
+            + zero_ngram_table(trigram_table, ngrams, |s| TrigramKey::try_from(s))?,
+    )
 }
 
-fn read_ngram_table<const N: usize, K: Into<usize>, R: Read>(
+fn zero_ngram_table<const N: usize, K: Into<[Symbol; N]>>(
+    table: &mut NgramTable<N>,
+    ngrams: &[String],
+    key_fn: impl Fn(&str) -> Result<K, String>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut n_zeroed = 0;
+    for key_str in ngrams {
+        // NOTE An n-gram with a character that cannot be a symbol is not in the table.
+        let is_present = intern_str(key_str).is_ok();
+        if key_str.chars().count() == N
+            && !is_reserved_key(key_str)
+            && is_present
+            && table.zero(key_fn(key_str)?.into())
+        {
+            n_zeroed += 1;
+        }
+    }
+    Ok(n_zeroed)
+}
+
+// NOTE The 'n_top' n-grams with the highest counts, in descending order of count (ties are
+// broken by key). N-grams with a count of zero are omitted.
+pub fn top_ngrams<const N: usize, K>(
+    table: &NgramTable<N>,
+    n_top: usize,
+    key_fn: impl Fn([Symbol; N]) -> K,
+) -> Vec<(K, u64)> {
+    top_ngrams_where(table, n_top, |_| true, key_fn)
+}
+
+fn top_ngrams_where<const N: usize, K>(
+    table: &NgramTable<N>,
+    n_top: usize,
+    predicate: impl Fn(&[Symbol; N]) -> bool,
+    key_fn: impl Fn([Symbol; N]) -> K,
+) -> Vec<(K, u64)> {
+    let mut entries = table
+        .entries()
+        .filter(|(key, _)| predicate(key))
+        .collect::<Vec<_>>();
+    entries.sort_by(|(key1, value1), (key2, value2)| value2.cmp(value1).then(key1.cmp(key2)));
+    entries
+        .into_iter()
+        .take(n_top)
+        .map(|(key, value)| (key_fn(key), value))
+        .collect()
+}
+
+// NOTE The coverage of an n-gram table by a set of characters: the sum of the table, the sum of
+// the n-grams made only of those characters, and the most frequent n-grams that are not.
+#[derive(Clone, Debug)]
+pub struct CoverageRow<K> {
+    pub sum: u64,
+    pub covered: u64,
+    pub uncovered: Vec<(K, u64)>,
+}
+
+impl<K> CoverageRow<K> {
+    fn new<const N: usize>(
+        table: &NgramTable<N>,
+        is_present: &[bool],
+        key_fn: impl Fn([Symbol; N]) -> K,
+    ) -> Self {
+        const N_UNCOVERED: usize = 5;
+        let is_covered = |key: &[Symbol; N]| key.iter().all(|&symbol| is_present[symbol as usize]);
+        let covered = table
+            .entries()
+            .filter(|(key, _)| is_covered(key))
+            .fold(0u64, |acc, (_, value)| acc.saturating_add(value));
+        Self {
+            sum: sum_ngram_table(table),
+            covered,
+            uncovered: top_ngrams_where(table, N_UNCOVERED, |key| !is_covered(key), key_fn),
+        }
+    }
+
+    pub fn perc(&self) -> Option<f64> {
+        calculate_perc(self.covered, self.sum)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.covered == self.sum
+    }
+}
+
+// NOTE Which share of each n-gram table can be typed with a set of characters (e.g., those of a key
+// table and its regions). N-grams with characters that are not present are never scored, so a
+// low coverage usually means a character is missing from the key table.
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    pub unigrams: CoverageRow<UnigramKey>,
+    pub bigrams: CoverageRow<BigramKey>,
+    pub trigrams: CoverageRow<TrigramKey>,
+}
+
+impl Coverage {
+    // NOTE 'is_present' is indexed by symbol.
+    pub fn new(
+        unigram_table: &UnigramTable,
+        bigram_table: &BigramTable,
+        trigram_table: &TrigramTable,
+        is_present: &[bool],
+    ) -> Self {
+        Self {
+            unigrams: CoverageRow::new(unigram_table, is_present, UnigramKey::from),
+            bigrams: CoverageRow::new(bigram_table, is_present, BigramKey::from),
+            trigrams: CoverageRow::new(trigram_table, is_present, TrigramKey::from),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.unigrams.is_complete() && self.bigrams.is_complete() && self.trigrams.is_complete()
+    }
+}
+
+impl<K: Display> From<&CoverageRow<K>> for Value {
+    fn from(value: &CoverageRow<K>) -> Self {
+        json!({
+            "sum": value.sum,
+            "covered": value.covered,
+            "perc": value.perc(),
+            "uncovered": value
+                .uncovered
+                .iter()
+                .map(|(key, count)| json!([key.to_string(), count]))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl From<&Coverage> for Value {
+    fn from(value: &Coverage) -> Self {
+        json!({
+            "unigrams": Value::from(&value.unigrams),
+            "bigrams": Value::from(&value.bigrams),
+            "trigrams": Value::from(&value.trigrams),
+        })
+    }
+}
+
+// NOTE Saturates at 'u64::MAX' rather than overflowing.
+pub fn sum_ngram_table<const N: usize>(table: &NgramTable<N>) -> u64 {
+    table
+        .counts
+        .iter()
+        .fold(0, |acc, &value| acc.saturating_add(value))
+}
+
+// NOTE Unless a case is given, the n-grams of genkey and oxeylyzer corpora are converted to
+// uppercase, and those of TSV files are not changed.
+fn read_ngram_table_from_path<const N: usize, K: Into<[Symbol; N]>>(
+    path: &Path,
+    format_opt: Option<NgramFormat>,
+    case_opt: Option<NgramCase>,
+    key_fn: impl Fn(&str) -> Result<K, String>,
+) -> ReadResult<N> {
+    let reader = BufReader::new(File::open(path)?);
+    let is_json = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    match format_opt {
+        Some(NgramFormat::Tsv) => {
+            read_ngram_table(reader, case_opt.unwrap_or(NgramCase::Preserve), key_fn)
+        }
+        None if !is_json => {
+            read_ngram_table(reader, case_opt.unwrap_or(NgramCase::Preserve), key_fn)
+        }
+        _ => read_ngram_table_json(
+            reader,
+            format_opt,
+            case_opt.unwrap_or(NgramCase::Upper),
+            key_fn,
+        ),
+    }
+}
+
+// NOTE The control characters SOH, STX, and ETX are reserved, so n-grams with them are skipped.
+fn is_reserved_key(key_str: &str) -> bool {
+    key_str.chars().any(|ch| ('\x01'..='\x03').contains(&ch))
+}
+
+// NOTE An n-gram with a character that cannot be a symbol (see 'symbols') cannot be typed on any
+// key table, so it is skipped when an n-gram table is read. Skipped n-grams are counted (with the
+// sum of their counts) so that they can be reported.
+#[derive(Clone, Debug, Default)]
+pub struct SkippedNgrams {
+    pub count: usize,
+    pub sum: u64,
+    pub first_opt: Option<String>,
+}
+
+impl SkippedNgrams {
+    fn add(&mut self, key_str: &str, value: u64) {
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.first_opt.get_or_insert_with(|| key_str.to_string());
+    }
+}
+
+type ReadResult<const N: usize> = Result<(NgramTable<N>, SkippedNgrams), Box<dyn Error>>;
+
+// NOTE Genkey stores counts, but oxeylyzer stores frequencies, which are scaled to integers.
+// Skipgrams are not used by Perky and are ignored. The counts of n-grams that become the same when
+// their case is changed are summed.
+fn read_ngram_table_json<const N: usize, K: Into<[Symbol; N]>, R: Read>(
     reader: R,
+    format_opt: Option<NgramFormat>,
+    case: NgramCase,
     key_fn: impl Fn(&str) -> Result<K, String>,
-) -> Result<Box<[u64; N]>, Box<dyn Error>> {
-    // NOTE This can cause a stack overflow for large values of N.
-    // let mut array = Box::new([0u64; N]);
-    let mut array: Box<[u64; N]> = vec![0u64; N]
-        .into_boxed_slice()
-        .try_into()
-        .map_err(|_| format!("Unable to allocate an array of {} elements", N))?;
+) -> ReadResult<N> {
+    const OXEYLYZER_SCALE: f64 = 1e12;
+    let value = read_json::<_, Value>(reader)?;
+    let object = value.as_object().ok_or("Expected top-level JSON object")?;
+    let format = match format_opt {
+        None => NgramFormat::detect_json(object)?,
+        Some(format) => format,
+    };
+    let field_name = format.field_names()[N - 1];
+    let entries = object
+        .get(field_name)
+        .ok_or_else(|| format!("Expected '{}' field", field_name))?
+        .as_object()
+        .ok_or_else(|| format!("Value of '{}' field must be an object", field_name))?;
+    let mut keys_and_values = Vec::with_capacity(entries.len());
+    let mut skipped = SkippedNgrams::default();
+    for (key_str, value) in entries {
+        let key_str = case.apply(key_str);
+        if is_reserved_key(&key_str) {
+            continue;
+        }
+        let value = match (format, value.as_u64(), value.as_f64()) {
+            (NgramFormat::Oxeylyzer, _, Some(f)) if f >= 0.0 => {
+                (f * OXEYLYZER_SCALE).round() as u64
+            }
+            (_, Some(u), _) => u,
+            (_, None, Some(f)) if f >= 0.0 => f.round() as u64,
+            _ => Err(format!("Invalid value '{}' for key '{}'", value, key_str))?,
+        };
+        if intern_str(&key_str).is_err() {
+            skipped.add(&key_str, value);
+            continue;
+        }
+        keys_and_values.push((key_fn(&key_str)?.into(), value));
+    }
+    Ok((NgramTable::from_entries(keys_and_values)?, skipped))
+}
+
+fn read_ngram_table<const N: usize, K: Into<[Symbol; N]>, R: Read>(
+    reader: R,
+    case: NgramCase,
+    key_fn: impl Fn(&str) -> Result<K, String>,
+) -> ReadResult<N> {
+    let mut keys_and_values = Vec::new();
+    let mut skipped = SkippedNgrams::default();
     for result in get_tsv_reader(reader).records() {
         let record: StringRecord = result?;
-        let key_str = unescape::<true>(record.get(0).ok_or("Missing key column")?)?;
-        // NOTE Characters must be ASCII, and the control characters SOH, STX, and ETX are reserved.
-        if key_str
-            .chars()
-            .all(|ch| ch == '\0' || ('\x04'..='\x7f').contains(&ch))
-        {
-            let key = key_fn(&key_str)?;
-            let value_str = record.get(1).ok_or("Missing value column")?;
-            let value: u64 = value_str.parse().map_err(|e| {
-                format!("Invalid value '{}' for key '{}': {}", value_str, key_str, e)
-            })?;
-            array[key.into()] = value;
+        let key_str = case.apply(&unescape::<true>(
+            record.get(0).ok_or("Missing key column")?,
+        )?);
+        if is_reserved_key(&key_str) {
+            continue;
+        }
+        let value_str = record.get(1).ok_or("Missing value column")?;
+        let value: u64 = value_str
+            .parse()
+            .map_err(|e| format!("Invalid value '{}' for key '{}': {}", value_str, key_str, e))?;
+        if intern_str(&key_str).is_err() {
+            skipped.add(&key_str, value);
+            continue;
         }
+        keys_and_values.push((key_fn(&key_str)?.into(), value));
     }
-    Ok(array)
+    Ok((NgramTable::from_entries(keys_and_values)?, skipped))
 }
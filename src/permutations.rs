@@ -1,39 +1,198 @@
-use core::{error::Error, sync::atomic, time::Duration};
+use core::{
+    error::Error,
+    hint::black_box,
+    ops::RangeInclusive,
+    sync::atomic::{self, AtomicU64},
+    time::Duration,
+};
 
 use std::{
     collections::VecDeque,
     sync::{Arc, Mutex},
     thread::sleep,
+    time::Instant,
 };
 
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::{
+    ThreadPool, ThreadPoolBuilder,
+    iter::{IntoParallelIterator, ParallelIterator},
+};
 
 use crate::{
-    goals::Goal,
-    util::math::{factorial, generate_permutations_to_limit, index_to_permutation_in_place},
+    goals::{Goal, Tolerance},
+    log_debug,
+    symbols::{Symbol, intern_str, resolve},
+    ui::log::{Verbosity, is_enabled},
+    util::{
+        hash::seeded_hash,
+        math::{
+            factorial, factorial_u128, generate_permutations_to_limit, index_to_permutation,
+            index_to_permutation_in_place, permutation_to_index, permutation_to_index_u128,
+        },
+    },
 };
 
+// NOTE The permutation index is the index that 'index_to_permutation' (applied to each region in
+// turn, with region 1 most significant) maps to this record, regardless of the order in which
+// permutations were visited. Each region's assignments are the characters placed at its
+// coordinates, in the order that the coordinates appear in the key table.
+#[derive(Clone, Debug)]
+pub struct Provenance {
+    pub permutation_index: u64,
+    pub assignments: [Vec<(Symbol, (usize, usize))>; 3],
+}
+
+pub fn calculate_provenance<const C: usize, const R: usize, const N: usize>(
+    matrix: &[[Symbol; C]; R],
+    region1: ([Symbol; N], usize, &[(usize, usize)]),
+    region2: ([Symbol; N], usize, &[(usize, usize)]),
+    region3: ([Symbol; N], usize, &[(usize, usize)]),
+) -> Option<Provenance> {
+    let mut permutation_index = 0u64;
+    let mut assignments: [Vec<(Symbol, (usize, usize))>; 3] = Default::default();
+    for (i, (array, length, coordinates)) in [region1, region2, region3].into_iter().enumerate() {
+        let length = length.min(N).min(coordinates.len());
+        let permutation = coordinates[..length]
+            .iter()
+            .map(|&(r, c)| matrix[r][c])
+            .collect::<Vec<_>>();
+        let index = permutation_to_index(&permutation, &array[..length])?;
+        permutation_index = permutation_index
+            .checked_mul(factorial(length as u64))?
+            .checked_add(index)?;
+        assignments[i] = permutation
+            .into_iter()
+            .zip(coordinates.iter().copied())
+            .collect();
+    }
+    assignments
+        .iter()
+        .any(|assignment| !assignment.is_empty())
+        .then_some(Provenance {
+            permutation_index,
+            assignments,
+        })
+}
+
+// NOTE A ratio is clamped to 0.0 to 1.0, and a percentage to at least 0.0.
 #[inline]
-fn calculate_threshold(goal: Goal, best: u64, tolerance: f64) -> u64 {
-    if tolerance == 1.0 {
-        best
-    } else {
-        use Goal::*;
-        match goal {
-            Max if tolerance == 0.0 => 0,
-            Max => ((best as f64) * tolerance).floor() as u64,
-            Min if tolerance == 0.0 => u64::MAX,
-            Min => ((best as f64) / tolerance).ceil() as u64,
+pub fn calculate_threshold(goal: Goal, best: u64, tolerance: Tolerance) -> u64 {
+    use Goal::*;
+    match tolerance {
+        Tolerance::Ratio(ratio) => {
+            let ratio = ratio.clamp(0.0, 1.0);
+            if ratio == 1.0 {
+                best
+            } else {
+                match goal {
+                    Max if ratio == 0.0 => 0,
+                    Max => ((best as f64) * ratio).floor() as u64,
+                    Min if ratio == 0.0 => u64::MAX,
+                    Min => ((best as f64) / ratio).ceil() as u64,
+                }
+            }
         }
+        Tolerance::Percent(percent) => {
+            let percent = percent.max(0.0);
+            if percent == 0.0 {
+                best
+            } else {
+                match goal {
+                    Max => ((best as f64) * (1.0 - percent / 100.0)).max(0.0).floor() as u64,
+                    Min => ((best as f64) * (1.0 + percent / 100.0)).ceil() as u64,
+                }
+            }
+        }
+        Tolerance::Absolute(delta) => match goal {
+            Max => best.saturating_sub(delta),
+            Min => best.saturating_add(delta),
+        },
     }
 }
 
+// NOTE Permutation indices are calculated in u128, so they are exact for regions of up to 34
+// characters (although no more than 'u64::MAX' permutations can be visited).
+fn calculate_totals(lengths: [usize; 3]) -> ([u128; 3], u128) {
+    let totals = lengths.map(|length| factorial_u128(length as u64));
+    let total_permutations = totals
+        .iter()
+        .fold(1u128, |acc, &total| acc.saturating_mul(total));
+    (totals, total_permutations)
+}
+
+// NOTE Splits a permutation index into the index of each region (region 1 most significant).
+// Each region's index is kept in u128, since a region of more than 20 characters has indices that
+// do not fit in a u64.
 #[inline]
-fn drop_above_threshold<const C: usize, const R: usize>(
-    deque: &mut VecDeque<(u64, u64, [[u8; C]; R])>,
-    threshold: u64,
-) {
-    while let Some((score, _, _)) = deque.front() {
+fn split_index(index: u128, totals: [u128; 3]) -> [u128; 3] {
+    let [_, total2, total3] = totals;
+    [
+        index / total2.saturating_mul(total3),
+        (index / total3) % total2,
+        index % total3,
+    ]
+}
+
+// NOTE As 'split_index', for the indices that are visited while permuting. Each region's index is
+// at most 'index', so a total that does not fit in a u64 can be replaced by 'u64::MAX' (which is
+// greater than any index visited) without changing the result.
+#[inline]
+fn split_index_u64(index: u64, totals: [u128; 3]) -> [u64; 3] {
+    let [_, total2, total3] = totals.map(|total| u64::try_from(total).unwrap_or(u64::MAX));
+    [
+        index / total2.saturating_mul(total3),
+        (index / total3) % total2,
+        index % total3,
+    ]
+}
+
+// NOTE The inverse of 'split_index'.
+#[inline]
+fn join_index(region_indices: [u128; 3], totals: [u128; 3]) -> u128 {
+    let [index1, index2, index3] = region_indices;
+    let [_, total2, total3] = totals;
+    index1
+        .saturating_mul(total2)
+        .saturating_add(index2)
+        .saturating_mul(total3)
+        .saturating_add(index3)
+}
+
+// NOTE While permuting, a record is retained as its score and permutation index (see
+// 'Provenance') rather than its key table, which is reconstructed only once permuting is done (see
+// 'reconstruct_matrix'). This is a fraction of the size of a key table.
+type RetainedRecord = (u64, u128);
+
+pub const RETAINED_RECORD_BYTES: usize = size_of::<RetainedRecord>();
+
+// NOTE The inverse of the permutation index: the key table with the permutation of each region
+// substituted at its coordinates.
+fn reconstruct_matrix<const C: usize, const R: usize>(
+    matrix: &[[Symbol; C]; R],
+    arrays: [&[Symbol]; 3],
+    coordinates: [&[(usize, usize)]; 3],
+    totals: [u128; 3],
+    index: u128,
+) -> [[Symbol; C]; R] {
+    let mut matrix = *matrix;
+    for ((region_index, array), coordinates) in split_index(index, totals)
+        .into_iter()
+        .zip(arrays)
+        .zip(coordinates)
+    {
+        for (byte, &(r, c)) in index_to_permutation(region_index, array)
+            .into_iter()
+            .zip(coordinates)
+        {
+            matrix[r][c] = byte;
+        }
+    }
+    matrix
+}
+
+#[inline]
+fn drop_above_threshold(deque: &mut VecDeque<RetainedRecord>, threshold: u64) {
+    while let Some((score, _)) = deque.front() {
         if *score <= threshold {
             break;
         }
@@ -42,11 +201,8 @@ fn drop_above_threshold<const C: usize, const R: usize>(
 }
 
 #[inline]
-fn drop_below_threshold<const C: usize, const R: usize>(
-    deque: &mut VecDeque<(u64, u64, [[u8; C]; R])>,
-    threshold: u64,
-) {
-    while let Some((score, _, _)) = deque.back() {
+fn drop_below_threshold(deque: &mut VecDeque<RetainedRecord>, threshold: u64) {
+    while let Some((score, _)) = deque.back() {
         if *score >= threshold {
             break;
         }
@@ -54,33 +210,53 @@ fn drop_below_threshold<const C: usize, const R: usize>(
     }
 }
 
+// NOTE Ties between records with the same score are broken by permutation index, so truncating
+// keeps the records with the lowest indices. Seeded, ties are broken by a hash of the permutation
+// index (which identifies the key table) instead, so truncating keeps a sample of the tied records
+// that is not biased towards any region of the permutation space, and is the same for the same
+// seed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TieBreak {
+    #[default]
+    Index,
+    Seeded(u64),
+}
+
+impl TieBreak {
+    #[inline]
+    pub fn compare(self, index1: u128, index2: u128) -> core::cmp::Ordering {
+        match self {
+            TieBreak::Index => index1.cmp(&index2),
+            TieBreak::Seeded(seed) => seeded_hash(seed, &index1.to_le_bytes())
+                .cmp(&seeded_hash(seed, &index2.to_le_bytes()))
+                .then(index1.cmp(&index2)),
+        }
+    }
+}
+
 #[inline]
-fn insert_sorted<const C: usize, const R: usize>(
-    deque: &mut VecDeque<(u64, u64, [[u8; C]; R])>,
+fn insert_sorted(
+    deque: &mut VecDeque<RetainedRecord>,
     score: u64,
-    index: u64,
-    matrix: [[u8; C]; R],
+    index: u128,
+    tie_break: TieBreak,
 ) {
-    let pos = match deque.binary_search_by(|(s, i, _)| match s.cmp(&score).reverse() {
-        core::cmp::Ordering::Equal => i.cmp(&index),
+    let pos = match deque.binary_search_by(|(s, i)| match s.cmp(&score).reverse() {
+        core::cmp::Ordering::Equal => tie_break.compare(*i, index),
         other => other,
     }) {
         Ok(i) => i + 1,
         Err(i) => i,
     };
     if pos == deque.len() {
-        deque.push_back((score, index, matrix));
+        deque.push_back((score, index));
     } else {
-        deque.insert(pos, (score, index, matrix));
+        deque.insert(pos, (score, index));
     }
 }
 
 #[inline]
-fn truncate<const C: usize, const R: usize>(
-    deque: &mut VecDeque<(u64, u64, [[u8; C]; R])>,
-    goal: Goal,
-    max_records_opt: Option<u64>,
-) {
+fn truncate(deque: &mut VecDeque<RetainedRecord>, goal: Goal, max_records_opt: Option<u64>) {
     if let Some(max_records) = max_records_opt {
         let max_records = max_records as usize;
         use Goal::*;
@@ -99,28 +275,76 @@ fn truncate<const C: usize, const R: usize>(
     }
 }
 
+// NOTE Each thread keeps its own best score, so improvements are also checked against the best
+// score across all threads, which is reported to the progress callback, before being logged.
+#[cold]
+fn update_best_score(goal: Goal, score: u64, index: u128, global_best_score: &AtomicU64) {
+    use Goal::*;
+    let improved = match goal {
+        Max => global_best_score.fetch_max(score, atomic::Ordering::Relaxed) < score,
+        Min => global_best_score.fetch_min(score, atomic::Ordering::Relaxed) > score,
+    };
+    if improved && is_enabled(Verbosity::Debug) {
+        log_debug!("Best score improved to {} at permutation {}", score, index);
+    }
+}
+
+// NOTE Reported to the progress callback. When permuting in parallel, the number of records is the
+// total retained by all threads so far, before their records are merged (and truncated).
+#[derive(Clone, Copy, Debug)]
+pub struct PermutationProgress {
+    pub n_permutations: u64,
+    pub best_score_opt: Option<u64>,
+    pub threshold_score_opt: Option<u64>,
+    pub n_records: u64,
+}
+
+fn load_progress(
+    n_permutations: u64,
+    goal: Goal,
+    tolerance: Tolerance,
+    global_best_score: &AtomicU64,
+    global_n_records: &AtomicU64,
+) -> PermutationProgress {
+    let best_score_opt =
+        (n_permutations > 0).then(|| global_best_score.load(atomic::Ordering::Relaxed));
+    PermutationProgress {
+        n_permutations,
+        best_score_opt,
+        threshold_score_opt: best_score_opt
+            .map(|best_score| calculate_threshold(goal, best_score, tolerance)),
+        n_records: global_n_records.load(atomic::Ordering::Relaxed),
+    }
+}
+
+// NOTE The permutation index (see 'Provenance') breaks ties between records with the same score
+// (see 'TieBreak'). It is calculated only if the record is kept.
 #[inline]
-fn consider_record<const C: usize, const R: usize>(
-    matrix: [[u8; C]; R],
+fn consider_record(
     score: u64,
-    index: u64,
+    index_fn: impl Fn() -> u128,
     goal: Goal,
-    tolerance: f64,
+    tolerance: Tolerance,
     max_records_opt: Option<u64>,
-    records: &mut VecDeque<(u64, u64, [[u8; C]; R])>,
+    tie_break: TieBreak,
+    records: &mut VecDeque<RetainedRecord>,
     best_score: &mut u64,
     threshold_score: &mut u64,
+    global_best_score: &AtomicU64,
+    global_n_records: &AtomicU64,
 ) {
     use Goal::*;
+    let length = records.len();
     match goal {
         Max => {
             if score > *best_score {
                 *best_score = score;
                 *threshold_score = calculate_threshold(goal, *best_score, tolerance);
                 drop_below_threshold(records, *threshold_score);
+                update_best_score(goal, score, index_fn(), global_best_score);
             }
             if score >= *threshold_score {
-                insert_sorted(records, score, index, matrix);
+                insert_sorted(records, score, index_fn(), tie_break);
                 truncate(records, goal, max_records_opt);
             }
         }
@@ -129,13 +353,20 @@ fn consider_record<const C: usize, const R: usize>(
                 *best_score = score;
                 *threshold_score = calculate_threshold(goal, *best_score, tolerance);
                 drop_above_threshold(records, *threshold_score);
+                update_best_score(goal, score, index_fn(), global_best_score);
             }
             if score <= *threshold_score {
-                insert_sorted(records, score, index, matrix);
+                insert_sorted(records, score, index_fn(), tie_break);
                 truncate(records, goal, max_records_opt);
             }
         }
     }
+    let new_length = records.len();
+    if new_length > length {
+        global_n_records.fetch_add((new_length - length) as u64, atomic::Ordering::Relaxed);
+    } else if new_length < length {
+        global_n_records.fetch_sub((length - new_length) as u64, atomic::Ordering::Relaxed);
+    }
 }
 
 pub fn convert_vec_opt_to_array<const N: usize, T: Default + Copy>(
@@ -157,23 +388,172 @@ pub fn convert_vec_opt_to_array<const N: usize, T: Default + Copy>(
     }
 }
 
+// NOTE A pin restricts some of a region's characters to the keys of that region in certain rows
+// and columns (counted from 0), such as "AEIOU@r1,c6-10". A key is in the pinned area if it is in
+// any of the rows (or there are none) and any of the columns (or there are none).
+#[derive(Clone, Debug)]
+pub struct Pin {
+    symbols: Vec<Symbol>,
+    rows: Vec<RangeInclusive<usize>>,
+    cols: Vec<RangeInclusive<usize>>,
+}
+
+impl Pin {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (characters, selectors) = s
+            .rsplit_once('@')
+            .ok_or("Expected characters, '@', and rows or columns (such as \"AEIOU@r1,c6-10\")")?;
+        let mut rows = Vec::new();
+        let mut cols = Vec::new();
+        for selector in selectors.split(',').map(str::trim) {
+            let (ranges, range) = match selector.split_at_checked(1) {
+                Some(("r", range)) => (&mut rows, range),
+                Some(("c", range)) => (&mut cols, range),
+                _ => Err(format!(
+                    "Invalid selector '{}'. Expected 'r' or 'c' followed by a number or a range",
+                    selector
+                ))?,
+            };
+            let parse = |s: &str| {
+                s.trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid selector '{}'", selector))
+            };
+            ranges.push(match range.split_once('-') {
+                Some((start, end)) => parse(start)?..=parse(end)?,
+                None => parse(range)?..=parse(range)?,
+            });
+        }
+        let symbols = intern_str(characters)?;
+        if symbols.is_empty() {
+            Err("Expected at least one character to pin")?;
+        }
+        Ok(Self {
+            symbols,
+            rows,
+            cols,
+        })
+    }
+
+    pub fn contains(&self, r: usize, c: usize) -> bool {
+        (self.rows.is_empty() || self.rows.iter().any(|range| range.contains(&r)))
+            && (self.cols.is_empty() || self.cols.iter().any(|range| range.contains(&c)))
+    }
+}
+
+// NOTE A region's characters, the number of them, and the coordinates of its keys.
+pub type Region<const N: usize> = ([Symbol; N], usize, Vec<(usize, usize)>);
+
+// NOTE Splits the pinned characters and keys from a region into a sub-region. The two are permuted
+// independently, so the region's n! permutations shrink to k! (n - k)!, for k pinned characters.
+pub fn split_region<const N: usize>(
+    region: &mut Region<N>,
+    pin: &Pin,
+) -> Result<Region<N>, String> {
+    let (array, length, coordinates) = region;
+    if *length == 0 {
+        Err("The region has no characters to pin")?;
+    }
+    let (pinned_coordinates, other_coordinates): (Vec<_>, Vec<_>) =
+        coordinates.iter().partition(|&&(r, c)| pin.contains(r, c));
+    if pinned_coordinates.len() != pin.symbols.len() {
+        Err(format!(
+            "There are ({}) keys of the region in the pinned rows and columns, \
+             but ({}) characters to pin",
+            pinned_coordinates.len(),
+            pin.symbols.len()
+        ))?
+    }
+    let mut other_symbols = array[..*length].to_vec();
+    for &symbol in &pin.symbols {
+        let i = other_symbols
+            .iter()
+            .position(|&other| other == symbol)
+            .ok_or_else(|| format!("Character '{}' is not in the region", resolve(symbol)))?;
+        other_symbols.remove(i);
+    }
+    let (pinned_array, pinned_length) =
+        convert_vec_opt_to_array::<N, _>(Some(pin.symbols.clone())).map_err(|e| e.to_string())?;
+    let (other_array, other_length) =
+        convert_vec_opt_to_array::<N, _>(Some(other_symbols)).map_err(|e| e.to_string())?;
+    *array = other_array;
+    *length = other_length;
+    *coordinates = other_coordinates;
+    Ok((pinned_array, pinned_length, pinned_coordinates))
+}
+
+pub fn estimate_duration_per_permutation<const C: usize, const R: usize, const N: usize>(
+    matrix: &[[Symbol; C]; R],
+    region1: ([Symbol; N], usize, &[(usize, usize)]),
+    region2: ([Symbol; N], usize, &[(usize, usize)]),
+    region3: ([Symbol; N], usize, &[(usize, usize)]),
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64>,
+    max_samples: u64,
+) -> Option<Duration> {
+    let (array1, length1, coordinates1) = region1;
+    let (array2, length2, coordinates2) = region2;
+    let (array3, length3, coordinates3) = region3;
+    let length1 = length1.min(N);
+    let length2 = length2.min(N);
+    let length3 = length3.min(N);
+    let (totals, total_permutations) = calculate_totals([length1, length2, length3]);
+    let total_permutations = u64::try_from(total_permutations).unwrap_or(u64::MAX);
+    let samples = max_samples.min(total_permutations);
+    if samples == 0 {
+        return None;
+    }
+    // NOTE Samples are spread evenly across the permutation space, so that the cost of
+    // substitution (which varies with the index) is representative of the whole run.
+    let stride = total_permutations / samples;
+    let mut matrix = *matrix;
+    let mut p1 = [0 as Symbol; N];
+    let mut p2 = [0 as Symbol; N];
+    let mut p3 = [0 as Symbol; N];
+    let start = Instant::now();
+    for sample in 0..samples {
+        let [index1, index2, index3] = split_index_u64(sample * stride, totals);
+        index_to_permutation_in_place::<N, Symbol>(index1, &array1[..length1], &mut p1[..length1]);
+        index_to_permutation_in_place::<N, Symbol>(index2, &array2[..length2], &mut p2[..length2]);
+        index_to_permutation_in_place::<N, Symbol>(index3, &array3[..length3], &mut p3[..length3]);
+        for (i, &(r, c)) in coordinates1[..length1].iter().enumerate() {
+            matrix[r][c] = p1[i];
+        }
+        for (i, &(r, c)) in coordinates2[..length2].iter().enumerate() {
+            matrix[r][c] = p2[i];
+        }
+        for (i, &(r, c)) in coordinates3[..length3].iter().enumerate() {
+            matrix[r][c] = p3[i];
+        }
+        black_box(scoring_fn(black_box(&matrix)));
+    }
+    Some(Duration::from_secs_f64(
+        start.elapsed().as_secs_f64() / samples as f64,
+    ))
+}
+
+// NOTE A permutation for which 'scoring_fn' returns 'None' is skipped (e.g., because it violates
+// a constraint). It is still counted, but it is never recorded.
 pub fn permute_and_substitute<const C: usize, const R: usize, const N: usize>(
-    matrix: &[[u8; C]; R],
-    region1: ([u8; N], usize, &[(usize, usize)]),
-    region2: ([u8; N], usize, &[(usize, usize)]),
-    region3: ([u8; N], usize, &[(usize, usize)]),
-    progress_fn: impl FnMut(u64, bool) -> bool + Send + Sync,
-    scoring_fn: impl Fn(&[[u8; C]; R]) -> u64 + Sync,
+    matrix: &[[Symbol; C]; R],
+    region1: ([Symbol; N], usize, &[(usize, usize)]),
+    region2: ([Symbol; N], usize, &[(usize, usize)]),
+    region3: ([Symbol; N], usize, &[(usize, usize)]),
+    progress_fn: impl FnMut(PermutationProgress, bool) -> bool + Send + Sync,
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64> + Sync,
     goal: Goal,
-    tolerance: f64,
+    tolerance: Tolerance,
     max_permutations_opt: Option<u64>,
     max_records_opt: Option<u32>,
+    tie_break: TieBreak,
     parallelize: bool,
+    threads: usize,
     sleep_ns: u64,
-) -> Result<(u64, bool, Vec<[[u8; C]; R]>, bool), Box<dyn Error>> {
+) -> Result<(u64, bool, Vec<[[Symbol; C]; R]>, bool), Box<dyn Error>> {
     let max_records_opt = max_records_opt.map(|max_records: u32| max_records as u64 + 1);
     let result = if parallelize {
+        let pool = build_thread_pool(threads)?;
         permute_and_substitute_parallel(
+            &pool,
             &matrix,
             region1,
             region2,
@@ -184,6 +564,7 @@ pub fn permute_and_substitute<const C: usize, const R: usize, const N: usize>(
             tolerance,
             max_permutations_opt,
             max_records_opt,
+            tie_break,
             sleep_ns,
         )
     } else {
@@ -198,14 +579,26 @@ pub fn permute_and_substitute<const C: usize, const R: usize, const N: usize>(
             tolerance,
             max_permutations_opt,
             max_records_opt,
+            tie_break,
             sleep_ns,
         )
     };
     result.map(
         |(total_permutations, permutations_truncated, mut records)| {
-            let records_truncated = max_records_opt.map_or(false, |max_records| {
-                records.len() as u64 >= max_records && records.pop().is_some()
-            });
+            // NOTE Records are sorted by descending score, so the worst record is at the back (if
+            // maximizing) or at the front (if minimizing).
+            let records_truncated =
+                max_records_opt.is_some_and(|max_records| records.len() as u64 >= max_records);
+            if records_truncated {
+                match goal {
+                    Goal::Max => {
+                        records.pop();
+                    }
+                    Goal::Min => {
+                        records.remove(0);
+                    }
+                }
+            }
             (
                 total_permutations,
                 permutations_truncated,
@@ -216,26 +609,167 @@ pub fn permute_and_substitute<const C: usize, const R: usize, const N: usize>(
     )
 }
 
+// NOTE Maps each item (e.g., the key table of each record to measure), in parallel if requested,
+// on a pool of the same number of threads as permuting. The results are in the order of the items.
+pub fn map_in_parallel<T: Send, U: Send>(
+    items: Vec<T>,
+    map_fn: impl Fn(T) -> U + Send + Sync,
+    parallelize: bool,
+    threads: usize,
+) -> Result<Vec<U>, Box<dyn Error>> {
+    if !parallelize {
+        return Ok(items.into_iter().map(map_fn).collect());
+    }
+    let pool = build_thread_pool(threads)?;
+    Ok(pool.install(|| items.into_par_iter().map(map_fn).collect()))
+}
+
+// NOTE Checks the invariants of permuting, for use in tests. Every permutation is enumerated (more
+// than once), so this is practical only for small regions. Returns a description of the first
+// invariant that does not hold:
+// - Permuting sequentially and in parallel visits the same number of permutations and retains the
+//   same records.
+// - The index of each permutation of each region round-trips through
+//   'index_to_permutation_in_place' and 'permutation_to_index'.
+// - The records retained are exactly those within the tolerance of the best score, truncated to
+//   the maximum number of records (keeping the best, with ties broken by permutation index).
+pub fn verify<const C: usize, const R: usize, const N: usize>(
+    matrix: &[[Symbol; C]; R],
+    regions: &[Region<N>; 3],
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64> + Sync,
+    goal: Goal,
+    tolerance: Tolerance,
+    max_records_opt: Option<u32>,
+    threads: usize,
+) -> Result<(), String> {
+    let [region1, region2, region3] = regions
+        .each_ref()
+        .map(|(array, length, coordinates)| (*array, *length, &coordinates[..]));
+    let permute_fn = |parallelize: bool| {
+        permute_and_substitute(
+            matrix,
+            region1,
+            region2,
+            region3,
+            |_, _| true,
+            &scoring_fn,
+            goal,
+            tolerance,
+            None,
+            max_records_opt,
+            TieBreak::Index,
+            parallelize,
+            threads,
+            0,
+        )
+        .map_err(|e| e.to_string())
+    };
+    let sorted = |mut records: Vec<[[Symbol; C]; R]>| {
+        records.sort();
+        records
+    };
+
+    let (total_permutations, _, sequential_records, sequential_truncated) = permute_fn(false)?;
+    let (parallel_total_permutations, _, parallel_records, parallel_truncated) = permute_fn(true)?;
+    if total_permutations != parallel_total_permutations {
+        Err(format!(
+            "Permuting sequentially visited {} permutations, but in parallel visited {}",
+            total_permutations, parallel_total_permutations
+        ))?;
+    }
+    let sequential_records = sorted(sequential_records);
+    if sequential_truncated != parallel_truncated || sequential_records != sorted(parallel_records)
+    {
+        Err("Permuting sequentially and in parallel retained different records")?;
+    }
+
+    let lengths = regions.each_ref().map(|(_, length, _)| (*length).min(N));
+    let (totals, _) = calculate_totals(lengths);
+    let mut candidates = VecDeque::new();
+    let mut permuted_matrices = Vec::new();
+    let mut permuted_matrix = *matrix;
+    let mut permutation = [0 as Symbol; N];
+    for index in 0..total_permutations {
+        let region_indices = split_index_u64(index, totals);
+        for (((array, _, coordinates), length), region_index) in
+            regions.iter().zip(lengths).zip(region_indices)
+        {
+            let (array, permutation) = (&array[..length], &mut permutation[..length]);
+            index_to_permutation_in_place::<N, Symbol>(region_index, array, permutation);
+            if permutation_to_index(permutation, array) != Some(region_index) {
+                Err(format!(
+                    "Index {} of a region of size {} does not round-trip",
+                    region_index, length
+                ))?;
+            }
+            for (&byte, &(r, c)) in permutation.iter().zip(&coordinates[..length]) {
+                permuted_matrix[r][c] = byte;
+            }
+        }
+        if let Some(score) = scoring_fn(&permuted_matrix) {
+            insert_sorted(&mut candidates, score, index as u128, TieBreak::Index);
+        }
+        permuted_matrices.push(permuted_matrix);
+    }
+
+    use Goal::*;
+    let best_score_opt = match goal {
+        Max => candidates.front(),
+        Min => candidates.back(),
+    }
+    .map(|&(score, _)| score);
+    if let Some(best_score) = best_score_opt {
+        let threshold_score = calculate_threshold(goal, best_score, tolerance);
+        match goal {
+            Max => drop_below_threshold(&mut candidates, threshold_score),
+            Min => drop_above_threshold(&mut candidates, threshold_score),
+        }
+    }
+    let expected_truncated =
+        max_records_opt.is_some_and(|max_records| candidates.len() > max_records as usize);
+    truncate(&mut candidates, goal, max_records_opt.map(u64::from));
+    let expected_records = sorted(
+        candidates
+            .into_iter()
+            .map(|(_, index)| permuted_matrices[index as usize])
+            .collect(),
+    );
+    if sequential_truncated != expected_truncated || sequential_records != expected_records {
+        Err("Records retained do not respect the tolerance or the maximum number of records")?;
+    }
+    Ok(())
+}
+
+fn build_thread_pool(threads: usize) -> Result<ThreadPool, Box<dyn Error>> {
+    // NOTE A thread count of 0 lets rayon choose (typically one thread per logical core).
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("perky-{}", i))
+        .build()
+        .map_err(|e| format!("Failed to initialize thread pool: {}", e).into())
+}
+
 fn permute_and_substitute_parallel<const C: usize, const R: usize, const N: usize>(
-    matrix: &[[u8; C]; R],
-    region1: ([u8; N], usize, &[(usize, usize)]),
-    region2: ([u8; N], usize, &[(usize, usize)]),
-    region3: ([u8; N], usize, &[(usize, usize)]),
-    progress_fn: impl FnMut(u64, bool) -> bool + Send + Sync,
-    scoring_fn: impl Fn(&[[u8; C]; R]) -> u64 + Sync,
+    pool: &ThreadPool,
+    matrix: &[[Symbol; C]; R],
+    region1: ([Symbol; N], usize, &[(usize, usize)]),
+    region2: ([Symbol; N], usize, &[(usize, usize)]),
+    region3: ([Symbol; N], usize, &[(usize, usize)]),
+    progress_fn: impl FnMut(PermutationProgress, bool) -> bool + Send + Sync,
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64> + Sync,
     goal: Goal,
-    tolerance: f64,
+    tolerance: Tolerance,
     max_permutations_opt: Option<u64>,
     max_records_opt: Option<u64>,
+    tie_break: TieBreak,
     sleep_ns: u64,
-) -> Result<(u64, bool, Vec<[[u8; C]; R]>), Box<dyn Error>> {
+) -> Result<(u64, bool, Vec<[[Symbol; C]; R]>), Box<dyn Error>> {
     const BATCH: u64 = 1000;
     use Goal::*;
     let initial_score = match goal {
         Max => 0,
         Min => u64::MAX,
     };
-    let tolerance = tolerance.clamp(0.0, 1.0);
     let (array1, length1, coordinates1) = region1;
     let (array2, length2, coordinates2) = region2;
     let (array3, length3, coordinates3) = region3;
@@ -245,222 +779,222 @@ fn permute_and_substitute_parallel<const C: usize, const R: usize, const N: usiz
     let coordinates1 = &coordinates1[..length1];
     let coordinates2 = &coordinates2[..length2];
     let coordinates3 = &coordinates3[..length3];
-    let n1 = length1 as u64;
-    let n2 = length2 as u64;
-    let n3 = length3 as u64;
-    let total1 = factorial(n1);
-    let total2 = factorial(n2);
-    let total3 = factorial(n3);
-    let total_permutations = total1.saturating_mul(total2).saturating_mul(total3);
+    let (totals, total_permutations) = calculate_totals([length1, length2, length3]);
     let max_permutations = max_permutations_opt.unwrap_or(u64::MAX);
-    let permutations_truncated = max_permutations < total_permutations;
-    let n_permutations = Arc::new(atomic::AtomicU64::new(0));
+    let permutations_truncated = (max_permutations as u128) < total_permutations;
+    let total_permutations = u64::try_from(total_permutations).unwrap_or(u64::MAX);
+    let n_permutations = Arc::new(AtomicU64::new(0));
+    let global_best_score = AtomicU64::new(initial_score);
+    let global_n_records = AtomicU64::new(0);
     let progress_fn = Arc::new(Mutex::new(progress_fn));
-    let (records, _best_score, _threshold_score) = (0..total_permutations.min(max_permutations))
-        .into_par_iter()
-        .fold(
-            || {
-                (
-                    VecDeque::with_capacity(max_records_opt.unwrap_or(0) as usize),
-                    initial_score,
-                    calculate_threshold(goal, initial_score, tolerance),
-                    0u64,
-                )
-            },
-            |(
-                mut local_records,
-                mut local_best_score,
-                mut local_threshold_score,
-                mut local_n_permutations,
-            ),
-             index| {
-                let mut matrix = *matrix;
-                let mut p1 = [0u8; N];
-                let mut p2 = [0u8; N];
-                let mut p3 = [0u8; N];
-                let index1 = index / (total2 * total3);
-                let index2 = (index / total3) % total2;
-                let index3 = index % total3;
-                index_to_permutation_in_place::<N, u8>(
-                    index1,
-                    &array1[..length1],
-                    &mut p1[..length1],
-                );
-                index_to_permutation_in_place::<N, u8>(
-                    index2,
-                    &array2[..length2],
-                    &mut p2[..length2],
-                );
-                index_to_permutation_in_place::<N, u8>(
-                    index3,
-                    &array3[..length3],
-                    &mut p3[..length3],
-                );
-                if length1 > 0 {
-                    for (i, &(r, c)) in coordinates1.iter().enumerate() {
-                        matrix[r][c] = p1[i];
-                    }
-                }
-                if length2 > 0 {
-                    for (i, &(r, c)) in coordinates2.iter().enumerate() {
-                        matrix[r][c] = p2[i];
-                    }
-                }
-                if length3 > 0 {
-                    for (i, &(r, c)) in coordinates3.iter().take(length3).enumerate() {
-                        matrix[r][c] = p3[i];
-                    }
-                }
-                let score = scoring_fn(&matrix);
-                consider_record(
-                    matrix,
-                    score,
-                    index,
-                    goal,
-                    tolerance,
-                    max_records_opt,
-                    &mut local_records,
-                    &mut local_best_score,
-                    &mut local_threshold_score,
-                );
-                local_n_permutations += 1;
-                if local_n_permutations % BATCH == 0 {
-                    let current =
-                        n_permutations.fetch_add(BATCH, atomic::Ordering::Relaxed) + BATCH;
-                    if let Ok(mut progress_fn) = progress_fn.lock() {
-                        progress_fn(current, false);
-                    }
-                    if sleep_ns != 0 {
-                        sleep(Duration::from_nanos(sleep_ns));
-                    }
-                }
-
-                (
-                    local_records,
-                    local_best_score,
-                    local_threshold_score,
-                    local_n_permutations,
-                )
-            },
-        )
-        .map(
-            |(local_records, local_best_score, local_threshold_score, local_n_permutations)| {
-                let remaining = local_n_permutations % BATCH;
-                if remaining != 0 {
-                    n_permutations.fetch_add(remaining, atomic::Ordering::Relaxed);
-                }
-                (local_records, local_best_score, local_threshold_score)
-            },
-        )
-        .reduce(
-            || {
-                (
-                    VecDeque::with_capacity(max_records_opt.unwrap_or(0) as usize),
-                    initial_score,
-                    calculate_threshold(goal, initial_score, tolerance),
-                )
-            },
-            |(records_1, best_score_1, threshold_score_1),
-             (records_2, best_score_2, threshold_score_2)| {
-                let (mut left, mut right, best_score, threshold_score) = match goal {
-                    Max => {
-                        if best_score_1 >= best_score_2 {
-                            (records_1, records_2, best_score_1, threshold_score_1)
-                        } else {
-                            (records_2, records_1, best_score_2, threshold_score_2)
+    let (records, _best_score, _threshold_score) = pool.install(|| {
+        (0..total_permutations.min(max_permutations))
+            .into_par_iter()
+            .fold(
+                || {
+                    (
+                        VecDeque::with_capacity(max_records_opt.unwrap_or(0) as usize),
+                        initial_score,
+                        calculate_threshold(goal, initial_score, tolerance),
+                        0u64,
+                    )
+                },
+                |(
+                    mut local_records,
+                    mut local_best_score,
+                    mut local_threshold_score,
+                    mut local_n_permutations,
+                ),
+                 index| {
+                    let mut matrix = *matrix;
+                    let mut p1 = [0 as Symbol; N];
+                    let mut p2 = [0 as Symbol; N];
+                    let mut p3 = [0 as Symbol; N];
+                    let [index1, index2, index3] = split_index_u64(index, totals);
+                    index_to_permutation_in_place::<N, Symbol>(
+                        index1,
+                        &array1[..length1],
+                        &mut p1[..length1],
+                    );
+                    index_to_permutation_in_place::<N, Symbol>(
+                        index2,
+                        &array2[..length2],
+                        &mut p2[..length2],
+                    );
+                    index_to_permutation_in_place::<N, Symbol>(
+                        index3,
+                        &array3[..length3],
+                        &mut p3[..length3],
+                    );
+                    if length1 > 0 {
+                        for (i, &(r, c)) in coordinates1.iter().enumerate() {
+                            matrix[r][c] = p1[i];
                         }
                     }
-                    Min => {
-                        if best_score_1 <= best_score_2 {
-                            (records_1, records_2, best_score_1, threshold_score_1)
-                        } else {
-                            (records_2, records_1, best_score_2, threshold_score_2)
+                    if length2 > 0 {
+                        for (i, &(r, c)) in coordinates2.iter().enumerate() {
+                            matrix[r][c] = p2[i];
                         }
                     }
-                };
-                match goal {
-                    Max => {
-                        drop_below_threshold(&mut left, threshold_score);
-                        drop_below_threshold(&mut right, threshold_score);
+                    if length3 > 0 {
+                        for (i, &(r, c)) in coordinates3.iter().take(length3).enumerate() {
+                            matrix[r][c] = p3[i];
+                        }
                     }
-                    Min => {
-                        drop_above_threshold(&mut left, threshold_score);
-                        drop_above_threshold(&mut right, threshold_score);
+                    if let Some(score) = scoring_fn(&matrix) {
+                        consider_record(
+                            score,
+                            || index as u128,
+                            goal,
+                            tolerance,
+                            max_records_opt,
+                            tie_break,
+                            &mut local_records,
+                            &mut local_best_score,
+                            &mut local_threshold_score,
+                            &global_best_score,
+                            &global_n_records,
+                        );
                     }
-                }
-                let mut merged: VecDeque<(u64, u64, [[u8; C]; R])> =
-                    VecDeque::with_capacity(max_records_opt.unwrap_or(0) as usize);
-                let max_records_opt = max_records_opt.map(|max_records| max_records as usize);
-                while !left.is_empty() && !right.is_empty() {
-                    if let Some(max_records) = max_records_opt {
-                        if merged.len() >= max_records {
-                            return (merged, best_score, threshold_score);
+                    local_n_permutations += 1;
+                    if local_n_permutations % BATCH == 0 {
+                        let current =
+                            n_permutations.fetch_add(BATCH, atomic::Ordering::Relaxed) + BATCH;
+                        if let Ok(mut progress_fn) = progress_fn.lock() {
+                            let progress = load_progress(
+                                current,
+                                goal,
+                                tolerance,
+                                &global_best_score,
+                                &global_n_records,
+                            );
+                            progress_fn(progress, false);
+                        }
+                        if sleep_ns != 0 {
+                            sleep(Duration::from_nanos(sleep_ns));
                         }
                     }
-                    let (s1, i1, _) = *left.front().unwrap();
-                    let (s2, i2, _) = *right.front().unwrap();
-                    if (s1 > s2) || (s1 == s2 && i1 <= i2) {
-                        let item = left.pop_front().unwrap();
-                        merged.push_back(item);
-                    } else {
-                        let item = right.pop_front().unwrap();
-                        merged.push_back(item);
+
+                    (
+                        local_records,
+                        local_best_score,
+                        local_threshold_score,
+                        local_n_permutations,
+                    )
+                },
+            )
+            .map(
+                |(local_records, local_best_score, local_threshold_score, local_n_permutations)| {
+                    let remaining = local_n_permutations % BATCH;
+                    if remaining != 0 {
+                        n_permutations.fetch_add(remaining, atomic::Ordering::Relaxed);
                     }
-                }
-                if let Some(max_records) = max_records_opt {
-                    while merged.len() < max_records {
-                        if let Some(item) = left.pop_front() {
-                            merged.push_back(item);
-                        } else {
-                            break;
+                    (local_records, local_best_score, local_threshold_score)
+                },
+            )
+            .reduce(
+                || {
+                    (
+                        VecDeque::with_capacity(max_records_opt.unwrap_or(0) as usize),
+                        initial_score,
+                        calculate_threshold(goal, initial_score, tolerance),
+                    )
+                },
+                |(records_1, best_score_1, threshold_score_1),
+                 (records_2, best_score_2, threshold_score_2)| {
+                    let (mut left, mut right, best_score, threshold_score) = match goal {
+                        Max => {
+                            if best_score_1 >= best_score_2 {
+                                (records_1, records_2, best_score_1, threshold_score_1)
+                            } else {
+                                (records_2, records_1, best_score_2, threshold_score_2)
+                            }
+                        }
+                        Min => {
+                            if best_score_1 <= best_score_2 {
+                                (records_1, records_2, best_score_1, threshold_score_1)
+                            } else {
+                                (records_2, records_1, best_score_2, threshold_score_2)
+                            }
+                        }
+                    };
+                    match goal {
+                        Max => {
+                            drop_below_threshold(&mut left, threshold_score);
+                            drop_below_threshold(&mut right, threshold_score);
+                        }
+                        Min => {
+                            drop_above_threshold(&mut left, threshold_score);
+                            drop_above_threshold(&mut right, threshold_score);
                         }
                     }
-                    while merged.len() < max_records {
-                        if let Some(item) = right.pop_front() {
+                    // NOTE Both are sorted by descending score, so the best records are at the
+                    // front (if maximizing) or at the back (if minimizing). They are merged in
+                    // full, then truncated from the appropriate end.
+                    let mut merged: VecDeque<RetainedRecord> =
+                        VecDeque::with_capacity(left.len() + right.len());
+                    while let (Some(&(s1, i1)), Some(&(s2, i2))) = (left.front(), right.front()) {
+                        if (s1 > s2) || (s1 == s2 && tie_break.compare(i1, i2).is_le()) {
+                            let item = left.pop_front().unwrap();
                             merged.push_back(item);
                         } else {
-                            break;
+                            let item = right.pop_front().unwrap();
+                            merged.push_back(item);
                         }
                     }
-                } else {
-                    for (s, i, m) in left {
-                        merged.push_back((s, i, m));
-                    }
-                    for (s, i, m) in right {
-                        merged.push_back((s, i, m));
-                    }
-                }
-                (merged, best_score, threshold_score)
-            },
-        );
+                    merged.extend(left);
+                    merged.extend(right);
+                    truncate(&mut merged, goal, max_records_opt);
+                    (merged, best_score, threshold_score)
+                },
+            )
+    });
     let n_permutations = n_permutations.load(atomic::Ordering::Relaxed);
     if let Ok(mut progress_fn) = progress_fn.lock() {
-        progress_fn(n_permutations, true);
+        let progress = load_progress(
+            n_permutations,
+            goal,
+            tolerance,
+            &global_best_score,
+            &global_n_records,
+        );
+        progress_fn(progress, true);
     }
-    let records: Vec<[[u8; C]; R]> = records.into_iter().map(|(_, _, m)| m).collect();
+    let records = records
+        .into_iter()
+        .map(|(_, index)| {
+            reconstruct_matrix::<C, R>(
+                matrix,
+                [&array1[..length1], &array2[..length2], &array3[..length3]],
+                [coordinates1, coordinates2, coordinates3],
+                totals,
+                index,
+            )
+        })
+        .collect();
     Ok((n_permutations, permutations_truncated, records))
 }
 
 fn permute_and_substitute_sequential<const C: usize, const R: usize, const N: usize>(
-    matrix: &[[u8; C]; R],
-    region1: ([u8; N], usize, &[(usize, usize)]),
-    region2: ([u8; N], usize, &[(usize, usize)]),
-    region3: ([u8; N], usize, &[(usize, usize)]),
-    mut progress_fn: impl FnMut(u64, bool) -> bool,
-    scoring_fn: impl Fn(&[[u8; C]; R]) -> u64,
+    matrix: &[[Symbol; C]; R],
+    region1: ([Symbol; N], usize, &[(usize, usize)]),
+    region2: ([Symbol; N], usize, &[(usize, usize)]),
+    region3: ([Symbol; N], usize, &[(usize, usize)]),
+    mut progress_fn: impl FnMut(PermutationProgress, bool) -> bool,
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64>,
     goal: Goal,
-    tolerance: f64,
+    tolerance: Tolerance,
     max_permutations_opt: Option<u64>,
     max_records_opt: Option<u64>,
+    tie_break: TieBreak,
     sleep_ns: u64,
-) -> Result<(u64, bool, Vec<[[u8; C]; R]>), Box<dyn Error>> {
+) -> Result<(u64, bool, Vec<[[Symbol; C]; R]>), Box<dyn Error>> {
     const BATCH: u64 = 1000000;
     use Goal::*;
     let initial_score = match goal {
         Max => 0,
         Min => u64::MAX,
     };
-    let tolerance = tolerance.clamp(0.0, 1.0);
     let (array1, length1, coordinates1) = region1;
     let (array2, length2, coordinates2) = region2;
     let (array3, length3, coordinates3) = region3;
@@ -470,24 +1004,20 @@ fn permute_and_substitute_sequential<const C: usize, const R: usize, const N: us
     let coordinates1 = &coordinates1[..length1];
     let coordinates2 = &coordinates2[..length2];
     let coordinates3 = &coordinates3[..length3];
-    let n1 = length1 as u64;
-    let n2 = length2 as u64;
-    let n3 = length3 as u64;
-    let total1 = factorial(n1);
-    let total2 = factorial(n2);
-    let total3 = factorial(n3);
-    let total_permutations = total1.saturating_mul(total2).saturating_mul(total3);
+    let (totals, total_permutations) = calculate_totals([length1, length2, length3]);
     let max_permutations = max_permutations_opt.unwrap_or(u64::MAX);
-    let permutations_truncated = max_permutations < total_permutations;
+    let permutations_truncated = (max_permutations as u128) < total_permutations;
     let mut n_permutations = 0u64;
-    let mut records: VecDeque<(u64, u64, [[u8; C]; R])> =
+    let mut records: VecDeque<RetainedRecord> =
         VecDeque::with_capacity(max_records_opt.unwrap_or(0) as usize);
     let mut best_score = initial_score;
     let mut threshold_score = calculate_threshold(goal, best_score, tolerance);
+    let global_best_score = AtomicU64::new(initial_score);
+    let global_n_records = AtomicU64::new(0);
     let mut matrix = *matrix;
-    generate_permutations_to_limit::<N, u8>(array1, length1, |p1| {
-        generate_permutations_to_limit::<N, u8>(array2, length2, |p2| {
-            generate_permutations_to_limit::<N, u8>(array3, length3, |p3| {
+    generate_permutations_to_limit::<N, Symbol>(array1, length1, |p1| {
+        generate_permutations_to_limit::<N, Symbol>(array2, length2, |p2| {
+            generate_permutations_to_limit::<N, Symbol>(array3, length3, |p3| {
                 if length1 > 0 {
                     for (i, &(r, c)) in coordinates1.iter().enumerate() {
                         matrix[r][c] = p1[i];
@@ -503,21 +1033,44 @@ fn permute_and_substitute_sequential<const C: usize, const R: usize, const N: us
                         matrix[r][c] = p3[i];
                     }
                 }
-                let score = scoring_fn(&matrix);
-                consider_record(
-                    matrix,
-                    score,
-                    n_permutations,
-                    goal,
-                    tolerance,
-                    max_records_opt,
-                    &mut records,
-                    &mut best_score,
-                    &mut threshold_score,
-                );
+                if let Some(score) = scoring_fn(&matrix) {
+                    // NOTE Permutations are not visited in lexicographic order, so the index is
+                    // calculated from the permutation of each region.
+                    let index_fn = || {
+                        join_index(
+                            [
+                                permutation_to_index_u128(&p1[..length1], &array1[..length1]),
+                                permutation_to_index_u128(&p2[..length2], &array2[..length2]),
+                                permutation_to_index_u128(&p3[..length3], &array3[..length3]),
+                            ]
+                            .map(Option::unwrap_or_default),
+                            totals,
+                        )
+                    };
+                    consider_record(
+                        score,
+                        index_fn,
+                        goal,
+                        tolerance,
+                        max_records_opt,
+                        tie_break,
+                        &mut records,
+                        &mut best_score,
+                        &mut threshold_score,
+                        &global_best_score,
+                        &global_n_records,
+                    );
+                }
                 n_permutations += 1;
                 if n_permutations % BATCH == 0 {
-                    progress_fn(n_permutations, false);
+                    let progress = load_progress(
+                        n_permutations,
+                        goal,
+                        tolerance,
+                        &global_best_score,
+                        &global_n_records,
+                    );
+                    progress_fn(progress, false);
                 }
                 if sleep_ns != 0 {
                     sleep(Duration::from_nanos(sleep_ns));
@@ -528,7 +1081,154 @@ fn permute_and_substitute_sequential<const C: usize, const R: usize, const N: us
         });
         n_permutations < max_permutations
     });
-    progress_fn(n_permutations, true);
-    let records: Vec<[[u8; C]; R]> = records.into_iter().map(|(_, _, m)| m).collect();
+    let progress = load_progress(
+        n_permutations,
+        goal,
+        tolerance,
+        &global_best_score,
+        &global_n_records,
+    );
+    progress_fn(progress, true);
+    let records = records
+        .into_iter()
+        .map(|(_, index)| {
+            reconstruct_matrix::<C, R>(
+                &matrix,
+                [&array1[..length1], &array2[..length2], &array3[..length3]],
+                [coordinates1, coordinates2, coordinates3],
+                totals,
+                index,
+            )
+        })
+        .collect();
     Ok((n_permutations, permutations_truncated, records))
 }
+
+#[cfg(test)]
+mod tests {
+    use core::array;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const C: usize = 4;
+    const R: usize = 2;
+    const N: usize = 4;
+
+    // NOTE The regions take consecutive keys of the matrix, row by row, and at most 4! 3! 1!
+    // permutations, so that every permutation can be enumerated.
+    fn regions(lengths: [usize; 3]) -> [Region<N>; 3] {
+        let arrays = [*b"ABCD", *b"EFGH", *b"IJKL"];
+        let mut start = 0;
+        array::from_fn(|i| {
+            let coordinates = (start..start + lengths[i])
+                .map(|p| (p / C, p % C))
+                .collect();
+            start += lengths[i];
+            (arrays[i].map(Symbol::from), lengths[i], coordinates)
+        })
+    }
+
+    // NOTE Scores are taken modulo a small number, so that many permutations tie.
+    fn scoring_fn(
+        weights: [u64; C * R],
+        modulus: u64,
+    ) -> impl Fn(&[[Symbol; C]; R]) -> Option<u64> {
+        move |matrix| {
+            let score = matrix
+                .as_flattened()
+                .iter()
+                .zip(weights)
+                .map(|(&byte, weight)| byte as u64 * weight)
+                .sum::<u64>();
+            Some(score % modulus)
+        }
+    }
+
+    fn tolerance() -> impl Strategy<Value = Tolerance> {
+        prop_oneof![
+            (0.0..=1.0).prop_map(Tolerance::Ratio),
+            (0.0..=50.0).prop_map(Tolerance::Percent),
+            (0..=20u64).prop_map(Tolerance::Absolute),
+        ]
+    }
+
+    fn check(
+        goal: Goal,
+        lengths: [usize; 3],
+        weights: [u64; C * R],
+        modulus: u64,
+        tolerance: Tolerance,
+        max_records_opt: Option<u32>,
+        threads: usize,
+    ) -> Result<(), TestCaseError> {
+        verify(
+            &[[b'.' as Symbol; C]; R],
+            &regions(lengths),
+            scoring_fn(weights, modulus),
+            goal,
+            tolerance,
+            max_records_opt,
+            threads,
+        )
+        .map_err(TestCaseError::fail)
+    }
+
+    // NOTE A region of more than 20 characters has indices that do not fit in a u64, so the last
+    // permutation (the characters reversed) can only be reconstructed from a u128 index.
+    #[test]
+    fn reconstruct_matrix_with_large_region() {
+        const LENGTH: usize = 22;
+        let array: [Symbol; LENGTH] = array::from_fn(|i| Symbol::from(b'A') + i as Symbol);
+        let coordinates = (0..LENGTH).map(|c| (0, c)).collect::<Vec<_>>();
+        let (totals, total_permutations) = calculate_totals([LENGTH, 0, 0]);
+        let matrix = reconstruct_matrix::<LENGTH, 1>(
+            &[[0; LENGTH]],
+            [&array, &[], &[]],
+            [&coordinates, &[], &[]],
+            totals,
+            total_permutations - 1,
+        );
+        let mut reversed = array;
+        reversed.reverse();
+        assert_eq!(matrix, [reversed]);
+    }
+
+    #[test]
+    fn split_index_u64_matches_split_index() {
+        let (totals, _) = calculate_totals([22, 3, 2]);
+        for index in [0, 1, 11, 12, u64::MAX - 1] {
+            let expected = split_index(index as u128, totals).map(|i| i as u64);
+            assert_eq!(split_index_u64(index, totals), expected);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn verify_max(
+            lengths in (0..=4usize, 0..=3usize, 0..=1usize),
+            weights in prop::array::uniform8(0..10u64),
+            modulus in 1..=64u64,
+            tolerance in tolerance(),
+            max_records_opt in prop::option::of(1..=8u32),
+            threads in 1..=3usize,
+        ) {
+            let (l1, l2, l3) = lengths;
+            check(Goal::Max, [l1, l2, l3], weights, modulus, tolerance, max_records_opt, threads)?;
+        }
+
+        #[test]
+        fn verify_min(
+            lengths in (0..=4usize, 0..=3usize, 0..=1usize),
+            weights in prop::array::uniform8(0..10u64),
+            modulus in 1..=64u64,
+            tolerance in tolerance(),
+            max_records_opt in prop::option::of(1..=8u32),
+            threads in 1..=3usize,
+        ) {
+            let (l1, l2, l3) = lengths;
+            check(Goal::Min, [l1, l2, l3], weights, modulus, tolerance, max_records_opt, threads)?;
+        }
+    }
+}
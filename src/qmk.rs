@@ -0,0 +1,214 @@
+use core::error::Error;
+
+use std::{io::Write, path::Path};
+
+use serde_json::Value;
+
+use crate::{
+    layouts::{LayoutTable, read_layout_table_object},
+    symbols::{Symbol, resolve},
+    tables::Table,
+};
+
+const KEYCODE_NONE: &str = "KC_NO";
+
+const DEFAULT_LAYOUT: &str = "LAYOUT";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QmkPosition(pub usize);
+
+impl TryFrom<&Value> for QmkPosition {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        value
+            .as_u64()
+            .map(|n| QmkPosition(n as usize))
+            .ok_or_else(|| "Invalid type: expected an unsigned integer".into())
+    }
+}
+
+pub struct QmkMapping<const C: usize, const R: usize> {
+    pub layout: String,
+    pub positions: Table<C, R, QmkPosition>,
+    pub size: usize,
+}
+
+impl<const C: usize, const R: usize> QmkMapping<C, R> {
+    // NOTE Without an explicit mapping, the keys of the layout table are numbered left to right,
+    // top to bottom, which matches the argument order of most QMK 'LAYOUT' macros.
+    pub fn from_layout_table(layout_table: &LayoutTable<C, R>) -> Self {
+        let mut positions = Table::<C, R, QmkPosition>::default();
+        let mut size = 0;
+        for (r, row) in layout_table.0.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                if cell.is_some() {
+                    positions[r][c] = Some(QmkPosition(size));
+                    size += 1;
+                }
+            }
+        }
+        Self {
+            layout: DEFAULT_LAYOUT.into(),
+            positions,
+            size,
+        }
+    }
+
+    pub fn try_from_value(
+        value: &Value,
+        layout_table: &LayoutTable<C, R>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let object = value.as_object().ok_or("Expected 'qmk' to be an object")?;
+        let mut mapping = Self::from_layout_table(layout_table);
+        if let Some(layout) = object.get("layout") {
+            mapping.layout = layout
+                .as_str()
+                .ok_or("Value of 'layout' field must be of type 'string'")?
+                .into();
+        }
+        if let Some(positions) = object.get("positions") {
+            mapping.positions = Table::try_from(positions)
+                .map_err(|e| format!("Invalid 'positions' field: {}", e))?;
+            mapping.size = mapping
+                .positions
+                .as_flattened()
+                .iter()
+                .flatten()
+                .map(|position| position.0 + 1)
+                .max()
+                .unwrap_or_default();
+        }
+        if let Some(size) = object.get("size") {
+            let size =
+                size.as_u64()
+                    .ok_or("Value of 'size' field must be of type 'u64'")? as usize;
+            if size < mapping.size {
+                Err(format!(
+                    "Value of 'size' field ({}) is less than the number of positions ({})",
+                    size, mapping.size
+                ))?;
+            }
+            mapping.size = size;
+        }
+        Ok(mapping)
+    }
+
+    // NOTE The mapping is read from the optional 'qmk' field of a layout table file.
+    pub fn read_from_path(
+        path: &Path,
+        layout_table: &LayoutTable<C, R>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let object = read_layout_table_object(path)?;
+        match object.get("qmk") {
+            None => Ok(Self::from_layout_table(layout_table)),
+            Some(value) => Self::try_from_value(value, layout_table),
+        }
+    }
+}
+
+pub fn keycode(byte: u8) -> Option<&'static str> {
+    const LETTERS: [&str; 26] = [
+        "KC_A", "KC_B", "KC_C", "KC_D", "KC_E", "KC_F", "KC_G", "KC_H", "KC_I", "KC_J", "KC_K",
+        "KC_L", "KC_M", "KC_N", "KC_O", "KC_P", "KC_Q", "KC_R", "KC_S", "KC_T", "KC_U", "KC_V",
+        "KC_W", "KC_X", "KC_Y", "KC_Z",
+    ];
+    const DIGITS: [&str; 10] = [
+        "KC_0", "KC_1", "KC_2", "KC_3", "KC_4", "KC_5", "KC_6", "KC_7", "KC_8", "KC_9",
+    ];
+    Some(match byte {
+        b'A'..=b'Z' => LETTERS[(byte - b'A') as usize],
+        b'a'..=b'z' => LETTERS[(byte - b'a') as usize],
+        b'0'..=b'9' => DIGITS[(byte - b'0') as usize],
+        b' ' => "KC_SPC",
+        b'!' => "KC_EXLM",
+        b'"' => "KC_DQUO",
+        b'#' => "KC_HASH",
+        b'$' => "KC_DLR",
+        b'%' => "KC_PERC",
+        b'&' => "KC_AMPR",
+        b'\'' => "KC_QUOT",
+        b'(' => "KC_LPRN",
+        b')' => "KC_RPRN",
+        b'*' => "KC_ASTR",
+        b'+' => "KC_PLUS",
+        b',' => "KC_COMM",
+        b'-' => "KC_MINS",
+        b'.' => "KC_DOT",
+        b'/' => "KC_SLSH",
+        b':' => "KC_COLN",
+        b';' => "KC_SCLN",
+        b'<' => "KC_LT",
+        b'=' => "KC_EQL",
+        b'>' => "KC_GT",
+        b'?' => "KC_QUES",
+        b'@' => "KC_AT",
+        b'[' => "KC_LBRC",
+        b'\\' => "KC_BSLS",
+        b']' => "KC_RBRC",
+        b'^' => "KC_CIRC",
+        b'_' => "KC_UNDS",
+        b'`' => "KC_GRV",
+        b'{' => "KC_LCBR",
+        b'|' => "KC_PIPE",
+        b'}' => "KC_RCBR",
+        b'~' => "KC_TILD",
+        _ => return None,
+    })
+}
+
+// NOTE Keys are written in position order. A new line is started whenever the key at a position
+// comes from a different row of the key table than the key before it. Positions without a key,
+// and keys still holding a placeholder, are written as 'KC_NO'.
+pub fn write_qmk_keymap<const C: usize, const R: usize, W: Write + ?Sized>(
+    writer: &mut W,
+    key_table_matrix: &[[Symbol; C]; R],
+    mapping: &QmkMapping<C, R>,
+) -> Result<(), Box<dyn Error>> {
+    let mut keycodes = vec![(None, KEYCODE_NONE); mapping.size];
+    for (r, row) in mapping.positions.iter().enumerate() {
+        for (c, cell) in row.iter().enumerate() {
+            let Some(QmkPosition(i)) = *cell else {
+                continue;
+            };
+            if keycodes[i].0.is_some() {
+                Err(format!("QMK position {} is mapped more than once", i))?;
+            }
+            let keycode = match key_table_matrix[r][c] {
+                0..=3 => KEYCODE_NONE,
+                byte => u8::try_from(byte).ok().and_then(keycode).ok_or_else(|| {
+                    format!(
+                        "No QMK keycode for character '{}' at ({}, {})",
+                        resolve(byte).escape_default(),
+                        r,
+                        c
+                    )
+                })?,
+            };
+            keycodes[i] = (Some(r), keycode);
+        }
+    }
+    writeln!(writer, "#include QMK_KEYBOARD_H")?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {{"
+    )?;
+    write!(writer, "    [0] = {}(", mapping.layout)?;
+    let mut previous_r_opt = None;
+    for (i, &(r_opt, keycode)) in keycodes.iter().enumerate() {
+        if i != 0 {
+            write!(writer, ",")?;
+        }
+        if i == 0 || (r_opt.is_some() && r_opt != previous_r_opt) {
+            write!(writer, "\n        ")?;
+        } else {
+            write!(writer, " ")?;
+        }
+        write!(writer, "{}", keycode)?;
+        previous_r_opt = r_opt.or(previous_r_opt);
+    }
+    writeln!(writer, "\n    )")?;
+    writeln!(writer, "}};")?;
+    Ok(())
+}
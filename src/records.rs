@@ -1,19 +1,109 @@
-use core::{cmp::Ordering, fmt::Display};
+use core::{fmt::Display, time::Duration};
 
 use std::collections::{BTreeMap, HashMap};
 
 use crate::{
-    expressions::{EvalError, Expression, Value},
+    expressions::{
+        Definition, EvalError, ExpressionSet, Type, Value, Variables, evaluate_definitions,
+        key_variable,
+    },
+    goals::Goal,
+    layouts::FingerWeights,
+    log_info,
     measurements::Measurement,
-    metrics::{BigramMetric, Metric, SortDirection, SortRule, TrigramMetric, UnigramMetric},
+    metrics::{
+        BigramMetric, Metric, MetricGoals, PER_BILLION_SUFFIX, PERCENTILE_SUFFIX, SortDirection,
+        SortRule, TrigramMetric, UnigramMetric,
+    },
     ngrams::{BigramKey, TrigramKey, UnigramKey},
-    scores::Score,
-    util::math::calculate_perc,
+    permutations::{Provenance, RETAINED_RECORD_BYTES},
+    scores::{Contribution, Score},
+    symbols::{Symbol, resolve},
+    util::{
+        hash::sha256_hex,
+        math::{calculate_per_billion, calculate_perc},
+    },
     weights::Weight,
+    writers::is_printable,
 };
 
+// NOTE Besides the metrics, an expression can refer to the hash of the key table of a record, and
+// to the position of each of its characters (using 'key').
+pub const HASH_VARIABLE: &str = "hash";
+
+pub const KEY_VARIABLE: &str = "key";
+
+pub fn get_key_table_variables() -> Variables {
+    [
+        (HASH_VARIABLE, Type::String),
+        (KEY_VARIABLE, Type::Position),
+    ]
+    .into_iter()
+    .map(|(name, ty)| (name.to_string(), ty))
+    .collect()
+}
+
+// NOTE An expression can also refer to quantities of the run, such as 'meta_bigram_sum' (the sum
+// of the bigram table of the record, as in the metadata) or 'meta_elapsed_secs'. They are the same
+// for every record of a run (the n-gram table sums are those of the record, so they can differ
+// between the key tables of a collection only if the n-gram tables were filtered differently).
+const META_VARIABLES: [&str; 5] = [
+    "meta_unigram_sum",
+    "meta_bigram_sum",
+    "meta_trigram_sum",
+    "meta_total_permutations",
+    "meta_elapsed_secs",
+];
+
+pub fn get_meta_variables() -> Variables {
+    META_VARIABLES
+        .into_iter()
+        .map(|name| (name.to_string(), Type::Number))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunQuantities {
+    pub total_permutations: u64,
+    pub elapsed_duration: Duration,
+}
+
+// NOTE The first 8 hex digits of the SHA-256 of the characters of a key table (row by row, with
+// U+0000 for each position without a printable character), encoded as UTF-8.
+pub fn hash_key_table<const C: usize, const R: usize>(
+    key_table_matrix: &[[Symbol; C]; R],
+) -> String {
+    let s = key_table_matrix
+        .iter()
+        .flat_map(|row| {
+            row.iter()
+                .map(|&byte| {
+                    if is_printable(byte) {
+                        resolve(byte)
+                    } else {
+                        '\0'
+                    }
+                })
+                .chain(['\n'])
+        })
+        .collect::<String>();
+    sha256_hex(s.as_bytes())[..8].to_string()
+}
+
+// NOTE Limits the detail rows of a measurement to its most significant n-grams: at most the top
+// N, and only while their percentage within the metric (by the selected weight) is at least the
+// minimum. Details are sorted by the selected weight, so the rows are cut off at the first n-gram
+// below the minimum.
+#[derive(Clone, Copy, Debug)]
+pub struct DetailLimit {
+    pub top_opt: Option<usize>,
+    pub min_perc_opt: Option<f64>,
+    pub weight: Weight,
+}
+
 struct DetailIter<'a, K: Clone> {
     iter: std::slice::Iter<'a, Score<K>>,
+    detail_limit: DetailLimit,
     cum: u64,
     cum_ew: u64,
     measurement_sum: u64,
@@ -27,9 +117,11 @@ impl<'a, K: Clone> DetailIter<'a, K> {
         measurement: &'a Measurement<K>,
         record_sum: u64,
         record_sum_ew: u64,
+        detail_limit: DetailLimit,
     ) -> Option<Self> {
         Some(Self {
             iter: measurement.details_opt.as_deref()?.iter(),
+            detail_limit,
             cum: 0,
             cum_ew: 0,
             measurement_sum: measurement.sum,
@@ -43,10 +135,13 @@ impl<'a, K: Clone> DetailIter<'a, K> {
 impl<'a, K: Clone> Iterator for DetailIter<'a, K> {
     type Item = DetailRow<K>;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.detail_limit.top_opt == Some(0) {
+            return None;
+        }
         let score = self.iter.next()?;
         self.cum += score.value;
         self.cum_ew += score.value_ew;
-        Some(DetailRow::new(
+        let detail_row = DetailRow::new(
             score,
             self.cum,
             self.cum_ew,
@@ -54,7 +149,21 @@ impl<'a, K: Clone> Iterator for DetailIter<'a, K> {
             self.measurement_sum_ew,
             self.record_sum,
             self.record_sum_ew,
-        ))
+        );
+        if let Some(min_perc) = self.detail_limit.min_perc_opt {
+            let perc_opt = match self.detail_limit.weight {
+                Weight::Effort => detail_row.value_ew_as_perc_measurement,
+                Weight::Raw => detail_row.value_as_perc_measurement,
+            };
+            if perc_opt.is_none_or(|perc| perc < min_perc) {
+                self.detail_limit.top_opt = Some(0);
+                return None;
+            }
+        }
+        if let Some(top) = &mut self.detail_limit.top_opt {
+            *top -= 1;
+        }
+        Some(detail_row)
     }
 }
 
@@ -109,21 +218,159 @@ pub struct SummaryRow {
     pub sum_as_perc: Option<f64>,
     pub sum_ew: u64,
     pub sum_ew_as_perc: Option<f64>,
+    pub sum_per_billion: Option<f64>,
+    pub sum_ew_per_billion: Option<f64>,
+    pub percentile_opt: Option<f64>,
 }
 
 impl SummaryRow {
-    pub fn new<K>(measurement: &Measurement<K>, record_sum: u64, record_sum_ew: u64) -> Self {
+    pub fn new<K>(
+        measurement: &Measurement<K>,
+        record_sum: u64,
+        record_sum_ew: u64,
+        table_sum: u64,
+        percentile_opt: Option<f64>,
+    ) -> Self {
         Self {
             sum: measurement.sum,
             sum_as_perc: calculate_perc(measurement.sum, record_sum),
             sum_ew: measurement.sum_ew,
             sum_ew_as_perc: calculate_perc(measurement.sum_ew, record_sum_ew),
+            sum_per_billion: calculate_per_billion(measurement.sum, table_sum),
+            sum_ew_per_billion: calculate_per_billion(measurement.sum_ew, table_sum),
+            percentile_opt,
+        }
+    }
+}
+
+// NOTE The bigrams contributing most to a bigram metric, in descending order of value (by the
+// selected weight), with the positions of their keys.
+pub struct OverlayBigram {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub value: u64,
+    pub perc_opt: Option<f64>,
+}
+
+pub struct Overlay {
+    pub metric: BigramMetric,
+    pub bigrams: Vec<OverlayBigram>,
+}
+
+// NOTE The n-grams contributing most to a metric of a record (by the selected weight), out of the
+// total number of n-grams that contribute to it, for '--explain'.
+pub struct Explanation {
+    pub metric: Metric,
+    pub weight: Weight,
+    pub sum: u64,
+    pub perc_opt: Option<f64>,
+    pub total: usize,
+    pub contributions: Vec<Contribution>,
+}
+
+// NOTE The summed contribution of each key to a metric (by the selected weight). An n-gram
+// contributes its full value to each of its keys.
+pub struct Heatmap<const C: usize, const R: usize> {
+    pub metric: Metric,
+    pub matrix: [[u64; C]; R],
+}
+
+// NOTE The load on each digit is the percentage of all unigrams (by the selected weight) typed by
+// it, and likewise for each hand. Dividing a digit's load by its weight (see
+// 'layouts::FingerWeights') shows which digits carry more than their share.
+pub struct DigitUtilization {
+    pub metric: UnigramMetric,
+    pub perc_opt: Option<f64>,
+    pub finger_weight: f64,
+    pub ratio_opt: Option<f64>,
+}
+
+pub struct HandUtilization {
+    pub metric: UnigramMetric,
+    pub perc_opt: Option<f64>,
+}
+
+pub struct Utilization {
+    pub weight: Weight,
+    pub digits: Vec<DigitUtilization>,
+    pub hands: Vec<HandUtilization>,
+}
+
+impl Utilization {
+    pub fn new<const C: usize, const R: usize>(
+        record: &Record<C, R>,
+        finger_weights: &FingerWeights,
+        weight: Weight,
+    ) -> Self {
+        let perc_fn = |metric| record.perc(Metric::Unigram(metric), weight);
+        let mut digits = Vec::new();
+        let mut hands = Vec::new();
+        for &metric in record.unigram_measurements.keys() {
+            match metric.digit_opt() {
+                Some(digit) => {
+                    let perc_opt = perc_fn(metric);
+                    let finger_weight = finger_weights.get(digit);
+                    digits.push(DigitUtilization {
+                        metric,
+                        perc_opt,
+                        finger_weight,
+                        ratio_opt: perc_opt.map(|perc| perc / finger_weight),
+                    });
+                }
+                None if metric == UnigramMetric::Pen => (),
+                None => hands.push(HandUtilization {
+                    metric,
+                    perc_opt: perc_fn(metric),
+                }),
+            }
+        }
+        Self {
+            weight,
+            digits,
+            hands,
         }
     }
 }
 
-pub struct Record {
-    pub key_table_matrix: [[u8; 16]; 8],
+// NOTE The dense rank of a record by a metric (1 for the best sum, by the metric's goal), out of
+// the number of records ranked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rank {
+    pub metric: Metric,
+    pub rank: usize,
+    pub total: usize,
+}
+
+// NOTE The percentage of a metric for each selected record, in the order the records are printed
+// (see '--print-sparklines').
+pub struct Sparkline {
+    pub metric: Metric,
+    pub percs: Vec<Option<f64>>,
+}
+
+impl Sparkline {
+    pub fn collect<const C: usize, const R: usize>(
+        records: &[Record<C, R>],
+        weight: Weight,
+    ) -> Vec<Self> {
+        let Some(first) = records.first() else {
+            return Vec::new();
+        };
+        first
+            .iter_metrics()
+            .map(|metric| Self {
+                metric,
+                percs: records
+                    .iter()
+                    .map(|record| record.perc(metric, weight))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+pub struct Record<const C: usize, const R: usize> {
+    pub key_table_matrix: [[Symbol; C]; R],
     pub unigram_measurements: BTreeMap<UnigramMetric, Measurement<UnigramKey>>,
     pub bigram_measurements: BTreeMap<BigramMetric, Measurement<BigramKey>>,
     pub trigram_measurements: BTreeMap<TrigramMetric, Measurement<TrigramKey>>,
@@ -133,10 +380,27 @@ pub struct Record {
     pub bf_sum_ew: u64,
     pub tf_sum: u64,
     pub tf_sum_ew: u64,
+    pub uf_table_sum: u64,
+    pub bf_table_sum: u64,
+    pub tf_table_sum: u64,
+    pub percentiles: BTreeMap<Metric, f64>,
+    pub provenance_opt: Option<Provenance>,
+    pub overlay_opt: Option<Overlay>,
+    pub heatmap_opt: Option<Heatmap<C, R>>,
+    pub utilization_opt: Option<Utilization>,
+    pub ranks: Vec<Rank>,
+    pub cluster_size_opt: Option<usize>,
+    pub source_opt: Option<String>,
 }
 
-impl Record {
-    pub fn build_symbol_table(&self, weight: Weight) -> HashMap<String, Value> {
+impl<const C: usize, const R: usize> Record<C, R> {
+    // NOTE Any definitions are evaluated last, so they may refer to any other name.
+    pub fn build_symbol_table(
+        &self,
+        weight: Weight,
+        run_quantities: &RunQuantities,
+        definitions: &[Definition],
+    ) -> Result<HashMap<String, Value>, EvalError> {
         fn iter_pairs<'a, T: Display, U>(
             map: &'a BTreeMap<T, Measurement<U>>,
             denominator: u64,
@@ -159,6 +423,30 @@ impl Record {
             })
         }
 
+        // NOTE Unlike percentages, values per billion n-grams are relative to the sum of the whole
+        // n-gram table (including n-grams that cannot be typed), so they can be compared across
+        // corpora of different sizes.
+        fn iter_per_billion_pairs<'a, T: Display, U>(
+            map: &'a BTreeMap<T, Measurement<U>>,
+            table_sum: u64,
+            weight: Weight,
+        ) -> impl 'a + Iterator<Item = (String, Value)> {
+            map.iter().filter_map(move |(metric, measurement)| {
+                calculate_per_billion(measurement.sum_by_weight(weight), table_sum).map(
+                    |per_billion| {
+                        (
+                            format!(
+                                "{}{}",
+                                metric.to_string().to_lowercase(),
+                                PER_BILLION_SUFFIX
+                            ),
+                            Value::Number(per_billion),
+                        )
+                    },
+                )
+            })
+        }
+
         let (unigram_denominator, bigram_denominator, trigram_denominator) = match weight {
             Weight::Effort => (self.uf_sum_ew, self.bf_sum_ew, self.tf_sum_ew),
             Weight::Raw => (self.uf_sum, self.bf_sum, self.tf_sum),
@@ -183,39 +471,93 @@ impl Record {
             trigram_denominator,
             weight,
         ));
-        symbol_table
+        symbol_table.extend(iter_per_billion_pairs(
+            &self.unigram_measurements,
+            self.uf_table_sum,
+            weight,
+        ));
+        symbol_table.extend(iter_per_billion_pairs(
+            &self.bigram_measurements,
+            self.bf_table_sum,
+            weight,
+        ));
+        symbol_table.extend(iter_per_billion_pairs(
+            &self.trigram_measurements,
+            self.tf_table_sum,
+            weight,
+        ));
+        symbol_table.extend(self.percentiles.iter().map(|(metric, percentile)| {
+            (
+                format!("{}{}", metric.to_string().to_lowercase(), PERCENTILE_SUFFIX),
+                Value::Number(*percentile),
+            )
+        }));
+        symbol_table.insert(
+            HASH_VARIABLE.to_string(),
+            Value::String(hash_key_table(&self.key_table_matrix)),
+        );
+        symbol_table.extend(
+            META_VARIABLES
+                .into_iter()
+                .zip([
+                    self.uf_table_sum as f64,
+                    self.bf_table_sum as f64,
+                    self.tf_table_sum as f64,
+                    run_quantities.total_permutations as f64,
+                    run_quantities.elapsed_duration.as_secs_f64(),
+                ])
+                .map(|(name, n)| (name.to_string(), Value::Number(n))),
+        );
+        // NOTE If a character is in the key table more than once, its first position is used.
+        for (r, row) in self.key_table_matrix.iter().enumerate() {
+            for (c, &byte) in row.iter().enumerate() {
+                if is_printable(byte) {
+                    symbol_table
+                        .entry(key_variable(resolve(byte)))
+                        .or_insert(Value::Position(Some((r, c))));
+                }
+            }
+        }
+        evaluate_definitions(definitions, &mut symbol_table)?;
+        Ok(symbol_table)
     }
 
     pub fn iter_unigram_details(
         &self,
         metric: UnigramMetric,
+        detail_limit: DetailLimit,
     ) -> Option<impl '_ + Iterator<Item = DetailRow<UnigramKey>>> {
         DetailIter::new(
             self.unigram_measurements.get(&metric)?,
             self.uf_sum,
             self.uf_sum_ew,
+            detail_limit,
         )
     }
 
     pub fn iter_bigram_details(
         &self,
         metric: BigramMetric,
+        detail_limit: DetailLimit,
     ) -> Option<impl '_ + Iterator<Item = DetailRow<BigramKey>>> {
         DetailIter::new(
             self.bigram_measurements.get(&metric)?,
             self.bf_sum,
             self.bf_sum_ew,
+            detail_limit,
         )
     }
 
     pub fn iter_trigram_details(
         &self,
         metric: TrigramMetric,
+        detail_limit: DetailLimit,
     ) -> Option<impl '_ + Iterator<Item = DetailRow<TrigramKey>>> {
         DetailIter::new(
             self.trigram_measurements.get(&metric)?,
             self.tf_sum,
             self.tf_sum_ew,
+            detail_limit,
         )
     }
 
@@ -225,7 +567,13 @@ impl Record {
             .map(move |(metric, measurement)| {
                 (
                     *metric,
-                    SummaryRow::new(measurement, self.uf_sum, self.uf_sum_ew),
+                    SummaryRow::new(
+                        measurement,
+                        self.uf_sum,
+                        self.uf_sum_ew,
+                        self.uf_table_sum,
+                        self.percentiles.get(&Metric::Unigram(*metric)).copied(),
+                    ),
                 )
             })
     }
@@ -236,7 +584,13 @@ impl Record {
             .map(move |(metric, measurement)| {
                 (
                     *metric,
-                    SummaryRow::new(measurement, self.bf_sum, self.bf_sum_ew),
+                    SummaryRow::new(
+                        measurement,
+                        self.bf_sum,
+                        self.bf_sum_ew,
+                        self.bf_table_sum,
+                        self.percentiles.get(&Metric::Bigram(*metric)).copied(),
+                    ),
                 )
             })
     }
@@ -247,11 +601,42 @@ impl Record {
             .map(move |(metric, measurement)| {
                 (
                     *metric,
-                    SummaryRow::new(measurement, self.tf_sum, self.tf_sum_ew),
+                    SummaryRow::new(
+                        measurement,
+                        self.tf_sum,
+                        self.tf_sum_ew,
+                        self.tf_table_sum,
+                        self.percentiles.get(&Metric::Trigram(*metric)).copied(),
+                    ),
                 )
             })
     }
 
+    // NOTE Bigram details are merged regardless of direction (e.g., 'TH' and 'HT'), and trigram
+    // details regardless of order, each under the key of its highest-scoring n-gram.
+    pub fn merge_unordered_details(&mut self, weight: Weight) {
+        for measurement in self.bigram_measurements.values_mut() {
+            measurement.merge_details_by(
+                |key| {
+                    let (a, b) = key.as_symbol_pair();
+                    (a.min(b), a.max(b))
+                },
+                weight,
+            );
+        }
+        for measurement in self.trigram_measurements.values_mut() {
+            measurement.merge_details_by(
+                |key| {
+                    let (a, b, c) = key.as_symbol_triple();
+                    let mut bytes = [a, b, c];
+                    bytes.sort_unstable();
+                    bytes
+                },
+                weight,
+            );
+        }
+    }
+
     pub fn normalize(&mut self, weight: Weight) {
         for measurement in self.unigram_measurements.values_mut() {
             measurement.retain_non_zero_details();
@@ -267,6 +652,81 @@ impl Record {
         }
     }
 
+    // NOTE The unigram metrics, then the bigram metrics, then the trigram metrics.
+    pub fn iter_metrics(&self) -> impl '_ + Iterator<Item = Metric> {
+        self.unigram_measurements
+            .keys()
+            .map(|&metric| Metric::Unigram(metric))
+            .chain(
+                self.bigram_measurements
+                    .keys()
+                    .map(|&metric| Metric::Bigram(metric)),
+            )
+            .chain(
+                self.trigram_measurements
+                    .keys()
+                    .map(|&metric| Metric::Trigram(metric)),
+            )
+    }
+
+    pub fn perc(&self, metric: Metric, weight: Weight) -> Option<f64> {
+        let denominator = match (metric, weight) {
+            (Metric::Unigram(_), Weight::Effort) => self.uf_sum_ew,
+            (Metric::Unigram(_), Weight::Raw) => self.uf_sum,
+            (Metric::Bigram(_), Weight::Effort) => self.bf_sum_ew,
+            (Metric::Bigram(_), Weight::Raw) => self.bf_sum,
+            (Metric::Trigram(_), Weight::Effort) => self.tf_sum_ew,
+            (Metric::Trigram(_), Weight::Raw) => self.tf_sum,
+        };
+        calculate_perc(self.sum(metric, weight)?, denominator)
+    }
+
+    // NOTE The percentile of a metric is the percentage of reference records that this record is
+    // strictly better than, according to the metric's goal.
+    pub fn calculate_percentiles(
+        &mut self,
+        references: &[Record<C, R>],
+        weight: Weight,
+        goals: &MetricGoals,
+    ) {
+        let metrics = self
+            .unigram_measurements
+            .keys()
+            .map(|&metric| Metric::Unigram(metric))
+            .chain(
+                self.bigram_measurements
+                    .keys()
+                    .map(|&metric| Metric::Bigram(metric)),
+            )
+            .chain(
+                self.trigram_measurements
+                    .keys()
+                    .map(|&metric| Metric::Trigram(metric)),
+            )
+            .collect::<Vec<_>>();
+        for metric in metrics {
+            let Some(perc) = self.perc(metric, weight) else {
+                continue;
+            };
+            let reference_percs = references
+                .iter()
+                .filter_map(|reference| reference.perc(metric, weight))
+                .collect::<Vec<_>>();
+            if reference_percs.is_empty() {
+                continue;
+            }
+            let worse = reference_percs
+                .iter()
+                .filter(|&&reference_perc| match goals.goal(metric) {
+                    Goal::Max => reference_perc < perc,
+                    Goal::Min => reference_perc > perc,
+                })
+                .count();
+            self.percentiles
+                .insert(metric, 100.0 * worse as f64 / reference_percs.len() as f64);
+        }
+    }
+
     pub fn sum(&self, metric: Metric, weight: Weight) -> Option<u64> {
         use Metric::*;
         match &metric {
@@ -286,37 +746,167 @@ impl Record {
     }
 }
 
-pub fn filter_records(
-    records: Vec<Record>,
-    filters: &[Expression],
+// NOTE 'drops[i]' counts the records dropped by filter 'i' (see 'log_filter_drops').
+pub fn filter_record<const C: usize, const R: usize>(
+    mut record: Record<C, R>,
+    filters: &ExpressionSet,
+    definitions: &[Definition],
     weight: Weight,
-) -> Result<Vec<Record>, EvalError> {
-    records
-        .into_iter()
-        .filter_map(|mut record| {
-            if !filters.is_empty() {
-                let symbol_table = record.build_symbol_table(weight);
-                for filter in filters {
-                    use Value::*;
-                    match filter.evaluate(&symbol_table) {
-                        Ok(Number(n)) if n == 0.0 => return None,
-                        Ok(Boolean(b)) if !b => return None,
-                        Ok(_) => continue,
-                        Err(e) => return Some(Err(e)),
-                    }
+    run_quantities: &RunQuantities,
+    drops: &mut [usize],
+) -> Result<Option<Record<C, R>>, EvalError> {
+    if !filters.is_empty() {
+        let symbol_table = record.build_symbol_table(weight, run_quantities, definitions)?;
+        let mut memo = filters.new_memo();
+        for (i, drops) in drops.iter_mut().enumerate().take(filters.len()) {
+            use Value::*;
+            match filters.evaluate(i, &symbol_table, &mut memo)? {
+                Number(n) if n == 0.0 => {
+                    *drops += 1;
+                    return Ok(None);
                 }
+                Boolean(b) if !b => {
+                    *drops += 1;
+                    return Ok(None);
+                }
+                _ => continue,
             }
-            record.normalize(weight);
-            Some(Ok(record))
+        }
+    }
+    record.normalize(weight);
+    Ok(Some(record))
+}
+
+pub fn log_filter_drops(filters: &ExpressionSet, drops: &[usize]) {
+    for (filter, drops) in filters.expressions().iter().zip(drops) {
+        log_info!("Filter '{}' dropped {} records", filter, drops);
+    }
+}
+
+pub fn filter_records<const C: usize, const R: usize>(
+    records: Vec<Record<C, R>>,
+    filters: &ExpressionSet,
+    definitions: &[Definition],
+    weight: Weight,
+    run_quantities: &RunQuantities,
+) -> Result<Vec<Record<C, R>>, EvalError> {
+    let mut drops = vec![0usize; filters.len()];
+    let records = records
+        .into_iter()
+        .filter_map(|record| {
+            filter_record(
+                record,
+                filters,
+                definitions,
+                weight,
+                run_quantities,
+                &mut drops,
+            )
+            .transpose()
         })
-        .collect::<Result<Vec<_>, _>>()
+        .collect::<Result<Vec<_>, _>>()?;
+    log_filter_drops(filters, &drops);
+    Ok(records)
+}
+
+// NOTE The number of key positions at which two key tables differ.
+pub fn hamming_distance<const C: usize, const R: usize>(
+    a: &[[Symbol; C]; R],
+    b: &[[Symbol; C]; R],
+) -> usize {
+    a.as_flattened()
+        .iter()
+        .zip(b.as_flattened())
+        .filter(|(a, b)| a != b)
+        .count()
 }
 
-pub fn select_records(
-    mut records: Vec<Record>,
+// NOTE Records are considered in order, and a record is selected if its key table differs from
+// that of every record already selected in at least 'min_distance' key positions, until
+// 'max_selections' records are selected. As records are usually sorted first, each selected record
+// is the best of its neighbourhood.
+pub fn select_diverse_records<const C: usize, const R: usize>(
+    records: Vec<Record<C, R>>,
+    max_selections: usize,
+    min_distance: usize,
+) -> Vec<Record<C, R>> {
+    let mut selected: Vec<Record<C, R>> = Vec::with_capacity(max_selections);
+    for record in records {
+        if selected.len() >= max_selections {
+            break;
+        }
+        if selected.iter().all(|other| {
+            hamming_distance(&record.key_table_matrix, &other.key_table_matrix) >= min_distance
+        }) {
+            selected.push(record);
+        }
+    }
+    selected
+}
+
+// NOTE Records are clustered by single-linkage agglomerative clustering: two records belong to the
+// same cluster if their key tables differ in at most 'max_distance' key positions, or if they are
+// linked by a chain of such records. Each cluster is represented by its first record, which is
+// kept (in order) with the size of its cluster, and the other records are dropped.
+pub fn cluster_records<const C: usize, const R: usize>(
+    records: Vec<Record<C, R>>,
+    max_distance: usize,
+) -> Vec<Record<C, R>> {
+    fn find(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    let mut parents = (0..records.len()).collect::<Vec<_>>();
+    for (i, a) in records.iter().enumerate() {
+        for (j, b) in records.iter().enumerate().skip(i + 1) {
+            if hamming_distance(&a.key_table_matrix, &b.key_table_matrix) <= max_distance {
+                let (root_i, root_j) = (find(&mut parents, i), find(&mut parents, j));
+                // NOTE The root of each cluster is its first record.
+                parents[root_i.max(root_j)] = root_i.min(root_j);
+            }
+        }
+    }
+    let mut sizes = vec![0; records.len()];
+    for i in 0..records.len() {
+        sizes[find(&mut parents, i)] += 1;
+    }
+    records
+        .into_iter()
+        .zip(sizes)
+        .filter(|(_, size)| *size > 0)
+        .map(|(mut record, size)| {
+            record.cluster_size_opt = Some(size);
+            record
+        })
+        .collect()
+}
+
+// NOTE Selections are applied in order: every Nth record, then the first N, then the last N, then
+// the maximum number of selections, and finally the index.
+pub fn select_records<const C: usize, const R: usize>(
+    mut records: Vec<Record<C, R>>,
+    every: Option<usize>,
+    head: Option<usize>,
+    tail: Option<usize>,
     max_selections: Option<usize>,
     index: Option<isize>,
-) -> Result<Vec<Record>, String> {
+) -> Result<Vec<Record<C, R>>, String> {
+    if let Some(every) = every {
+        if every == 0 {
+            return Err("'--every' must be at least 1".into());
+        }
+        records = records.into_iter().step_by(every).collect();
+    }
+    if let Some(head) = head {
+        records.truncate(head);
+    }
+    if let Some(tail) = tail {
+        records.drain(..records.len().saturating_sub(tail));
+    }
     if let Some(max_selections) = max_selections {
         records.truncate(max_selections);
     }
@@ -335,22 +925,201 @@ pub fn select_records(
     Ok(records)
 }
 
-pub fn sort_records(records: &mut [Record], sort_rules: &[SortRule], weight: Weight) {
-    records.sort_by(|a, b| {
-        use Ordering::*;
-        use SortDirection::*;
-        for sort_rule in sort_rules {
-            let ordering = a
-                .sum(sort_rule.metric, weight)
-                .cmp(&b.sum(sort_rule.metric, weight));
-            let ordering = match sort_rule.sort_direction {
-                Ascending => ordering,
-                Descending => ordering.reverse(),
-            };
-            if ordering != Equal {
-                return ordering;
-            }
+// NOTE Selects records one at a time, as they are streamed, with the same result as
+// 'select_records' (but without '--tail' or a negative index, which need every record). Once no
+// more records can be selected, the rest need not be measured.
+pub struct StreamingSelection {
+    every: usize,
+    limit_opt: Option<usize>,
+    index_opt: Option<usize>,
+    n_seen: usize,
+    n_stepped: usize,
+}
+
+impl StreamingSelection {
+    pub fn new(
+        every: Option<usize>,
+        head: Option<usize>,
+        max_selections: Option<usize>,
+        index: Option<usize>,
+    ) -> Result<Self, String> {
+        let every = every.unwrap_or(1);
+        if every == 0 {
+            return Err("'--every' must be at least 1".into());
+        }
+        let limit_opt = match (head, max_selections) {
+            (Some(head), Some(max_selections)) => Some(head.min(max_selections)),
+            (head_opt, max_selections_opt) => head_opt.or(max_selections_opt),
+        };
+        Ok(Self {
+            every,
+            limit_opt,
+            index_opt: index,
+            n_seen: 0,
+            n_stepped: 0,
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        let end = match (self.limit_opt, self.index_opt) {
+            (Some(limit), Some(index)) => limit.min(index + 1),
+            (Some(limit), None) => limit,
+            (None, Some(index)) => index + 1,
+            (None, None) => return false,
+        };
+        self.n_stepped >= end
+    }
+
+    pub fn select(&mut self) -> bool {
+        if self.is_done() {
+            return false;
+        }
+        let i = self.n_seen;
+        self.n_seen += 1;
+        if !i.is_multiple_of(self.every) {
+            return false;
+        }
+        let j = self.n_stepped;
+        self.n_stepped += 1;
+        self.index_opt.is_none_or(|index| index == j)
+    }
+
+    pub fn finish(&self) -> Result<(), String> {
+        match self.index_opt {
+            Some(index) if index >= self.n_stepped => Err(format!(
+                "Index {} out of bounds for {} entries",
+                index, self.n_stepped
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// NOTE An estimate of the memory used by records, in bytes. While permuting, each thread retains up
+// to the maximum number of records (each a score and a permutation index) until they are merged;
+// after permuting, each retained record is reconstructed as a key table, and each record that is
+// measured also holds its measurements and the rows of its detail reports.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryEstimate {
+    pub retained_record_bytes: u64,
+    pub reconstructed_record_bytes: u64,
+    pub measured_record_bytes: u64,
+}
+
+impl MemoryEstimate {
+    // NOTE 'n_detail_rows' is the number of detail rows of each record, by n-gram class (at most
+    // the number of fingerings of each metric with details).
+    pub fn new<const C: usize, const R: usize>(
+        n_metrics: usize,
+        n_detail_rows: [usize; 3],
+    ) -> Self {
+        let measurement_bytes = size_of::<Metric>() + size_of::<Measurement<TrigramKey>>();
+        let [n_unigram_rows, n_bigram_rows, n_trigram_rows] = n_detail_rows;
+        let measured_record_bytes = size_of::<Record<C, R>>()
+            + n_metrics * measurement_bytes
+            + n_unigram_rows * size_of::<Score<UnigramKey>>()
+            + n_bigram_rows * size_of::<Score<BigramKey>>()
+            + n_trigram_rows * size_of::<Score<TrigramKey>>();
+        Self {
+            retained_record_bytes: RETAINED_RECORD_BYTES as u64,
+            reconstructed_record_bytes: size_of::<[[Symbol; C]; R]>() as u64,
+            measured_record_bytes: measured_record_bytes as u64,
+        }
+    }
+
+    pub fn peak_bytes(&self, n_retained: u64, n_threads: u64, n_measured: u64) -> u64 {
+        n_retained
+            .saturating_mul(n_threads)
+            .saturating_mul(self.retained_record_bytes)
+            .saturating_add(n_retained.saturating_mul(self.reconstructed_record_bytes))
+            .saturating_add(n_measured.saturating_mul(self.measured_record_bytes))
+    }
+}
+
+// NOTE 'ranks[i][j]' is the dense rank of record 'i' by sort rule 'j': records with equal sums
+// share a rank, and the next distinct sum has the next rank. 'order' holds the indices of the
+// records, ordered by their ranks for each sort rule in turn. Records with equal ranks keep their
+// relative order.
+pub struct Ranking {
+    pub order: Vec<usize>,
+    pub ranks: Vec<Vec<usize>>,
+}
+
+pub fn rank_records<const C: usize, const R: usize>(
+    records: &[Record<C, R>],
+    sort_rules: &[SortRule],
+    weight: Weight,
+) -> Ranking {
+    let mut ranks = vec![Vec::with_capacity(sort_rules.len()); records.len()];
+    for sort_rule in sort_rules {
+        let sums = records
+            .iter()
+            .map(|record| record.sum(sort_rule.metric, weight))
+            .collect::<Vec<_>>();
+        let mut distinct_sums = sums.clone();
+        distinct_sums.sort_unstable();
+        distinct_sums.dedup();
+        for (record_ranks, sum) in ranks.iter_mut().zip(&sums) {
+            let i = distinct_sums.partition_point(|distinct_sum| distinct_sum < sum);
+            use SortDirection::*;
+            record_ranks.push(match sort_rule.sort_direction {
+                Ascending => i + 1,
+                Descending => distinct_sums.len() - i,
+            });
         }
-        Equal
-    });
+    }
+    let mut order = (0..records.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| ranks[a].cmp(&ranks[b]));
+    Ranking { order, ranks }
+}
+
+// NOTE Each record is ranked by each metric, in the direction of the metric's goal.
+pub fn assign_ranks<const C: usize, const R: usize>(
+    records: &mut [Record<C, R>],
+    metrics: &[Metric],
+    weight: Weight,
+    goals: &MetricGoals,
+) {
+    if metrics.is_empty() {
+        return;
+    }
+    let sort_rules = metrics
+        .iter()
+        .map(|&metric| SortRule {
+            metric,
+            sort_direction: match goals.goal(metric) {
+                Goal::Max => SortDirection::Descending,
+                Goal::Min => SortDirection::Ascending,
+            },
+        })
+        .collect::<Vec<_>>();
+    let Ranking { ranks, .. } = rank_records(records, &sort_rules, weight);
+    let total = records.len();
+    for (record, record_ranks) in records.iter_mut().zip(ranks) {
+        record.ranks = metrics
+            .iter()
+            .zip(record_ranks)
+            .map(|(&metric, rank)| Rank {
+                metric,
+                rank,
+                total,
+            })
+            .collect();
+    }
+}
+
+pub fn sort_records<const C: usize, const R: usize>(
+    records: Vec<Record<C, R>>,
+    sort_rules: &[SortRule],
+    weight: Weight,
+) -> Vec<Record<C, R>> {
+    if sort_rules.is_empty() {
+        return records;
+    }
+    let Ranking { order, .. } = rank_records(&records, sort_rules, weight);
+    let mut records = records.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .filter_map(|i| records[i].take())
+        .collect()
 }
@@ -1,13 +1,21 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use crate::{
-    fingerings::{BigramFingering, TrigramFingering, UnigramFingering},
+    fingerings::{
+        BigramFingering, BigramFingeringArrays, Effort, Fingering, TrigramFingering,
+        TrigramFingeringArrays, UnigramFingering, UnigramFingeringArrays,
+    },
     ngrams::{BigramKey, BigramTable, TrigramKey, TrigramTable, UnigramKey, UnigramTable},
+    symbols::Symbol,
+    util::simd,
+    weights::Weight,
 };
 
 impl UnigramKey {
     #[inline]
     pub fn from_fingering<const C: usize, const R: usize>(
         uf: &UnigramFingering,
-        key_table_matrix: &[[u8; C]; R],
+        key_table_matrix: &[[Symbol; C]; R],
     ) -> Self {
         let &((r1, c1, ..), _) = uf;
         Self::from(key_table_matrix[r1][c1])
@@ -18,7 +26,7 @@ impl BigramKey {
     #[inline]
     pub fn from_fingering<const C: usize, const R: usize>(
         bf: &BigramFingering,
-        key_table_matrix: &[[u8; C]; R],
+        key_table_matrix: &[[Symbol; C]; R],
     ) -> Self {
         let &((r1, c1, ..), (r2, c2, ..), _) = bf;
         Self::from((key_table_matrix[r1][c1], key_table_matrix[r2][c2]))
@@ -29,7 +37,7 @@ impl TrigramKey {
     #[inline]
     pub fn from_fingering<const C: usize, const R: usize>(
         tf: &TrigramFingering,
-        key_table_matrix: &[[u8; C]; R],
+        key_table_matrix: &[[Symbol; C]; R],
     ) -> Self {
         let &((r1, c1, ..), (r2, c2, ..), (r3, c3, ..), _) = tf;
         Self::from((
@@ -40,6 +48,23 @@ impl TrigramKey {
     }
 }
 
+// NOTE Sums saturate at 'u64::MAX' rather than wrapping (as do effort-weighted values, which are
+// cast from f64). Whether any sum has saturated since the last call to 'take_saturated' is
+// recorded, so that it can be reported in the metadata.
+static SATURATED: AtomicBool = AtomicBool::new(false);
+
+#[inline]
+fn note_saturation(sum: u64, sum_ew: u64) -> (u64, u64) {
+    if sum == u64::MAX || sum_ew == u64::MAX {
+        SATURATED.store(true, Ordering::Relaxed);
+    }
+    (sum, sum_ew)
+}
+
+pub fn take_saturated() -> bool {
+    SATURATED.swap(false, Ordering::Relaxed)
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Score<K> {
     pub key: K,
@@ -53,6 +78,9 @@ impl<K> Score<K> {
     }
 }
 
+// NOTE The unsafe summary skips bounds checks, relying on the fingering arrays having been
+// validated (see 'metrics::UnigramFingerings::validate').
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ScoreMode {
     Detailed,
     SummarySafe,
@@ -62,11 +90,11 @@ pub enum ScoreMode {
 #[inline]
 pub fn score_uf<const C: usize, const R: usize>(
     uf: &UnigramFingering,
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     unigram_table: &UnigramTable,
 ) -> Score<UnigramKey> {
     let key = UnigramKey::from_fingering(uf, key_table_matrix);
-    let value = unigram_table[key.as_usize()];
+    let value = unigram_table.get(key.as_symbols());
     let &(_, effort) = uf;
     let value_ew = (value as f64 * effort) as u64;
     Score {
@@ -79,7 +107,8 @@ pub fn score_uf<const C: usize, const R: usize>(
 #[inline]
 pub fn score_ufs<const C: usize, const R: usize>(
     uf_slice: &[UnigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    uf_arrays: &UnigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
     unigram_table: &UnigramTable,
     mode: ScoreMode,
 ) -> (Option<Vec<Score<UnigramKey>>>, u64, u64) {
@@ -87,16 +116,18 @@ pub fn score_ufs<const C: usize, const R: usize>(
         ScoreMode::Detailed => {
             let (details, sum, sum_ew) =
                 score_ufs_with_details(uf_slice, key_table_matrix, unigram_table);
+            let (sum, sum_ew) = note_saturation(sum, sum_ew);
             (Some(details), sum, sum_ew)
         }
         ScoreMode::SummarySafe => {
             let (sum, sum_ew) =
                 score_ufs_without_details_safe(uf_slice, key_table_matrix, unigram_table);
+            let (sum, sum_ew) = note_saturation(sum, sum_ew);
             (None, sum, sum_ew)
         }
         ScoreMode::SummaryUnsafe => {
             let (sum, sum_ew) =
-                score_ufs_without_details_unsafe(uf_slice, key_table_matrix, unigram_table);
+                score_ufs_without_details_unsafe(uf_arrays, key_table_matrix, unigram_table);
             (None, sum, sum_ew)
         }
     }
@@ -105,7 +136,7 @@ pub fn score_ufs<const C: usize, const R: usize>(
 #[inline]
 pub fn score_ufs_with_details<const C: usize, const R: usize>(
     uf_slice: &[UnigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     unigram_table: &UnigramTable,
 ) -> (Vec<Score<UnigramKey>>, u64, u64) {
     uf_slice
@@ -115,41 +146,88 @@ pub fn score_ufs_with_details<const C: usize, const R: usize>(
             (Vec::with_capacity(uf_slice.len()), 0, 0),
             |(mut unigrams, a, a_ew), score| {
                 unigrams.push(score.clone());
-                (unigrams, a + score.value, a_ew + score.value_ew)
+                (
+                    unigrams,
+                    a.saturating_add(score.value),
+                    a_ew.saturating_add(score.value_ew),
+                )
             },
         )
 }
 
+// NOTE Each score is added to every key of its n-gram, so keys that take part in many (or
+// frequent) n-grams of a metric accumulate more heat.
+pub fn heat_ufs<const C: usize, const R: usize>(
+    uf_slice: &[UnigramFingering],
+    key_table_matrix: &[[Symbol; C]; R],
+    unigram_table: &UnigramTable,
+) -> [[(u64, u64); C]; R] {
+    let mut heat_matrix = [[(0u64, 0u64); C]; R];
+    for uf in uf_slice {
+        let score = score_uf(uf, key_table_matrix, unigram_table);
+        let &((r1, c1, ..), _) = uf;
+        let (heat, heat_ew) = &mut heat_matrix[r1][c1];
+        *heat = heat.saturating_add(score.value);
+        *heat_ew = heat_ew.saturating_add(score.value_ew);
+    }
+    heat_matrix
+}
+
 #[inline]
 pub fn score_ufs_without_details_safe<const C: usize, const R: usize>(
     uf_slice: &[UnigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     unigram_table: &UnigramTable,
 ) -> (u64, u64) {
     uf_slice
         .iter()
         .map(|uf| score_uf(uf, key_table_matrix, unigram_table))
         .fold((0, 0), |(a, a_ew), score| {
-            (a + score.value, a_ew + score.value_ew)
+            (
+                a.saturating_add(score.value),
+                a_ew.saturating_add(score.value_ew),
+            )
         })
 }
 
 #[inline]
 pub fn score_ufs_without_details_unsafe<const C: usize, const R: usize>(
-    uf_slice: &[UnigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    uf_arrays: &UnigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
     unigram_table: &UnigramTable,
 ) -> (u64, u64) {
+    let (sum, sum_ew) = if simd::is_available() {
+        let keys = key_table_matrix.as_flattened();
+        simd::sum_lanes(uf_arrays.len(), |i| {
+            let o = unsafe { *uf_arrays.offsets.get_unchecked(i) } as usize;
+            let s = unsafe { *keys.get_unchecked(o) };
+            let value = unigram_table.get_unchecked([s]);
+            let effort = unsafe { *uf_arrays.efforts.get_unchecked(i) };
+            (value, effort)
+        })
+    } else {
+        score_ufs_without_details_unsafe_scalar(uf_arrays, key_table_matrix, unigram_table)
+    };
+    note_saturation(sum, sum_ew)
+}
+
+#[inline]
+fn score_ufs_without_details_unsafe_scalar<const C: usize, const R: usize>(
+    uf_arrays: &UnigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
+    unigram_table: &UnigramTable,
+) -> (u64, u64) {
+    let keys = key_table_matrix.as_flattened();
     let mut a = 0u64;
     let mut a_ew = 0u64;
-    for i in 0..uf_slice.len() {
-        let ((r, c, ..), effort) = uf_slice[i];
-        let b = unsafe { *key_table_matrix.get_unchecked(r).get_unchecked(c) };
-        let key = UnigramKey::from(b).as_usize();
-        let value = unsafe { *unigram_table.get_unchecked(key) };
+    for i in 0..uf_arrays.len() {
+        let o = unsafe { *uf_arrays.offsets.get_unchecked(i) } as usize;
+        let s = unsafe { *keys.get_unchecked(o) };
+        let value = unigram_table.get_unchecked([s]);
+        let effort = unsafe { *uf_arrays.efforts.get_unchecked(i) };
         let value_ew = (value as f64 * effort) as u64;
-        a += value;
-        a_ew += value_ew;
+        a = a.saturating_add(value);
+        a_ew = a_ew.saturating_add(value_ew);
     }
     (a, a_ew)
 }
@@ -157,11 +235,11 @@ pub fn score_ufs_without_details_unsafe<const C: usize, const R: usize>(
 #[inline]
 pub fn score_bf<const C: usize, const R: usize>(
     bf: &BigramFingering,
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     bigram_table: &BigramTable,
 ) -> Score<BigramKey> {
     let key = BigramKey::from_fingering(bf, key_table_matrix);
-    let value = bigram_table[key.as_usize()];
+    let value = bigram_table.get(key.as_symbols());
     let &(.., effort) = bf;
     let value_ew = (value as f64 * effort) as u64;
     Score {
@@ -174,7 +252,8 @@ pub fn score_bf<const C: usize, const R: usize>(
 #[inline]
 pub fn score_bfs<const C: usize, const R: usize>(
     bf_slice: &[BigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    bf_arrays: &BigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
     bigram_table: &BigramTable,
     mode: ScoreMode,
 ) -> (Option<Vec<Score<BigramKey>>>, u64, u64) {
@@ -182,16 +261,18 @@ pub fn score_bfs<const C: usize, const R: usize>(
         ScoreMode::Detailed => {
             let (details, sum, sum_ew) =
                 score_bfs_with_details(bf_slice, key_table_matrix, bigram_table);
+            let (sum, sum_ew) = note_saturation(sum, sum_ew);
             (Some(details), sum, sum_ew)
         }
         ScoreMode::SummarySafe => {
             let (sum, sum_ew) =
                 score_bfs_without_details_safe(bf_slice, key_table_matrix, bigram_table);
+            let (sum, sum_ew) = note_saturation(sum, sum_ew);
             (None, sum, sum_ew)
         }
         ScoreMode::SummaryUnsafe => {
             let (sum, sum_ew) =
-                score_bfs_without_details_unsafe(bf_slice, key_table_matrix, bigram_table);
+                score_bfs_without_details_unsafe(bf_arrays, key_table_matrix, bigram_table);
             (None, sum, sum_ew)
         }
     }
@@ -200,7 +281,7 @@ pub fn score_bfs<const C: usize, const R: usize>(
 #[inline]
 pub fn score_bfs_with_details<const C: usize, const R: usize>(
     bf_slice: &[BigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     bigram_table: &BigramTable,
 ) -> (Vec<Score<BigramKey>>, u64, u64) {
     bf_slice
@@ -210,42 +291,197 @@ pub fn score_bfs_with_details<const C: usize, const R: usize>(
             (Vec::with_capacity(bf_slice.len()), 0, 0),
             |(mut bigrams, a, a_ew), score| {
                 bigrams.push(score.clone());
-                (bigrams, a + score.value, a_ew + score.value_ew)
+                (
+                    bigrams,
+                    a.saturating_add(score.value),
+                    a_ew.saturating_add(score.value_ew),
+                )
             },
         )
 }
 
+// NOTE A scored fingering of an n-gram, with the character and fingering of each of its keys (in
+// order), and the effort of the fingering as a whole.
+pub struct Contribution {
+    pub key: String,
+    pub value: u64,
+    pub value_ew: u64,
+    pub keys: Vec<(Symbol, Fingering)>,
+    pub effort: Effort,
+}
+
+impl Contribution {
+    pub fn value_by_weight(&self, weight: Weight) -> u64 {
+        use Weight::*;
+        match weight {
+            Effort => self.value_ew,
+            Raw => self.value,
+        }
+    }
+}
+
+pub fn explain_ufs<const C: usize, const R: usize>(
+    uf_slice: &[UnigramFingering],
+    key_table_matrix: &[[Symbol; C]; R],
+    unigram_table: &UnigramTable,
+) -> Vec<Contribution> {
+    uf_slice
+        .iter()
+        .filter_map(|uf| {
+            let score = score_uf(uf, key_table_matrix, unigram_table);
+            let &(f, effort) = uf;
+            (!score.is_zero()).then(|| Contribution {
+                key: score.key.to_string(),
+                value: score.value,
+                value_ew: score.value_ew,
+                keys: vec![(key_table_matrix[f.0][f.1], f)],
+                effort,
+            })
+        })
+        .collect()
+}
+
+pub fn explain_bfs<const C: usize, const R: usize>(
+    bf_slice: &[BigramFingering],
+    key_table_matrix: &[[Symbol; C]; R],
+    bigram_table: &BigramTable,
+) -> Vec<Contribution> {
+    bf_slice
+        .iter()
+        .filter_map(|bf| {
+            let score = score_bf(bf, key_table_matrix, bigram_table);
+            let &(f1, f2, effort) = bf;
+            (!score.is_zero()).then(|| Contribution {
+                key: score.key.to_string(),
+                value: score.value,
+                value_ew: score.value_ew,
+                keys: [f1, f2].map(|f| (key_table_matrix[f.0][f.1], f)).to_vec(),
+                effort,
+            })
+        })
+        .collect()
+}
+
+pub fn explain_tfs<const C: usize, const R: usize>(
+    tf_slice: &[TrigramFingering],
+    key_table_matrix: &[[Symbol; C]; R],
+    trigram_table: &TrigramTable,
+) -> Vec<Contribution> {
+    tf_slice
+        .iter()
+        .filter_map(|tf| {
+            let score = score_tf(tf, key_table_matrix, trigram_table);
+            let &(f1, f2, f3, effort) = tf;
+            (!score.is_zero()).then(|| Contribution {
+                key: score.key.to_string(),
+                value: score.value,
+                value_ew: score.value_ew,
+                keys: [f1, f2, f3]
+                    .map(|f| (key_table_matrix[f.0][f.1], f))
+                    .to_vec(),
+                effort,
+            })
+        })
+        .collect()
+}
+
+// NOTE Like 'score_bfs_with_details', but each score keeps the positions of its two keys, so that it
+// can be located on the board. Scores of zero are omitted.
+// NOTE A scored bigram fingering, with the (row, column) coordinates of its first and second keys.
+pub type BigramLocation = (Score<BigramKey>, (usize, usize), (usize, usize));
+
+pub fn locate_bfs<const C: usize, const R: usize>(
+    bf_slice: &[BigramFingering],
+    key_table_matrix: &[[Symbol; C]; R],
+    bigram_table: &BigramTable,
+) -> Vec<BigramLocation> {
+    bf_slice
+        .iter()
+        .filter_map(|bf| {
+            let score = score_bf(bf, key_table_matrix, bigram_table);
+            let &((r1, c1, ..), (r2, c2, ..), _) = bf;
+            (!score.is_zero()).then_some((score, (r1, c1), (r2, c2)))
+        })
+        .collect()
+}
+
+pub fn heat_bfs<const C: usize, const R: usize>(
+    bf_slice: &[BigramFingering],
+    key_table_matrix: &[[Symbol; C]; R],
+    bigram_table: &BigramTable,
+) -> [[(u64, u64); C]; R] {
+    let mut heat_matrix = [[(0u64, 0u64); C]; R];
+    for bf in bf_slice {
+        let score = score_bf(bf, key_table_matrix, bigram_table);
+        let &((r1, c1, ..), (r2, c2, ..), _) = bf;
+        for (r, c) in [(r1, c1), (r2, c2)] {
+            let (heat, heat_ew) = &mut heat_matrix[r][c];
+            *heat = heat.saturating_add(score.value);
+            *heat_ew = heat_ew.saturating_add(score.value_ew);
+        }
+    }
+    heat_matrix
+}
+
 #[inline]
 pub fn score_bfs_without_details_safe<const C: usize, const R: usize>(
     bf_slice: &[BigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     bigram_table: &BigramTable,
 ) -> (u64, u64) {
     bf_slice
         .iter()
         .map(|bf| score_bf(bf, key_table_matrix, bigram_table))
         .fold((0, 0), |(a, a_ew), score| {
-            (a + score.value, a_ew + score.value_ew)
+            (
+                a.saturating_add(score.value),
+                a_ew.saturating_add(score.value_ew),
+            )
         })
 }
 
 #[inline]
 pub fn score_bfs_without_details_unsafe<const C: usize, const R: usize>(
-    bf_slice: &[BigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    bf_arrays: &BigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
+    bigram_table: &BigramTable,
+) -> (u64, u64) {
+    let (sum, sum_ew) = if simd::is_available() {
+        let keys = key_table_matrix.as_flattened();
+        simd::sum_lanes(bf_arrays.len(), |i| {
+            let o1 = unsafe { *bf_arrays.offsets1.get_unchecked(i) } as usize;
+            let o2 = unsafe { *bf_arrays.offsets2.get_unchecked(i) } as usize;
+            let s1 = unsafe { *keys.get_unchecked(o1) };
+            let s2 = unsafe { *keys.get_unchecked(o2) };
+            let value = bigram_table.get_unchecked([s1, s2]);
+            let effort = unsafe { *bf_arrays.efforts.get_unchecked(i) };
+            (value, effort)
+        })
+    } else {
+        score_bfs_without_details_unsafe_scalar(bf_arrays, key_table_matrix, bigram_table)
+    };
+    note_saturation(sum, sum_ew)
+}
+
+#[inline]
+fn score_bfs_without_details_unsafe_scalar<const C: usize, const R: usize>(
+    bf_arrays: &BigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
     bigram_table: &BigramTable,
 ) -> (u64, u64) {
+    let keys = key_table_matrix.as_flattened();
     let mut a = 0u64;
     let mut a_ew = 0u64;
-    for i in 0..bf_slice.len() {
-        let ((r1, c1, ..), (r2, c2, ..), effort) = bf_slice[i];
-        let b1 = unsafe { *key_table_matrix.get_unchecked(r1).get_unchecked(c1) };
-        let b2 = unsafe { *key_table_matrix.get_unchecked(r2).get_unchecked(c2) };
-        let key = BigramKey::from((b1, b2)).as_usize();
-        let value = unsafe { *bigram_table.get_unchecked(key) };
+    for i in 0..bf_arrays.len() {
+        let o1 = unsafe { *bf_arrays.offsets1.get_unchecked(i) } as usize;
+        let o2 = unsafe { *bf_arrays.offsets2.get_unchecked(i) } as usize;
+        let s1 = unsafe { *keys.get_unchecked(o1) };
+        let s2 = unsafe { *keys.get_unchecked(o2) };
+        let value = bigram_table.get_unchecked([s1, s2]);
+        let effort = unsafe { *bf_arrays.efforts.get_unchecked(i) };
         let value_ew = (value as f64 * effort) as u64;
-        a += value;
-        a_ew += value_ew;
+        a = a.saturating_add(value);
+        a_ew = a_ew.saturating_add(value_ew);
     }
     (a, a_ew)
 }
@@ -253,11 +489,11 @@ pub fn score_bfs_without_details_unsafe<const C: usize, const R: usize>(
 #[inline]
 pub fn score_tf<const C: usize, const R: usize>(
     tf: &TrigramFingering,
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     trigram_table: &TrigramTable,
 ) -> Score<TrigramKey> {
     let key = TrigramKey::from_fingering(tf, key_table_matrix);
-    let value = trigram_table[key.as_usize()];
+    let value = trigram_table.get(key.as_symbols());
     let &(.., effort) = tf;
     let value_ew = (value as f64 * effort) as u64;
     Score {
@@ -270,7 +506,8 @@ pub fn score_tf<const C: usize, const R: usize>(
 #[inline]
 pub fn score_tfs<const C: usize, const R: usize>(
     tf_slice: &[TrigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    tf_arrays: &TrigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
     trigram_table: &TrigramTable,
     mode: ScoreMode,
 ) -> (Option<Vec<Score<TrigramKey>>>, u64, u64) {
@@ -278,16 +515,18 @@ pub fn score_tfs<const C: usize, const R: usize>(
         ScoreMode::Detailed => {
             let (details, sum, sum_ew) =
                 score_tfs_with_details(tf_slice, key_table_matrix, trigram_table);
+            let (sum, sum_ew) = note_saturation(sum, sum_ew);
             (Some(details), sum, sum_ew)
         }
         ScoreMode::SummarySafe => {
             let (sum, sum_ew) =
                 score_tfs_without_details_safe(tf_slice, key_table_matrix, trigram_table);
+            let (sum, sum_ew) = note_saturation(sum, sum_ew);
             (None, sum, sum_ew)
         }
         ScoreMode::SummaryUnsafe => {
             let (sum, sum_ew) =
-                score_tfs_without_details_unsafe(tf_slice, key_table_matrix, trigram_table);
+                score_tfs_without_details_unsafe(tf_arrays, key_table_matrix, trigram_table);
             (None, sum, sum_ew)
         }
     }
@@ -296,7 +535,7 @@ pub fn score_tfs<const C: usize, const R: usize>(
 #[inline]
 pub fn score_tfs_with_details<const C: usize, const R: usize>(
     tf_slice: &[TrigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     trigram_table: &TrigramTable,
 ) -> (Vec<Score<TrigramKey>>, u64, u64) {
     tf_slice
@@ -306,43 +545,120 @@ pub fn score_tfs_with_details<const C: usize, const R: usize>(
             (Vec::with_capacity(tf_slice.len()), 0, 0),
             |(mut trigrams, a, a_ew), score| {
                 trigrams.push(score.clone());
-                (trigrams, a + score.value, a_ew + score.value_ew)
+                (
+                    trigrams,
+                    a.saturating_add(score.value),
+                    a_ew.saturating_add(score.value_ew),
+                )
             },
         )
 }
 
+// NOTE Scales a heat matrix so that its hottest cell is 1.0. A matrix with no heat is all 0.0.
+// NOTE Reduces a matrix of raw and effort-weighted heats (as returned by 'heat_ufs', 'heat_bfs', or
+// 'heat_tfs') to the heat of each key by the selected weight.
+pub fn weigh_heat_matrix<const C: usize, const R: usize>(
+    heat_matrix: &[[(u64, u64); C]; R],
+    weight: Weight,
+) -> [[u64; C]; R] {
+    heat_matrix.map(|row| {
+        row.map(|(heat, heat_ew)| match weight {
+            Weight::Effort => heat_ew,
+            Weight::Raw => heat,
+        })
+    })
+}
+
+pub fn normalize_heat_matrix<const C: usize, const R: usize>(
+    heat_matrix: &[[u64; C]; R],
+) -> [[f64; C]; R] {
+    match heat_matrix.as_flattened().iter().max() {
+        None | Some(0) => [[0.0; C]; R],
+        Some(&max) => heat_matrix.map(|row| row.map(|heat| heat as f64 / max as f64)),
+    }
+}
+
+pub fn heat_tfs<const C: usize, const R: usize>(
+    tf_slice: &[TrigramFingering],
+    key_table_matrix: &[[Symbol; C]; R],
+    trigram_table: &TrigramTable,
+) -> [[(u64, u64); C]; R] {
+    let mut heat_matrix = [[(0u64, 0u64); C]; R];
+    for tf in tf_slice {
+        let score = score_tf(tf, key_table_matrix, trigram_table);
+        let &((r1, c1, ..), (r2, c2, ..), (r3, c3, ..), _) = tf;
+        for (r, c) in [(r1, c1), (r2, c2), (r3, c3)] {
+            let (heat, heat_ew) = &mut heat_matrix[r][c];
+            *heat = heat.saturating_add(score.value);
+            *heat_ew = heat_ew.saturating_add(score.value_ew);
+        }
+    }
+    heat_matrix
+}
+
 #[inline]
 pub fn score_tfs_without_details_safe<const C: usize, const R: usize>(
     tf_slice: &[TrigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
     trigram_table: &TrigramTable,
 ) -> (u64, u64) {
     tf_slice
         .iter()
         .map(|tf| score_tf(tf, key_table_matrix, trigram_table))
         .fold((0, 0), |(a, a_ew), score| {
-            (a + score.value, a_ew + score.value_ew)
+            (
+                a.saturating_add(score.value),
+                a_ew.saturating_add(score.value_ew),
+            )
         })
 }
 
 #[inline]
 pub fn score_tfs_without_details_unsafe<const C: usize, const R: usize>(
-    tf_slice: &[TrigramFingering],
-    key_table_matrix: &[[u8; C]; R],
+    tf_arrays: &TrigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
+    trigram_table: &TrigramTable,
+) -> (u64, u64) {
+    let (sum, sum_ew) = if simd::is_available() {
+        let keys = key_table_matrix.as_flattened();
+        simd::sum_lanes(tf_arrays.len(), |i| {
+            let o1 = unsafe { *tf_arrays.offsets1.get_unchecked(i) } as usize;
+            let o2 = unsafe { *tf_arrays.offsets2.get_unchecked(i) } as usize;
+            let o3 = unsafe { *tf_arrays.offsets3.get_unchecked(i) } as usize;
+            let s1 = unsafe { *keys.get_unchecked(o1) };
+            let s2 = unsafe { *keys.get_unchecked(o2) };
+            let s3 = unsafe { *keys.get_unchecked(o3) };
+            let value = trigram_table.get_unchecked([s1, s2, s3]);
+            let effort = unsafe { *tf_arrays.efforts.get_unchecked(i) };
+            (value, effort)
+        })
+    } else {
+        score_tfs_without_details_unsafe_scalar(tf_arrays, key_table_matrix, trigram_table)
+    };
+    note_saturation(sum, sum_ew)
+}
+
+#[inline]
+fn score_tfs_without_details_unsafe_scalar<const C: usize, const R: usize>(
+    tf_arrays: &TrigramFingeringArrays,
+    key_table_matrix: &[[Symbol; C]; R],
     trigram_table: &TrigramTable,
 ) -> (u64, u64) {
+    let keys = key_table_matrix.as_flattened();
     let mut a = 0u64;
     let mut a_ew = 0u64;
-    for i in 0..tf_slice.len() {
-        let ((r1, c1, ..), (r2, c2, ..), (r3, c3, ..), effort) = tf_slice[i];
-        let b1 = unsafe { *key_table_matrix.get_unchecked(r1).get_unchecked(c1) };
-        let b2 = unsafe { *key_table_matrix.get_unchecked(r2).get_unchecked(c2) };
-        let b3 = unsafe { *key_table_matrix.get_unchecked(r3).get_unchecked(c3) };
-        let key = TrigramKey::from((b1, b2, b3)).as_usize();
-        let value = unsafe { *trigram_table.get_unchecked(key) };
+    for i in 0..tf_arrays.len() {
+        let o1 = unsafe { *tf_arrays.offsets1.get_unchecked(i) } as usize;
+        let o2 = unsafe { *tf_arrays.offsets2.get_unchecked(i) } as usize;
+        let o3 = unsafe { *tf_arrays.offsets3.get_unchecked(i) } as usize;
+        let s1 = unsafe { *keys.get_unchecked(o1) };
+        let s2 = unsafe { *keys.get_unchecked(o2) };
+        let s3 = unsafe { *keys.get_unchecked(o3) };
+        let value = trigram_table.get_unchecked([s1, s2, s3]);
+        let effort = unsafe { *tf_arrays.efforts.get_unchecked(i) };
         let value_ew = (value as f64 * effort) as u64;
-        a += value;
-        a_ew += value_ew;
+        a = a.saturating_add(value);
+        a_ew = a_ew.saturating_add(value_ew);
     }
     (a, a_ew)
 }
@@ -0,0 +1,489 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    fingerings::EffortModel,
+    keys::{Key, KeyTable},
+    layouts::LayoutTable,
+    measurements::Measurement,
+    metrics::{
+        BigramFingerings, BigramMetric, Metric, ThumbMode, TrigramFingerings, TrigramMetric,
+        UnigramFingerings, UnigramMetric,
+    },
+    ngrams::{BigramTable, TrigramTable, UnigramTable, sum_ngram_table},
+    records::Record,
+    scores::{ScoreMode, score_bfs, score_tfs, score_ufs},
+    symbols::Symbol,
+};
+
+// NOTE These entry points work entirely in memory (there is no file I/O), and the same inputs
+// always produce the same record, so records can be compared against golden JSON (see
+// 'archives::record_to_value'; see the tests). Use 'testing' to build small inputs in unit tests.
+
+// NOTE The sum of each table is calculated once, here, rather than once per record.
+pub struct NgramTables<'a> {
+    unigram_table: &'a UnigramTable,
+    bigram_table: &'a BigramTable,
+    trigram_table: &'a TrigramTable,
+    sums: [u64; 3],
+}
+
+impl<'a> NgramTables<'a> {
+    pub fn new(
+        unigram_table: &'a UnigramTable,
+        bigram_table: &'a BigramTable,
+        trigram_table: &'a TrigramTable,
+    ) -> Self {
+        Self {
+            unigram_table,
+            bigram_table,
+            trigram_table,
+            sums: [
+                sum_ngram_table(unigram_table),
+                sum_ngram_table(bigram_table),
+                sum_ngram_table(trigram_table),
+            ],
+        }
+    }
+
+    pub fn sums(&self) -> [u64; 3] {
+        self.sums
+    }
+}
+
+// NOTE The metrics to measure for each record. Measuring a metric of an n-gram class also measures
+// the totals of that class (which its percentages need); a class with no metric to measure is
+// skipped entirely, and its totals are left at 0. Metrics given details are always measured.
+#[derive(Clone, Debug)]
+pub struct MeasurementPlan {
+    metrics_opt: Option<BTreeSet<Metric>>,
+    detailed_metrics: Vec<Metric>,
+}
+
+impl MeasurementPlan {
+    // NOTE Every metric is measured.
+    pub fn all(detailed_metrics: &[Metric]) -> Self {
+        Self {
+            metrics_opt: None,
+            detailed_metrics: detailed_metrics.to_vec(),
+        }
+    }
+
+    // NOTE Only the detailed metrics are measured, until more are added.
+    pub fn only(detailed_metrics: &[Metric]) -> Self {
+        Self {
+            metrics_opt: Some(detailed_metrics.iter().copied().collect()),
+            detailed_metrics: detailed_metrics.to_vec(),
+        }
+    }
+
+    pub fn measure_all(&mut self) {
+        self.metrics_opt = None;
+    }
+
+    pub fn extend(&mut self, metrics: impl IntoIterator<Item = Metric>) {
+        if let Some(planned_metrics) = &mut self.metrics_opt {
+            planned_metrics.extend(metrics);
+        }
+    }
+
+    pub fn is_measured(&self, metric: Metric) -> bool {
+        self.metrics_opt
+            .as_ref()
+            .is_none_or(|planned_metrics| planned_metrics.contains(&metric))
+    }
+
+    pub fn is_detailed(&self, metric: Metric) -> bool {
+        self.detailed_metrics.contains(&metric)
+    }
+
+    // NOTE The number of metrics to measure, out of the number of metrics.
+    pub fn count(&self) -> (usize, usize) {
+        let total = Metric::iter_all().count();
+        let measured = Metric::iter_all()
+            .filter(|&metric| self.is_measured(metric))
+            .count();
+        (measured, total)
+    }
+}
+
+// NOTE Only the metrics of 'plan' are measured, and only its detailed metrics keep their details;
+// the rest are summarized using 'summary_mode' (either 'ScoreMode::SummarySafe' or
+// 'ScoreMode::SummaryUnsafe'). The record has no percentiles, provenance, overlay, heatmap,
+// utilization, ranks, cluster size, or source.
+pub fn measure<const C: usize, const R: usize>(
+    unigram_fingerings: &UnigramFingerings<C, R>,
+    bigram_fingerings: &BigramFingerings<C, R>,
+    trigram_fingerings: &TrigramFingerings<C, R>,
+    ngram_tables: &NgramTables,
+    key_table_matrix: [[Symbol; C]; R],
+    plan: &MeasurementPlan,
+    summary_mode: ScoreMode,
+) -> Record<C, R> {
+    let score_mode_fn = |metric: Metric| {
+        if plan.is_detailed(metric) {
+            ScoreMode::Detailed
+        } else {
+            summary_mode
+        }
+    };
+
+    let NgramTables {
+        unigram_table,
+        bigram_table,
+        trigram_table,
+        sums: [uf_table_sum, bf_table_sum, tf_table_sum],
+    } = *ngram_tables;
+
+    let unigram_measurements = UnigramMetric::VARIANT_ARRAY
+        .iter()
+        .filter(|&&metric| plan.is_measured(Metric::Unigram(metric)))
+        .map(|&metric| {
+            let (details_opt, f_sum, f_sum_ew) = score_ufs(
+                unigram_fingerings.get_by_metric(metric),
+                unigram_fingerings.get_arrays_by_metric(metric),
+                &key_table_matrix,
+                unigram_table,
+                score_mode_fn(Metric::Unigram(metric)),
+            );
+            (metric, Measurement::new(details_opt, f_sum, f_sum_ew))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let bigram_measurements = BigramMetric::VARIANT_ARRAY
+        .iter()
+        .filter(|&&metric| plan.is_measured(Metric::Bigram(metric)))
+        .map(|&metric| {
+            let (details_opt, f_sum, f_sum_ew) = score_bfs(
+                bigram_fingerings.get_by_metric(metric),
+                bigram_fingerings.get_arrays_by_metric(metric),
+                &key_table_matrix,
+                bigram_table,
+                score_mode_fn(Metric::Bigram(metric)),
+            );
+            let mut measurement = Measurement::new(details_opt, f_sum, f_sum_ew);
+            if metric.is_weighted_by_effort() {
+                measurement.weigh_by_effort();
+            }
+            (metric, measurement)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let trigram_measurements = TrigramMetric::VARIANT_ARRAY
+        .iter()
+        .filter(|&&metric| plan.is_measured(Metric::Trigram(metric)))
+        .map(|&metric| {
+            let (details_opt, f_sum, f_sum_ew) = score_tfs(
+                trigram_fingerings.get_by_metric(metric),
+                trigram_fingerings.get_arrays_by_metric(metric),
+                &key_table_matrix,
+                trigram_table,
+                score_mode_fn(Metric::Trigram(metric)),
+            );
+            (metric, Measurement::new(details_opt, f_sum, f_sum_ew))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let (uf_sum, uf_sum_ew) = if unigram_measurements.is_empty() {
+        (0, 0)
+    } else {
+        let (_, sum, sum_ew) = score_ufs(
+            unigram_fingerings.get(),
+            unigram_fingerings.get_arrays(),
+            &key_table_matrix,
+            unigram_table,
+            summary_mode,
+        );
+        (sum, sum_ew)
+    };
+
+    let (bf_sum, bf_sum_ew) = if bigram_measurements.is_empty() {
+        (0, 0)
+    } else {
+        let (_, sum, sum_ew) = score_bfs(
+            bigram_fingerings.get(),
+            bigram_fingerings.get_arrays(),
+            &key_table_matrix,
+            bigram_table,
+            summary_mode,
+        );
+        (sum, sum_ew)
+    };
+
+    let (tf_sum, tf_sum_ew) = if trigram_measurements.is_empty() {
+        (0, 0)
+    } else {
+        let (_, sum, sum_ew) = score_tfs(
+            trigram_fingerings.get(),
+            trigram_fingerings.get_arrays(),
+            &key_table_matrix,
+            trigram_table,
+            summary_mode,
+        );
+        (sum, sum_ew)
+    };
+
+    Record {
+        key_table_matrix,
+        unigram_measurements,
+        bigram_measurements,
+        trigram_measurements,
+        uf_sum,
+        uf_sum_ew,
+        bf_sum,
+        bf_sum_ew,
+        tf_sum,
+        tf_sum_ew,
+        uf_table_sum,
+        bf_table_sum,
+        tf_table_sum,
+        percentiles: BTreeMap::new(),
+        provenance_opt: None,
+        overlay_opt: None,
+        heatmap_opt: None,
+        utilization_opt: None,
+        ranks: Vec::new(),
+        cluster_size_opt: None,
+        source_opt: None,
+    }
+}
+
+// NOTE What to do with the placeholders of a region that has no characters for permutation. They
+// are left in the key table: with 'Allow', they are scored as if they were characters (which no
+// n-gram contains); with 'Mask', their keys are masked out of the layout table, as if they were
+// empty; and with 'Error', they are an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartialMode {
+    Allow,
+    Mask,
+    Error,
+}
+
+impl PartialMode {
+    // NOTE 'lengths' are the numbers of characters given for regions 1, 2, and 3. Returns the
+    // coordinates of the placeholders of the regions with none.
+    pub fn apply<const C: usize, const R: usize>(
+        self,
+        layout_table: &mut LayoutTable<C, R>,
+        key_table: &KeyTable<C, R>,
+        lengths: [usize; 3],
+    ) -> Result<BTreeSet<(usize, usize)>, String> {
+        let mut unfilled_coordinates = BTreeSet::new();
+        let mut counts = [0; 3];
+        for (r, row) in key_table.0.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                let i = match cell {
+                    Some(Key::One) => 0,
+                    Some(Key::Two) => 1,
+                    Some(Key::Three) => 2,
+                    _ => continue,
+                };
+                if lengths[i] == 0 {
+                    unfilled_coordinates.insert((r, c));
+                    counts[i] += 1;
+                }
+            }
+        }
+        match self {
+            PartialMode::Allow => (),
+            PartialMode::Mask => {
+                layout_table.mask(|r, c, _digit| !unfilled_coordinates.contains(&(r, c)))
+            }
+            PartialMode::Error => {
+                if let Some(i) = counts.iter().position(|&count| count > 0) {
+                    Err(format!(
+                        "There are ({}) {}s in the key table. \
+                         Provide a string for permutation of the same length via '-{}' \
+                         (or specify '--partial allow' or '--partial mask')",
+                        counts[i],
+                        i + 1,
+                        i + 1
+                    ))?
+                }
+            }
+        }
+        Ok(unfilled_coordinates)
+    }
+}
+
+// NOTE As with the CLI, positions of the layout table that have no key in the key table are
+// masked out before the fingerings are generated. Only a single record is measured, so the safe
+// summary is used.
+pub fn score_key_table<const C: usize, const R: usize>(
+    mut layout_table: LayoutTable<C, R>,
+    key_table: &KeyTable<C, R>,
+    effort_model: &EffortModel<C, R>,
+    thumb_mode: ThumbMode,
+    sfb_include_repeats: bool,
+    ngram_tables: &NgramTables,
+    detailed_metrics: &[Metric],
+) -> Result<Record<C, R>, String> {
+    layout_table.mask(|r, c, _digit| key_table.0[r][c].is_some());
+    Ok(measure(
+        &layout_table.unigram_fingerings(effort_model),
+        &layout_table.bigram_fingerings(effort_model, sfb_include_repeats)?,
+        &layout_table.trigram_fingerings(effort_model, thumb_mode)?,
+        ngram_tables,
+        key_table.to_byte_matrix(),
+        &MeasurementPlan::all(detailed_metrics),
+        ScoreMode::SummarySafe,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{archives::record_to_value, testing, weights::Weight};
+
+    // NOTE Fixtures of any size can be scored; this one has two rows of eight keys.
+    #[test]
+    fn score_small_key_table() -> Result<(), Box<dyn core::error::Error>> {
+        let layout_table =
+            testing::layout_table::<8, 2>(&["lp lr lm li ri rm rr rp", "lp lr lm li ri rm rr rp"])?;
+        let key_table = testing::key_table::<8, 2>(&["QWERUIOP", "ASDFJKL;"])?;
+        let unigram_table = testing::unigram_table(&[("A", 4), ("F", 3), ("J", 2), ("Q", 1)])?;
+        let bigram_table = testing::bigram_table(&[("FR", 10), ("ED", 3), ("AS", 5)])?;
+        let trigram_table = testing::trigram_table(&[("ASD", 7), ("FJK", 2)])?;
+        let record = score_key_table(
+            layout_table,
+            &key_table,
+            &testing::effort_model(),
+            ThumbMode::Hand,
+            false,
+            &NgramTables::new(&unigram_table, &bigram_table, &trigram_table),
+            &[],
+        )?;
+        let sum = |metric| record.sum(metric, Weight::Raw);
+        assert_eq!(sum(Metric::Unigram(UnigramMetric::Lp)), Some(5));
+        assert_eq!(sum(Metric::Unigram(UnigramMetric::Li)), Some(3));
+        assert_eq!(sum(Metric::Bigram(BigramMetric::Sfb)), Some(13));
+        assert_eq!(sum(Metric::Trigram(TrigramMetric::Rol)), Some(2));
+        assert_eq!((record.uf_sum, record.bf_sum, record.tf_sum), (10, 18, 9));
+        Ok(())
+    }
+
+    // NOTE The size of an n-gram table does not limit the characters of a key table, so a corpus
+    // with more than 128 distinct non-ASCII characters can be scored.
+    #[test]
+    fn score_key_table_with_large_alphabet() -> Result<(), Box<dyn core::error::Error>> {
+        let layout_table =
+            testing::layout_table::<8, 2>(&["lp lr lm li ri rm rr rp", "lp lr lm li ri rm rr rp"])?;
+        let key_table = testing::key_table::<8, 2>(&["ЙЦУКГШЩЗ", "ФЫВАОЛДÜ"])?;
+        let unigrams = ('\u{400}'..='\u{4FF}')
+            .map(|ch| ch.to_string())
+            .collect::<Vec<_>>();
+        let unigram_table = testing::unigram_table(
+            &unigrams
+                .iter()
+                .map(|s| (s.as_str(), 1))
+                .chain([("Ü", 2)])
+                .collect::<Vec<_>>(),
+        )?;
+        let bigram_table = testing::bigram_table(&[("ФЙ", 4), ("ÜЗ", 3)])?;
+        let trigram_table = testing::trigram_table(&[("ФЫВ", 5)])?;
+        let record = score_key_table(
+            layout_table,
+            &key_table,
+            &testing::effort_model(),
+            ThumbMode::Hand,
+            false,
+            &NgramTables::new(&unigram_table, &bigram_table, &trigram_table),
+            &[],
+        )?;
+        let sum = |metric| record.sum(metric, Weight::Raw);
+        assert_eq!(sum(Metric::Unigram(UnigramMetric::Lp)), Some(2));
+        assert_eq!(sum(Metric::Unigram(UnigramMetric::Rp)), Some(3));
+        assert_eq!(sum(Metric::Bigram(BigramMetric::Sfb)), Some(7));
+        assert_eq!((record.uf_sum, record.bf_sum, record.tf_sum), (17, 7, 5));
+        Ok(())
+    }
+
+    // NOTE A key table with a placeholder of region 1 at (0, 3), for which no characters are given.
+    fn score_partial_key_table(
+        partial_mode: PartialMode,
+    ) -> Result<Record<8, 2>, Box<dyn core::error::Error>> {
+        let mut layout_table =
+            testing::layout_table::<8, 2>(&["lp lr lm li ri rm rr rp", "lp lr lm li ri rm rr rp"])?;
+        let key_table = testing::key_table::<8, 2>(&["QWE1UIOP", "ASDFJKL;"])?;
+        let unfilled_coordinates = partial_mode.apply(&mut layout_table, &key_table, [0, 0, 0])?;
+        assert_eq!(unfilled_coordinates, BTreeSet::from([(0, 3)]));
+        let unigram_table = testing::unigram_table(&[("A", 4), ("F", 3), ("E", 2)])?;
+        let bigram_table = testing::bigram_table(&[("FE", 10), ("ED", 3)])?;
+        let trigram_table = testing::trigram_table(&[("ASD", 7)])?;
+        Ok(score_key_table(
+            layout_table,
+            &key_table,
+            &testing::effort_model(),
+            ThumbMode::Hand,
+            false,
+            &NgramTables::new(&unigram_table, &bigram_table, &trigram_table),
+            &[Metric::Unigram(UnigramMetric::Li)],
+        )?)
+    }
+
+    #[test]
+    fn partial_mode_allow_scores_placeholders() -> Result<(), Box<dyn core::error::Error>> {
+        let record = score_partial_key_table(PartialMode::Allow)?;
+        let details = record.unigram_measurements[&UnigramMetric::Li]
+            .details_opt
+            .as_ref()
+            .ok_or("Expected details")?;
+        let keys = details
+            .iter()
+            .map(|score| score.key.as_symbol())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [1, b'F' as Symbol]);
+        assert_eq!(
+            record.sum(Metric::Unigram(UnigramMetric::Li), Weight::Raw),
+            Some(3)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn partial_mode_mask_excludes_placeholders() -> Result<(), Box<dyn core::error::Error>> {
+        let record = score_partial_key_table(PartialMode::Mask)?;
+        let details = record.unigram_measurements[&UnigramMetric::Li]
+            .details_opt
+            .as_ref()
+            .ok_or("Expected details")?;
+        let keys = details
+            .iter()
+            .map(|score| score.key.as_symbol())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [b'F' as Symbol]);
+        // NOTE The totals are those of the same key table with no key at the placeholder.
+        let layout_table =
+            testing::layout_table::<8, 2>(&["lp lr lm li ri rm rr rp", "lp lr lm li ri rm rr rp"])?;
+        let key_table = testing::key_table::<8, 2>(&["QWE UIOP", "ASDFJKL;"])?;
+        let unigram_table = testing::unigram_table(&[("A", 4), ("F", 3), ("E", 2)])?;
+        let bigram_table = testing::bigram_table(&[("FE", 10), ("ED", 3)])?;
+        let trigram_table = testing::trigram_table(&[("ASD", 7)])?;
+        let expected = score_key_table(
+            layout_table,
+            &key_table,
+            &testing::effort_model(),
+            ThumbMode::Hand,
+            false,
+            &NgramTables::new(&unigram_table, &bigram_table, &trigram_table),
+            &[Metric::Unigram(UnigramMetric::Li)],
+        )?;
+        assert_eq!(
+            record_to_value(&record)["measurements"],
+            record_to_value(&expected)["measurements"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn partial_mode_error_rejects_placeholders() -> Result<(), Box<dyn core::error::Error>> {
+        let error = score_partial_key_table(PartialMode::Error)
+            .err()
+            .ok_or("Expected an error")?;
+        assert!(
+            error
+                .to_string()
+                .starts_with("There are (1) 1s in the key table")
+        );
+        Ok(())
+    }
+}
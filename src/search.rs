@@ -0,0 +1,563 @@
+use core::error::Error;
+
+use std::collections::HashSet;
+
+use crate::{
+    goals::{Goal, Tolerance},
+    log_info,
+    permutations::{
+        PermutationProgress, TieBreak, calculate_threshold, map_in_parallel, permute_and_substitute,
+    },
+    symbols::Symbol,
+    util::hash::seeded_hash,
+};
+
+pub type Region<'a, const N: usize> = ([Symbol; N], usize, &'a [(usize, usize)]);
+
+// NOTE See 'search'.
+pub type SearchResult<const C: usize, const R: usize> = (u64, bool, Vec<[[Symbol; C]; R]>, bool);
+
+type ScoredRecord<const C: usize, const R: usize> = (u64, [[Symbol; C]; R]);
+
+// NOTE The options of 'permute_and_substitute' that are the same for every phase.
+#[derive(Clone, Copy, Debug)]
+pub struct PermutationOptions {
+    pub goal: Goal,
+    pub tolerance: Tolerance,
+    pub max_permutations_opt: Option<u64>,
+    pub max_records_opt: Option<u32>,
+    pub tie_break: TieBreak,
+    pub parallelize: bool,
+    pub threads: usize,
+    pub sleep_ns: u64,
+}
+
+// NOTE Phase one permutes every region, scoring each permutation by a cheap metric (see
+// 'phase1_scoring_fn'), and keeps only the 'top' best records, regardless of the tolerance. Phase
+// two starts from the key table of each of them and permutes only the regions in 'regions' (the
+// other regions keep the characters that phase one assigned them), scoring each permutation by the
+// full objective.
+#[derive(Clone, Copy, Debug)]
+pub struct TwoPhaseSearch {
+    pub goal: Goal,
+    pub top: u32,
+    pub max_permutations_opt: Option<u64>,
+    pub regions: [bool; 3],
+}
+
+impl TwoPhaseSearch {
+    // NOTE The number of permutations each phase will consider, given the number of permutations
+    // of every region and of only the regions permuted by phase two (each 'None' if more than
+    // 'u64::MAX').
+    pub fn expected_permutations(
+        &self,
+        possible_permutations_opt: Option<u64>,
+        phase2_permutations_opt: Option<u64>,
+        max_permutations_opt: Option<u64>,
+    ) -> [u64; 2] {
+        let phase1_permutations = self
+            .max_permutations_opt
+            .unwrap_or(u64::MAX)
+            .min(possible_permutations_opt.unwrap_or(u64::MAX));
+        let phase2_permutations = max_permutations_opt
+            .unwrap_or(u64::MAX)
+            .min(phase2_permutations_opt.unwrap_or(u64::MAX))
+            .saturating_mul(phase1_permutations.min(self.top as u64));
+        [phase1_permutations, phase2_permutations]
+    }
+}
+
+// NOTE The result has the same form as that of 'permute_and_substitute': the permutations
+// considered (by both phases), whether either phase was truncated by its maximum permutations, the
+// records (sorted by descending score, without duplicates), and whether they were truncated.
+// Progress is reported as a count of the permutations of both phases.
+pub fn search<const C: usize, const R: usize, const N: usize>(
+    matrix: &[[Symbol; C]; R],
+    regions: [Region<N>; 3],
+    two_phase_search: &TwoPhaseSearch,
+    options: &PermutationOptions,
+    mut progress_fn: impl FnMut(PermutationProgress, bool) -> bool + Send + Sync,
+    phase1_scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64> + Sync,
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64> + Sync,
+) -> Result<SearchResult<C, R>, Box<dyn Error>> {
+    let [region1, region2, region3] = regions;
+
+    // Phase one
+
+    let (phase1_permutations, phase1_truncated, phase1_records, _) = permute_and_substitute(
+        matrix,
+        region1,
+        region2,
+        region3,
+        |progress, _| progress_fn(progress, false),
+        phase1_scoring_fn,
+        two_phase_search.goal,
+        Tolerance::Ratio(0.0),
+        two_phase_search.max_permutations_opt,
+        Some(two_phase_search.top),
+        options.tie_break,
+        options.parallelize,
+        options.threads,
+        options.sleep_ns,
+    )?;
+
+    log_info!(
+        "Phase one kept {} of {} permutations",
+        phase1_records.len(),
+        phase1_permutations
+    );
+
+    // Phase two
+
+    // NOTE A region that phase two does not permute is given no characters, so the characters
+    // that phase one assigned to it are left in place.
+    let phase2_regions = [region1, region2, region3]
+        .into_iter()
+        .zip(two_phase_search.regions)
+        .map(|((array, length, coordinates), is_permuted)| {
+            (array, if is_permuted { length } else { 0 }, coordinates)
+        })
+        .collect::<Vec<_>>();
+
+    let mut n_permutations = phase1_permutations;
+    let mut permutations_truncated = phase1_truncated;
+    let mut scored_records = Vec::new();
+    let n_phase1_records = phase1_records.len();
+    for (i, phase1_record) in phase1_records.iter().enumerate() {
+        let is_last = i + 1 == n_phase1_records;
+        let (phase2_permutations, phase2_truncated, records, _) = permute_and_substitute(
+            phase1_record,
+            phase2_regions[0],
+            phase2_regions[1],
+            phase2_regions[2],
+            |progress: PermutationProgress, force| {
+                progress_fn(
+                    PermutationProgress {
+                        n_permutations: n_permutations + progress.n_permutations,
+                        ..progress
+                    },
+                    force && is_last,
+                )
+            },
+            &scoring_fn,
+            options.goal,
+            options.tolerance,
+            options.max_permutations_opt,
+            options.max_records_opt,
+            options.tie_break,
+            options.parallelize,
+            options.threads,
+            options.sleep_ns,
+        )?;
+        n_permutations = n_permutations.saturating_add(phase2_permutations);
+        permutations_truncated |= phase2_truncated;
+        scored_records.extend(
+            records
+                .into_iter()
+                .filter_map(|record| scoring_fn(&record).map(|score| (score, record))),
+        );
+    }
+
+    log_info!(
+        "Phase two kept {} of {} permutations",
+        scored_records.len(),
+        n_permutations - phase1_permutations
+    );
+
+    // NOTE The records of each phase-one record are within the tolerance of their own best score,
+    // so they are retained again by the best score of all of them.
+    let (records, records_truncated) = retain_records(scored_records, options);
+
+    Ok((
+        n_permutations,
+        permutations_truncated,
+        unscore_records(records),
+        records_truncated,
+    ))
+}
+
+// NOTE A beam search assigns the characters of the regions to their coordinates one coordinate at
+// a time (region 1 first, then region 2, then region 3, each in the order that the coordinates
+// appear in the key table). At each step, every partial key table in the beam is extended by each
+// character that remains to be assigned in the region, and only the 'width' best are kept. A
+// coordinate that is not yet assigned keeps its placeholder, which no n-gram contains, so a partial
+// key table is scored by only the fingerings whose keys are all assigned.
+#[derive(Clone, Copy, Debug)]
+pub struct BeamSearch {
+    pub width: usize,
+}
+
+impl BeamSearch {
+    // NOTE An upper bound: each step extends at most 'width' partial key tables by each character
+    // that remains.
+    pub fn expected_permutations(&self, lengths: [usize; 3]) -> u64 {
+        lengths
+            .iter()
+            .map(|&length| (length as u64).saturating_mul(length as u64 + 1) / 2)
+            .fold(0u64, u64::saturating_add)
+            .saturating_mul(self.width as u64)
+    }
+}
+
+// NOTE 'partial_scoring_fn' scores a partial key table, and 'scoring_fn' a complete one (which may
+// skip it, e.g., because it violates a constraint). The result is as for 'search', where the
+// permutations are the partial and complete key tables scored.
+pub fn beam_search<const C: usize, const R: usize, const N: usize>(
+    matrix: &[[Symbol; C]; R],
+    regions: [Region<N>; 3],
+    beam_search: &BeamSearch,
+    options: &PermutationOptions,
+    mut progress_fn: impl FnMut(PermutationProgress, bool) -> bool + Send + Sync,
+    partial_scoring_fn: impl Fn(&[[Symbol; C]; R]) -> u64 + Send + Sync,
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64> + Send + Sync,
+) -> Result<SearchResult<C, R>, Box<dyn Error>> {
+    let goal = options.goal;
+    let width = beam_search.width.max(1);
+    let steps = regions
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &(_, length, coordinates))| {
+            coordinates[..length.min(coordinates.len())]
+                .iter()
+                .map(move |&coordinate| (i, coordinate))
+        })
+        .collect::<Vec<_>>();
+    let remaining = regions.map(|(array, length, _)| array[..length].to_vec());
+
+    let mut n_permutations = 0u64;
+    let mut beam = vec![(0u64, *matrix, remaining)];
+    for (step, &(i, (r, c))) in steps.iter().enumerate() {
+        let is_last = step + 1 == steps.len();
+        let mut candidates = Vec::new();
+        for (_, matrix, remaining) in &beam {
+            let mut seen = HashSet::new();
+            for (j, &byte) in remaining[i].iter().enumerate() {
+                if !seen.insert(byte) {
+                    continue;
+                }
+                let mut matrix = *matrix;
+                matrix[r][c] = byte;
+                let mut remaining = remaining.clone();
+                remaining[i].remove(j);
+                candidates.push((matrix, remaining));
+            }
+        }
+        n_permutations = n_permutations.saturating_add(candidates.len() as u64);
+        let scores = map_in_parallel(
+            candidates.iter().map(|(matrix, _)| matrix).collect(),
+            |matrix| {
+                if is_last {
+                    scoring_fn(matrix)
+                } else {
+                    Some(partial_scoring_fn(matrix))
+                }
+            },
+            options.parallelize,
+            options.threads,
+        )?;
+        let mut scored_candidates = scores
+            .into_iter()
+            .zip(candidates)
+            .filter_map(|(score_opt, (matrix, remaining))| {
+                score_opt.map(|score| (score, matrix, remaining))
+            })
+            .collect::<Vec<_>>();
+        // NOTE The best partial key tables are first; ties keep the order in which they were
+        // extended.
+        match goal {
+            Goal::Max => scored_candidates.sort_by_key(|&(score, _, _)| core::cmp::Reverse(score)),
+            Goal::Min => scored_candidates.sort_by_key(|&(score, _, _)| score),
+        }
+        if !is_last {
+            scored_candidates.truncate(width);
+        }
+        beam = scored_candidates;
+        progress_fn(
+            PermutationProgress {
+                n_permutations,
+                best_score_opt: beam.first().map(|&(score, _, _)| score),
+                threshold_score_opt: None,
+                n_records: beam.len() as u64,
+            },
+            is_last,
+        );
+    }
+
+    // NOTE With no characters to assign, the key table is scored as it is.
+    if steps.is_empty() {
+        n_permutations = 1;
+        beam = scoring_fn(matrix)
+            .map(|score| (score, *matrix, Default::default()))
+            .into_iter()
+            .collect();
+    }
+
+    log_info!(
+        "Beam search scored {} key tables in {} steps, and kept {} complete key tables",
+        n_permutations,
+        steps.len(),
+        beam.len()
+    );
+
+    let (records, records_truncated) = retain_records(
+        beam.into_iter()
+            .map(|(score, matrix, _)| (score, matrix))
+            .collect(),
+        options,
+    );
+
+    Ok((
+        n_permutations,
+        false,
+        unscore_records(records),
+        records_truncated,
+    ))
+}
+
+// NOTE A swap search considers only the key tables within 'max_swaps' swaps (each of two characters
+// of the same region) of a reference key table, which must assign the characters of each region to
+// its placeholders. The key tables are enumerated by their distance from the reference key table
+// (the fewest swaps that produce them), so each is scored once. Only three distances are held in
+// memory at a time: the previous and current distances, to exclude them, and the next distance as
+// it is found.
+#[derive(Clone, Copy, Debug)]
+pub struct SwapSearch {
+    pub max_swaps: usize,
+}
+
+impl SwapSearch {
+    // NOTE The permutations of n distinct characters at a distance of d swaps are those with n - d
+    // cycles, counted by the (unsigned) Stirling numbers of the first kind; the counts of each
+    // region are convolved to count those of all regions.
+    pub fn expected_permutations(&self, lengths: [usize; 3]) -> u64 {
+        let max_swaps = self.max_swaps;
+        let mut counts = vec![0u64; max_swaps + 1];
+        counts[0] = 1;
+        for length in lengths {
+            let mut region_counts = vec![0u64; max_swaps + 1];
+            region_counts[0] = 1;
+            for n in 1..length as u64 {
+                for d in (1..=max_swaps).rev() {
+                    region_counts[d] =
+                        region_counts[d].saturating_add(n.saturating_mul(region_counts[d - 1]));
+                }
+            }
+            let mut convolved_counts = vec![0u64; max_swaps + 1];
+            for (d1, &count1) in counts.iter().enumerate() {
+                for (d2, &count2) in region_counts[..=max_swaps - d1].iter().enumerate() {
+                    convolved_counts[d1 + d2] =
+                        convolved_counts[d1 + d2].saturating_add(count1.saturating_mul(count2));
+                }
+            }
+            counts = convolved_counts;
+        }
+        counts.into_iter().fold(0u64, u64::saturating_add)
+    }
+}
+
+// NOTE The result is as for 'search'. Key tables are scored a chunk at a time, and the records are
+// retained after each chunk, so the memory used does not grow with the number of key tables.
+pub fn swap_search<const C: usize, const R: usize, const N: usize>(
+    matrix: &[[Symbol; C]; R],
+    regions: [Region<N>; 3],
+    reference: &[[Symbol; C]; R],
+    swap_search: &SwapSearch,
+    options: &PermutationOptions,
+    mut progress_fn: impl FnMut(PermutationProgress, bool) -> bool + Send + Sync,
+    scoring_fn: impl Fn(&[[Symbol; C]; R]) -> Option<u64> + Send + Sync,
+) -> Result<SearchResult<C, R>, Box<dyn Error>> {
+    const CHUNK: usize = 1 << 16;
+
+    let mut coordinates = Vec::new();
+    let mut pairs = Vec::new();
+    for (i, &(array, length, region_coordinates)) in regions.iter().enumerate() {
+        let length = length.min(region_coordinates.len());
+        let start = coordinates.len();
+        coordinates.extend_from_slice(&region_coordinates[..length]);
+        let mut expected = array[..length].to_vec();
+        let mut actual = region_coordinates[..length]
+            .iter()
+            .map(|&(r, c)| reference[r][c])
+            .collect::<Vec<_>>();
+        expected.sort_unstable();
+        actual.sort_unstable();
+        if expected != actual {
+            Err(format!(
+                "The reference key table does not assign the characters of region {} to its \
+                 placeholders",
+                i + 1
+            ))?;
+        }
+        for j in start..coordinates.len() {
+            for k in (j + 1)..coordinates.len() {
+                pairs.push((j, k));
+            }
+        }
+    }
+
+    let substitute = |assignment: &[Symbol]| {
+        let mut matrix = *matrix;
+        for (&byte, &(r, c)) in assignment.iter().zip(&coordinates) {
+            matrix[r][c] = byte;
+        }
+        matrix
+    };
+
+    let max_permutations = options.max_permutations_opt.unwrap_or(u64::MAX);
+    let mut n_permutations = 0u64;
+    let mut permutations_truncated = false;
+    let mut scored_records = Vec::new();
+    let mut records_truncated = false;
+
+    // NOTE Records are sorted by descending score.
+    let progress =
+        |n_permutations: u64, scored_records: &[ScoredRecord<C, R>]| PermutationProgress {
+            n_permutations,
+            best_score_opt: match options.goal {
+                Goal::Max => scored_records.first(),
+                Goal::Min => scored_records.last(),
+            }
+            .map(|&(score, _)| score),
+            threshold_score_opt: None,
+            n_records: scored_records.len() as u64,
+        };
+
+    let reference_assignment = coordinates
+        .iter()
+        .map(|&(r, c)| reference[r][c])
+        .collect::<Vec<_>>();
+    let mut previous = HashSet::new();
+    let mut current = vec![reference_assignment.clone()];
+    let mut current_set = HashSet::from([reference_assignment]);
+    'distances: for distance in 0..=swap_search.max_swaps {
+        for chunk in current.chunks(CHUNK) {
+            let remaining = max_permutations - n_permutations;
+            let chunk = if chunk.len() as u64 > remaining {
+                permutations_truncated = true;
+                &chunk[..remaining as usize]
+            } else {
+                chunk
+            };
+            n_permutations += chunk.len() as u64;
+            let chunk_scored_records = map_in_parallel(
+                chunk.iter().collect(),
+                |assignment| {
+                    let matrix = substitute(assignment);
+                    scoring_fn(&matrix).map(|score| (score, matrix))
+                },
+                options.parallelize,
+                options.threads,
+            )?;
+            scored_records.extend(chunk_scored_records.into_iter().flatten());
+            let truncated;
+            (scored_records, truncated) = retain_records(scored_records, options);
+            records_truncated |= truncated;
+            progress_fn(progress(n_permutations, &scored_records), false);
+            if permutations_truncated {
+                break 'distances;
+            }
+        }
+        if distance == swap_search.max_swaps {
+            break;
+        }
+        // NOTE A swap changes the distance by one (unless it swaps two of the same character), so
+        // the key tables at the next distance are the neighbours of those at this distance that
+        // are not at this distance or the previous one.
+        let mut next = Vec::new();
+        let mut next_set = HashSet::new();
+        for assignment in &current {
+            for &(j, k) in &pairs {
+                if assignment[j] == assignment[k] {
+                    continue;
+                }
+                let mut neighbour = assignment.clone();
+                neighbour.swap(j, k);
+                if !previous.contains(&neighbour)
+                    && !current_set.contains(&neighbour)
+                    && next_set.insert(neighbour.clone())
+                {
+                    next.push(neighbour);
+                }
+            }
+        }
+        log_info!(
+            "Found {} key tables at a distance of {} swaps",
+            next.len(),
+            distance + 1
+        );
+        previous = current_set;
+        current_set = next_set;
+        current = next;
+    }
+
+    progress_fn(progress(n_permutations, &scored_records), true);
+
+    Ok((
+        n_permutations,
+        permutations_truncated,
+        unscore_records(scored_records),
+        records_truncated,
+    ))
+}
+
+// NOTE As when permuting, the records within the tolerance of the best score are retained (without
+// duplicates), sorted by descending score, and truncated to the maximum number of records (so the
+// worst records are dropped from the back if maximizing, or from the front if minimizing).
+fn retain_records<const C: usize, const R: usize>(
+    mut scored_records: Vec<ScoredRecord<C, R>>,
+    options: &PermutationOptions,
+) -> (Vec<ScoredRecord<C, R>>, bool) {
+    let goal = options.goal;
+    let best_score_opt = match goal {
+        Goal::Max => scored_records.iter().map(|&(score, _)| score).max(),
+        Goal::Min => scored_records.iter().map(|&(score, _)| score).min(),
+    };
+    if let Some(best_score) = best_score_opt {
+        let threshold = calculate_threshold(goal, best_score, options.tolerance);
+        scored_records.retain(|&(score, _)| match goal {
+            Goal::Max => score >= threshold,
+            Goal::Min => score <= threshold,
+        });
+    }
+    let mut seen = HashSet::new();
+    scored_records.retain(|(_, record)| seen.insert(*record));
+    // NOTE Records are not permuted by index here, so a seeded tie break hashes the key table.
+    match options.tie_break {
+        TieBreak::Index => scored_records.sort_by_key(|&(score, _)| core::cmp::Reverse(score)),
+        TieBreak::Seeded(seed) => scored_records.sort_by_cached_key(|(score, record)| {
+            (
+                core::cmp::Reverse(*score),
+                seeded_hash(
+                    seed,
+                    &record
+                        .as_flattened()
+                        .iter()
+                        .flat_map(|symbol| symbol.to_le_bytes())
+                        .collect::<Vec<_>>(),
+                ),
+            )
+        }),
+    }
+
+    let max_records = options.max_records_opt.map_or(usize::MAX, |n| n as usize);
+    let records_truncated = scored_records.len() > max_records;
+    if records_truncated {
+        let excess = scored_records.len() - max_records;
+        match goal {
+            Goal::Max => scored_records.truncate(max_records),
+            Goal::Min => {
+                scored_records.drain(..excess);
+            }
+        }
+    }
+    (scored_records, records_truncated)
+}
+
+fn unscore_records<const C: usize, const R: usize>(
+    scored_records: Vec<ScoredRecord<C, R>>,
+) -> Vec<[[Symbol; C]; R]> {
+    scored_records
+        .into_iter()
+        .map(|(_, record)| record)
+        .collect()
+}
@@ -0,0 +1,22 @@
+pub type Symbol = u16;
+
+// NOTE Each character is its own symbol (its code point), so symbols need no shared table, and a
+// symbol means the same character in every run and every thread. Only the characters of the Basic
+// Multilingual Plane (U+0000 to U+FFFF) can be symbols, which covers the alphabets of all modern
+// languages; n-gram tables are sized by the characters they contain (see 'ngrams::NgramTable').
+pub fn intern(ch: char) -> Result<Symbol, String> {
+    Symbol::try_from(ch as u32).map_err(|_| {
+        format!(
+            "Unable to use character '{}': only characters from U+0000 to U+FFFF are supported",
+            ch
+        )
+    })
+}
+
+pub fn intern_str(s: &str) -> Result<Vec<Symbol>, String> {
+    s.chars().map(intern).collect()
+}
+
+pub fn resolve(symbol: Symbol) -> char {
+    char::from_u32(symbol as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
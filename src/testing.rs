@@ -0,0 +1,92 @@
+use core::error::Error;
+
+use serde_json::Value;
+
+use crate::{
+    fingerings::EffortModel,
+    keys::KeyTable,
+    layouts::{FingerWeights, LayoutTable},
+    ngrams::{
+        BigramKey, BigramTable, NgramTable, TrigramKey, TrigramTable, UnigramKey, UnigramTable,
+    },
+    symbols::Symbol,
+};
+
+// NOTE Helpers to build small fixtures in memory (see 'scoring'), in tables of any size. In a layout
+// table, each row is a string of digits separated by whitespace (for example, "lp lr lm li ri rm rr
+// rp"), with "--" for a position that has no digit. In a key table, each row is a string of
+// characters, one per position, with ' ' for a position that has no key, and '1', '2', or '3' for a
+// placeholder of that region.
+
+pub fn layout_table<const C: usize, const R: usize>(
+    rows: &[&str],
+) -> Result<LayoutTable<C, R>, String> {
+    let value = Value::Array(
+        rows.iter()
+            .map(|row| {
+                Value::Array(
+                    row.split_whitespace()
+                        .map(|cell| match cell {
+                            "--" => Value::Null,
+                            _ => Value::from(cell),
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+    );
+    LayoutTable::try_from(&value)
+}
+
+pub fn key_table<const C: usize, const R: usize>(rows: &[&str]) -> Result<KeyTable<C, R>, String> {
+    let value = Value::Array(
+        rows.iter()
+            .map(|row| {
+                Value::Array(
+                    row.chars()
+                        .map(|ch| match ch {
+                            ' ' => Value::Null,
+                            '1'..='3' => Value::from(ch as u64 - '0' as u64),
+                            _ => Value::from(ch.to_string()),
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+    );
+    KeyTable::try_from_value(&value, false)
+}
+
+// NOTE The default effort model (no geometry, equal finger weights, and no effort expression).
+pub fn effort_model<'a, const C: usize, const R: usize>() -> EffortModel<'a, C, R> {
+    EffortModel {
+        geometry_opt: None,
+        finger_weights: FingerWeights::default(),
+        expression_opt: None,
+        penalties_opt: None,
+    }
+}
+
+fn ngram_table<const N: usize, K: Into<[Symbol; N]>>(
+    entries: &[(&str, u64)],
+    key_fn: impl Fn(&str) -> Result<K, String>,
+) -> Result<NgramTable<N>, Box<dyn Error>> {
+    NgramTable::from_entries(
+        entries
+            .iter()
+            .map(|&(key_str, value)| Ok((key_fn(key_str)?.into(), value)))
+            .collect::<Result<_, String>>()?,
+    )
+}
+
+pub fn unigram_table(entries: &[(&str, u64)]) -> Result<UnigramTable, Box<dyn Error>> {
+    ngram_table(entries, |s| UnigramKey::try_from(s))
+}
+
+pub fn bigram_table(entries: &[(&str, u64)]) -> Result<BigramTable, Box<dyn Error>> {
+    ngram_table(entries, |s| BigramKey::try_from(s))
+}
+
+pub fn trigram_table(entries: &[(&str, u64)]) -> Result<TrigramTable, Box<dyn Error>> {
+    ngram_table(entries, |s| TrigramKey::try_from(s))
+}
@@ -1,3 +1,5 @@
 pub mod colors;
+pub mod log;
+pub mod pages;
 pub mod progress;
 pub mod styles;
@@ -16,3 +16,66 @@ pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
     let b = ((b1 + m) * 255.0).round() as u8;
     (r, g, b)
 }
+
+fn interpolate_rgb(stops: &[(u8, u8, u8)], t: f32) -> (u8, u8, u8) {
+    let t = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let i = (t.floor() as usize).min(stops.len() - 2);
+    let f = t - i as f32;
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * f).round() as u8;
+    let (r1, g1, b1) = stops[i];
+    let (r2, g2, b2) = stops[i + 1];
+    (lerp(r1, r2), lerp(g1, g2), lerp(b1, b2))
+}
+
+// NOTE 'Hue' varies the saturation (and, slightly, the value) of a single hue, from grey (no heat)
+// to fully saturated (most heat). It is hard to read for those with some colour vision
+// deficiencies, so the other palettes vary lightness as well as hue (or only lightness).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Palette {
+    #[default]
+    Hue,
+    Viridis,
+    BlueOrange,
+    Grayscale,
+}
+
+impl Palette {
+    pub fn heat_to_rgb(&self, hue: f32, heat: f32) -> (u8, u8, u8) {
+        const VALUE_MIN: f32 = 0.75;
+        const VIRIDIS: [(u8, u8, u8); 5] = [
+            (72, 40, 120),
+            (62, 74, 137),
+            (38, 130, 142),
+            (53, 183, 121),
+            (253, 231, 37),
+        ];
+        const BLUE_ORANGE: [(u8, u8, u8); 3] = [(67, 147, 195), (191, 191, 191), (230, 97, 1)];
+        const GRAYSCALE: [(u8, u8, u8); 2] = [(112, 112, 112), (255, 255, 255)];
+        let heat = heat.clamp(0.0, 1.0);
+        use Palette::*;
+        match self {
+            Hue => hsv_to_rgb(hue, heat, VALUE_MIN + heat * (1.0 - VALUE_MIN)),
+            Viridis => interpolate_rgb(&VIRIDIS, heat),
+            BlueOrange => interpolate_rgb(&BLUE_ORANGE, heat),
+            Grayscale => interpolate_rgb(&GRAYSCALE, heat),
+        }
+    }
+}
+
+impl TryFrom<&str> for Palette {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        use Palette::*;
+        match s {
+            "hue" => Ok(Hue),
+            "viridis" => Ok(Viridis),
+            "blue-orange" => Ok(BlueOrange),
+            "grayscale" => Ok(Grayscale),
+            _ => Err(format!(
+                "Unknown palette '{}': expected 'hue', 'viridis', 'blue-orange', or 'grayscale'",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Info,
+    Debug,
+}
+
+impl Verbosity {
+    fn from_u8(value: u8) -> Self {
+        use Verbosity::*;
+        match value {
+            0 => Quiet,
+            1 => Normal,
+            2 => Info,
+            _ => Debug,
+        }
+    }
+
+    // NOTE Each '-v' raises the verbosity by one level (up to 'Debug'), and '-q' lowers it to
+    // 'Quiet'.
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        if quiet {
+            Verbosity::Quiet
+        } else {
+            Self::from_u8((Verbosity::Normal as u8).saturating_add(verbose))
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        use Verbosity::*;
+        match self {
+            Quiet | Normal => "",
+            Info => "info",
+            Debug => "debug",
+        }
+    }
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    Verbosity::from_u8(VERBOSITY.load(Ordering::Relaxed))
+}
+
+pub fn is_enabled(verbosity: Verbosity) -> bool {
+    self::verbosity() >= verbosity
+}
+
+// NOTE Log messages are written to stderr unbuffered, so that they are not held back behind (or
+// interleaved with) the buffered progress bar. On a terminal, the line is cleared first, in case
+// a progress bar was written to it.
+pub fn write_log(verbosity: Verbosity, args: fmt::Arguments) {
+    let mut stderr = io::stderr().lock();
+    if atty::is(atty::Stream::Stderr) {
+        write!(stderr, "\r\x1b[K").ok();
+    }
+    writeln!(stderr, "[{}] {}", verbosity.label(), args).ok();
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if $crate::ui::log::is_enabled($crate::ui::log::Verbosity::Info) {
+            $crate::ui::log::write_log($crate::ui::log::Verbosity::Info, format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        if $crate::ui::log::is_enabled($crate::ui::log::Verbosity::Debug) {
+            $crate::ui::log::write_log($crate::ui::log::Verbosity::Debug, format_args!($($arg)*));
+        }
+    };
+}
@@ -0,0 +1,68 @@
+use std::io::{self, BufRead};
+
+use termcolor::WriteColor;
+
+use crate::ui::styles::theme;
+
+// NOTE Splits the records printed in the text format into pages of 'page_size' records, each
+// after the first preceded by a separator. If pausing, Perky waits for Enter before each separator,
+// but only if both stdout and stdin are terminals, so piped output is never held up. Pausing stops
+// at the end of stdin.
+pub struct Pager {
+    page_size_opt: Option<usize>,
+    pause: bool,
+    total_opt: Option<usize>,
+    n_records: usize,
+}
+
+impl Pager {
+    pub fn new(page_size_opt: Option<usize>, pause: bool, total_opt: Option<usize>) -> Self {
+        Self {
+            page_size_opt,
+            pause: pause && atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stdin),
+            total_opt,
+            n_records: 0,
+        }
+    }
+
+    // NOTE The number of records written so far, including the one about to be written.
+    pub fn n_records(&self) -> usize {
+        self.n_records
+    }
+
+    // NOTE Call before writing each record.
+    pub fn next_record(&mut self, writer: &mut dyn WriteColor) -> io::Result<()> {
+        let n_records = self.n_records;
+        self.n_records += 1;
+        let Some(page_size) = self.page_size_opt else {
+            return Ok(());
+        };
+        if n_records == 0 || !n_records.is_multiple_of(page_size) {
+            return Ok(());
+        }
+        if self.pause {
+            self.pause = wait_for_enter(writer)?;
+        }
+        let page = n_records / page_size + 1;
+        let label = match self.total_opt {
+            Some(total) => format!("Page {} of {}", page, total.div_ceil(page_size)),
+            None => format!("Page {}", page),
+        };
+        writeln!(writer)?;
+        writer.set_color(&theme().title)?;
+        write!(writer, "--- {} ---", label)?;
+        writer.reset()?;
+        writeln!(writer)
+    }
+}
+
+// NOTE The prompt is written to stdout (which is a terminal, and at the start of a line), then
+// erased once Enter is pressed. Returns false at the end of stdin.
+fn wait_for_enter(writer: &mut dyn WriteColor) -> io::Result<bool> {
+    write!(writer, "Press Enter to continue")?;
+    writer.flush()?;
+    let mut line = String::new();
+    let n_bytes = io::stdin().lock().read_line(&mut line)?;
+    write!(writer, "\x1b[1A\x1b[2K")?;
+    Ok(n_bytes != 0)
+}
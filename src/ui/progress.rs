@@ -1,4 +1,6 @@
-use core::iter;
+use core::{iter, time::Duration};
+
+use std::collections::VecDeque;
 
 pub fn create_progress_bar(length: usize, value: f32) -> String {
     const BLOCKS: [char; 9] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
@@ -15,3 +17,38 @@ pub fn create_progress_bar(length: usize, value: f32) -> String {
     }
     s
 }
+
+// NOTE The rate is measured over a sliding window of recent samples, so that estimates follow
+// changes in speed (such as when other processes compete for the CPU) rather than averaging over
+// the whole run.
+pub struct RateWindow {
+    window: Duration,
+    samples: VecDeque<(Duration, u64)>,
+}
+
+impl RateWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, elapsed: Duration, n: u64) {
+        self.samples.push_back((elapsed, n));
+        while self.samples.len() > 2
+            && let Some(&(front_elapsed, _)) = self.samples.front()
+            && elapsed.saturating_sub(front_elapsed) > self.window
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    // NOTE Returns the rate per second, or 'None' until two samples at different times are known.
+    pub fn rate(&self) -> Option<f64> {
+        let (&(first_elapsed, first_n), &(last_elapsed, last_n)) =
+            (self.samples.front()?, self.samples.back()?);
+        let seconds = last_elapsed.saturating_sub(first_elapsed).as_secs_f64();
+        (seconds > 0.0).then(|| last_n.saturating_sub(first_n) as f64 / seconds)
+    }
+}
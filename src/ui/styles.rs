@@ -1,6 +1,12 @@
-use std::io;
+use core::{error::Error, str::FromStr};
 
-use termcolor::{ColorChoice, WriteColor};
+use std::{env, fs::File, io, path::Path, sync::OnceLock};
+
+use serde_json::{Map, Value};
+
+use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
+
+use crate::{json::read_envelope, ui::colors::Palette};
 
 pub trait WriteStyled {
     fn write_styled(&self, writer: &mut dyn WriteColor) -> io::Result<()>;
@@ -12,13 +18,243 @@ pub enum StylePolicy {
     On,
 }
 
+// NOTE See https://no-color.org.
+fn is_no_color() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+// NOTE See https://bixense.com/clicolors.
+fn is_clicolor_force() -> bool {
+    env::var_os("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0")
+}
+
 impl StylePolicy {
-    pub fn color_choice(&self) -> ColorChoice {
+    // NOTE Under 'Auto', NO_COLOR takes precedence over CLICOLOR_FORCE, and otherwise each stream
+    // is styled only if it is a terminal (stdout and stderr may differ, e.g. when stdout is piped
+    // but the progress bar is still shown on the terminal). 'On' and 'Off' ignore the environment.
+    pub fn color_choice(&self, stream: atty::Stream) -> ColorChoice {
         use StylePolicy::*;
         match self {
-            Auto if atty::is(atty::Stream::Stdout) => ColorChoice::Auto,
+            Auto if is_no_color() => ColorChoice::Never,
+            Auto if is_clicolor_force() => ColorChoice::Always,
+            Auto if atty::is(stream) => ColorChoice::Auto,
             On => ColorChoice::Always,
             _ => ColorChoice::Never,
         }
     }
 }
+
+// NOTE A theme holds the style of each role in the text format, and the palette (and, for the
+// 'hue' palette, the hue in degrees) used to colour keys by heat. A theme file overrides individual attributes of
+// individual roles; anything it does not mention keeps its default.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub changed: ColorSpec,
+    pub duration_complete: ColorSpec,
+    pub duration_incomplete: ColorSpec,
+    pub heat_hue: f32,
+    pub heat_palette: Palette,
+    pub index: ColorSpec,
+    pub none: ColorSpec,
+    pub overlay: ColorSpec,
+    pub perc: ColorSpec,
+    pub perc_complete: ColorSpec,
+    pub space: ColorSpec,
+    pub substitution: ColorSpec,
+    pub title: ColorSpec,
+    pub unprintable: ColorSpec,
+    pub unigram_key: ColorSpec,
+    pub bigram_key: ColorSpec,
+    pub trigram_key: ColorSpec,
+    pub unigram_metric: ColorSpec,
+    pub bigram_metric: ColorSpec,
+    pub trigram_metric: ColorSpec,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        fn style(f: impl FnOnce(&mut ColorSpec)) -> ColorSpec {
+            let mut color_spec = ColorSpec::new();
+            f(&mut color_spec);
+            color_spec
+        }
+        Self {
+            changed: style(|c| {
+                c.set_bold(true)
+                    .set_bg(Some(Color::Yellow))
+                    .set_fg(Some(Color::Black));
+            }),
+            duration_complete: style(|c| {
+                c.set_fg(Some(Color::Cyan)).set_intense(true);
+            }),
+            duration_incomplete: style(|c| {
+                c.set_fg(Some(Color::Yellow)).set_intense(true);
+            }),
+            heat_hue: 0.0,
+            heat_palette: Palette::default(),
+            index: style(|c| {
+                c.set_bold(true).set_underline(true);
+            }),
+            none: style(|c| {
+                c.set_dimmed(true);
+            }),
+            overlay: style(|c| {
+                c.set_bold(true)
+                    .set_bg(Some(Color::Blue))
+                    .set_fg(Some(Color::White));
+            }),
+            perc: style(|c| {
+                c.set_italic(true);
+            }),
+            perc_complete: style(|c| {
+                c.set_fg(Some(Color::Green)).set_intense(true);
+            }),
+            space: style(|c| {
+                c.set_bg(Some(Color::White)).set_fg(Some(Color::Black));
+            }),
+            substitution: style(|c| {
+                c.set_bold(true)
+                    .set_intense(true)
+                    .set_bg(Some(Color::Red))
+                    .set_fg(Some(Color::White));
+            }),
+            title: style(|c| {
+                c.set_underline(true);
+            }),
+            unprintable: style(|c| {
+                c.set_dimmed(true);
+            }),
+            unigram_key: style(|c| {
+                c.set_bold(true).set_fg(Some(Color::Yellow));
+            }),
+            bigram_key: style(|c| {
+                c.set_bold(true).set_fg(Some(Color::Blue));
+            }),
+            trigram_key: style(|c| {
+                c.set_bold(true).set_fg(Some(Color::Magenta));
+            }),
+            unigram_metric: style(|c| {
+                c.set_bold(true);
+            }),
+            bigram_metric: style(|c| {
+                c.set_bold(true);
+            }),
+            trigram_metric: style(|c| {
+                c.set_bold(true);
+            }),
+        }
+    }
+}
+
+fn read_color(value: &Value) -> Result<Option<Color>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => Color::from_str(s).map(Some).map_err(|e| e.to_string()),
+        _ => Err("Invalid type: expected a string or null".into()),
+    }
+}
+
+fn apply_style(color_spec: &mut ColorSpec, object: &Map<String, Value>) -> Result<(), String> {
+    for (key, value) in object {
+        match key.as_str() {
+            "fg" => {
+                color_spec.set_fg(read_color(value).map_err(|e| format!("'{}': {}", key, e))?);
+            }
+            "bg" => {
+                color_spec.set_bg(read_color(value).map_err(|e| format!("'{}': {}", key, e))?);
+            }
+            _ => {
+                let b = value
+                    .as_bool()
+                    .ok_or_else(|| format!("Value of '{}' field must be of type 'bool'", key))?;
+                match key.as_str() {
+                    "bold" => color_spec.set_bold(b),
+                    "dimmed" => color_spec.set_dimmed(b),
+                    "intense" => color_spec.set_intense(b),
+                    "italic" => color_spec.set_italic(b),
+                    "underline" => color_spec.set_underline(b),
+                    _ => Err(format!("Unknown attribute '{}'", key))?,
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Theme {
+    fn get_mut(&mut self, role: &str) -> Option<&mut ColorSpec> {
+        Some(match role {
+            "changed" => &mut self.changed,
+            "duration_complete" => &mut self.duration_complete,
+            "duration_incomplete" => &mut self.duration_incomplete,
+            "index" => &mut self.index,
+            "none" => &mut self.none,
+            "overlay" => &mut self.overlay,
+            "perc" => &mut self.perc,
+            "perc_complete" => &mut self.perc_complete,
+            "space" => &mut self.space,
+            "substitution" => &mut self.substitution,
+            "title" => &mut self.title,
+            "unprintable" => &mut self.unprintable,
+            "unigram_key" => &mut self.unigram_key,
+            "bigram_key" => &mut self.bigram_key,
+            "trigram_key" => &mut self.trigram_key,
+            "unigram_metric" => &mut self.unigram_metric,
+            "bigram_metric" => &mut self.bigram_metric,
+            "trigram_metric" => &mut self.trigram_metric,
+            _ => return None,
+        })
+    }
+
+    pub fn try_from_value(value: &Value) -> Result<Self, String> {
+        let object = value.as_object().ok_or("Expected 'data' to be an object")?;
+        let mut theme = Self::default();
+        for (role, value) in object {
+            if role == "heat_hue" {
+                theme.heat_hue = value
+                    .as_f64()
+                    .ok_or("Value of 'heat_hue' field must be a number")?
+                    .rem_euclid(360.0) as f32;
+                continue;
+            }
+            if role == "heat_palette" {
+                theme.heat_palette = Palette::try_from(
+                    value
+                        .as_str()
+                        .ok_or("Value of 'heat_palette' field must be of type 'string'")?,
+                )?;
+                continue;
+            }
+            let color_spec = theme
+                .get_mut(role)
+                .ok_or_else(|| format!("Unknown role '{}'", role))?;
+            let object = value
+                .as_object()
+                .ok_or_else(|| format!("Value of '{}' field must be an object", role))?;
+            apply_style(color_spec, object)
+                .map_err(|e| format!("Invalid '{}' field: {}", role, e))?;
+        }
+        Ok(theme)
+    }
+
+    pub fn read_from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        const EXPECTED_VERSION: u64 = 1;
+        let file = File::open(path)?;
+        let object = read_envelope(file, EXPECTED_VERSION)?;
+        Ok(Self::try_from_value(
+            object.get("data").ok_or("Expected 'data' field")?,
+        )?)
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+// NOTE The theme can be set only once, before anything is written. Until then (or if it is never
+// set), the default theme is used.
+pub fn set_theme(theme: Theme) -> bool {
+    THEME.set(theme).is_ok()
+}
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
@@ -1,6 +1,10 @@
+pub mod files;
 pub mod format;
+pub mod hash;
 pub mod math;
 pub mod signals;
+pub mod simd;
 pub mod strings;
+pub mod system;
 pub mod threads;
 pub mod time;
@@ -0,0 +1,26 @@
+use core::time::Duration;
+
+use std::{fs, path::PathBuf, thread::sleep, time::SystemTime};
+
+pub fn read_modification_times(fpaths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    fpaths
+        .iter()
+        .map(|fpath| fs::metadata(fpath).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+pub fn wait_for_modification(
+    fpaths: &[PathBuf],
+    previous: Vec<Option<SystemTime>>,
+    interval: Duration,
+) -> Vec<Option<SystemTime>> {
+    loop {
+        sleep(interval);
+        let current = read_modification_times(fpaths);
+        // NOTE A file that is missing (e.g., while an editor replaces it) is not considered
+        // modified until it reappears.
+        if current.iter().all(Option::is_some) && current != previous {
+            return current;
+        }
+    }
+}
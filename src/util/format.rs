@@ -1,3 +1,125 @@
+use std::{env, sync::OnceLock};
+
+// Numbers
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Notation {
+    Engineering,
+    Grouped(char),
+    Plain,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct NumberFormat {
+    pub decimal_places_opt: Option<usize>,
+    pub notation: Notation,
+}
+
+static NUMBER_FORMAT: OnceLock<NumberFormat> = OnceLock::new();
+
+// NOTE The number format applies only to text. It can be set only once, before anything is
+// written. Until then (or if it is never set), counts are printed plainly and each writer uses its
+// own number of decimal places.
+pub fn set_number_format(number_format: NumberFormat) -> bool {
+    NUMBER_FORMAT.set(number_format).is_ok()
+}
+
+pub fn decimal_places_or(default: usize) -> usize {
+    NUMBER_FORMAT
+        .get()
+        .and_then(|number_format| number_format.decimal_places_opt)
+        .unwrap_or(default)
+}
+
+pub fn format_count(value: u64) -> String {
+    const ENGINEERING_THRESHOLD: u64 = 1_000_000;
+    match NUMBER_FORMAT
+        .get()
+        .map(|number_format| number_format.notation)
+    {
+        Some(Notation::Engineering) if value >= ENGINEERING_THRESHOLD => {
+            format_engineering(value, decimal_places_or(3))
+        }
+        Some(Notation::Grouped(separator)) => format_grouped(value, separator),
+        _ => value.to_string(),
+    }
+}
+
+// NOTE Counts of permutations saturate at 'u64::MAX' (see 'math::factorial'), so that value is
+// printed as a bound.
+pub fn format_permutation_count(value: u64) -> String {
+    if value == u64::MAX {
+        format!("more than {}", format_count(value))
+    } else {
+        format_count(value)
+    }
+}
+
+// NOTE The exponent is a multiple of three, so the mantissa is at least 1 and less than 1000
+// (after rounding).
+fn format_engineering(value: u64, decimal_places: usize) -> String {
+    let mut exponent = value.checked_ilog10().unwrap_or_default() / 3 * 3;
+    let mut mantissa = value as f64 / 10f64.powi(exponent as i32);
+    let scale = 10f64.powi(decimal_places as i32);
+    if (mantissa * scale).round() / scale >= 1000.0 {
+        exponent += 3;
+        mantissa /= 1000.0;
+    }
+    format!("{:.*}e{}", decimal_places, mantissa, exponent)
+}
+
+fn format_grouped(value: u64, separator: char) -> String {
+    let digits = value.to_string();
+    let mut s = String::with_capacity(digits.len() * 4 / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            s.push(separator);
+        }
+        s.push(ch);
+    }
+    s
+}
+
+// NOTE The separator follows the language of the first of LC_ALL, LC_NUMERIC, and LANG that is
+// set (for example, 'de_DE.UTF-8' groups with '.' and 'fr_FR.UTF-8' with ' '), defaulting to ','.
+pub fn locale_thousands_separator() -> char {
+    let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let language = locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default();
+    match language {
+        "da" | "de" | "el" | "es" | "id" | "it" | "nl" | "pt" | "ro" | "sl" | "tr" | "vi" => '.',
+        "bg" | "cs" | "et" | "fi" | "fr" | "hu" | "lt" | "lv" | "nb" | "nn" | "no" | "pl"
+        | "ru" | "sk" | "sv" | "uk" => ' ',
+        _ => ',',
+    }
+}
+
+// NOTE Sizes are printed in binary units (e.g., 1 KiB is 1024 bytes), with one decimal place.
+pub fn format_bytes(value: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if value < 1024 {
+        return format!("{} B", value);
+    }
+    let mut size = value as f64;
+    let mut unit = UNITS[0];
+    for candidate in UNITS {
+        size /= 1024.0;
+        unit = candidate;
+        if size < 1024.0 {
+            break;
+        }
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+// Percentages
+
 pub fn format_perc(decimal_places: usize, value_opt: Option<f64>) -> String {
     value_opt.map_or_else(
         || "n/a".to_string(),
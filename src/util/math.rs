@@ -10,6 +10,10 @@ pub fn calculate_perc(n: u64, t: u64) -> Option<f64> {
     calculate_frac(n, t).map(|f| f * 100.0)
 }
 
+pub fn calculate_per_billion(n: u64, t: u64) -> Option<f64> {
+    calculate_frac(n, t).map(|f| f * 1e9)
+}
+
 pub fn crop_matrix<const C: usize, const R: usize, T>(
     matrix: &[[T; C]; R],
     predicate: impl Fn(&T) -> bool,
@@ -45,14 +49,38 @@ pub fn crop_matrix<const C: usize, const R: usize, T>(
     (top, right, bottom, left)
 }
 
+// NOTE 20! is the largest factorial that fits in a u64, and 34! the largest that fits in a u128.
+pub fn checked_factorial_u128(n: u64) -> Option<u128> {
+    (1..=n as u128).try_fold(1u128, |acc, i| acc.checked_mul(i))
+}
+
+pub fn checked_factorial(n: u64) -> Option<u64> {
+    checked_factorial_u128(n).and_then(|f| u64::try_from(f).ok())
+}
+
+// NOTE Saturates at 'u128::MAX' rather than overflowing.
+pub fn factorial_u128(n: u64) -> u128 {
+    checked_factorial_u128(n).unwrap_or(u128::MAX)
+}
+
+// NOTE Saturates at 'u64::MAX' rather than overflowing.
 pub fn factorial(n: u64) -> u64 {
-    (1..=n).product()
+    checked_factorial(n).unwrap_or(u64::MAX)
+}
+
+// NOTE The number of permutations of regions of the given lengths (the product of their
+// factorials), or 'None' if it does not fit in a u64.
+pub fn checked_permutation_count(lengths: &[usize]) -> Option<u64> {
+    lengths.iter().try_fold(1u64, |acc, &length| {
+        acc.checked_mul(checked_factorial(length as u64)?)
+    })
 }
 
-pub fn index_to_permutation<T: Copy>(mut index: u64, input: &[T]) -> Vec<T> {
+// NOTE The index is a u128, so that every permutation of up to 34 elements can be reached.
+pub fn index_to_permutation<T: Copy>(mut index: u128, input: &[T]) -> Vec<T> {
     let input_length = input.len();
     debug_assert!(
-        index < factorial(input_length as u64),
+        index < factorial_u128(input_length as u64),
         "index {} out of bounds for {}-length permutation",
         index,
         input_length
@@ -60,7 +88,7 @@ pub fn index_to_permutation<T: Copy>(mut index: u64, input: &[T]) -> Vec<T> {
     let mut available = input.to_vec();
     let mut output = Vec::with_capacity(available.len());
     for i in (1..=available.len()).rev() {
-        let f = factorial(i as u64 - 1);
+        let f = factorial_u128(i as u64 - 1);
         let pos = (index / f) as usize;
         index %= f;
         output.push(available.remove(pos));
@@ -90,16 +118,16 @@ pub fn index_to_permutation_in_place<const N: usize, T: Copy + Default>(
         input_length,
         output_length
     );
-    let mut f = factorial(input_length as u64);
+    const MAX_EXACT_FACTORIAL: usize = 20;
     debug_assert!(
-        index < f,
+        index < factorial(input_length as u64),
         "index {} out of bounds for {}-length permutation",
         index,
         input_length
     );
     let mut available = [T::default(); N];
     available[..input_length].copy_from_slice(input);
-    f /= input_length as u64;
+    let mut f = factorial(input_length as u64 - 1);
     let mut remaining = input_length;
     for i in 0..input_length {
         let pos = (index / f) as usize;
@@ -109,12 +137,38 @@ pub fn index_to_permutation_in_place<const N: usize, T: Copy + Default>(
             available[j] = available[j + 1];
         }
         remaining -= 1;
-        if remaining > 1 {
+        // NOTE A factorial that saturated cannot be divided down exactly, so it is calculated
+        // again instead.
+        if remaining > MAX_EXACT_FACTORIAL {
+            f = factorial(remaining as u64 - 1);
+        } else if remaining > 1 {
             f /= remaining as u64;
         }
     }
 }
 
+// NOTE The inverse of 'index_to_permutation'. Returns None if 'permutation' is not a permutation
+// of 'input', or if its index does not fit in a u64.
+pub fn permutation_to_index<T: PartialEq>(permutation: &[T], input: &[T]) -> Option<u64> {
+    permutation_to_index_u128(permutation, input).and_then(|index| u64::try_from(index).ok())
+}
+
+pub fn permutation_to_index_u128<T: PartialEq>(permutation: &[T], input: &[T]) -> Option<u128> {
+    if permutation.len() != input.len() {
+        return None;
+    }
+    let mut available = input.iter().collect::<Vec<_>>();
+    let mut index = 0;
+    for item in permutation {
+        let pos = available.iter().position(|&a| a == item)?;
+        available.remove(pos);
+        index = checked_factorial_u128(available.len() as u64)
+            .and_then(|f| f.checked_mul(pos as u128))
+            .and_then(|offset| offset.checked_add(index))?;
+    }
+    Some(index)
+}
+
 pub fn generate_permutations<const N: usize, T>(
     array: [T; N],
     callback: impl FnMut(&[T; N]) -> bool,
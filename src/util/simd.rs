@@ -0,0 +1,60 @@
+use std::sync::LazyLock;
+
+pub const LANES: usize = 8;
+
+static AVAILABLE: LazyLock<bool> = LazyLock::new(detect);
+
+#[allow(unreachable_code)]
+fn detect() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    return is_x86_feature_detected!("avx2");
+    #[cfg(target_arch = "aarch64")]
+    return std::arch::is_aarch64_feature_detected!("neon");
+    false
+}
+
+pub fn is_available() -> bool {
+    *AVAILABLE
+}
+
+#[inline(always)]
+fn sum_lanes_generic(len: usize, gather: impl Fn(usize) -> (u64, f64)) -> (u64, u64) {
+    let mut a = [0u64; LANES];
+    let mut a_ew = [0u64; LANES];
+    let chunked_len = len - len % LANES;
+    for i in (0..chunked_len).step_by(LANES) {
+        let mut values = [0u64; LANES];
+        let mut efforts = [0f64; LANES];
+        for j in 0..LANES {
+            (values[j], efforts[j]) = gather(i + j);
+        }
+        for j in 0..LANES {
+            a[j] = a[j].saturating_add(values[j]);
+            a_ew[j] = a_ew[j].saturating_add((values[j] as f64 * efforts[j]) as u64);
+        }
+    }
+    let saturating_sum = |lanes: [u64; LANES]| lanes.into_iter().fold(0u64, u64::saturating_add);
+    let (mut sum, mut sum_ew) = (saturating_sum(a), saturating_sum(a_ew));
+    for i in chunked_len..len {
+        let (value, effort) = gather(i);
+        sum = sum.saturating_add(value);
+        sum_ew = sum_ew.saturating_add((value as f64 * effort) as u64);
+    }
+    (sum, sum_ew)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn sum_lanes_avx2(len: usize, gather: impl Fn(usize) -> (u64, f64)) -> (u64, u64) {
+    sum_lanes_generic(len, gather)
+}
+
+// NOTE Callers must check `is_available` first, and fall back to a scalar loop otherwise.
+#[inline]
+pub fn sum_lanes(len: usize, gather: impl Fn(usize) -> (u64, f64)) -> (u64, u64) {
+    debug_assert!(is_available());
+    #[cfg(target_arch = "x86_64")]
+    return unsafe { sum_lanes_avx2(len, gather) };
+    #[cfg(not(target_arch = "x86_64"))]
+    sum_lanes_generic(len, gather)
+}
@@ -0,0 +1,31 @@
+use std::{env, fs};
+
+fn read_trimmed(fpath: &str) -> Option<String> {
+    fs::read_to_string(fpath)
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+}
+
+// NOTE Without a dependency on the platform's C library, the hostname is read from the environment
+// (as set by most shells on Unix, and always on Windows), falling back to the files that hold it on
+// Linux.
+pub fn hostname() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .into_iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|s| !s.is_empty())
+        .or_else(|| read_trimmed("/proc/sys/kernel/hostname"))
+        .or_else(|| read_trimmed("/etc/hostname"))
+}
+
+// NOTE The CPU model is only known on Linux, where it is read from '/proc/cpuinfo'.
+pub fn cpu_model() -> Option<String> {
+    fs::read_to_string("/proc/cpuinfo")
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == "model name").then(|| value.trim().to_owned())
+        })
+}
@@ -14,3 +14,21 @@ impl Display for Weight {
         }
     }
 }
+
+// NOTE Which families of values are printed in detail rows, summary rows, and totals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Columns {
+    Both,
+    Effort,
+    Raw,
+}
+
+impl Columns {
+    pub fn includes(self, weight: Weight) -> bool {
+        match self {
+            Columns::Both => true,
+            Columns::Effort => weight == Weight::Effort,
+            Columns::Raw => weight == Weight::Raw,
+        }
+    }
+}
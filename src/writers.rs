@@ -1,115 +1,108 @@
-use core::{fmt::Display, iter, time::Duration};
+use core::{array, fmt::Display, iter, time::Duration};
 
-use std::{collections::BTreeMap, io, sync::LazyLock};
+use std::{collections::BTreeMap, io};
 
 use serde_json::{Value, json};
 
 use termcolor::{Color, ColorSpec, WriteColor};
 
 use crate::{
+    goals::Goal,
     json::write_json_flatten_primitive_arrays,
     keys::KeyTable,
-    records::{DetailRow, Record, SummaryRow},
-    ui::{colors::hsv_to_rgb, progress::create_progress_bar, styles::WriteStyled},
+    layouts::Digit,
+    metrics::{Metric, MetricGoals},
+    ngrams::{Coverage, CoverageRow},
+    permutations::{PermutationProgress, Provenance},
+    records::{
+        DetailLimit, DetailRow, Explanation, Heatmap, Overlay, Rank, Record, Sparkline, SummaryRow,
+        Utilization,
+    },
+    scores::Contribution,
+    symbols::{Symbol, resolve},
+    tables::Table,
+    ui::{
+        colors::hsv_to_rgb,
+        pages::Pager,
+        progress::create_progress_bar,
+        styles::{WriteStyled, theme},
+    },
     util::{
-        format::format_perc,
-        math::{calculate_frac, crop_matrix},
+        format::{decimal_places_or, format_count, format_perc, format_permutation_count},
+        math::{calculate_frac, calculate_perc, crop_matrix},
         time::format_seconds_f64,
     },
+    weights::{Columns, Weight},
 };
 
 // Indices
 
-pub static STYLE_INDEX: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec.set_underline(true);
-    color_spec
-});
-
 pub fn write_index(writer: &mut dyn WriteColor, s: &str) -> io::Result<()> {
-    writer.set_color(&STYLE_INDEX)?;
+    writer.set_color(&theme().index)?;
     writeln!(writer, "{}", s)?;
     writer.reset()
 }
 
 // Matrices
 
-pub static STYLE_NONE: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_dimmed(true);
-    color_spec
-});
-
-pub static STYLE_SPACE: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec
-        .set_bg(Some(Color::White))
-        .set_fg(Some(Color::Black));
-    color_spec
-});
-
-pub static STYLE_SUBSTITUTION: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_bold(true);
-    color_spec.set_intense(true);
-    color_spec
-        .set_bg(Some(Color::Red))
-        .set_fg(Some(Color::White));
-    color_spec
-});
-
-pub static STYLE_UNPRINTABLE: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_dimmed(true);
-    color_spec
-});
-
-pub fn is_printable(byte: u8) -> bool {
-    (0x20..=0x7E).contains(&byte)
+// NOTE Returns the heat (from 0.0 to 1.0) of each cell of a key table matrix, which determines the
+// colour of its key.
+pub type HeatFn<'a, const C: usize, const R: usize> =
+    &'a dyn Fn(&[[Symbol; C]; R]) -> [[f64; C]; R];
+
+pub fn is_printable(byte: Symbol) -> bool {
+    byte >= 0x20 && char::from_u32(byte as u32).is_some_and(|ch| !ch.is_control())
+}
+
+fn write_matrix_cell(
+    writer: &mut dyn WriteColor,
+    byte: Symbol,
+    heat: f64,
+    style_opt: Option<&ColorSpec>,
+) -> io::Result<()> {
+    const CHAR_UNKNOWN: char = '?';
+    match byte {
+        0 => {
+            writer.set_color(style_opt.unwrap_or(&theme().none))?;
+            write!(writer, " ")
+        }
+        1..=3 => {
+            writer.set_color(&theme().substitution)?;
+            write!(writer, "{}", (b'0' + byte as u8) as char)
+        }
+        0x20 => {
+            writer.set_color(style_opt.unwrap_or(&theme().space))?;
+            write!(writer, " ")
+        }
+        _ if is_printable(byte) => {
+            match style_opt {
+                Some(style) => writer.set_color(style)?,
+                None => {
+                    let theme = theme();
+                    let (r, g, b) = theme.heat_palette.heat_to_rgb(theme.heat_hue, heat as f32);
+                    writer.set_color(ColorSpec::new().set_fg(Some(Color::Rgb(r, g, b))))?
+                }
+            }
+            write!(writer, "{}", resolve(byte))
+        }
+        _ => {
+            writer.set_color(style_opt.unwrap_or(&theme().unprintable))?;
+            write!(writer, "{}", CHAR_UNKNOWN)
+        }
+    }?;
+    writer.reset()
 }
 
 pub fn write_matrix<const C: usize, const R: usize>(
     writer: &mut dyn WriteColor,
-    matrix: &[[u8; C]; R],
+    matrix: &[[Symbol; C]; R],
     crop_rect_trbl_opt: Option<(usize, usize, usize, usize)>,
-    saturation_map: &[f64; 1 << 8],
+    heat_matrix: &[[f64; C]; R],
 ) -> io::Result<()> {
-    const CHAR_UNKNOWN: char = '?';
-    const HUE: f32 = 0.0;
-    const VALUE_MIN: f32 = 0.75;
-    let mut color_spec = ColorSpec::new();
     let (top, right, bottom, left) = crop_rect_trbl_opt.unwrap_or((0, 0, 0, 0));
     for row in top..R.saturating_sub(bottom) {
         for col in left..C.saturating_sub(right) {
-            let byte = matrix[row][col];
-            match byte {
-                0 => {
-                    writer.set_color(&STYLE_NONE)?;
-                    write!(writer, " ")
-                }
-                1..=3 => {
-                    writer.set_color(&STYLE_SUBSTITUTION)?;
-                    write!(writer, "{}", (b'0' + byte) as char)
-                }
-                b' ' => {
-                    writer.set_color(&STYLE_SPACE)?;
-                    write!(writer, " ")
-                }
-                _ if is_printable(byte) => {
-                    let s = saturation_map[byte as usize] as f32;
-                    let v = VALUE_MIN + s * (1.0 - VALUE_MIN);
-                    let (r, g, b) = hsv_to_rgb(HUE, s, v);
-                    color_spec.set_fg(Some(Color::Rgb(r, g, b)));
-                    writer.set_color(&color_spec)?;
-                    write!(writer, "{}", byte as char)
-                }
-                _ => {
-                    writer.set_color(&STYLE_UNPRINTABLE)?;
-                    write!(writer, "{}", CHAR_UNKNOWN)
-                }
-            }?;
-            writer.reset()?;
+            write_matrix_cell(writer, matrix[row][col], heat_matrix[row][col], None)?;
             write!(writer, " ")?;
         }
         writer.reset()?;
@@ -120,44 +113,47 @@ pub fn write_matrix<const C: usize, const R: usize>(
 
 // Percentages
 
-pub static STYLE_PERC: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_italic(true);
-    color_spec
-});
-
 pub fn write_perc(
     writer: &mut dyn WriteColor,
     decimal_places: usize,
     value_opt: Option<f64>,
 ) -> io::Result<()> {
-    writer.set_color(&STYLE_PERC)?;
+    writer.set_color(&theme().perc)?;
     write!(writer, "{}", format_perc(decimal_places, value_opt))?;
     writer.reset()
 }
 
 // Progress
 
-pub static STYLE_PERC_COMPLETE: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_fg(Some(Color::Green));
-    color_spec.set_intense(true);
-    color_spec
-});
-
-pub static STYLE_DURATION_COMPLETE: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_fg(Some(Color::Cyan));
-    color_spec.set_intense(true);
-    color_spec
-});
-
-pub static STYLE_DURATION_INCOMPLETE: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_fg(Some(Color::Yellow));
-    color_spec.set_intense(true);
-    color_spec
-});
+pub fn write_estimate(
+    writer: &mut dyn WriteColor,
+    n: u64,
+    duration_per_permutation: Duration,
+    estimated_duration: Duration,
+    threads: usize,
+    decimal_places: usize,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "Estimated duration for {} permutations: ",
+        format_permutation_count(n)
+    )?;
+    writer.set_color(&theme().duration_incomplete)?;
+    write!(
+        writer,
+        "{}",
+        format_seconds_f64(estimated_duration.as_secs_f64(), decimal_places)
+    )?;
+    writer.reset()?;
+    writeln!(
+        writer,
+        "  ({:?} / permutation, {} thread{})",
+        duration_per_permutation,
+        threads,
+        if threads == 1 { "" } else { "s" }
+    )?;
+    writer.flush()
+}
 
 pub fn write_progress(
     writer: &mut dyn WriteColor,
@@ -165,6 +161,8 @@ pub fn write_progress(
     total_opt: Option<u64>,
     duration_complete_opt: Option<Duration>,
     estimate_duration_incomplete: bool,
+    rate_opt: Option<f64>,
+    best_score_opt: Option<u64>,
     decimal_places: usize,
     carriage_width_opt: Option<usize>,
     progress_bar_width_opt: Option<usize>,
@@ -182,16 +180,16 @@ pub fn write_progress(
             create_progress_bar(progress_bar_width, frac_complete as f32)
         )?;
         let perc_complete = frac_complete * 100.0;
-        writer.set_color(&STYLE_PERC_COMPLETE)?;
+        writer.set_color(&theme().perc_complete)?;
         write!(writer, "{:.*}%  ", decimal_places, perc_complete)?;
         writer.reset()?;
     }
-    write!(writer, "{}", n)?;
+    write!(writer, "{}", format_count(n))?;
     if let Some(total) = total_opt {
-        write!(writer, " / {}", total)?;
+        write!(writer, " / {}", format_permutation_count(total))?;
     }
     if let Some(duration_complete) = duration_complete_opt {
-        writer.set_color(&STYLE_DURATION_COMPLETE)?;
+        writer.set_color(&theme().duration_complete)?;
         let duration_complete_seconds = duration_complete.as_secs_f64();
         write!(
             writer,
@@ -199,31 +197,158 @@ pub fn write_progress(
             format_seconds_f64(duration_complete_seconds, decimal_places)
         )?;
         writer.reset()?;
-        if estimate_duration_incomplete {
-            if let Some(frac_complete) = frac_complete_opt {
-                if frac_complete > 0.0 {
-                    let estimated_total_seconds = duration_complete_seconds / frac_complete;
-                    let estimated_remaining_seconds =
-                        estimated_total_seconds - duration_complete_seconds;
-                    writer.set_color(&STYLE_DURATION_INCOMPLETE)?;
-                    write!(
-                        writer,
-                        "  (~ {} remaining)",
-                        format_seconds_f64(estimated_remaining_seconds, decimal_places)
-                    )?;
-                    writer.reset()?;
-                }
-            }
+        if estimate_duration_incomplete
+            && let Some(total) = total_opt
+            && let Some(estimated_remaining_seconds) =
+                estimate_remaining_seconds(n, total, duration_complete_seconds, rate_opt)
+        {
+            writer.set_color(&theme().duration_incomplete)?;
+            write!(
+                writer,
+                "  (~ {} remaining)",
+                format_seconds_f64(estimated_remaining_seconds, decimal_places)
+            )?;
+            writer.reset()?;
         }
     }
+    if let Some(rate) = rate_opt {
+        write!(writer, "  {} / s", format_count(rate.round() as u64))?;
+    }
+    if let Some(best_score) = best_score_opt {
+        write!(writer, "  best: {}", format_count(best_score))?;
+    }
     writer.flush()
 }
 
+// NOTE The remaining duration is estimated from the recent rate if known, and otherwise by
+// extrapolating linearly from the duration so far.
+fn estimate_remaining_seconds(
+    n: u64,
+    total: u64,
+    duration_complete_seconds: f64,
+    rate_opt: Option<f64>,
+) -> Option<f64> {
+    match (rate_opt, calculate_frac(n, total)) {
+        (Some(rate), _) if rate > 0.0 => Some(total.saturating_sub(n) as f64 / rate),
+        (_, Some(frac_complete)) if frac_complete > 0.0 => {
+            Some(duration_complete_seconds / frac_complete - duration_complete_seconds)
+        }
+        _ => None,
+    }
+}
+
+// NOTE Each update moves the cursor back up over the rows written by the previous update and
+// clears them, so that the status area is redrawn in place. Returns the number of rows written.
+pub fn write_progress_rich(
+    writer: &mut dyn WriteColor,
+    previous_rows: usize,
+    progress: &PermutationProgress,
+    total: u64,
+    duration_complete: Duration,
+    rate_opt: Option<f64>,
+    threads: usize,
+) -> io::Result<usize> {
+    let decimal_places = decimal_places_or(1);
+    const PROGRESS_BAR_WIDTH: usize = 40;
+    const NONE: &str = "-";
+    let n = progress.n_permutations;
+    if previous_rows > 0 {
+        write!(writer, "\x1b[{}A", previous_rows)?;
+    }
+    let mut rows = 0;
+    let mut start_row = |writer: &mut dyn WriteColor, label: &str| {
+        rows += 1;
+        write!(writer, "\r\x1b[2K{:<12}", label)
+    };
+    start_row(writer, "Progress:")?;
+    let frac_complete = calculate_frac(n, total).unwrap_or_default();
+    write!(
+        writer,
+        "[{}]  ",
+        create_progress_bar(PROGRESS_BAR_WIDTH, frac_complete as f32)
+    )?;
+    writer.set_color(&theme().perc_complete)?;
+    write!(writer, "{:.*}%", decimal_places, frac_complete * 100.0)?;
+    writer.reset()?;
+    writeln!(
+        writer,
+        "  {} / {}",
+        format_count(n),
+        format_permutation_count(total)
+    )?;
+    start_row(writer, "Elapsed:")?;
+    let duration_complete_seconds = duration_complete.as_secs_f64();
+    writer.set_color(&theme().duration_complete)?;
+    write!(
+        writer,
+        "{}",
+        format_seconds_f64(duration_complete_seconds, decimal_places)
+    )?;
+    writer.reset()?;
+    if let Some(estimated_remaining_seconds) =
+        estimate_remaining_seconds(n, total, duration_complete_seconds, rate_opt)
+    {
+        writer.set_color(&theme().duration_incomplete)?;
+        write!(
+            writer,
+            "  (~ {} remaining)",
+            format_seconds_f64(estimated_remaining_seconds, decimal_places)
+        )?;
+        writer.reset()?;
+    }
+    writeln!(writer)?;
+    start_row(writer, "Rate:")?;
+    match rate_opt {
+        Some(rate) => write!(writer, "{} / s", format_count(rate.round() as u64))?,
+        None => write!(writer, "{}", NONE)?,
+    }
+    writeln!(
+        writer,
+        "  ({} thread{})",
+        threads,
+        if threads == 1 { "" } else { "s" }
+    )?;
+    start_row(writer, "Best:")?;
+    match progress.best_score_opt {
+        Some(best_score) => writeln!(writer, "{}", format_count(best_score))?,
+        None => writeln!(writer, "{}", NONE)?,
+    }
+    start_row(writer, "Threshold:")?;
+    match progress.threshold_score_opt {
+        Some(threshold_score) => writeln!(writer, "{}", format_count(threshold_score))?,
+        None => writeln!(writer, "{}", NONE)?,
+    }
+    start_row(writer, "Records:")?;
+    writeln!(writer, "{}", format_count(progress.n_records))?;
+    writer.flush()?;
+    Ok(rows)
+}
+
 // Records
 
 const TOTALS: &str = "TOTALS";
 
-pub fn write_detail_row_json<K: Display>(detail_row: &DetailRow<K>, print_perc: bool) -> Value {
+// NOTE Which rows are printed in detail reports, and which values are printed in detail rows,
+// summary rows, and totals. Values per billion n-grams are printed only in summary rows. The goal of
+// each metric is shown by an arrow after its name.
+#[derive(Clone, Copy, Debug)]
+pub struct RowOptions<'a> {
+    pub print_perc: bool,
+    pub print_per_billion: bool,
+    pub columns: Columns,
+    pub detail_limit: DetailLimit,
+    pub goals: &'a MetricGoals,
+}
+
+pub fn write_detail_row_json<K: Display>(
+    detail_row: &DetailRow<K>,
+    row_options: RowOptions,
+) -> Value {
+    let RowOptions {
+        print_perc,
+        columns,
+        ..
+    } = row_options;
     let mut raw = vec![Value::from(detail_row.value), Value::from(detail_row.cum)];
     if print_perc {
         raw.push(Value::from(detail_row.value_as_perc_measurement));
@@ -241,103 +366,485 @@ pub fn write_detail_row_json<K: Display>(detail_row: &DetailRow<K>, print_perc:
         effort.push(Value::from(detail_row.value_ew_as_perc_record));
         effort.push(Value::from(detail_row.cum_ew_as_perc_record));
     }
-    Value::Array(vec![
-        Value::from(detail_row.key.to_string()),
+    let mut values = vec![Value::from(detail_row.key.to_string())];
+    values.extend(select_columns(
+        columns,
         Value::Array(raw),
         Value::Array(effort),
-    ])
+    ));
+    Value::Array(values)
 }
 
 pub fn write_detail_row_text<K: WriteStyled>(
     writer: &mut dyn WriteColor,
     detail_row: &DetailRow<K>,
     decimal_places: usize,
-    print_perc: bool,
+    row_options: RowOptions,
 ) -> io::Result<()> {
+    let RowOptions {
+        print_perc,
+        columns,
+        ..
+    } = row_options;
     detail_row.key.write_styled(writer)?;
-    write!(writer, ", ")?;
-    write!(writer, "{}", detail_row.value)?;
-    write!(writer, ", ")?;
-    write!(writer, "{}", detail_row.cum)?;
-    if print_perc {
+    if columns.includes(Weight::Raw) {
         write!(writer, ", ")?;
-        write_perc(writer, decimal_places, detail_row.value_as_perc_measurement)?;
+        write!(writer, "{}", format_count(detail_row.value))?;
         write!(writer, ", ")?;
-        write_perc(writer, decimal_places, detail_row.cum_as_perc_measurement)?;
-        write!(writer, ", ")?;
-        write_perc(writer, decimal_places, detail_row.value_as_perc_record)?;
-        write!(writer, ", ")?;
-        write_perc(writer, decimal_places, detail_row.cum_as_perc_record)?;
+        write!(writer, "{}", format_count(detail_row.cum))?;
+        if print_perc {
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, detail_row.value_as_perc_measurement)?;
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, detail_row.cum_as_perc_measurement)?;
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, detail_row.value_as_perc_record)?;
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, detail_row.cum_as_perc_record)?;
+        }
     }
-    write!(writer, ", ")?;
-    write!(writer, "{}", detail_row.value_ew)?;
-    write!(writer, ", ")?;
-    write!(writer, "{}", detail_row.cum_ew)?;
-    if print_perc {
-        write!(writer, ", ")?;
-        write_perc(
-            writer,
-            decimal_places,
-            detail_row.value_ew_as_perc_measurement,
-        )?;
-        write!(writer, ", ")?;
-        write_perc(
-            writer,
-            decimal_places,
-            detail_row.cum_ew_as_perc_measurement,
-        )?;
+    if columns.includes(Weight::Effort) {
         write!(writer, ", ")?;
-        write_perc(writer, decimal_places, detail_row.value_ew_as_perc_record)?;
+        write!(writer, "{}", format_count(detail_row.value_ew))?;
         write!(writer, ", ")?;
-        write_perc(writer, decimal_places, detail_row.cum_ew_as_perc_record)?;
+        write!(writer, "{}", format_count(detail_row.cum_ew))?;
+        if print_perc {
+            write!(writer, ", ")?;
+            write_perc(
+                writer,
+                decimal_places,
+                detail_row.value_ew_as_perc_measurement,
+            )?;
+            write!(writer, ", ")?;
+            write_perc(
+                writer,
+                decimal_places,
+                detail_row.cum_ew_as_perc_measurement,
+            )?;
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, detail_row.value_ew_as_perc_record)?;
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, detail_row.cum_ew_as_perc_record)?;
+        }
     }
     Ok(())
 }
 
-pub fn write_summary_row_json(summary_row: &SummaryRow, print_perc: bool) -> Value {
-    let raw = if print_perc {
-        Value::Array(vec![
-            Value::from(summary_row.sum),
-            Value::from(summary_row.sum_as_perc),
-        ])
-    } else {
-        Value::from(summary_row.sum)
-    };
-    let effort = if print_perc {
-        Value::Array(vec![
-            Value::from(summary_row.sum_ew),
-            Value::from(summary_row.sum_ew_as_perc),
-        ])
-    } else {
-        Value::from(summary_row.sum_ew)
+// NOTE Each family is a single sum, or an array of the sum followed by its percentage and its
+// value per billion n-grams (whichever are printed).
+pub fn write_summary_row_json(summary_row: &SummaryRow, row_options: RowOptions) -> Value {
+    let family = |sum: u64, perc_opt: Option<f64>, per_billion_opt: Option<f64>| {
+        let mut values = vec![Value::from(sum)];
+        if row_options.print_perc {
+            values.push(Value::from(perc_opt));
+        }
+        if row_options.print_per_billion {
+            values.push(Value::from(per_billion_opt));
+        }
+        match <[Value; 1]>::try_from(values) {
+            Ok([value]) => value,
+            Err(values) => Value::Array(values),
+        }
     };
-    Value::Array(vec![raw, effort])
+    let raw = family(
+        summary_row.sum,
+        summary_row.sum_as_perc,
+        summary_row.sum_per_billion,
+    );
+    let effort = family(
+        summary_row.sum_ew,
+        summary_row.sum_ew_as_perc,
+        summary_row.sum_ew_per_billion,
+    );
+    let mut values = select_columns(row_options.columns, raw, effort);
+    if let Some(percentile) = summary_row.percentile_opt {
+        values.push(Value::from(percentile));
+    }
+    Value::Array(values)
 }
 
 pub fn write_summary_row_text(
     writer: &mut dyn WriteColor,
     summary_row: &SummaryRow,
     decimal_places: usize,
-    print_perc: bool,
+    row_options: RowOptions,
 ) -> io::Result<()> {
-    write!(writer, "{}", summary_row.sum)?;
-    if print_perc {
-        write!(writer, ", ")?;
-        write_perc(writer, decimal_places, summary_row.sum_as_perc)?;
+    let RowOptions {
+        print_perc,
+        print_per_billion,
+        columns,
+        ..
+    } = row_options;
+    if columns.includes(Weight::Raw) {
+        write!(writer, "{}", format_count(summary_row.sum))?;
+        if print_perc {
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, summary_row.sum_as_perc)?;
+        }
+        if print_per_billion {
+            write!(writer, ", ")?;
+            write_per_billion(writer, summary_row.sum_per_billion)?;
+        }
     }
-    write!(writer, ", {}", summary_row.sum_ew)?;
-    if print_perc {
+    if columns == Columns::Both {
         write!(writer, ", ")?;
-        write_perc(writer, decimal_places, summary_row.sum_ew_as_perc)?;
+    }
+    if columns.includes(Weight::Effort) {
+        write!(writer, "{}", format_count(summary_row.sum_ew))?;
+        if print_perc {
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, summary_row.sum_ew_as_perc)?;
+        }
+        if print_per_billion {
+            write!(writer, ", ")?;
+            write_per_billion(writer, summary_row.sum_ew_per_billion)?;
+        }
+    }
+    if let Some(percentile) = summary_row.percentile_opt {
+        write!(writer, ", better than ")?;
+        write_perc(writer, decimal_places, Some(percentile))?;
+    }
+    Ok(())
+}
+
+fn write_totals_json(sum: u64, sum_ew: u64, columns: Columns) -> Value {
+    Value::Array(select_columns(
+        columns,
+        Value::from(sum),
+        Value::from(sum_ew),
+    ))
+}
+
+fn write_totals_text(
+    writer: &mut dyn WriteColor,
+    sum: u64,
+    sum_ew: u64,
+    columns: Columns,
+) -> io::Result<()> {
+    write!(writer, "{}: ", TOTALS)?;
+    match columns {
+        Columns::Both => write!(writer, "{}, {}", format_count(sum), format_count(sum_ew)),
+        Columns::Effort => write!(writer, "{}", format_count(sum_ew)),
+        Columns::Raw => write!(writer, "{}", format_count(sum)),
+    }
+}
+
+// NOTE Rounded to a whole number of n-grams, as the table sums are usually large.
+fn write_per_billion(writer: &mut dyn WriteColor, value_opt: Option<f64>) -> io::Result<()> {
+    match value_opt {
+        None => write!(writer, "n/a"),
+        Some(value) => write!(writer, "{} ppb", format_count(value.round() as u64)),
+    }
+}
+
+// NOTE The raw values come before the effort-weighted values, and either may be left out.
+fn select_columns(columns: Columns, raw: Value, effort: Value) -> Vec<Value> {
+    [(Weight::Raw, raw), (Weight::Effort, effort)]
+        .into_iter()
+        .filter_map(|(weight, value)| columns.includes(weight).then_some(value))
+        .collect()
+}
+
+// Ranks
+
+fn write_ranks_json(ranks: &[Rank]) -> Value {
+    Value::Object(
+        ranks
+            .iter()
+            .map(|rank| (rank.metric.to_string(), json!([rank.rank, rank.total])))
+            .collect(),
+    )
+}
+
+fn write_ranks_text(writer: &mut dyn WriteColor, ranks: &[Rank]) -> io::Result<()> {
+    for rank in ranks {
+        write!(writer, "rank by ")?;
+        rank.metric.write_styled(writer)?;
+        writeln!(writer, ": {} / {}", rank.rank, rank.total)?;
+    }
+    Ok(())
+}
+
+// Provenance
+
+fn write_provenance_json(provenance: &Provenance) -> Value {
+    json!({
+        "permutation_index": provenance.permutation_index,
+        "regions": provenance
+            .assignments
+            .iter()
+            .map(|assignment| {
+                assignment
+                    .iter()
+                    .map(|&(byte, (r, c))| json!([resolve(byte).to_string(), r, c]))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn write_provenance_text(writer: &mut dyn WriteColor, provenance: &Provenance) -> io::Result<()> {
+    write_title(writer, "Provenance:")?;
+    writeln!(
+        writer,
+        "permutation index: {}",
+        provenance.permutation_index
+    )?;
+    for (i, assignment) in provenance.assignments.iter().enumerate() {
+        if assignment.is_empty() {
+            continue;
+        }
+        write!(writer, "region {}: ", i + 1)?;
+        for (j, &(byte, (r, c))) in assignment.iter().enumerate() {
+            if j != 0 {
+                write!(writer, ", ")?;
+            }
+            write!(writer, "{} ({}, {})", resolve(byte), r, c)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// Overlays
+
+// NOTE The direction from the first key of a bigram to the second, as seen on the board.
+fn overlay_arrow((r1, c1): (usize, usize), (r2, c2): (usize, usize)) -> char {
+    use core::cmp::Ordering::*;
+    match (r2.cmp(&r1), c2.cmp(&c1)) {
+        (Less, Less) => '↖',
+        (Less, Equal) => '↑',
+        (Less, Greater) => '↗',
+        (Equal, Less) => '←',
+        (Equal, Equal) => '↻',
+        (Equal, Greater) => '→',
+        (Greater, Less) => '↙',
+        (Greater, Equal) => '↓',
+        (Greater, Greater) => '↘',
+    }
+}
+
+fn write_overlay_json<const C: usize, const R: usize>(
+    overlay: &Overlay,
+    key_table_matrix: &[[Symbol; C]; R],
+) -> Value {
+    json!({
+        "metric": overlay.metric.to_string(),
+        "bigrams": overlay
+            .bigrams
+            .iter()
+            .map(|bigram| {
+                let (r1, c1) = bigram.from;
+                let (r2, c2) = bigram.to;
+                json!([
+                    format!(
+                        "{}{}",
+                        resolve(key_table_matrix[r1][c1]),
+                        resolve(key_table_matrix[r2][c2])
+                    ),
+                    [r1, c1],
+                    [r2, c2],
+                    bigram.value,
+                    bigram.perc_opt,
+                ])
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+// NOTE The keys of the listed bigrams are highlighted in a copy of the key table, and each bigram
+// is listed with an arrow showing its direction, coloured by its value relative to the first.
+fn write_overlay_text<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    overlay: &Overlay,
+    key_table_matrix: &[[Symbol; C]; R],
+    print_perc: bool,
+    goal: Goal,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    write_title(writer, &format!("{} {} overlay:", overlay.metric, goal))?;
+    let (top, right, bottom, left) = crop_matrix(key_table_matrix, |b| is_printable(*b));
+    for (r, row) in key_table_matrix
+        .iter()
+        .enumerate()
+        .take(R.saturating_sub(bottom))
+        .skip(top)
+    {
+        for (c, &byte) in row
+            .iter()
+            .enumerate()
+            .take(C.saturating_sub(right))
+            .skip(left)
+        {
+            let is_highlighted = overlay
+                .bigrams
+                .iter()
+                .any(|bigram| bigram.from == (r, c) || bigram.to == (r, c));
+            let style_opt = is_highlighted.then_some(&theme().overlay);
+            write_matrix_cell(writer, byte, 0.0, style_opt)?;
+            write!(writer, " ")?;
+        }
+        writeln!(writer)?;
+    }
+    let max = overlay.bigrams.first().map_or(0, |bigram| bigram.value);
+    let width = overlay.bigrams.len().to_string().len();
+    for (i, bigram) in overlay.bigrams.iter().enumerate() {
+        let (r1, c1) = bigram.from;
+        let (r2, c2) = bigram.to;
+        write!(writer, "{:>width$}. ", i + 1)?;
+        write_matrix_cell(writer, key_table_matrix[r1][c1], 1.0, None)?;
+        write!(writer, " ")?;
+        let theme = theme();
+        let heat = calculate_frac(bigram.value, max).unwrap_or_default();
+        let (r, g, b) = theme.heat_palette.heat_to_rgb(theme.heat_hue, heat as f32);
+        writer.set_color(ColorSpec::new().set_fg(Some(Color::Rgb(r, g, b))))?;
+        write!(writer, "{}", overlay_arrow(bigram.from, bigram.to))?;
+        writer.reset()?;
+        write!(writer, " ")?;
+        write_matrix_cell(writer, key_table_matrix[r2][c2], 1.0, None)?;
+        write!(writer, ", {}", format_count(bigram.value))?;
+        if print_perc {
+            write!(writer, ", ")?;
+            write_perc(writer, decimal_places, bigram.perc_opt)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// Heatmaps
+
+fn write_heatmap_json<const C: usize, const R: usize>(
+    heatmap: &Heatmap<C, R>,
+    key_table_matrix: &[[Symbol; C]; R],
+) -> Value {
+    let table = Table::<C, R, u64>(array::from_fn(|r| {
+        array::from_fn(|c| (key_table_matrix[r][c] != 0).then_some(heatmap.matrix[r][c]))
+    }));
+    json!({
+        "metric": heatmap.metric.to_string(),
+        "matrix": Value::from(&table),
+    })
+}
+
+// NOTE The heatmap is cropped like the key table, so each value lines up with its key. Values are
+// right-aligned and coloured by their heat relative to the hottest key.
+fn write_heatmap_text<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    heatmap_matrix: &[[u64; C]; R],
+    key_table_matrix: &[[Symbol; C]; R],
+) -> io::Result<()> {
+    let (top, right, bottom, left) = crop_matrix(key_table_matrix, |b| is_printable(*b));
+    let max = heatmap_matrix
+        .as_flattened()
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or_default();
+    let width = heatmap_matrix
+        .as_flattened()
+        .iter()
+        .map(|&value| format_count(value).len())
+        .max()
+        .unwrap_or_default();
+    let theme = theme();
+    for (row, heatmap_row) in key_table_matrix
+        .iter()
+        .zip(heatmap_matrix)
+        .take(R.saturating_sub(bottom))
+        .skip(top)
+    {
+        for (&byte, &value) in row
+            .iter()
+            .zip(heatmap_row)
+            .take(C.saturating_sub(right))
+            .skip(left)
+        {
+            if byte == 0 {
+                write!(writer, "{:width$} ", "")?;
+                continue;
+            }
+            let heat = calculate_frac(value, max).unwrap_or_default();
+            let (r, g, b) = theme.heat_palette.heat_to_rgb(theme.heat_hue, heat as f32);
+            writer.set_color(ColorSpec::new().set_fg(Some(Color::Rgb(r, g, b))))?;
+            write!(writer, "{:>width$}", format_count(value))?;
+            writer.reset()?;
+            write!(writer, " ")?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// Utilization
+
+fn write_utilization_json(utilization: &Utilization) -> Value {
+    json!({
+        "weight": utilization.weight.to_string(),
+        "digits": utilization
+            .digits
+            .iter()
+            .map(|digit| {
+                (
+                    digit.metric.to_string(),
+                    json!({
+                        "perc": digit.perc_opt,
+                        "finger_weight": digit.finger_weight,
+                        "ratio": digit.ratio_opt,
+                    }),
+                )
+            })
+            .collect::<BTreeMap<_, _>>(),
+        "hands": utilization
+            .hands
+            .iter()
+            .map(|hand| (hand.metric.to_string(), json!({ "perc": hand.perc_opt })))
+            .collect::<BTreeMap<_, _>>(),
+    })
+}
+
+// NOTE One row per digit (its load, finger weight, and ratio of load to weight), followed by one
+// row per hand (its load).
+fn write_utilization_text(
+    writer: &mut dyn WriteColor,
+    utilization: &Utilization,
+    decimal_places: usize,
+) -> io::Result<()> {
+    write_title(
+        writer,
+        &format!(
+            "Utilization report ({} weight):",
+            utilization.weight.to_string().to_lowercase()
+        ),
+    )?;
+    for digit in &utilization.digits {
+        digit.metric.write_styled(writer)?;
+        write!(writer, ": ")?;
+        write_perc(writer, decimal_places, digit.perc_opt)?;
+        write!(
+            writer,
+            ", finger weight {:.*}, ratio ",
+            decimal_places, digit.finger_weight
+        )?;
+        match digit.ratio_opt {
+            Some(ratio) => writeln!(writer, "{:.*}", decimal_places, ratio)?,
+            None => writeln!(writer, "n/a")?,
+        }
+    }
+    for hand in &utilization.hands {
+        hand.metric.write_styled(writer)?;
+        write!(writer, ": ")?;
+        write_perc(writer, decimal_places, hand.perc_opt)?;
+        writeln!(writer)?;
     }
     Ok(())
 }
 
-pub fn write_record_json(
+pub fn write_record_json<const C: usize, const R: usize>(
     index_and_total_pair_opt: Option<(usize, usize)>,
-    record: Record,
+    record: Record<C, R>,
     print_summaries: bool,
-    print_perc: bool,
+    row_options: RowOptions,
 ) -> Value {
     let key_table = KeyTable::from_byte_matrix(&record.key_table_matrix);
     let key_table_json: Value = (&key_table).into();
@@ -345,48 +852,54 @@ pub fn write_record_json(
         .unigram_measurements
         .iter()
         .filter_map(|(metric, _)| {
-            record.iter_unigram_details(*metric).map(|detail_rows| {
-                (
-                    metric.to_string(),
-                    Value::Array(
-                        detail_rows
-                            .map(|detail_row| write_detail_row_json(&detail_row, print_perc))
-                            .collect(),
-                    ),
-                )
-            })
+            record
+                .iter_unigram_details(*metric, row_options.detail_limit)
+                .map(|detail_rows| {
+                    (
+                        metric.to_string(),
+                        Value::Array(
+                            detail_rows
+                                .map(|detail_row| write_detail_row_json(&detail_row, row_options))
+                                .collect(),
+                        ),
+                    )
+                })
         })
         .collect::<BTreeMap<_, _>>();
     let bigram_details_json = record
         .bigram_measurements
         .iter()
         .filter_map(|(metric, _)| {
-            record.iter_bigram_details(*metric).map(|detail_rows| {
-                (
-                    metric.to_string(),
-                    Value::Array(
-                        detail_rows
-                            .map(|detail_row| write_detail_row_json(&detail_row, print_perc))
-                            .collect(),
-                    ),
-                )
-            })
+            record
+                .iter_bigram_details(*metric, row_options.detail_limit)
+                .map(|detail_rows| {
+                    (
+                        metric.to_string(),
+                        Value::Array(
+                            detail_rows
+                                .map(|detail_row| write_detail_row_json(&detail_row, row_options))
+                                .collect(),
+                        ),
+                    )
+                })
         })
         .collect::<BTreeMap<_, _>>();
     let trigram_details_json = record
         .trigram_measurements
         .iter()
         .filter_map(|(metric, _)| {
-            record.iter_trigram_details(*metric).map(|detail_rows| {
-                (
-                    metric.to_string(),
-                    Value::Array(
-                        detail_rows
-                            .map(|detail_row| write_detail_row_json(&detail_row, print_perc))
-                            .collect(),
-                    ),
-                )
-            })
+            record
+                .iter_trigram_details(*metric, row_options.detail_limit)
+                .map(|detail_rows| {
+                    (
+                        metric.to_string(),
+                        Value::Array(
+                            detail_rows
+                                .map(|detail_row| write_detail_row_json(&detail_row, row_options))
+                                .collect(),
+                        ),
+                    )
+                })
         })
         .collect::<BTreeMap<_, _>>();
     let unigram_summaries_json = print_summaries.then(|| {
@@ -395,15 +908,12 @@ pub fn write_record_json(
             .map(|(metric, summary_row)| {
                 (
                     metric.to_string(),
-                    write_summary_row_json(&summary_row, print_perc),
+                    write_summary_row_json(&summary_row, row_options),
                 )
             })
             .chain(iter::once((
                 TOTALS.to_owned(),
-                Value::Array(vec![
-                    Value::from(record.uf_sum),
-                    Value::from(record.uf_sum_ew),
-                ]),
+                write_totals_json(record.uf_sum, record.uf_sum_ew, row_options.columns),
             )))
             .collect::<BTreeMap<_, _>>()
     });
@@ -413,15 +923,12 @@ pub fn write_record_json(
             .map(|(metric, summary_row)| {
                 (
                     metric.to_string(),
-                    write_summary_row_json(&summary_row, print_perc),
+                    write_summary_row_json(&summary_row, row_options),
                 )
             })
             .chain(iter::once((
                 TOTALS.to_owned(),
-                Value::Array(vec![
-                    Value::from(record.bf_sum),
-                    Value::from(record.bf_sum_ew),
-                ]),
+                write_totals_json(record.bf_sum, record.bf_sum_ew, row_options.columns),
             )))
             .collect::<BTreeMap<_, _>>()
     });
@@ -431,21 +938,31 @@ pub fn write_record_json(
             .map(|(metric, summary_row)| {
                 (
                     metric.to_string(),
-                    write_summary_row_json(&summary_row, print_perc),
+                    write_summary_row_json(&summary_row, row_options),
                 )
             })
             .chain(iter::once((
                 TOTALS.to_owned(),
-                Value::Array(vec![
-                    Value::from(record.tf_sum),
-                    Value::from(record.tf_sum_ew),
-                ]),
+                write_totals_json(record.tf_sum, record.tf_sum_ew, row_options.columns),
             )))
             .collect::<BTreeMap<_, _>>()
     });
     json!({
         "index": index_and_total_pair_opt.map(|(index, _total)| index),
         "key_table": key_table_json,
+        "source": record.source_opt,
+        "ranks": (!record.ranks.is_empty()).then(|| write_ranks_json(&record.ranks)),
+        "cluster_size": record.cluster_size_opt,
+        "provenance": record.provenance_opt.as_ref().map(write_provenance_json),
+        "overlay": record
+            .overlay_opt
+            .as_ref()
+            .map(|overlay| write_overlay_json(overlay, &record.key_table_matrix)),
+        "heatmap": record
+            .heatmap_opt
+            .as_ref()
+            .map(|heatmap| write_heatmap_json(heatmap, &record.key_table_matrix)),
+        "report": record.utilization_opt.as_ref().map(write_utilization_json),
         "measurements": {
             "unigram": {
                 "details": (!unigram_details_json.is_empty()).then_some(unigram_details_json),
@@ -463,15 +980,15 @@ pub fn write_record_json(
     })
 }
 
-pub fn write_record_text(
+pub fn write_record_text<const C: usize, const R: usize>(
     writer: &mut dyn WriteColor,
     index_and_total_pair_opt: Option<(usize, usize)>,
-    record: Record,
-    unigram_table_normalized: [f64; 1 << 8],
+    record: Record<C, R>,
+    heat_fn: HeatFn<C, R>,
     print_summaries: bool,
-    print_perc: bool,
+    row_options: RowOptions,
 ) -> io::Result<()> {
-    const DECIMAL_PLACES: usize = 3;
+    let decimal_places = decimal_places_or(3);
     if let Some((index, total)) = index_and_total_pair_opt {
         write_index(writer, &format!("{} / {}", index, total))?;
         writeln!(writer)?;
@@ -480,34 +997,78 @@ pub fn write_record_text(
         writer,
         &record.key_table_matrix,
         Some(crop_matrix(&record.key_table_matrix, |b| is_printable(*b))),
-        &unigram_table_normalized,
+        &heat_fn(&record.key_table_matrix),
     )?;
+    if !record.ranks.is_empty() || record.cluster_size_opt.is_some() || record.source_opt.is_some()
+    {
+        writeln!(writer)?;
+        if let Some(source) = &record.source_opt {
+            writeln!(writer, "source: {}", source)?;
+        }
+        write_ranks_text(writer, &record.ranks)?;
+        if let Some(cluster_size) = record.cluster_size_opt {
+            writeln!(writer, "cluster size: {}", cluster_size)?;
+        }
+    }
+    if let Some(provenance) = &record.provenance_opt {
+        writeln!(writer)?;
+        write_provenance_text(writer, provenance)?;
+    }
+    if let Some(overlay) = &record.overlay_opt {
+        writeln!(writer)?;
+        write_overlay_text(
+            writer,
+            overlay,
+            &record.key_table_matrix,
+            row_options.print_perc,
+            row_options.goals.goal(Metric::Bigram(overlay.metric)),
+        )?;
+    }
+    if let Some(heatmap) = &record.heatmap_opt {
+        writeln!(writer)?;
+        write_title(
+            writer,
+            &format!(
+                "{} {} heatmap:",
+                heatmap.metric,
+                row_options.goals.goal(heatmap.metric)
+            ),
+        )?;
+        write_heatmap_text(writer, &heatmap.matrix, &record.key_table_matrix)?;
+    }
+    if let Some(utilization) = &record.utilization_opt {
+        writeln!(writer)?;
+        write_utilization_text(writer, utilization, decimal_places)?;
+    }
     for metric in record.unigram_measurements.keys() {
-        if let Some(detail_rows) = record.iter_unigram_details(*metric) {
+        if let Some(detail_rows) = record.iter_unigram_details(*metric, row_options.detail_limit) {
+            let goal = row_options.goals.goal(Metric::Unigram(*metric));
             writeln!(writer)?;
-            write_title(writer, &format!("{} {}:", metric, metric.goal()))?;
+            write_title(writer, &format!("{} {}:", metric, goal))?;
             for detail_row in detail_rows {
-                write_detail_row_text(writer, &detail_row, DECIMAL_PLACES, print_perc)?;
+                write_detail_row_text(writer, &detail_row, decimal_places, row_options)?;
                 writeln!(writer)?;
             }
         }
     }
     for metric in record.bigram_measurements.keys() {
-        if let Some(detail_rows) = record.iter_bigram_details(*metric) {
+        if let Some(detail_rows) = record.iter_bigram_details(*metric, row_options.detail_limit) {
+            let goal = row_options.goals.goal(Metric::Bigram(*metric));
             writeln!(writer)?;
-            write_title(writer, &format!("{} {}:", metric, metric.goal()))?;
+            write_title(writer, &format!("{} {}:", metric, goal))?;
             for detail_row in detail_rows {
-                write_detail_row_text(writer, &detail_row, DECIMAL_PLACES, print_perc)?;
+                write_detail_row_text(writer, &detail_row, decimal_places, row_options)?;
                 writeln!(writer)?;
             }
         }
     }
     for metric in record.trigram_measurements.keys() {
-        if let Some(detail_rows) = record.iter_trigram_details(*metric) {
+        if let Some(detail_rows) = record.iter_trigram_details(*metric, row_options.detail_limit) {
+            let goal = row_options.goals.goal(Metric::Trigram(*metric));
             writeln!(writer)?;
-            write_title(writer, &format!("{} {}:", metric, metric.goal()))?;
+            write_title(writer, &format!("{} {}:", metric, goal))?;
             for detail_row in detail_rows {
-                write_detail_row_text(writer, &detail_row, DECIMAL_PLACES, print_perc)?;
+                write_detail_row_text(writer, &detail_row, decimal_places, row_options)?;
                 writeln!(writer)?;
             }
         }
@@ -517,15 +1078,15 @@ pub fn write_record_text(
         write_title(writer, "Unigram summaries:")?;
         for (metric, summary_row) in record.iter_unigram_summaries() {
             metric.write_styled(writer)?;
-            write!(writer, " {}: ", metric.goal())?;
-            write_summary_row_text(writer, &summary_row, DECIMAL_PLACES, print_perc)?;
+            write!(
+                writer,
+                " {}: ",
+                row_options.goals.goal(Metric::Unigram(metric))
+            )?;
+            write_summary_row_text(writer, &summary_row, decimal_places, row_options)?;
             writeln!(writer)?;
         }
-        write!(
-            writer,
-            "{}: {}, {}",
-            TOTALS, record.uf_sum, record.uf_sum_ew
-        )?;
+        write_totals_text(writer, record.uf_sum, record.uf_sum_ew, row_options.columns)?;
         writeln!(writer)?;
     }
     if print_summaries && !record.bigram_measurements.is_empty() {
@@ -533,15 +1094,15 @@ pub fn write_record_text(
         write_title(writer, "Bigram summaries:")?;
         for (metric, summary_row) in record.iter_bigram_summaries() {
             metric.write_styled(writer)?;
-            write!(writer, " {}: ", metric.goal())?;
-            write_summary_row_text(writer, &summary_row, DECIMAL_PLACES, print_perc)?;
+            write!(
+                writer,
+                " {}: ",
+                row_options.goals.goal(Metric::Bigram(metric))
+            )?;
+            write_summary_row_text(writer, &summary_row, decimal_places, row_options)?;
             writeln!(writer)?;
         }
-        write!(
-            writer,
-            "{}: {}, {}",
-            TOTALS, record.bf_sum, record.bf_sum_ew
-        )?;
+        write_totals_text(writer, record.bf_sum, record.bf_sum_ew, row_options.columns)?;
         writeln!(writer)?;
     }
     if print_summaries && !record.trigram_measurements.is_empty() {
@@ -549,33 +1110,33 @@ pub fn write_record_text(
         write_title(writer, "Trigram summaries:")?;
         for (metric, summary_row) in record.iter_trigram_summaries() {
             metric.write_styled(writer)?;
-            write!(writer, " {}: ", metric.goal())?;
-            write_summary_row_text(writer, &summary_row, DECIMAL_PLACES, print_perc)?;
+            write!(
+                writer,
+                " {}: ",
+                row_options.goals.goal(Metric::Trigram(metric))
+            )?;
+            write_summary_row_text(writer, &summary_row, decimal_places, row_options)?;
             writeln!(writer)?;
         }
-        write!(
-            writer,
-            "{}: {}, {}",
-            TOTALS, record.tf_sum, record.tf_sum_ew
-        )?;
+        write_totals_text(writer, record.tf_sum, record.tf_sum_ew, row_options.columns)?;
         writeln!(writer)?;
     }
     Ok(())
 }
 
-pub fn write_records_json(
+pub fn write_records_json<const C: usize, const R: usize>(
     writer: &mut dyn WriteColor,
-    records: impl Iterator<Item = Record>,
+    records: impl Iterator<Item = Record<C, R>>,
     total_opt: Option<usize>,
     print_summaries: bool,
-    print_perc: bool,
+    row_options: RowOptions,
 ) -> io::Result<()> {
     for (i, record) in records.enumerate() {
         let record_json = write_record_json(
             total_opt.map(|total| (i + 1, total)),
             record,
             print_summaries,
-            print_perc,
+            row_options,
         );
         write_json_flatten_primitive_arrays::<2, _>(writer, &record_json, 0)?;
         writeln!(writer)?;
@@ -584,39 +1145,724 @@ pub fn write_records_json(
     Ok(())
 }
 
-pub fn write_records_text(
+pub fn write_records_text<const C: usize, const R: usize>(
     writer: &mut dyn WriteColor,
-    records: impl Iterator<Item = Record>,
+    records: impl Iterator<Item = Record<C, R>>,
     total_opt: Option<usize>,
-    unigram_table_normalized: [f64; 1 << 8],
+    heat_fn: HeatFn<C, R>,
     print_summaries: bool,
-    print_perc: bool,
+    row_options: RowOptions,
+    pager: &mut Pager,
 ) -> io::Result<()> {
     for (i, record) in records.into_iter().enumerate() {
+        pager.next_record(writer)?;
         writeln!(writer)?;
         write_record_text(
             writer,
             total_opt.map(|total| (i + 1, total)),
             record,
-            unigram_table_normalized,
+            heat_fn,
             print_summaries,
-            print_perc,
+            row_options,
         )?;
         writer.flush()?;
     }
     Ok(())
 }
 
-// Titles
+// NOTE Used with '--quiet', where only the key table of each record is printed.
+pub fn write_key_tables_json<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    records: impl Iterator<Item = Record<C, R>>,
+) -> io::Result<()> {
+    for record in records {
+        let key_table = KeyTable::from_byte_matrix(&record.key_table_matrix);
+        write_json_flatten_primitive_arrays::<2, _>(writer, &Value::from(&key_table), 0)?;
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+pub fn write_key_tables_text<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    records: impl Iterator<Item = Record<C, R>>,
+    heat_fn: HeatFn<C, R>,
+    pager: &mut Pager,
+) -> io::Result<()> {
+    for record in records {
+        pager.next_record(writer)?;
+        if pager.n_records() > 1 {
+            writeln!(writer)?;
+        }
+        write_matrix(
+            writer,
+            &record.key_table_matrix,
+            Some(crop_matrix(&record.key_table_matrix, |b| is_printable(*b))),
+            &heat_fn(&record.key_table_matrix),
+        )?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// Comparisons
+
+const COMPARISON_GUTTER: &str = "   ";
+
+fn iter_changed_positions<'a, const C: usize, const R: usize>(
+    matrix1: &'a [[Symbol; C]; R],
+    matrix2: &'a [[Symbol; C]; R],
+) -> impl 'a + Iterator<Item = (usize, usize)> {
+    (0..R).flat_map(move |r| {
+        (0..C).filter_map(move |c| (matrix1[r][c] != matrix2[r][c]).then_some((r, c)))
+    })
+}
+
+pub fn write_comparison_json<const C: usize, const R: usize>(
+    record1: &Record<C, R>,
+    record2: &Record<C, R>,
+    weight: Weight,
+) -> Value {
+    let key_table1 = KeyTable::from_byte_matrix(&record1.key_table_matrix);
+    let key_table2 = KeyTable::from_byte_matrix(&record2.key_table_matrix);
+    let changed = iter_changed_positions(&record1.key_table_matrix, &record2.key_table_matrix)
+        .map(|(r, c)| json!([r, c]))
+        .collect::<Vec<_>>();
+    let deltas = record1
+        .iter_metrics()
+        .map(|metric| {
+            let perc1_opt = record1.perc(metric, weight);
+            let perc2_opt = record2.perc(metric, weight);
+            let delta_opt = perc1_opt.zip(perc2_opt).map(|(perc1, perc2)| perc2 - perc1);
+            (metric.to_string(), json!([perc1_opt, perc2_opt, delta_opt]))
+        })
+        .collect::<BTreeMap<_, _>>();
+    json!({
+        "key_tables": [Value::from(&key_table1), Value::from(&key_table2)],
+        "changed": changed,
+        "deltas": deltas,
+    })
+}
+
+// NOTE The two key tables are written side by side, with the positions that differ highlighted,
+// followed by the score of each metric for both key tables and the difference between them.
+pub fn write_comparison_text<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    record1: &Record<C, R>,
+    record2: &Record<C, R>,
+    weight: Weight,
+    goals: &MetricGoals,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    let matrix1 = &record1.key_table_matrix;
+    let matrix2 = &record2.key_table_matrix;
+    let (top1, right1, bottom1, left1) = crop_matrix(matrix1, |b| is_printable(*b));
+    let (top2, right2, bottom2, left2) = crop_matrix(matrix2, |b| is_printable(*b));
+    let (top, right, bottom, left) = (
+        top1.min(top2),
+        right1.min(right2),
+        bottom1.min(bottom2),
+        left1.min(left2),
+    );
+    let rows = top..matrix1.len().saturating_sub(bottom);
+    let cols = left..matrix1[0].len().saturating_sub(right);
+    for r in rows {
+        for (i, matrix) in [matrix1, matrix2].into_iter().enumerate() {
+            if i > 0 {
+                write!(writer, "{}", COMPARISON_GUTTER)?;
+            }
+            for c in cols.clone() {
+                let style_opt = (matrix1[r][c] != matrix2[r][c]).then_some(&theme().changed);
+                write_matrix_cell(writer, matrix[r][c], 0.0, style_opt)?;
+                write!(writer, " ")?;
+            }
+        }
+        writeln!(writer)?;
+    }
+    writeln!(writer)?;
+    write_title(writer, "Deltas:")?;
+    for metric in record1.iter_metrics() {
+        let perc1_opt = record1.perc(metric, weight);
+        let perc2_opt = record2.perc(metric, weight);
+        metric.write_styled(writer)?;
+        write!(writer, " {}: ", goals.goal(metric))?;
+        write_perc(writer, decimal_places, perc1_opt)?;
+        write!(writer, ", ")?;
+        write_perc(writer, decimal_places, perc2_opt)?;
+        if let (Some(perc1), Some(perc2)) = (perc1_opt, perc2_opt) {
+            write!(writer, ", {:+.*}", decimal_places, perc2 - perc1)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// Explanations
+
+fn ngram_name(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Unigram(_) => "unigram",
+        Metric::Bigram(_) => "bigram",
+        Metric::Trigram(_) => "trigram",
+    }
+}
+
+fn contribution_perc(contribution: &Contribution, explanation: &Explanation) -> Option<f64> {
+    calculate_perc(
+        contribution.value_by_weight(explanation.weight),
+        explanation.sum,
+    )
+}
+
+pub fn write_explanation_json(explanation: &Explanation) -> Value {
+    let contributions = explanation
+        .contributions
+        .iter()
+        .map(|contribution| {
+            json!({
+                "ngram": contribution.key,
+                "value": contribution.value,
+                "value_ew": contribution.value_ew,
+                "perc": contribution_perc(contribution, explanation),
+                "effort": contribution.effort,
+                "keys": contribution
+                    .keys
+                    .iter()
+                    .map(|&(byte, (r, c, laterality, position))| {
+                        json!([resolve(byte).to_string(), r, c, Digit(laterality, position).to_string()])
+                    })
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "metric": explanation.metric.to_string(),
+        "weight": explanation.weight.to_string(),
+        "sum": explanation.sum,
+        "perc": explanation.perc_opt,
+        "total": explanation.total,
+        "contributions": contributions,
+    })
+}
+
+// NOTE Each n-gram is listed with its score and percentage of the metric (by the selected
+// weight), the effort of its fingering, and the coordinates and digit of each of its keys.
+pub fn write_explanation_text(
+    writer: &mut dyn WriteColor,
+    explanation: &Explanation,
+    goals: &MetricGoals,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    let metric = explanation.metric;
+    let ngram = ngram_name(metric);
+    write_title(
+        writer,
+        &format!("{} {} explanation:", metric, goals.goal(metric)),
+    )?;
+    write!(
+        writer,
+        "{} scored {} ({} weight), ",
+        metric,
+        format_count(explanation.sum),
+        explanation.weight.to_string().to_lowercase()
+    )?;
+    write_perc(writer, decimal_places, explanation.perc_opt)?;
+    writeln!(
+        writer,
+        " of all {}s, from {} {}{}.",
+        ngram,
+        explanation.total,
+        ngram,
+        if explanation.total == 1 { "" } else { "s" }
+    )?;
+    let width = explanation.contributions.len().to_string().len();
+    for (i, contribution) in explanation.contributions.iter().enumerate() {
+        writeln!(writer)?;
+        write!(
+            writer,
+            "{:>width$}. {} contributed {} (",
+            i + 1,
+            contribution.key,
+            format_count(contribution.value_by_weight(explanation.weight))
+        )?;
+        write_perc(
+            writer,
+            decimal_places,
+            contribution_perc(contribution, explanation),
+        )?;
+        writeln!(
+            writer,
+            " of {}), with an effort of {:.*}:",
+            metric, decimal_places, contribution.effort
+        )?;
+        for &(byte, (r, c, laterality, position)) in &contribution.keys {
+            write!(writer, "{:width$}  ", "")?;
+            write_matrix_cell(writer, byte, 1.0, None)?;
+            writeln!(
+                writer,
+                " at ({}, {}), typed by {}",
+                r,
+                c,
+                Digit(laterality, position)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Leaderboards
+
+// NOTE One row per key table (in the order given), with the percentage of every metric.
+pub fn write_leaderboard_json<const C: usize, const R: usize>(
+    records: &[Record<C, R>],
+    weight: Weight,
+) -> Value {
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let percs = record
+                .iter_metrics()
+                .map(|metric| (metric.to_string(), json!(record.perc(metric, weight))))
+                .collect::<BTreeMap<_, _>>();
+            json!({
+                "rank": i + 1,
+                "source": record.source_opt,
+                "percs": percs,
+            })
+        })
+        .collect()
+}
 
-pub static STYLE_TITLE: LazyLock<ColorSpec> = LazyLock::new(|| {
-    let mut color_spec = ColorSpec::new();
-    color_spec.set_underline(true);
-    color_spec
-});
+pub fn write_leaderboard_text<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    records: &[Record<C, R>],
+    weight: Weight,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    const SOURCE: &str = "source";
+    let Some(first) = records.first() else {
+        return Ok(());
+    };
+    let metrics = first.iter_metrics().collect::<Vec<_>>();
+    let index_width = records.len().to_string().len();
+    let source_width = records
+        .iter()
+        .filter_map(|record| record.source_opt.as_ref())
+        .map(|source| source.chars().count())
+        .chain(iter::once(SOURCE.len()))
+        .max()
+        .unwrap_or_default();
+    writer.set_color(&theme().title)?;
+    write!(writer, "{:>index_width$}  {:<source_width$}", "#", SOURCE)?;
+    // NOTE Wide enough for "100%" with the decimal places, and a space.
+    let column_width = decimal_places + 6;
+    for metric in &metrics {
+        write!(writer, "{:>column_width$}", metric.to_string())?;
+    }
+    writer.reset()?;
+    writeln!(writer)?;
+    for (i, record) in records.iter().enumerate() {
+        write!(
+            writer,
+            "{:>index_width$}  {:<source_width$}",
+            i + 1,
+            record.source_opt.as_deref().unwrap_or_default()
+        )?;
+        writer.set_color(&theme().perc)?;
+        for &metric in &metrics {
+            let perc = format_perc(decimal_places, record.perc(metric, weight));
+            write!(writer, "{:>column_width$}", perc)?;
+        }
+        writer.reset()?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// Grids
+
+// NOTE Returns the grade (from 0.0 for the worst to 1.0 for the best) of each cell of a grid, scaled
+// within its column according to the goal of its metric. If every value of a column is the same,
+// each is graded as the best.
+fn grade_grid<const C: usize, const R: usize>(
+    records: &[Record<C, R>],
+    metrics: &[Metric],
+    weight: Weight,
+    goals: &MetricGoals,
+) -> Vec<Vec<Option<f64>>> {
+    let bounds = metrics
+        .iter()
+        .map(|&metric| {
+            records
+                .iter()
+                .filter_map(|record| record.perc(metric, weight))
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), perc| {
+                    (min.min(perc), max.max(perc))
+                })
+        })
+        .collect::<Vec<_>>();
+    records
+        .iter()
+        .map(|record| {
+            metrics
+                .iter()
+                .zip(&bounds)
+                .map(|(&metric, &(min, max))| {
+                    let perc = record.perc(metric, weight)?;
+                    let frac = if max > min {
+                        (perc - min) / (max - min)
+                    } else {
+                        1.0
+                    };
+                    Some(match goals.goal(metric) {
+                        Goal::Max => frac,
+                        Goal::Min => 1.0 - frac,
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// NOTE From red (the worst) through yellow to green (the best).
+fn grade_to_rgb(grade: f64, saturation: f32, value: f32) -> (u8, u8, u8) {
+    const HUE_BEST: f32 = 120.0;
+    hsv_to_rgb(HUE_BEST * grade.clamp(0.0, 1.0) as f32, saturation, value)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// NOTE Percentages are written as numbers (without '%'), and a metric that cannot be measured is
+// left empty.
+pub fn write_grid_csv<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    records: &[Record<C, R>],
+    weight: Weight,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    let Some(first) = records.first() else {
+        return Ok(());
+    };
+    let metrics = first.iter_metrics().collect::<Vec<_>>();
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    let header = ["rank".to_string(), "source".to_string()]
+        .into_iter()
+        .chain(metrics.iter().map(Metric::to_string));
+    csv_writer.write_record(header).map_err(io::Error::other)?;
+    for (i, record) in records.iter().enumerate() {
+        let row = [
+            (i + 1).to_string(),
+            record.source_opt.clone().unwrap_or_default(),
+        ]
+        .into_iter()
+        .chain(metrics.iter().map(|&metric| {
+            record
+                .perc(metric, weight)
+                .map(|perc| format!("{:.*}", decimal_places, perc))
+                .unwrap_or_default()
+        }));
+        csv_writer.write_record(row).map_err(io::Error::other)?;
+    }
+    csv_writer.flush()
+}
+
+// NOTE A standalone document, in which the background of each cell shows its grade.
+pub fn write_grid_html<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    records: &[Record<C, R>],
+    weight: Weight,
+    goals: &MetricGoals,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    let metrics = records
+        .first()
+        .map(|first| first.iter_metrics().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let grades = grade_grid(records, &metrics, weight, goals);
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html>")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>Perky</title>")?;
+    writeln!(
+        writer,
+        "<style>table {{ border-collapse: collapse; font-family: monospace; }} \
+         th, td {{ border: 1px solid #ccc; padding: 2px 6px; }} \
+         td.perc {{ text-align: right; }}</style>"
+    )?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<table>")?;
+    write!(writer, "<thead><tr><th>#</th><th>source</th>")?;
+    for &metric in &metrics {
+        write!(writer, "<th>{} {}</th>", metric, goals.goal(metric))?;
+    }
+    writeln!(writer, "</tr></thead>")?;
+    writeln!(writer, "<tbody>")?;
+    for (i, (record, grade_row)) in records.iter().zip(&grades).enumerate() {
+        write!(
+            writer,
+            "<tr><td>{}</td><td>{}</td>",
+            i + 1,
+            escape_html(record.source_opt.as_deref().unwrap_or_default())
+        )?;
+        for (&metric, grade_opt) in metrics.iter().zip(grade_row) {
+            let perc = format_perc(decimal_places, record.perc(metric, weight));
+            match grade_opt {
+                Some(grade) => {
+                    let (r, g, b) = grade_to_rgb(*grade, 0.35, 1.0);
+                    write!(
+                        writer,
+                        "<td class=\"perc\" style=\"background-color: #{:02x}{:02x}{:02x}\">{}</td>",
+                        r, g, b, perc
+                    )?;
+                }
+                None => write!(writer, "<td class=\"perc\">{}</td>", perc)?,
+            }
+        }
+        writeln!(writer, "</tr>")?;
+    }
+    writeln!(writer, "</tbody>")?;
+    writeln!(writer, "</table>")?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")
+}
+
+// NOTE Laid out like the leaderboard, with each percentage coloured by its grade.
+pub fn write_grid_text<const C: usize, const R: usize>(
+    writer: &mut dyn WriteColor,
+    records: &[Record<C, R>],
+    weight: Weight,
+    goals: &MetricGoals,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    const SOURCE: &str = "source";
+    let Some(first) = records.first() else {
+        return Ok(());
+    };
+    let metrics = first.iter_metrics().collect::<Vec<_>>();
+    let grades = grade_grid(records, &metrics, weight, goals);
+    let index_width = records.len().to_string().len();
+    let source_width = records
+        .iter()
+        .filter_map(|record| record.source_opt.as_ref())
+        .map(|source| source.chars().count())
+        .chain(iter::once(SOURCE.len()))
+        .max()
+        .unwrap_or_default();
+    writer.set_color(&theme().title)?;
+    write!(writer, "{:>index_width$}  {:<source_width$}", "#", SOURCE)?;
+    // NOTE Wide enough for "100%" with the decimal places, and a space.
+    let column_width = decimal_places + 6;
+    for &metric in &metrics {
+        let title = format!("{} {}", metric, goals.goal(metric));
+        write!(writer, "{:>column_width$}", title)?;
+    }
+    writer.reset()?;
+    writeln!(writer)?;
+    for (i, (record, grade_row)) in records.iter().zip(&grades).enumerate() {
+        write!(
+            writer,
+            "{:>index_width$}  {:<source_width$}",
+            i + 1,
+            record.source_opt.as_deref().unwrap_or_default()
+        )?;
+        for (&metric, grade_opt) in metrics.iter().zip(grade_row) {
+            let perc = format_perc(decimal_places, record.perc(metric, weight));
+            match grade_opt {
+                Some(grade) => {
+                    let (r, g, b) = grade_to_rgb(*grade, 1.0, 0.9);
+                    writer.set_color(ColorSpec::new().set_fg(Some(Color::Rgb(r, g, b))))?;
+                }
+                None => writer.set_color(&theme().none)?,
+            }
+            write!(writer, "{:>column_width$}", perc)?;
+        }
+        writer.reset()?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// N-gram tables
+
+// NOTE Each row is the n-gram, its count, the cumulative count, and both as percentages of the
+// sum of the whole table.
+pub fn write_top_ngrams_json<K: Display>(entries: &[(K, u64)], sum: u64) -> Value {
+    let mut cum = 0u64;
+    let rows = entries
+        .iter()
+        .map(|(key, value)| {
+            cum = cum.saturating_add(*value);
+            json!([
+                key.to_string(),
+                value,
+                cum,
+                calculate_perc(*value, sum),
+                calculate_perc(cum, sum),
+            ])
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "sum": sum,
+        "ngrams": rows,
+    })
+}
+
+pub fn write_top_ngrams_text<K: WriteStyled>(
+    writer: &mut dyn WriteColor,
+    entries: &[(K, u64)],
+    sum: u64,
+) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    let mut cum = 0u64;
+    for (key, value) in entries {
+        cum = cum.saturating_add(*value);
+        key.write_styled(writer)?;
+        write!(writer, ", ")?;
+        write!(writer, "{}", format_count(*value))?;
+        write!(writer, ", ")?;
+        write!(writer, "{}", format_count(cum))?;
+        write!(writer, ", ")?;
+        write_perc(writer, decimal_places, calculate_perc(*value, sum))?;
+        write!(writer, ", ")?;
+        write_perc(writer, decimal_places, calculate_perc(cum, sum))?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// NOTE Each line is the coverage of one n-gram table, followed by its most frequent n-grams that
+// are not covered (each with its percentage of the sum of the whole table). These n-grams are
+// quoted and escaped, as they often contain whitespace.
+fn write_coverage_row_text<K: Display>(
+    writer: &mut dyn WriteColor,
+    name: &str,
+    coverage_row: &CoverageRow<K>,
+    key_style: &ColorSpec,
+    decimal_places: usize,
+) -> io::Result<()> {
+    write!(writer, "{}: ", name)?;
+    write_perc(writer, decimal_places, coverage_row.perc())?;
+    for (i, (key, value)) in coverage_row.uncovered.iter().enumerate() {
+        write!(writer, "{}", if i == 0 { "; missing: " } else { ", " })?;
+        writer.set_color(key_style)?;
+        write!(writer, "\"{}\"", key.to_string().escape_debug())?;
+        writer.reset()?;
+        write!(writer, " ")?;
+        write_perc(
+            writer,
+            decimal_places,
+            calculate_perc(*value, coverage_row.sum),
+        )?;
+    }
+    writeln!(writer)
+}
+
+pub fn write_coverage_text(writer: &mut dyn WriteColor, coverage: &Coverage) -> io::Result<()> {
+    let decimal_places = decimal_places_or(3);
+    write_title(writer, "Coverage of the n-gram tables by the key table:")?;
+    let theme = theme();
+    write_coverage_row_text(
+        writer,
+        "Unigrams",
+        &coverage.unigrams,
+        &theme.unigram_key,
+        decimal_places,
+    )?;
+    write_coverage_row_text(
+        writer,
+        "Bigrams",
+        &coverage.bigrams,
+        &theme.bigram_key,
+        decimal_places,
+    )?;
+    write_coverage_row_text(
+        writer,
+        "Trigrams",
+        &coverage.trigrams,
+        &theme.trigram_key,
+        decimal_places,
+    )
+}
+
+// Sparklines
+
+const SPARKLINE_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+const SPARKLINE_WIDTH_MAX: usize = 64;
+
+// NOTE Each character is the percentage of one record (or, if there are more records than fit, the
+// mean of a run of consecutive records), scaled from the lowest percentage of the metric (▁) to the
+// highest (█), and followed by both. A record for which the metric cannot be measured is a space.
+pub fn write_sparklines_text(
+    writer: &mut dyn WriteColor,
+    sparklines: &[Sparkline],
+    goals: &MetricGoals,
+) -> io::Result<()> {
+    if sparklines.is_empty() {
+        return Ok(());
+    }
+    let decimal_places = decimal_places_or(3);
+    writeln!(writer)?;
+    write_title(writer, "Sparklines:")?;
+    for sparkline in sparklines {
+        let percs = &sparkline.percs;
+        let width = percs.len().min(SPARKLINE_WIDTH_MAX);
+        let means = (0..width)
+            .map(|i| {
+                let run = &percs[i * percs.len() / width..(i + 1) * percs.len() / width];
+                let values = run.iter().flatten().collect::<Vec<_>>();
+                (!values.is_empty())
+                    .then(|| values.iter().copied().sum::<f64>() / values.len() as f64)
+            })
+            .collect::<Vec<_>>();
+        let (min, max) = means
+            .iter()
+            .flatten()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &mean| {
+                (min.min(mean), max.max(mean))
+            });
+        sparkline.metric.write_styled(writer)?;
+        write!(writer, " {}: ", goals.goal(sparkline.metric))?;
+        writer.set_color(&theme().perc)?;
+        for mean_opt in &means {
+            let ch = match mean_opt {
+                None => ' ',
+                Some(mean) => {
+                    let frac = if max > min {
+                        (mean - min) / (max - min)
+                    } else {
+                        0.0
+                    };
+                    SPARKLINE_CHARS[(frac * (SPARKLINE_CHARS.len() - 1) as f64).round() as usize]
+                }
+            };
+            write!(writer, "{}", ch)?;
+        }
+        writer.reset()?;
+        if min <= max {
+            write!(writer, " ")?;
+            write_perc(writer, decimal_places, Some(min))?;
+            write!(writer, " to ")?;
+            write_perc(writer, decimal_places, Some(max))?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// Titles
 
 pub fn write_title(writer: &mut dyn WriteColor, s: &str) -> io::Result<()> {
-    writer.set_color(&STYLE_TITLE)?;
+    writer.set_color(&theme().title)?;
     writeln!(writer, "{}", s)?;
     writer.reset()
 }
@@ -0,0 +1,390 @@
+use core::error::Error;
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde_json::Value;
+
+use crate::{
+    keys::{Key, KeyLayers, KeyTable},
+    tables::Table,
+};
+
+// NOTE Includes are resolved relative to the directory of the file, as in an XKB symbols
+// directory. This bounds how deeply they can be nested (and so breaks cycles).
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// NOTE The keys of the three letter rows are imported, in the same positions as the built-in key
+// tables (and 'default.lt.json'). The backslash key (which is also the ISO key at the end of the
+// home row) is placed at the end of the top row.
+fn position_from_key_name(name: &str) -> Option<(usize, usize)> {
+    let index = |prefix: &str, n: usize| {
+        name.strip_prefix(prefix)?
+            .parse::<usize>()
+            .ok()
+            .filter(|i| (1..=n).contains(i))
+            .map(|i| i - 1)
+    };
+    match name {
+        "BKSL" | "AC12" => Some((0, 12)),
+        _ => index("AD", 12)
+            .map(|c| (0, c))
+            .or_else(|| index("AC", 11).map(|c| (1, c)))
+            .or_else(|| index("AB", 10).map(|c| (2, c))),
+    }
+}
+
+// NOTE The names of the Latin-1 keysyms (whose values are their code points), and of a few others
+// used by national layouts. Letters and digits are not included, as they are their own names.
+const KEYSYMS: &[(&str, char)] = &[
+    ("space", ' '),
+    ("exclam", '!'),
+    ("quotedbl", '"'),
+    ("numbersign", '#'),
+    ("dollar", '$'),
+    ("percent", '%'),
+    ("ampersand", '&'),
+    ("apostrophe", '\''),
+    ("quoteright", '\''),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("asterisk", '*'),
+    ("plus", '+'),
+    ("comma", ','),
+    ("minus", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("colon", ':'),
+    ("semicolon", ';'),
+    ("less", '<'),
+    ("equal", '='),
+    ("greater", '>'),
+    ("question", '?'),
+    ("at", '@'),
+    ("bracketleft", '['),
+    ("backslash", '\\'),
+    ("bracketright", ']'),
+    ("asciicircum", '^'),
+    ("underscore", '_'),
+    ("grave", '`'),
+    ("quoteleft", '`'),
+    ("braceleft", '{'),
+    ("bar", '|'),
+    ("braceright", '}'),
+    ("asciitilde", '~'),
+    ("nobreakspace", '\u{a0}'),
+    ("exclamdown", '¡'),
+    ("cent", '¢'),
+    ("sterling", '£'),
+    ("currency", '¤'),
+    ("yen", '¥'),
+    ("brokenbar", '¦'),
+    ("section", '§'),
+    ("diaeresis", '¨'),
+    ("copyright", '©'),
+    ("ordfeminine", 'ª'),
+    ("guillemotleft", '«'),
+    ("guillemetleft", '«'),
+    ("notsign", '¬'),
+    ("hyphen", '\u{ad}'),
+    ("registered", '®'),
+    ("macron", '¯'),
+    ("degree", '°'),
+    ("plusminus", '±'),
+    ("twosuperior", '²'),
+    ("threesuperior", '³'),
+    ("acute", '´'),
+    ("mu", 'µ'),
+    ("paragraph", '¶'),
+    ("periodcentered", '·'),
+    ("cedilla", '¸'),
+    ("onesuperior", '¹'),
+    ("masculine", 'º'),
+    ("ordmasculine", 'º'),
+    ("guillemotright", '»'),
+    ("guillemetright", '»'),
+    ("onequarter", '¼'),
+    ("onehalf", '½'),
+    ("threequarters", '¾'),
+    ("questiondown", '¿'),
+    ("Agrave", 'À'),
+    ("Aacute", 'Á'),
+    ("Acircumflex", 'Â'),
+    ("Atilde", 'Ã'),
+    ("Adiaeresis", 'Ä'),
+    ("Aring", 'Å'),
+    ("AE", 'Æ'),
+    ("Ccedilla", 'Ç'),
+    ("Egrave", 'È'),
+    ("Eacute", 'É'),
+    ("Ecircumflex", 'Ê'),
+    ("Ediaeresis", 'Ë'),
+    ("Igrave", 'Ì'),
+    ("Iacute", 'Í'),
+    ("Icircumflex", 'Î'),
+    ("Idiaeresis", 'Ï'),
+    ("ETH", 'Ð'),
+    ("Eth", 'Ð'),
+    ("Ntilde", 'Ñ'),
+    ("Ograve", 'Ò'),
+    ("Oacute", 'Ó'),
+    ("Ocircumflex", 'Ô'),
+    ("Otilde", 'Õ'),
+    ("Odiaeresis", 'Ö'),
+    ("multiply", '×'),
+    ("Oslash", 'Ø'),
+    ("Ooblique", 'Ø'),
+    ("Ugrave", 'Ù'),
+    ("Uacute", 'Ú'),
+    ("Ucircumflex", 'Û'),
+    ("Udiaeresis", 'Ü'),
+    ("Yacute", 'Ý'),
+    ("THORN", 'Þ'),
+    ("Thorn", 'Þ'),
+    ("ssharp", 'ß'),
+    ("agrave", 'à'),
+    ("aacute", 'á'),
+    ("acircumflex", 'â'),
+    ("atilde", 'ã'),
+    ("adiaeresis", 'ä'),
+    ("aring", 'å'),
+    ("ae", 'æ'),
+    ("ccedilla", 'ç'),
+    ("egrave", 'è'),
+    ("eacute", 'é'),
+    ("ecircumflex", 'ê'),
+    ("ediaeresis", 'ë'),
+    ("igrave", 'ì'),
+    ("iacute", 'í'),
+    ("icircumflex", 'î'),
+    ("idiaeresis", 'ï'),
+    ("eth", 'ð'),
+    ("ntilde", 'ñ'),
+    ("ograve", 'ò'),
+    ("oacute", 'ó'),
+    ("ocircumflex", 'ô'),
+    ("otilde", 'õ'),
+    ("odiaeresis", 'ö'),
+    ("division", '÷'),
+    ("oslash", 'ø'),
+    ("ugrave", 'ù'),
+    ("uacute", 'ú'),
+    ("ucircumflex", 'û'),
+    ("udiaeresis", 'ü'),
+    ("yacute", 'ý'),
+    ("thorn", 'þ'),
+    ("ydiaeresis", 'ÿ'),
+    ("EuroSign", '€'),
+    ("oe", 'œ'),
+    ("OE", 'Œ'),
+];
+
+// NOTE Letters and digits are their own keysyms. Keysyms may also be given as 'U' followed by a
+// code point, or as a number. Keysyms that are not characters (such as dead keys) are 'None'.
+fn char_from_keysym(keysym: &str) -> Option<char> {
+    let mut chars = keysym.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next())
+        && ch.is_ascii_alphanumeric()
+    {
+        return Some(ch);
+    }
+    if let Some(hex) = keysym.strip_prefix('U')
+        && let Ok(code) = u32::from_str_radix(hex, 16)
+    {
+        return char::from_u32(code);
+    }
+    if let Some(hex) = keysym.strip_prefix("0x") {
+        let code = u32::from_str_radix(hex, 16).ok()?;
+        return match code {
+            0x20..=0x7e | 0xa0..=0xff => char::from_u32(code),
+            0x0100_0100..=0x0110_ffff => char::from_u32(code - 0x0100_0000),
+            _ => None,
+        };
+    }
+    KEYSYMS
+        .iter()
+        .find(|(name, _)| *name == keysym)
+        .map(|&(_, ch)| ch)
+}
+
+fn strip_comments(s: &str) -> String {
+    s.lines()
+        .map(|line| line.split_once("//").map_or(line, |(code, _)| code))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn quoted(s: &str) -> Option<&str> {
+    let (_, rest) = s.split_once('"')?;
+    let (quoted, _) = rest.split_once('"')?;
+    Some(quoted)
+}
+
+// NOTE The body of the 'xkb_symbols' block with the given name or, if there is no name, of the
+// block marked 'default' (or else the first block).
+fn find_block<'a>(s: &'a str, name_opt: Option<&str>) -> Option<&'a str> {
+    let mut blocks = Vec::new();
+    let mut rest = s;
+    let mut offset = 0;
+    while let Some(i) = rest.find("xkb_symbols") {
+        let flags = &rest[..i];
+        let start = i + rest[i..].find('{')? + 1;
+        let mut depth = 1;
+        let mut end = start;
+        for (j, ch) in rest[start..].char_indices() {
+            match ch {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                end = start + j;
+                break;
+            }
+        }
+        if depth != 0 {
+            return None;
+        }
+        let name = quoted(&rest[i..start]).unwrap_or_default();
+        let is_default = flags
+            .rsplit(';')
+            .next()
+            .is_some_and(|flags| flags.split_whitespace().any(|flag| flag == "default"));
+        blocks.push((name, is_default, &s[offset + start..offset + end]));
+        offset += end + 1;
+        rest = &rest[end + 1..];
+    }
+    match name_opt {
+        Some(name) => blocks.iter().find(|block| block.0 == name),
+        None => blocks.iter().find(|block| block.1).or(blocks.first()),
+    }
+    .map(|block| block.2)
+}
+
+// NOTE The keysyms of the first group of a key, by level. Brackets that follow a name (such as
+// 'symbols[Group1]') are not lists of keysyms.
+fn keysyms(statement: &str) -> Option<Vec<&str>> {
+    let statement = match statement.find("symbols[") {
+        Some(i) => &statement[i + statement[i..].find('=')? + 1..],
+        None => statement,
+    };
+    let mut previous = ' ';
+    for (i, ch) in statement.char_indices() {
+        if ch == '[' && !previous.is_alphanumeric() {
+            let list = &statement[i + 1..];
+            let list = &list[..list.find(']')?];
+            return Some(list.split(',').map(str::trim).collect());
+        }
+        if !ch.is_whitespace() {
+            previous = ch;
+        }
+    }
+    None
+}
+
+// NOTE An include names one or more blocks (such as 'latin(type4)' or 'pc+us'), each a file in
+// the same directory with an optional block name. Blocks for groups other than the first (such as
+// 'ru:2') are ignored.
+fn read_include(
+    dpath: &Path,
+    include: &str,
+    depth: usize,
+    keys: &mut BTreeMap<(usize, usize), (char, Option<char>)>,
+) -> Result<(), Box<dyn Error>> {
+    for part in include.split(['+', '|']).filter(|part| !part.is_empty()) {
+        let part = match part.split_once(':') {
+            None => part,
+            Some((part, "1")) => part,
+            Some(_) => continue,
+        };
+        let (fname, name_opt) = match part.split_once('(') {
+            None => (part, None),
+            Some((fname, name)) => (fname, Some(name.trim_end_matches(')'))),
+        };
+        let s = fs::read_to_string(dpath.join(fname))
+            .map_err(|e| format!("Failed to include '{}': {e}", part))?;
+        read_block(dpath, &s, name_opt, depth + 1, keys)
+            .map_err(|e| format!("Failed to include '{}': {e}", part))?;
+    }
+    Ok(())
+}
+
+fn read_block(
+    dpath: &Path,
+    s: &str,
+    name_opt: Option<&str>,
+    depth: usize,
+    keys: &mut BTreeMap<(usize, usize), (char, Option<char>)>,
+) -> Result<(), Box<dyn Error>> {
+    if depth > MAX_INCLUDE_DEPTH {
+        Err(format!(
+            "Includes are nested too deeply (maximum is {})",
+            MAX_INCLUDE_DEPTH
+        ))?;
+    }
+    let s = strip_comments(s);
+    let block = find_block(&s, name_opt).ok_or_else(|| match name_opt {
+        Some(name) => format!("Expected 'xkb_symbols' block '{}'", name),
+        None => "Expected 'xkb_symbols' block".to_string(),
+    })?;
+    for statement in block.split(';').map(str::trim) {
+        let statement = statement
+            .strip_prefix("replace")
+            .or_else(|| statement.strip_prefix("override"))
+            .or_else(|| statement.strip_prefix("augment"))
+            .map_or(statement, str::trim_start);
+        if statement.starts_with("include") {
+            let include = quoted(statement).ok_or("Expected a quoted name after 'include'")?;
+            read_include(dpath, include, depth, keys)?;
+        } else if let Some(rest) = statement.strip_prefix("key") {
+            let Some(name) = rest
+                .trim_start()
+                .strip_prefix('<')
+                .and_then(|rest| rest.split_once('>'))
+                .map(|(name, _)| name)
+            else {
+                continue;
+            };
+            let Some(position) = position_from_key_name(name) else {
+                continue;
+            };
+            let keysyms = keysyms(rest).unwrap_or_default();
+            let mut chars = keysyms.iter().map(|keysym| char_from_keysym(keysym));
+            match (chars.next().flatten(), chars.next().flatten()) {
+                (Some(ch), shifted_ch_opt) => keys.insert(position, (ch, shifted_ch_opt)),
+                (None, _) => keys.remove(&position),
+            };
+        }
+    }
+    Ok(())
+}
+
+// NOTE The first level of each key is placed in the key table, and the second (its shifted
+// character) in a layer (see 'KeyLayers::from_shifted'). Keys whose first level is not a character
+// (such as a dead key) are left empty.
+pub fn read_xkb_from_path<const C: usize, const R: usize>(
+    path: &Path,
+    name_opt: Option<&str>,
+) -> Result<(KeyTable<C, R>, KeyLayers<C, R>), Box<dyn Error>> {
+    let s = fs::read_to_string(path)?;
+    let dpath = path.parent().unwrap_or(Path::new("."));
+    let mut keys = BTreeMap::new();
+    read_block(dpath, &s, name_opt, 0, &mut keys)?;
+    let mut key_table = KeyTable::<C, R>::default();
+    let mut shifted = Table::<C, R, Key>::default();
+    for ((r, c), (ch, shifted_ch_opt)) in keys {
+        if r >= R || c >= C {
+            Err(format!(
+                "Key ({}, {}) is outside of the table (maximum is {} columns by {} rows)",
+                r, c, C, R
+            ))?;
+        }
+        let to_key = |ch: char| {
+            Key::try_from(&Value::String(ch.to_string()))
+                .map_err(|e| format!("Invalid key ({}, {}): {}", r, c, e))
+        };
+        key_table.0[r][c] = Some(to_key(ch)?);
+        shifted[r][c] = shifted_ch_opt.map(to_key).transpose()?;
+    }
+    let key_layers = KeyLayers::from_shifted(&key_table, &shifted);
+    Ok((key_table, key_layers))
+}
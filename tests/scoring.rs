@@ -0,0 +1,64 @@
+use std::{env, error::Error, fs, path::Path};
+
+use perky::{
+    archives::record_to_value,
+    keys::KeyTable,
+    layouts::LayoutTable,
+    metrics::ThumbMode,
+    ngrams::{read_bigram_table, read_trigram_table, read_unigram_table},
+    scoring::{NgramTables, score_key_table},
+    testing,
+};
+
+const C: usize = 16;
+const R: usize = 8;
+
+const KEY_TABLES: [&str; 5] = ["colemak", "colemak-dh", "dvorak", "qwerty", "workman"];
+
+// NOTE Each example key table is scored with the default layout table and the Shakespeare corpus,
+// and its record (as JSON) is compared against a golden file. To update the golden files after an
+// intended change to scoring, run the tests with 'PERKY_UPDATE_GOLDEN=1'.
+#[test]
+fn score_example_key_tables() -> Result<(), Box<dyn Error>> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let corpus = root.join("resources/charfreq-shakespeare");
+    let unigram_table = read_unigram_table(fs::File::open(corpus.join("1-grams-uc.tsv"))?)?;
+    let bigram_table = read_bigram_table(fs::File::open(corpus.join("2-grams-uc.tsv"))?)?;
+    let trigram_table = read_trigram_table(fs::File::open(corpus.join("3-grams-uc.tsv"))?)?;
+    let ngram_tables = NgramTables::new(&unigram_table, &bigram_table, &trigram_table);
+    let layout_table_path = root.join("examples/layout-tables/default.lt.json");
+    let effort_model = testing::effort_model();
+    let update = env::var_os("PERKY_UPDATE_GOLDEN").is_some();
+    for name in KEY_TABLES {
+        // NOTE Scoring masks the layout table by the key table, so it is read for each.
+        let layout_table = LayoutTable::<C, R>::read_from_path(&layout_table_path, true)?;
+        let key_table = KeyTable::<C, R>::read_from_path(
+            &root.join(format!("examples/key-tables/{name}.kt.json")),
+            true,
+            false,
+        )?;
+        let record = score_key_table(
+            layout_table,
+            &key_table,
+            &effort_model,
+            ThumbMode::Hand,
+            false,
+            &ngram_tables,
+            &[],
+        )?;
+        let actual = serde_json::to_string_pretty(&record_to_value(&record))? + "\n";
+        let golden_path = root.join(format!("tests/golden/{name}.json"));
+        if update {
+            fs::write(&golden_path, &actual)?;
+            continue;
+        }
+        let expected = fs::read_to_string(&golden_path)
+            .map_err(|e| format!("Failed to load file '{}': {e}", golden_path.display()))?;
+        assert!(
+            actual == expected,
+            "The record of '{name}' differs from '{}'",
+            golden_path.display()
+        );
+    }
+    Ok(())
+}